@@ -1,143 +1,1087 @@
-use colored::Colorize;
-use reqwest::StatusCode;
+use http::StatusCode;
+#[cfg(feature = "danbooru")]
+use rand::seq::IteratorRandom;
+#[cfg(feature = "danbooru")]
+use rand::Rng;
+#[cfg(feature = "danbooru")]
+use regex::Regex;
+#[cfg(feature = "danbooru")]
+use reqwest::blocking::{Client, RequestBuilder};
 use serde::Deserialize;
+#[cfg(feature = "danbooru")]
+use serde::Serialize;
 use serde_json::Value;
-use std::error::Error;
-use std::fmt;
+#[cfg(feature = "danbooru")]
+use std::collections::BTreeSet;
+#[cfg(feature = "danbooru")]
+use std::time::Duration;
 
-use crate::api::reformat_search_tags;
-use crate::app::Danbooru;
+use crate::api::{encode_tags, parse_suggestions, Booru, Http, Post, Query, Source};
+#[cfg(feature = "danbooru")]
+use crate::api::{excluded_tags, split_tags, Filters, Orientation, Search, MAX_SEARCHES_AGAIN};
+use crate::error::WaifuError;
+#[cfg(feature = "danbooru")]
+use crate::exit;
+#[cfg(feature = "danbooru")]
+use crate::net;
+#[cfg(feature = "danbooru")]
+use crate::random;
+use crate::rating::Rating;
+#[cfg(feature = "danbooru")]
+use crate::retry::Retry;
+use crate::video;
 
-pub fn grab_random_image(args: Danbooru) -> String {
-    let request_url = evaluate_arguments(&args);
-    let data = match fetch_api_data(request_url) {
-        Ok(json_data) => json_data,
-        Err(error) => {
-            eprintln!("{}\n", error);
-            std::process::exit(1);
+/// A search for a random post, like `waifu dan` makes
+#[cfg(feature = "danbooru")]
+#[derive(Debug, Clone, Default)]
+pub struct RandomSearch {
+    /// Tags and metatags, separated by spaces or commas
+    pub tags: Option<String>,
+    /// Only posts with one of these ratings, or any when it's empty
+    pub ratings: Vec<Rating>,
+    pub filters: Filters,
+    /// Over the tags Danbooru searches for at once without a login, search for
+    /// the ones with the fewest posts and check the posts found for the rest
+    pub narrow_tags: bool,
+    /// Pick from the posts this user has favorited. Their favorites have to be public
+    pub from_favorites: Option<String>,
+    /// Browse this way instead of searching
+    pub explore: Option<Explore>,
+}
+
+/// Other ways to browse Danbooru than a random search
+#[cfg(feature = "danbooru")]
+#[derive(Debug, Clone)]
+pub enum Explore {
+    /// The posts trending on Danbooru, its most popular ones of the day, week
+    /// or month
+    Popular { period: Period },
+}
+
+/// The time popular posts are ranked over
+#[cfg(feature = "danbooru")]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Period {
+    Day,
+    Week,
+    Month,
+}
+
+#[cfg(feature = "danbooru")]
+impl Period {
+    /// The name Danbooru gives it
+    pub fn scale(self) -> &'static str {
+        match self {
+            Period::Day => "day",
+            Period::Week => "week",
+            Period::Month => "month",
         }
-    };
+    }
+}
 
-    let valid_data: Vec<&ImageData> = data
-        .iter()
-        .filter(|image| !image.file_url.is_empty())
-        .collect();
-    if valid_data.is_empty() {
-        eprintln!("Danbooru returned no images with accessible URLs.");
-        std::process::exit(1);
-    }
-    let image = &valid_data[0];
-    let image_url = &image.file_url;
-
-    if args.details {
-        if let Err(error) = print_image_details(image) {
-            eprintln!("{}\n", error);
-            println!(
-                "{}: There was an error when printing the tags. Please try again later.",
-                "help".green()
+#[cfg(feature = "danbooru")]
+impl Api {
+    /// Pick a random post. Video posts are passed over unless `videos` is set, and so
+    /// are the `shown` ones, searching again when nothing else is found
+    pub fn grab_random_image(
+        &self,
+        search: &RandomSearch,
+        videos: bool,
+        shown: &BTreeSet<u32>,
+    ) -> Result<Post, WaifuError> {
+        let (searched, checked) = self.narrowed(search, search_terms(search));
+        let limited: Vec<&String> = searched.iter().filter(|term| !is_free(term)).collect();
+        if search.explore.is_none() && limited.len() > ANONYMOUS_TAG_LIMIT && !self.logged_in() {
+            let names: Vec<&str> = limited.iter().map(|term| term.as_str()).collect();
+            let message = format!(
+                "Danbooru only searches for {} tags at once without a login, and this search has {}: {}",
+                ANONYMOUS_TAG_LIMIT,
+                limited.len(),
+                names.join(" ")
             );
-            std::process::exit(1);
+            let narrow = if search.narrow_tags {
+                ""
+            } else {
+                ", or pass --narrow-tags to search for the ones with the \
+                 fewest posts and check the posts found for the rest"
+            };
+            let login = if self.base_url == DANBOORU {
+                "Log in with `waifu auth login dan`"
+            } else {
+                "Pass your --username and --key for the site"
+            };
+            let help = format!("{}{}", login, narrow);
+            return Err(exit::with_help(message, help));
         }
+        let mut request_url = self.terms_url(search, &searched, !checked.is_empty(), 0);
+        if random::seeded() && search.explore.is_none() {
+            let page = self.seeded_page(&request_url, search_limit(!checked.is_empty()))?;
+            request_url = self.terms_url(search, &searched, !checked.is_empty(), page);
+        }
+        let fetch = |url: String| self.fetch_api_data(url);
+        let ttl = self.http.search_ttl;
+        let mut found = Search::run(request_url.clone(), self.samples, ttl, fetch)?;
+
+        let ratings = &search.ratings;
+        let usable = |image: &Post| {
+            image.has_file()
+                && (videos || !video::is_video(&image.url))
+                && (ratings.is_empty()
+                    || image.rating.is_some_and(|rating| ratings.contains(&rating)))
+        };
+        // The popular posts can't be searched for a size
+        let fits = |image: &Post| {
+            let filters = &search.filters;
+            filters.fits(image.width, image.height, &image.url, image.file_size)
+                && has_tags(image, &checked)
+        };
+        let mut index = None;
+        let mut rng = random::rng();
+        for searches in 0.. {
+            // Any of the usable posts rather than the first, as popular ones come
+            // ranked by score
+            index = (0..found.posts.len())
+                .filter(|&i| {
+                    let image = &found.posts[i];
+                    usable(image) && fits(image) && !shown.contains(&image.id)
+                })
+                .choose(&mut rng);
+            let any_usable = found.posts.iter().any(usable);
+            // The popular posts are the same every time, and so is a seeded page
+            let again =
+                search.explore.is_none() && !random::seeded() && searches < MAX_SEARCHES_AGAIN;
+            if index.is_some() || !any_usable || !again {
+                break;
+            }
+            // order:random finds others every time
+            found.search_again(request_url.clone(), fetch)?;
+        }
+        let Some(index) = index else {
+            let fitting = found.posts.iter().any(|image| usable(image) && fits(image));
+            let message = if !shown.is_empty() && fitting {
+                "Danbooru only returned images that were shown recently."
+            } else if found.posts.iter().any(usable) {
+                "Danbooru returned no images that fit the filters given."
+            } else if videos {
+                "Danbooru returned no images with accessible URLs."
+            } else {
+                "Danbooru returned only videos or images without accessible URLs."
+            };
+            return Err(WaifuError::NoResults(message.into()));
+        };
+        Ok(found.take(index))
     }
 
-    image_url.to_string()
-}
+    /// Look up a single post by its ID, e.g. one taken from a post page URL, along
+    /// with its details
+    pub fn grab_post(&self, id: u32) -> Result<Post, WaifuError> {
+        let mut data = self.fetch_api_data(format!("{}/posts/{}.json", self.base_url, id))?;
+        let image = data.swap_remove(0);
+        if !image.has_file() {
+            return Err(format!("Danbooru post #{} has no accessible image URL", id).into());
+        }
+
+        Ok(image)
+    }
+
+    /// Look up the post whose file has this MD5, along with its details
+    pub fn grab_by_md5(&self, md5: &str) -> Result<Post, WaifuError> {
+        let url = format!("{}/posts.json?tags=md5:{}", self.base_url, md5);
+        let mut data = match self.fetch_api_data(url) {
+            Err(WaifuError::NoResults(_)) => {
+                let message = format!("No Danbooru post has the MD5 {}", md5);
+                return Err(WaifuError::NoResults(message));
+            }
+            data => data?,
+        };
+
+        Ok(data.swap_remove(0))
+    }
+
+    /// Favorite the post as the user logged in, or take it back out of their
+    /// favorites
+    pub fn favorite(&self, id: u32, add: bool) -> Result<(), WaifuError> {
+        let client = self.http.client()?;
+        let request = if add {
+            client.post(format!("{}/favorites.json?post_id={}", self.base_url, id))
+        } else {
+            client.delete(format!("{}/favorites/{}.json", self.base_url, id))
+        };
+
+        self.send_as_user(request, "Favoriting posts")
+    }
+
+    /// Vote the post up with a score of 1, or down with -1, as the user logged in.
+    /// Voting again replaces the vote they gave before
+    pub fn vote(&self, id: u32, score: i8) -> Result<(), WaifuError> {
+        let request = self.http.client()?.post(format!(
+            "{}/posts/{}/votes.json?score={}",
+            self.base_url, id, score
+        ));
+
+        self.send_as_user(request, "Voting")
+    }
+
+    /// Send a request that changes something for the user logged in, saying what
+    /// it was `doing` when there's no login
+    fn send_as_user(&self, request: RequestBuilder, doing: &str) -> Result<(), WaifuError> {
+        let Some((user, key)) = &self.http.login else {
+            let message = if self.base_url == DANBOORU {
+                format!(
+                    "{} on Danbooru needs a login, add yours with `waifu auth login dan`",
+                    doing
+                )
+            } else {
+                format!(
+                    "{} on {} needs a login, and the Danbooru one isn't sent to other sites",
+                    doing, self.base_url
+                )
+            };
+            return Err(message.into());
+        };
+        let response = self.http.send(request.basic_auth(user, Some(key)))?;
+        let status = response.status();
+        if status.is_success() {
+            return Ok(());
+        }
+
+        let text = response.text().unwrap_or_default();
+        let message = match serde_json::from_str::<FailureResponse>(&text) {
+            Ok(err) => err.message,
+            Err(_) => "Unexpected response.".to_string(),
+        };
+        Err(WaifuError::api(status, message))
+    }
 
-fn check_env_variables() -> (Option<String>, Option<String>) {
-    use std::env;
+    /// Look up a pool by its ID, or else the one with the most posts whose name
+    /// has this in it
+    pub fn find_pool(&self, id_or_name: &str) -> Result<Pool, WaifuError> {
+        let url = match id_or_name.parse::<u32>() {
+            Ok(id) => format!("{}/pools/{}.json", self.base_url, id),
+            Err(_) => {
+                let name = id_or_name.trim().replace(' ', "_");
+                let mut url = reqwest::Url::parse(&format!("{}/pools.json", self.base_url))
+                    .map_err(|e| e.to_string())?;
+                url.query_pairs_mut()
+                    .append_pair("search[name_matches]", &format!("*{}*", name))
+                    .append_pair("search[order]", "post_count")
+                    .append_pair("limit", "1");
+                url.to_string()
+            }
+        };
+        let response = self.http.send(self.http.client()?.get(url))?;
+        let missing = || {
+            let message = format!("Danbooru has no pool like {}", id_or_name);
+            WaifuError::NoResults(message)
+        };
+        if response.status() == StatusCode::NOT_FOUND {
+            return Err(missing());
+        }
+        if !response.status().is_success() {
+            return Err(WaifuError::api(
+                response.status(),
+                "Danbooru couldn't find the pool",
+            ));
+        }
+
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Found {
+            One(PoolData),
+            Some(Vec<PoolData>),
+        }
+        #[derive(Deserialize)]
+        struct PoolData {
+            id: u32,
+            name: String,
+            #[serde(default)]
+            category: String,
+            #[serde(default)]
+            description: String,
+            #[serde(default)]
+            post_ids: Vec<u32>,
+        }
+        let pool = match response.json()? {
+            Found::One(pool) => pool,
+            Found::Some(pools) => pools.into_iter().next().ok_or_else(missing)?,
+        };
+
+        Ok(Pool {
+            id: pool.id,
+            name: pool.name,
+            category: pool.category,
+            description: excerpt(&pool.description).join("\n\n"),
+            post_ids: pool.post_ids,
+        })
+    }
+
+    /// Every post in the pool with an image to download, in the pool's order
+    pub fn pool_posts(&self, pool: &Pool) -> Result<Vec<Post>, WaifuError> {
+        let query = Query::builder()
+            .tags([format!("ordpool:{}", pool.id)])
+            .limit(200)
+            .build();
+        let mut posts = Vec::new();
+        for post in self.search_iter(query) {
+            let post = post?;
+            if post.has_file() {
+                posts.push(post);
+            }
+        }
+
+        Ok(posts)
+    }
+
+    /// Tags starting with the prefix, the most used first, from Danbooru's
+    /// autocomplete. A leading - for leaving a tag out is kept on them
+    pub fn complete_tag(&self, prefix: &str) -> Result<Vec<String>, WaifuError> {
+        let (negated, prefix) = match prefix.strip_prefix('-') {
+            Some(prefix) => ("-", prefix),
+            None => ("", prefix),
+        };
+        if prefix.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let (status, body) = self.http.get(&self.complete_url(prefix))?;
+
+        Ok(self
+            .parse_completions(status, &body)?
+            .into_iter()
+            .map(|tag| format!("{}{}", negated, tag))
+            .collect())
+    }
+
+    /// Tags matching the pattern, the most used first. * in it stands for anything,
+    /// and one without any matches tags containing it
+    pub fn search_tags(&self, pattern: &str, limit: usize) -> Result<Vec<TagInfo>, WaifuError> {
+        let pattern = if pattern.contains('*') {
+            pattern.to_string()
+        } else {
+            format!("*{}*", pattern)
+        };
+
+        self.fetch_tags(&[
+            ("search[name_matches]", pattern.as_str()),
+            ("search[order]", "count"),
+            ("search[hide_empty]", "true"),
+            ("limit", &limit.to_string()),
+        ])
+    }
+
+    /// The category and post count of each of the tags, in the same order. Ones
+    /// Danbooru doesn't have, like some of Safebooru's, are general with no posts
+    pub fn tag_info(&self, names: &[String]) -> Result<Vec<TagInfo>, WaifuError> {
+        let mut found = Vec::new();
+        // A hundred at a time keeps the URL short enough
+        for chunk in names.chunks(100) {
+            let names = chunk.join(",");
+            found.extend(
+                self.fetch_tags(&[("search[name_comma]", names.as_str()), ("limit", "100")])?,
+            );
+        }
+
+        Ok(names
+            .iter()
+            .map(|name| {
+                found
+                    .iter()
+                    .find(|tag| &tag.name == name)
+                    .cloned()
+                    .unwrap_or_else(|| TagInfo {
+                        name: name.clone(),
+                        category: "general",
+                        post_count: 0,
+                    })
+            })
+            .collect())
+    }
 
-    let mut login_info = (None, None);
+    /// The tag of a category, like character or artist, that a name best matches,
+    /// e.g. komeiji_koishi for "koishi". The words can be in either order, and when
+    /// no tag has them all the closest spelt of those with the longest is taken.
+    /// The most used wins among tags that match as well
+    pub fn resolve_tag(&self, name: &str, category: &str) -> Result<TagInfo, WaifuError> {
+        let id = match category {
+            "artist" => "1",
+            "copyright" => "3",
+            "character" => "4",
+            "meta" => "5",
+            _ => "0",
+        };
+        let name = name.trim().to_lowercase().replace(' ', "_");
+        let words: Vec<&str> = name.split('_').filter(|word| !word.is_empty()).collect();
+        if words.is_empty() {
+            return Err(format!("Give the name of the {} to look for", category).into());
+        }
+        let longest = words
+            .iter()
+            .max_by_key(|word| word.len())
+            .copied()
+            .unwrap_or("");
+        let reversed: Vec<&str> = words.iter().rev().copied().collect();
+        let patterns = [
+            format!("*{}*", words.join("*")),
+            format!("*{}*", reversed.join("*")),
+            format!("*{}*", longest),
+        ];
 
-    for (key, value) in env::vars() {
-        let key = key.as_str();
-        match key {
-            "DANBOORU_USERNAME" => {
-                login_info.0 = Some(value);
+        for (i, pattern) in patterns.iter().enumerate() {
+            if i > 0 && *pattern == patterns[i - 1] {
+                continue;
             }
-            "DANBOORU_API_KEY" => {
-                login_info.1 = Some(value);
+            let mut tags = self.fetch_tags(&[
+                ("search[name_matches]", pattern.as_str()),
+                ("search[category]", id),
+                ("search[order]", "count"),
+                ("search[hide_empty]", "true"),
+                ("limit", "50"),
+            ])?;
+            if tags.is_empty() {
+                continue;
             }
-            &_ => (),
+            if let Some(exact) = tags.iter().position(|tag| tag.name == name) {
+                return Ok(tags.swap_remove(exact));
+            }
+            if i == patterns.len() - 1 {
+                // Sorting is stable, so the most used stays first among equally close ones
+                tags.sort_by(|a, b| {
+                    let closeness = |tag: &TagInfo| strsim::jaro_winkler(&name, &tag.name);
+                    closeness(b).total_cmp(&closeness(a))
+                });
+            }
+            return Ok(tags.swap_remove(0));
         }
+
+        let message = format!("Danbooru has no {} tag like {}", category, name);
+        Err(WaifuError::NoResults(message))
     }
 
-    login_info
-}
+    /// The profile for an artist tag, or None when nobody has written one
+    pub fn artist_profile(&self, tag: &str) -> Result<Option<ArtistProfile>, WaifuError> {
+        #[derive(Deserialize)]
+        struct Artist {
+            name: String,
+            #[serde(default)]
+            other_names: Vec<String>,
+            #[serde(default)]
+            group_name: String,
+            #[serde(default)]
+            urls: Vec<ArtistUrl>,
+        }
+        #[derive(Deserialize)]
+        struct ArtistUrl {
+            url: String,
+            #[serde(default = "active")]
+            is_active: bool,
+        }
+        fn active() -> bool {
+            true
+        }
 
-fn evaluate_arguments(args: &Danbooru) -> String {
-    // Use limit=1 and order:random in tags; some deployments 403 on random=true
-    let mut api = String::from("https://danbooru.donmai.us/posts.json?limit=1");
+        let request = self
+            .http
+            .client()?
+            .get(format!("{}/artists.json", self.base_url))
+            .query(&[
+                ("search[name]", tag),
+                ("only", "name,other_names,group_name,urls"),
+            ]);
+        let response = self.http.send(request)?;
+        if !response.status().is_success() {
+            return Err(WaifuError::api(
+                response.status(),
+                "Danbooru couldn't look up the artist",
+            ));
+        }
+        let artists: Vec<Artist> = response.json()?;
 
-    if let Some(username) = &args.username {
-        if let Some(api_key) = &args.key {
+        Ok(artists.into_iter().next().map(|artist| ArtistProfile {
+            name: artist.name,
+            other_names: artist.other_names,
+            group_name: (!artist.group_name.is_empty()).then_some(artist.group_name),
+            urls: artist
+                .urls
+                .into_iter()
+                .filter(|url| url.is_active)
+                .map(|url| url.url)
+                .collect(),
+        }))
+    }
+
+    fn fetch_tags(&self, query: &[(&str, &str)]) -> Result<Vec<TagInfo>, WaifuError> {
+        let request = self
+            .http
+            .client()?
+            .get(format!("{}/tags.json", self.base_url))
+            .query(query);
+        let response = self.http.send(request)?;
+        if !response.status().is_success() {
+            return Err(WaifuError::api(
+                response.status(),
+                "Danbooru couldn't look up the tags",
+            ));
+        }
+        let tags: Vec<TagData> = response.json()?;
+
+        Ok(tags.into_iter().map(TagInfo::from).collect())
+    }
+
+    /// The notes on the post, from the top of the image down
+    pub fn notes(&self, post_id: u32) -> Result<Vec<Note>, WaifuError> {
+        let request = self
+            .http
+            .client()?
+            .get(format!("{}/notes.json", self.base_url))
+            .query(&[
+                ("search[post_id]", post_id.to_string().as_str()),
+                ("search[is_active]", "true"),
+                ("limit", "1000"),
+            ]);
+        let response = self.http.send(request)?;
+        if !response.status().is_success() {
+            return Err(WaifuError::api(
+                response.status(),
+                "Danbooru couldn't find the notes",
+            ));
+        }
+        let mut notes: Vec<Note> = response.json()?;
+
+        for note in &mut notes {
+            note.body = note_text(&note.body);
+        }
+        notes.sort_by_key(|note| (note.y, note.x));
+        Ok(notes)
+    }
+
+    /// The newest comments on the post, up to `limit` of them, oldest first
+    pub fn comments(&self, post_id: u32, limit: u32) -> Result<Vec<Comment>, WaifuError> {
+        #[derive(Deserialize)]
+        struct Creator {
+            name: String,
+        }
+        #[derive(Deserialize)]
+        struct Found {
+            created_at: String,
+            score: i32,
+            body: String,
+            creator: Option<Creator>,
+        }
+
+        let request = self
+            .http
+            .client()?
+            .get(format!("{}/comments.json", self.base_url))
+            .query(&[
+                ("search[post_id]", post_id.to_string().as_str()),
+                ("search[is_deleted]", "false"),
+                ("only", "created_at,score,body,creator[name]"),
+                ("limit", limit.to_string().as_str()),
+            ]);
+        let response = self.http.send(request)?;
+        if !response.status().is_success() {
+            return Err(WaifuError::api(
+                response.status(),
+                "Danbooru couldn't find the comments",
+            ));
+        }
+        let found: Vec<Found> = response.json()?;
+
+        // They come newest first
+        Ok(found
+            .into_iter()
+            .rev()
+            .map(|comment| Comment {
+                creator: comment
+                    .creator
+                    .map_or_else(|| "Someone".to_string(), |creator| creator.name),
+                created_at: comment.created_at,
+                score: comment.score,
+                body: comment.body.trim().replace("\r\n", "\n"),
+            })
+            .collect())
+    }
+
+    /// The tags most often on the same posts as this one, the most often first
+    pub fn related_tags(&self, tag: &str, limit: usize) -> Result<Vec<RelatedTag>, WaifuError> {
+        #[derive(Deserialize)]
+        struct Response {
+            #[serde(default)]
+            related_tags: Vec<Related>,
+        }
+        #[derive(Deserialize)]
+        struct Related {
+            tag: TagData,
+            #[serde(default)]
+            frequency: f64,
+        }
+
+        let tag = tag.trim().to_lowercase().replace(' ', "_");
+        let request = self
+            .http
+            .client()?
+            .get(format!("{}/related_tag.json", self.base_url))
+            .query(&[("query", tag.as_str()), ("limit", &(limit + 1).to_string())]);
+        let response = self.http.send(request)?;
+        if !response.status().is_success() {
+            return Err(WaifuError::api(
+                response.status(),
+                "Danbooru couldn't find related tags",
+            ));
+        }
+        let response: Response = response.json()?;
+
+        Ok(response
+            .related_tags
+            .into_iter()
+            // The tag itself always comes first
+            .filter(|related| related.tag.name != tag)
+            .take(limit)
+            .map(|related| RelatedTag {
+                tag: related.tag.into(),
+                frequency: related.frequency,
+            })
+            .collect())
+    }
+
+    /// Look up the wiki page for a tag. Spaces in it count as underscores
+    pub fn wiki_page(&self, tag: &str) -> Result<WikiPage, WaifuError> {
+        #[derive(Deserialize)]
+        struct Page {
+            title: String,
+            body: String,
+            #[serde(default)]
+            other_names: Vec<String>,
+        }
+
+        let tag = tag.trim().to_lowercase().replace(' ', "_");
+        let mut url = reqwest::Url::parse(&format!("{}/wiki_pages", self.base_url))
+            .map_err(|e| e.to_string())?;
+        url.path_segments_mut()
+            .map_err(|_| "Couldn't make the wiki page's URL")?
+            .push(&format!("{}.json", tag));
+        let response = self.http.send(self.http.client()?.get(url))?;
+        if response.status() == StatusCode::NOT_FOUND {
+            let message = format!("Danbooru has no wiki page for {}", tag);
+            return Err(WaifuError::NoResults(message));
+        }
+        if !response.status().is_success() {
+            return Err(WaifuError::api(
+                response.status(),
+                "Danbooru couldn't get the wiki page",
+            ));
+        }
+        let page: Page = response.json()?;
+
+        Ok(WikiPage {
+            title: page.title,
+            other_names: page.other_names,
+            excerpt: excerpt(&page.body),
+        })
+    }
+
+    /// The API URL the search is made with
+    pub fn search_url(&self, search: &RandomSearch) -> String {
+        let (searched, checked) = self.narrowed(search, search_terms(search));
+        self.terms_url(search, &searched, !checked.is_empty(), 0)
+    }
+
+    /// A page of the search picked with the seeded random numbers, out of those
+    /// there are of the posts it finds
+    fn seeded_page(&self, request_url: &str, limit: u32) -> Result<u32, WaifuError> {
+        let counts_url = request_url.replacen("/posts.json?", "/counts/posts.json?", 1);
+        let (status, body) = self.http.get(&counts_url)?;
+        if !status.is_success() {
+            let message = "Danbooru couldn't count the posts the search finds";
+            return Err(WaifuError::api(status, message));
+        }
+        let counts: Value = serde_json::from_slice(&body)?;
+        let count = counts["counts"]["posts"].as_u64().unwrap_or(0) as u32;
+        let pages = count.div_ceil(limit).clamp(1, MAX_PAGE);
+        let page = random::rng().gen_range(0..pages);
+        tracing::debug!("Searching page {} of the {} posts found", page + 1, count);
+
+        Ok(page)
+    }
+
+    /// The API URL to search for the terms with
+    fn terms_url(
+        &self,
+        search: &RandomSearch,
+        terms: &[String],
+        narrowed: bool,
+        page: u32,
+    ) -> String {
+        // Use order:random in tags; some deployments 403 on random=true. It can't be
+        // seeded, so seeded searches go by the posts' MD5s, which are as good as
+        // shuffled but come in the same order every time, on a page picked here
+        let order = if random::seeded() {
+            "order:md5"
+        } else {
+            "order:random"
+        };
+        let mut query = Query::builder()
+            .tags(terms)
+            .tags([order])
+            .limit(search_limit(narrowed))
+            .page(page);
+        for &rating in &search.ratings {
+            query = query.rating(rating);
+        }
+        let mut api = match &search.explore {
+            // The popular posts can't be searched, so their ratings are checked after
+            Some(Explore::Popular { period }) => format!(
+                "{}/explore/posts/popular.json?scale={}",
+                self.base_url,
+                period.scale()
+            ),
+            None => self.url(&query.build()),
+        };
+
+        if let Some((username, api_key)) = &self.http.login {
             let login_info = format!("&login={}&api_key={}", username, api_key);
             api.push_str(login_info.as_str());
         }
-    } else if let (Some(username), Some(api_key)) = check_env_variables() {
-        let login_info = format!("&login={}&api_key={}", username, api_key);
-        api.push_str(login_info.as_str());
+
+        api
     }
 
-    let Danbooru {
-        safe,
-        questionable,
-        explicit,
-        tags,
-        ..
-    } = args;
+    /// Whether the search is made as a user rather than anonymously
+    fn logged_in(&self) -> bool {
+        self.http.login.is_some()
+    }
 
-    let tags = match tags {
-        Some(search_items) => search_items,
-        None => "",
-    };
+    /// The terms to search for, and the tags to check the posts found for instead.
+    /// With narrow_tags, a search over the limit without a login keeps the tags
+    /// with the fewest posts, since they narrow it down the most, and its metatags
+    fn narrowed(&self, search: &RandomSearch, terms: Vec<String>) -> (Vec<String>, Vec<String>) {
+        let limited = terms.iter().filter(|term| !is_free(term)).count();
+        if !search.narrow_tags || limited <= ANONYMOUS_TAG_LIMIT || self.logged_in() {
+            return (terms, Vec::new());
+        }
 
-    let search_tags = String::from(tags);
-    let mut tags = reformat_search_tags(search_tags);
+        // Only plain tags can be checked on the posts, and only the ones that have
+        // to be there narrow the search down. The size and type of file the filters
+        // ask for are checked on them anyway
+        let plain = |term: &String| !term.contains(':') && !term.contains('*');
+        let (tags, metatags): (Vec<String>, Vec<String>) = terms.into_iter().partition(plain);
+        let filtered = ["width:", "height:", "ratio:", "filesize:", "filetype:"];
+        let metatags: Vec<String> = metatags
+            .into_iter()
+            .filter(|term| !filtered.iter().any(|metatag| term.starts_with(metatag)))
+            .collect();
+        let fixed = metatags.iter().filter(|term| !is_free(term)).count();
+        let (mut wanted, mut checked): (Vec<String>, Vec<String>) = tags
+            .into_iter()
+            .partition(|tag| !tag.starts_with('-') && !tag.starts_with('~'));
+        let counts = self.tag_info(&wanted).unwrap_or_else(|error| {
+            tracing::warn!("Couldn't look up how many posts the tags have: {}", error);
+            Vec::new()
+        });
+        let count = |tag: &String| {
+            counts
+                .iter()
+                .find(|info| &info.name == tag)
+                .map_or(u32::MAX, |info| info.post_count)
+        };
+        wanted.sort_by_key(count);
 
-    if *safe {
-        tags.push_str("%20rating:s");
-    } else if *questionable {
-        tags.push_str("%20rating:q");
-    } else if *explicit {
-        tags.push_str("%20rating:e");
+        let kept = ANONYMOUS_TAG_LIMIT.saturating_sub(fixed).min(wanted.len());
+        checked.extend(wanted.split_off(kept));
+        let mut searched = metatags;
+        searched.extend(wanted);
+        (searched, checked)
     }
-    // Randomize via tag ordering to avoid random=true 403s
-    tags.push_str("%20order:random");
 
-    let tags = format!("&tags={}", tags);
-    api.push_str(&tags);
+    fn fetch_api_data(&self, url: String) -> Result<Vec<Post>, WaifuError> {
+        let (status, body) = self.http.get(&url)?;
+        self.parse_api_data(status, &body)
+    }
+}
 
-    api
+/// A pool of posts meant to be seen in order, like a comic, or as a set
+#[cfg(feature = "danbooru")]
+#[derive(Serialize, Debug)]
+pub struct Pool {
+    pub id: u32,
+    pub name: String,
+    /// series or collection
+    pub category: String,
+    pub description: String,
+    pub post_ids: Vec<u32>,
 }
 
-#[derive(Debug)]
-struct ImageData {
-    source: String,
-    pixiv_id: Option<u32>,
-    file_url: String,
-    tag_string_character: String,
-    tag_string_artist: String,
-    rating: char,
-    image_width: u32,
-    image_height: u32,
-    tag_string: String,
+/// A tag Danbooru has, for `waifu tag search` and --tag-info
+#[cfg(feature = "danbooru")]
+#[derive(Serialize, Debug, Clone)]
+pub struct TagInfo {
+    pub name: String,
+    /// general, artist, copyright, character or meta
+    pub category: &'static str,
+    pub post_count: u32,
 }
 
-#[derive(Deserialize, Debug)]
-struct FailureResponse {
-    message: String,
+#[cfg(feature = "danbooru")]
+#[derive(Deserialize)]
+struct TagData {
+    name: String,
+    category: u8,
+    post_count: u32,
+}
+
+#[cfg(feature = "danbooru")]
+impl From<TagData> for TagInfo {
+    fn from(tag: TagData) -> TagInfo {
+        TagInfo {
+            name: tag.name,
+            category: match tag.category {
+                1 => "artist",
+                3 => "copyright",
+                4 => "character",
+                5 => "meta",
+                _ => "general",
+            },
+            post_count: tag.post_count,
+        }
+    }
+}
+
+/// What Danbooru knows of an artist, for `waifu artist`
+#[cfg(feature = "danbooru")]
+#[derive(Serialize, Debug)]
+pub struct ArtistProfile {
+    pub name: String,
+    /// Other names they go by, often in Japanese
+    pub other_names: Vec<String>,
+    /// The circle they draw with, when they have one
+    pub group_name: Option<String>,
+    /// Their pixiv, twitter and other pages that are still up
+    pub urls: Vec<String>,
+}
+
+/// A note left on part of a post's image, usually translating the text there
+#[cfg(feature = "danbooru")]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Note {
+    /// Where the note's box is on the full size image, in pixels from the top left
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    pub body: String,
+}
+
+/// The text of a note's HTML, with its line breaks
+#[cfg(feature = "danbooru")]
+fn note_text(html: &str) -> String {
+    let breaks = Regex::new(r"(?i)<br\s*/?>|</p>|</div>").unwrap();
+    let tags = Regex::new(r"<[^>]*>").unwrap();
+    let text = breaks.replace_all(html, "\n");
+    let text = tags.replace_all(&text, "");
+    let text = text
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&");
+
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// A comment left on a post
+#[cfg(feature = "danbooru")]
+#[derive(Serialize, Debug)]
+pub struct Comment {
+    /// The name of the user who left it
+    pub creator: String,
+    pub created_at: String,
+    pub score: i32,
+    pub body: String,
+}
+
+#[cfg(feature = "danbooru")]
+impl Comment {
+    /// The day it was left on, e.g. 2024-05-01
+    pub fn date(&self) -> &str {
+        self.created_at.get(..10).unwrap_or(&self.created_at)
+    }
+}
+
+/// A tag often found on the same posts as another, for `waifu related`
+#[cfg(feature = "danbooru")]
+#[derive(Serialize, Debug)]
+pub struct RelatedTag {
+    #[serde(flatten)]
+    pub tag: TagInfo,
+    /// How many of the other tag's posts have this one too, from 0 to 1
+    pub frequency: f64,
+}
+
+/// What a tag means, from its Danbooru wiki page
+#[cfg(feature = "danbooru")]
+#[derive(Serialize, Debug)]
+pub struct WikiPage {
+    pub title: String,
+    /// Its names in other languages, often Japanese
+    pub other_names: Vec<String>,
+    /// The paragraphs before the page's first heading, without the markup
+    pub excerpt: Vec<String>,
+}
+
+/// The paragraphs of DText before its first heading, as plain text
+#[cfg(feature = "danbooru")]
+fn excerpt(body: &str) -> Vec<String> {
+    let links = Regex::new(r"\[\[([^\]|]*)\|?([^\]]*)\]\]").unwrap();
+    let markup = Regex::new(r"\[/?(b|i|u|s|tn|spoiler|nodtext|code|quote)\]|\{\{|\}\}").unwrap();
+    let heading = Regex::new(r"^h[1-6](#[\w-]+)?\.").unwrap();
+    let item = Regex::new(r"^\*+ ").unwrap();
+
+    let mut paragraphs = Vec::new();
+    let mut paragraph = String::new();
+    for line in body.lines().map(str::trim) {
+        if heading.is_match(line) {
+            break;
+        }
+        if line.is_empty() {
+            if !paragraph.is_empty() {
+                paragraphs.push(std::mem::take(&mut paragraph));
+            }
+            continue;
+        }
+
+        // [[tag]] and [[tag|text]] links, keeping what they read as
+        let line = links.replace_all(line, |captures: &regex::Captures| {
+            match captures
+                .get(2)
+                .map(|text| text.as_str())
+                .filter(|text| !text.is_empty())
+            {
+                Some(text) => text.to_string(),
+                None => captures[1].replace('_', " "),
+            }
+        });
+        let line = markup.replace_all(&line, "");
+        let listed = item.is_match(&line);
+        if !paragraph.is_empty() {
+            // Each item of a list stays on a line of its own
+            paragraph.push(if listed { '\n' } else { ' ' });
+        }
+        paragraph.push_str(&item.replace(&line, "• "));
+    }
+    if !paragraph.is_empty() {
+        paragraphs.push(paragraph);
+    }
+
+    paragraphs
 }
 
-#[derive(Debug)]
-struct ResponseError(String);
+/// How many posts a search fetches: a few, so there's something left after
+/// skipping videos, and plenty when they're checked for the tags left out of it
+#[cfg(feature = "danbooru")]
+fn search_limit(narrowed: bool) -> u32 {
+    if narrowed {
+        200
+    } else {
+        20
+    }
+}
+
+/// Danbooru goes no further than this many pages without a Gold account
+#[cfg(feature = "danbooru")]
+const MAX_PAGE: u32 = 1000;
 
-impl fmt::Display for ResponseError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.0)
+/// Every tag and metatag the search is for, besides the order and the
+/// ratings, which are free of the tag limit anyway
+#[cfg(feature = "danbooru")]
+fn search_terms(search: &RandomSearch) -> Vec<String> {
+    let RandomSearch {
+        tags,
+        from_favorites,
+        filters,
+        ..
+    } = search;
+
+    let mut terms: Vec<String> = split_tags(tags.as_deref())
+        .into_iter()
+        .map(String::from)
+        .collect();
+
+    if let Some(user) = from_favorites {
+        // Usernames have no spaces, only underscores in place of them
+        let user = user.trim().replace(' ', "_");
+        terms.push(format!("fav:{}", user));
+    }
+    terms.extend(excluded_tags(filters.exclude.as_deref()));
+    match split_tags(filters.any_of.as_deref()).as_slice() {
+        [tag] => terms.push(tag.to_string()),
+        any_of => terms.extend(any_of.iter().map(|tag| format!("~{}", tag))),
     }
+    if let Some(score) = filters.min_score {
+        terms.push(format!("score:>={}", score));
+    }
+    if let Some(width) = filters.min_width {
+        terms.push(format!("width:>={}", width));
+    }
+    if let Some(height) = filters.min_height {
+        terms.push(format!("height:>={}", height));
+    }
+    match filters.orientation {
+        Some(Orientation::Landscape) => terms.push("ratio:>1".to_string()),
+        Some(Orientation::Portrait) => terms.push("ratio:<1".to_string()),
+        Some(Orientation::Square) => terms.push("ratio:1".to_string()),
+        None => (),
+    }
+    if let Some(size) = filters.max_filesize {
+        terms.push(format!("filesize:<={}", size));
+    }
+    if let Some(filetype) = filters.filetype {
+        terms.push(format!("filetype:{}", filetype.extension()));
+    }
+
+    terms
 }
 
-impl Error for ResponseError {}
+/// How many tags Danbooru searches for at once without a login
+#[cfg(feature = "danbooru")]
+pub const ANONYMOUS_TAG_LIMIT: usize = 2;
+
+/// Whether Danbooru leaves the term out of the tag limit
+#[cfg(feature = "danbooru")]
+fn is_free(term: &str) -> bool {
+    let term = term.trim_start_matches('-').to_lowercase();
+    ["rating:", "order:", "limit:", "status:", "is:"]
+        .iter()
+        .any(|metatag| term.starts_with(metatag))
+}
+
+/// Whether the post has the tags the search was narrowed down without: all
+/// the plain ones, none of the ones with a -, and at least one with a ~
+#[cfg(feature = "danbooru")]
+fn has_tags(image: &Post, checked: &[String]) -> bool {
+    let tags: BTreeSet<&str> = image.tags.iter().map(String::as_str).collect();
+    let mut any_of = checked
+        .iter()
+        .filter_map(|tag| tag.strip_prefix('~'))
+        .peekable();
+    let any = any_of.peek().is_none() || any_of.any(|tag| tags.contains(tag));
+
+    any && checked.iter().all(|tag| match tag.chars().next() {
+        Some('-') => !tags.contains(&tag[1..]),
+        Some('~') => true,
+        _ => tags.contains(tag.as_str()),
+    })
+}
+
+#[derive(Deserialize, Debug)]
+struct FailureResponse {
+    message: String,
+}
 
 fn value_to_string(v: Option<&Value>) -> String {
     match v {
@@ -163,165 +1107,498 @@ fn parse_opt_u32(v: Option<&Value>) -> Option<u32> {
     }
 }
 
-fn fetch_api_data(url: String) -> Result<Vec<ImageData>, Box<dyn Error>> {
-    use reqwest::blocking::Client;
-    use std::time::Duration;
+/// The URL of the post's file, or when it's restricted to Gold users or banned,
+/// of a smaller one, or else of the source when that's a file itself. The
+/// sample goes first when `samples` are asked for and the file is bigger
+fn file_url(item: &Value, samples: bool) -> (String, Option<String>) {
+    let url = |key: &str| {
+        item.get(key)
+            .and_then(Value::as_str)
+            .filter(|url| !url.is_empty())
+    };
+    if samples {
+        if let Some(sample) =
+            url("large_file_url").filter(|&sample| url("file_url") != Some(sample))
+        {
+            return (sample.to_string(), Some("sample".to_string()));
+        }
+    }
+    if let Some(url) = url("file_url") {
+        return (url.to_string(), None);
+    }
+    let fallbacks = [
+        ("large_file_url", "sample"),
+        ("preview_file_url", "preview"),
+    ];
+    for (key, variant) in fallbacks {
+        if let Some(url) = url(key) {
+            return (url.to_string(), Some(variant.to_string()));
+        }
+    }
+    match url("source") {
+        Some(source) if source.starts_with("http") && is_file(source) => {
+            (source.to_string(), Some("source".to_string()))
+        }
+        _ => (String::new(), None),
+    }
+}
 
-    let client = Client::builder()
-        .timeout(Duration::from_secs(15))
-        .user_agent("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/124.0 Safari/537.36 Waifu/1.0")
-        .build()?;
-    let mut req = client
-        .get(&url)
-        .header(reqwest::header::ACCEPT, "application/json, text/plain, */*")
-        .header(reqwest::header::ACCEPT_LANGUAGE, "en-US,en;q=0.9")
-        .header(reqwest::header::REFERER, "https://danbooru.donmai.us/");
-    if let (Some(user), Some(key)) = check_env_variables() {
-        req = req.basic_auth(user, Some(key));
+/// Whether the URL is of an image or video file rather than a page
+fn is_file(url: &str) -> bool {
+    let path = url.split(['?', '#']).next().unwrap_or(url);
+    let extension = path.rsplit_once('.').map_or("", |(_, extension)| extension);
+    ["jpg", "jpeg", "png", "gif", "webp"]
+        .iter()
+        .chain(video::EXTENSIONS.iter())
+        .any(|file| extension.eq_ignore_ascii_case(file))
+}
+
+pub const DANBOORU: &str = "https://danbooru.donmai.us";
+
+/// Danbooru's API, which reads what waifu's own Http or another gets from it
+pub struct Api<H: Http = Web> {
+    base_url: String,
+    samples: bool,
+    http: H,
+}
+
+impl Api {
+    pub fn new() -> Self {
+        Api::with_http(Web::new())
     }
-    let response = req.send()?;
-    let status = response.status();
-    let text = response.text()?;
+}
 
-    if text.trim_start().starts_with('<') {
-        let message = format!("{}: API returned HTML or an unexpected response.", status);
-        return Err(Box::new(ResponseError(message)));
+impl Default for Api {
+    fn default() -> Self {
+        Api::new()
     }
+}
 
-    if status != StatusCode::OK {
-        if let Ok(err) = serde_json::from_str::<FailureResponse>(&text) {
-            let message = format!("{}: {}", status, err.message);
-            return Err(Box::new(ResponseError(message)));
-        } else {
-            let message = format!("{}: Unexpected response.", status);
-            return Err(Box::new(ResponseError(message)));
+impl<H: Http> Api<H> {
+    /// The API, with its requests sent by `http`
+    pub fn with_http(http: H) -> Self {
+        Api {
+            base_url: DANBOORU.to_string(),
+            samples: false,
+            http,
         }
     }
 
-    let raw: Value = serde_json::from_str(&text)
-        .map_err(|e| ResponseError(format!("Failed to parse JSON: {}", e)))?;
-    let arr = raw
-        .as_array()
-        .ok_or_else(|| ResponseError("Unexpected JSON structure".into()))?;
+    /// The API of another site running Danbooru instead, like testbooru.donmai.us
+    /// or one of your own
+    pub fn at(self, base_url: &str) -> Self {
+        Api {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            ..self
+        }
+    }
 
-    let mut data = Vec::new();
-    for item in arr {
-        let source = value_to_string(item.get("source"));
-        let pixiv_id = parse_opt_u32(item.get("pixiv_id"));
-        let file_url_raw = item
-            .get("file_url")
-            .and_then(Value::as_str)
-            .or_else(|| item.get("large_file_url").and_then(Value::as_str))
-            .unwrap_or("");
-        let mut file_url = file_url_raw.to_string();
-        if file_url.starts_with("//") {
-            file_url = format!("https:{}", file_url);
-        }
-        let tag_string_character = value_to_string(item.get("tag_string_character"));
-        let tag_string_artist = value_to_string(item.get("tag_string_artist"));
-        let rating = item
-            .get("rating")
-            .and_then(Value::as_str)
-            .and_then(|s| s.chars().next())
-            .unwrap_or('s');
-        let image_width = parse_u32(item.get("image_width"));
-        let image_height = parse_u32(item.get("image_height"));
-        let tag_string = value_to_string(item.get("tag_string"));
-
-        data.push(ImageData {
-            source,
-            pixiv_id,
-            file_url,
-            tag_string_character,
-            tag_string_artist,
-            rating,
-            image_width,
-            image_height,
-            tag_string,
-        });
+    /// Whether posts link the smaller sample of a file rather than the full size
+    /// one, when there's one
+    pub fn with_samples(self, samples: bool) -> Self {
+        Api { samples, ..self }
     }
 
-    if data.is_empty() {
-        let message = format!(
-            "{}: Although the request succeeded, there are no images associated with your tags.",
-            status
-        );
-        return Err(Box::new(ResponseError(message)));
+    /// Where the site is, without a slash at the end
+    pub fn base_url(&self) -> &str {
+        &self.base_url
     }
 
-    Ok(data)
+    /// Whether a URL's host is the site's, e.g. for its post pages
+    pub fn is_host(&self, host: &str) -> bool {
+        let base = self.base_url.as_str();
+        let base = base.split_once("://").map_or(base, |(_, rest)| rest);
+        let base = base.split(['/', ':']).next().unwrap_or(base);
+        base.strip_prefix("www.").unwrap_or(base) == host
+    }
+
+    /// The posts in what the API answered
+    fn parse_api_data(&self, status: StatusCode, body: &[u8]) -> Result<Vec<Post>, WaifuError> {
+        let text = String::from_utf8_lossy(body);
+
+        if text.trim_start().starts_with('<') {
+            let message = "API returned HTML or an unexpected response.";
+            return Err(WaifuError::api(status, message));
+        }
+
+        if status != StatusCode::OK {
+            if let Ok(err) = serde_json::from_str::<FailureResponse>(&text) {
+                return Err(WaifuError::api(status, err.message));
+            } else {
+                return Err(WaifuError::api(status, "Unexpected response."));
+            }
+        }
+
+        let raw: Value = serde_json::from_str(&text)
+            .map_err(|e| WaifuError::api(status, format!("Failed to parse JSON: {}", e)))?;
+        // Searches give a list of posts, looking one up by its ID gives just that post
+        let arr = match &raw {
+            Value::Array(arr) => arr.as_slice(),
+            Value::Object(_) => std::slice::from_ref(&raw),
+            _ => return Err(WaifuError::api(status, "Unexpected JSON structure")),
+        };
+
+        let mut data = Vec::new();
+        for item in arr {
+            let non_empty =
+                |key: &str| Some(value_to_string(item.get(key))).filter(|s| !s.is_empty());
+            let (mut url, variant) = file_url(item, self.samples);
+            if url.starts_with("//") {
+                url = format!("https:{}", url);
+            }
+            let rating = item
+                .get("rating")
+                .and_then(Value::as_str)
+                .and_then(|s| s.chars().next())
+                .and_then(Rating::from_letter);
+            let source = value_to_string(item.get("source"));
+            let id = parse_u32(item.get("id"));
+
+            data.push(Post {
+                site: "danbooru".to_string(),
+                id,
+                url,
+                variant,
+                rating,
+                width: parse_u32(item.get("image_width")),
+                height: parse_u32(item.get("image_height")),
+                file_size: item.get("file_size").and_then(Value::as_u64),
+                tags: value_to_string(item.get("tag_string"))
+                    .split_whitespace()
+                    .map(String::from)
+                    .collect(),
+                artist: non_empty("tag_string_artist"),
+                character: non_empty("tag_string_character"),
+                source: Source::of(&source, parse_opt_u32(item.get("pixiv_id"))),
+                page: format!("{}/posts/{}", self.base_url, id),
+            });
+        }
+
+        if data.is_empty() {
+            let message = format!(
+                "{}: Although the request succeeded, there are no images associated with your tags.",
+                status
+            );
+            return Err(WaifuError::NoResults(message));
+        }
+
+        Ok(data)
+    }
 }
 
-fn print_image_details(info: &ImageData) -> Result<(), Box<dyn std::error::Error>> {
-    use std::io::{self, Write};
+impl<H: Http> Booru for Api<H> {
+    fn url(&self, query: &Query) -> String {
+        let mut terms = query.tags.clone();
+        if !query.rating.is_empty() {
+            let letters: Vec<String> = query
+                .rating
+                .iter()
+                .map(|rating| rating.letter().to_string())
+                .collect();
+            terms.push(format!("rating:{}", letters.join(",")));
+        }
+        terms.extend(query.score_term());
 
-    let ImageData {
-        source,
-        pixiv_id,
-        file_url,
-        tag_string_character,
-        tag_string_artist,
-        rating,
-        image_height,
-        image_width,
-        tag_string,
-    } = info;
+        // Danbooru's pages start at 1
+        format!(
+            "{}/posts.json?limit={}&page={}&tags={}",
+            self.base_url,
+            query.limit,
+            query.page + 1,
+            encode_tags(&terms)
+        )
+    }
 
-    if !tag_string_character.is_empty() {
-        println!(
-            "✨ {title}: {}",
-            tag_string_character,
-            title = "Character".purple()
-        );
+    fn parse(&self, status: StatusCode, body: &[u8]) -> Result<Vec<Post>, WaifuError> {
+        match self.parse_api_data(status, body) {
+            Err(WaifuError::NoResults(_)) => Ok(Vec::new()),
+            posts => posts,
+        }
     }
 
-    if !source.is_empty() {
-        if source.contains("pixiv") || source.contains("pximg") {
-            if let Some(id) = pixiv_id {
-                let pixiv_source = format!("https://pixiv.net/en/artworks/{}", id);
-                println!("ℹ️ {title}: {}", pixiv_source, title = "Source".purple());
-            } else {
-                // Fallback to printing the provided source if no pixiv_id available
-                println!("ℹ️ {title}: {}", source, title = "Source".purple());
-            }
-        } else {
-            println!("ℹ️ {title}: {}", source, title = "Source".purple());
+    fn complete_url(&self, prefix: &str) -> String {
+        format!(
+            "{}/autocomplete.json?search%5Bquery%5D={}&search%5Btype%5D=tag_query&limit=20",
+            self.base_url,
+            encode_tags(&[prefix])
+        )
+    }
+
+    fn parse_completions(
+        &self,
+        status: StatusCode,
+        body: &[u8],
+    ) -> Result<Vec<String>, WaifuError> {
+        parse_suggestions("Danbooru", status, body)
+    }
+
+    fn http(&self) -> &dyn Http {
+        &self.http
+    }
+}
+
+/// Sends requests to Danbooru the way a browser does, with the login when
+/// there's one
+#[cfg(feature = "danbooru")]
+#[derive(Clone, Default)]
+pub struct Web {
+    client: Option<Client>,
+    retry: Retry,
+    login: Option<(String, String)>,
+    revalidate: bool,
+    search_ttl: Duration,
+}
+
+#[cfg(feature = "danbooru")]
+impl Web {
+    pub fn new() -> Self {
+        Web::default()
+    }
+
+    /// Send the requests with this client, e.g. one going through a proxy
+    pub fn with_client(client: Client) -> Self {
+        Web {
+            client: Some(client),
+            ..Web::default()
         }
     }
 
-    if !tag_string_artist.is_empty() {
-        println!(
-            "🎨 {title}: {}",
-            tag_string_artist,
-            title = "Artist".purple()
-        );
+    /// Try requests again and keep to the rate limit like this
+    pub fn with_retry(self, retry: Retry) -> Self {
+        Web { retry, ..self }
     }
 
-    println!("✉️ {title}: {}", file_url, title = "Link".purple());
+    /// Log in as this user with their API key
+    pub fn with_login(self, username: &str, api_key: &str) -> Self {
+        Web {
+            login: Some((username.to_string(), api_key.to_string())),
+            ..self
+        }
+    }
 
-    match rating {
-        's' => println!("⚖️ {title}: safe", title = "Rating".purple()),
-        'q' => println!("⚖️ {title}: questionable", title = "Rating".purple()),
-        'e' => println!("⚖️ {title}: explicit", title = "Rating".purple()),
-        _ => (),
+    /// With `revalidate`, keep API responses to use again when they haven't
+    /// changed. Searches are kept for `search_ttl` to pick from again
+    pub fn with_cache(self, revalidate: bool, search_ttl: Duration) -> Self {
+        Web {
+            revalidate,
+            search_ttl,
+            ..self
+        }
     }
 
-    println!(
-        "📐 {title}: {w} x {h}",
-        title = "Dimensions".purple(),
-        w = image_width,
-        h = image_height
-    );
+    fn client(&self) -> Result<&Client, WaifuError> {
+        match &self.client {
+            Some(client) => Ok(client),
+            None => net::default_client(),
+        }
+    }
 
-    let tags: Vec<&str> = tag_string.split(' ').collect();
-    let stdout = io::stdout();
-    let lock = stdout.lock();
-    let mut buffer = io::BufWriter::new(lock);
+    fn send(&self, request: RequestBuilder) -> Result<reqwest::blocking::Response, WaifuError> {
+        net::send_with(&self.retry, request)
+    }
+}
 
-    write!(buffer, "🏷️ {}:", "Tags".purple())?;
-    tags.iter().try_for_each(|tag| write!(buffer, " {}", tag))?;
+#[cfg(feature = "danbooru")]
+impl Http for Web {
+    fn get(&self, url: &str) -> Result<(StatusCode, Vec<u8>), WaifuError> {
+        let origin = reqwest::Url::parse(url)
+            .map(|url| url.origin().ascii_serialization())
+            .unwrap_or_default();
+        let mut req = self
+            .client()?
+            .get(url)
+            .header(reqwest::header::ACCEPT, "application/json, text/plain, */*")
+            .header(reqwest::header::ACCEPT_LANGUAGE, "en-US,en;q=0.9")
+            .header(reqwest::header::REFERER, format!("{}/", origin));
+        if let Some((user, key)) = &self.login {
+            req = req.basic_auth(user, Some(key));
+        }
 
-    writeln!(buffer)?;
+        net::send_revalidated(url, req, &self.retry, self.revalidate)
+    }
+}
+
+/// Danbooru's API answers, read into posts by Booru::parse
+#[cfg(not(feature = "danbooru"))]
+#[derive(Default)]
+pub struct Web;
 
-    Ok(())
+#[cfg(not(feature = "danbooru"))]
+impl Web {
+    pub fn new() -> Self {
+        Web
+    }
+}
+
+// Without the command there's no client, the program gets the URL itself and
+// has Booru::parse read what it answered
+#[cfg(not(feature = "danbooru"))]
+impl Http for Web {
+    fn get(&self, url: &str) -> Result<(StatusCode, Vec<u8>), WaifuError> {
+        Err(super::no_client(url))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Answers every request with the same status and body
+    struct Canned(StatusCode, &'static str);
+
+    impl Http for Canned {
+        fn get(&self, _url: &str) -> Result<(StatusCode, Vec<u8>), WaifuError> {
+            Ok((self.0, self.1.as_bytes().to_vec()))
+        }
+    }
+
+    fn search(status: StatusCode, body: &'static str) -> Result<Vec<Post>, WaifuError> {
+        Api::with_http(Canned(status, body)).search(&Query::default())
+    }
+
+    #[test]
+    fn reads_a_search() {
+        let posts = search(
+            StatusCode::OK,
+            r#"[{"id": 1, "file_url": "https://cdn.donmai.us/original/ab/cd.png",
+                 "rating": "g", "image_width": 640, "image_height": "480",
+                 "tag_string": "scenery sky", "tag_string_artist": "someone",
+                 "tag_string_character": ""}]"#,
+        )
+        .unwrap();
+
+        assert_eq!(posts.len(), 1);
+        let post = &posts[0];
+        assert_eq!(post.site, "danbooru");
+        assert_eq!(post.id, 1);
+        assert_eq!(post.url, "https://cdn.donmai.us/original/ab/cd.png");
+        assert_eq!(post.variant, None);
+        assert_eq!(post.rating, Some(Rating::General));
+        assert_eq!((post.width, post.height), (640, 480));
+        assert_eq!(post.tags, ["scenery", "sky"]);
+        assert_eq!(post.artist.as_deref(), Some("someone"));
+        assert_eq!(post.character, None);
+    }
+
+    #[test]
+    fn reads_a_single_post() {
+        let posts = search(
+            StatusCode::OK,
+            r#"{"id": 7, "file_url": "https://x/y.png"}"#,
+        )
+        .unwrap();
+        assert_eq!(posts.len(), 1);
+        assert_eq!(posts[0].id, 7);
+    }
+
+    #[test]
+    fn finds_nothing_in_an_empty_list() {
+        assert!(search(StatusCode::OK, "[]").unwrap().is_empty());
+    }
+
+    #[test]
+    fn fails_on_an_empty_body() {
+        let error = search(StatusCode::OK, "").unwrap_err();
+        assert!(matches!(error, WaifuError::Api { .. }), "{:?}", error);
+    }
+
+    #[test]
+    fn fails_on_an_html_page() {
+        let error = search(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "<!DOCTYPE html><html><body>Down for maintenance</body></html>",
+        )
+        .unwrap_err();
+        match error {
+            WaifuError::Api { status, message } => {
+                assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+                assert!(message.contains("HTML"), "{}", message);
+            }
+            error => panic!("{:?}", error),
+        }
+    }
+
+    #[test]
+    fn gives_the_api_error_message() {
+        let error = search(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            r#"{"success": false, "message": "You cannot search for more than 2 tags at a time."}"#,
+        )
+        .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            WaifuError::api(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "You cannot search for more than 2 tags at a time."
+            )
+            .to_string()
+        );
+    }
+
+    #[test]
+    fn falls_back_from_a_missing_file_url() {
+        let posts = search(
+            StatusCode::OK,
+            r#"[{"id": 1, "large_file_url": "https://cdn.donmai.us/sample/ab/sample-cd.jpg"},
+                {"id": 2, "preview_file_url": "https://cdn.donmai.us/180x180/ab/cd.jpg"},
+                {"id": 3, "source": "https://example.com/art.png"},
+                {"id": 4, "file_url": "//cdn.donmai.us/original/ab/cd.png"},
+                {"id": 5}]"#,
+        )
+        .unwrap();
+
+        let urls: Vec<(&str, Option<&str>)> = posts
+            .iter()
+            .map(|post| (post.url.as_str(), post.variant.as_deref()))
+            .collect();
+        assert_eq!(
+            urls,
+            [
+                (
+                    "https://cdn.donmai.us/sample/ab/sample-cd.jpg",
+                    Some("sample")
+                ),
+                ("https://cdn.donmai.us/180x180/ab/cd.jpg", Some("preview")),
+                ("https://example.com/art.png", Some("source")),
+                ("https://cdn.donmai.us/original/ab/cd.png", None),
+                ("", None),
+            ]
+        );
+        assert!(!posts[4].has_file());
+    }
+
+    #[test]
+    fn encodes_the_query() {
+        let query = Query::builder()
+            .tags(["fate/stay_night", "c++", "~cat_ears", "-rating:e"])
+            .rating(Rating::General)
+            .rating(Rating::Sensitive)
+            .min_score(5)
+            .limit(3)
+            .page(1)
+            .build();
+
+        assert_eq!(
+            Api::new().url(&query),
+            format!(
+                "{}/posts.json?limit=3&page=2&tags=fate%2Fstay_night%20c%2B%2B%20~cat_ears\
+                 %20-rating:e%20rating:g,s%20score:%3E%3D5",
+                DANBOORU
+            )
+        );
+    }
+
+    #[test]
+    fn reads_completions() {
+        let api = Api::with_http(Canned(StatusCode::OK, ""));
+        let body = br#"[{"type": "tag-word", "label": "cat ears", "value": "cat_ears"},
+                        {"type": "tag-word", "label": "cat", "value": "cat"}]"#;
+        assert_eq!(
+            api.parse_completions(StatusCode::OK, body).unwrap(),
+            ["cat_ears", "cat"]
+        );
+        assert!(api
+            .parse_completions(StatusCode::INTERNAL_SERVER_ERROR, b"")
+            .is_err());
+    }
 }
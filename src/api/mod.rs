@@ -1,15 +1,672 @@
+#[cfg(feature = "json")]
 pub mod danbooru;
+/// Searching the boorus without blocking, for programs that run on tokio
+#[cfg(feature = "async")]
+pub mod nonblocking;
+#[cfg(feature = "json")]
 pub mod safebooru;
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
 use regex::Regex;
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+use serde::de::DeserializeOwned;
+#[cfg(feature = "json")]
+use serde::{Deserialize, Serialize};
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+use std::path::Path;
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+use std::time::{Duration, SystemTime};
 
-pub fn reformat_search_tags(tags: String) -> String {
-    let extra_spaces = Regex::new(r"\s{2,}").unwrap();
-    let delimiters = Regex::new(r"[,\s]").unwrap();
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+use crate::cache;
+use crate::error::WaifuError;
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+use crate::random;
+use crate::rating::Rating;
 
-    // Collapse runs of whitespace to a single space, then replace spaces/commas with %20
-    let trimmed = tags.trim();
-    let collapsed = extra_spaces.replace_all(trimmed, " ");
-    let search_tags = delimiters.replace_all(&collapsed, "%20");
+/// How the boorus' clients try requests again, and how often they send them
+#[cfg(any(feature = "network", feature = "async"))]
+pub use crate::retry::{Backoff, Retry};
+/// The status of an answer, as [`Http::get`] gives it and [`Booru::parse`] reads it
+pub use http::StatusCode;
 
-    search_tags.to_string()
+/// A post as the boorus' APIs give it, the same whichever it's from
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+pub struct Post {
+    /// The booru it came from, e.g. "danbooru"
+    pub site: String,
+    pub id: u32,
+    /// The file's URL, empty when the booru doesn't give one
+    pub url: String,
+    /// Which other file `url` is when the original is restricted, e.g. "sample"
+    #[cfg_attr(
+        feature = "json",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub variant: Option<String>,
+    pub rating: Option<Rating>,
+    /// In pixels
+    pub width: u32,
+    pub height: u32,
+    /// In bytes, when the booru says
+    #[cfg_attr(
+        feature = "json",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub file_size: Option<u64>,
+    pub tags: Vec<String>,
+    /// Space separated artist tags, when the booru splits them out
+    pub artist: Option<String>,
+    pub character: Option<String>,
+    pub source: Option<Source>,
+    /// The post's page on its booru
+    #[cfg_attr(feature = "json", serde(default))]
+    pub page: String,
+}
+
+impl Post {
+    /// Whether the file can be downloaded at all
+    pub fn has_file(&self) -> bool {
+        !self.url.is_empty()
+    }
+
+    /// Rated questionable or explicit
+    pub fn nsfw(&self) -> bool {
+        matches!(self.rating, Some(Rating::Questionable | Rating::Explicit))
+    }
+
+    /// One line of attribution, e.g. for captions
+    pub fn attribution(&self) -> String {
+        let source = self.source.as_ref().map(Source::url);
+        [&self.artist, &self.character, &source]
+            .into_iter()
+            .flatten()
+            .map(String::as_str)
+            .collect::<Vec<_>>()
+            .join(" | ")
+    }
+}
+
+/// Where a post's art was first posted
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "json", serde(rename_all = "lowercase"))]
+pub enum Source {
+    /// An artwork on pixiv, by its ID
+    Pixiv(u32),
+    Url(String),
+}
+
+impl Source {
+    /// The source a booru gives, or the pixiv artwork when it knows the ID.
+    /// None when it's empty
+    pub fn of(url: &str, pixiv_id: Option<u32>) -> Option<Source> {
+        match pixiv_id {
+            Some(id) => Some(Source::Pixiv(id)),
+            _ if url.is_empty() => None,
+            _ => Some(Source::Url(url.to_string())),
+        }
+    }
+
+    pub fn url(&self) -> String {
+        match self {
+            Source::Pixiv(id) => format!("https://pixiv.net/en/artworks/{}", id),
+            Source::Url(url) => url.clone(),
+        }
+    }
+}
+
+/// Sends a booru's API requests. Each booru has its own that waifu uses, and
+/// tests or other programs can answer with responses of their own instead
+///
+/// ```
+/// use waifu::api::{safebooru, Booru, Http, Query, StatusCode};
+/// use waifu::WaifuError;
+///
+/// struct Canned(&'static str);
+///
+/// impl Http for Canned {
+///     fn get(&self, _url: &str) -> Result<(StatusCode, Vec<u8>), WaifuError> {
+///         Ok((StatusCode::OK, self.0.as_bytes().to_vec()))
+///     }
+/// }
+///
+/// let posts = r#"[{"id": 1, "directory": "ab", "image": "cd.png", "tags": "scenery"}]"#;
+/// let api = safebooru::Api::with_http("safebooru.org", Canned(posts));
+/// let found = api.search(&Query::default())?;
+/// assert_eq!(found[0].tags, ["scenery"]);
+/// # Ok::<(), WaifuError>(())
+/// ```
+pub trait Http {
+    /// The status and body of what the URL answered
+    fn get(&self, url: &str) -> Result<(StatusCode, Vec<u8>), WaifuError>;
+}
+
+/// Sent unless another User-Agent is asked for. It looks enough like a browser's
+/// to get past the boorus' filters, while still saying who's asking
+pub const USER_AGENT: &str = concat!(
+    "Mozilla/5.0 (compatible; waifu/",
+    env!("CARGO_PKG_VERSION"),
+    "; +https://github.com/lenkat101/waifu)"
+);
+
+/// The error for a request when waifu is built without the booru it goes to
+#[cfg(all(
+    feature = "json",
+    not(all(feature = "danbooru", feature = "safebooru"))
+))]
+fn no_client(url: &str) -> WaifuError {
+    format!(
+        "Built without a client to send requests with, so {} has to be fetched some other way",
+        url
+    )
+    .into()
+}
+
+/// The tags in what a booru's autocomplete answered, which is the same list for
+/// each of them
+#[cfg(feature = "json")]
+fn parse_suggestions(
+    site: &str,
+    status: StatusCode,
+    body: &[u8],
+) -> Result<Vec<String>, WaifuError> {
+    #[derive(Deserialize)]
+    struct Suggestion {
+        value: String,
+    }
+
+    if !status.is_success() {
+        let message = format!("{} couldn't complete the tag", site);
+        return Err(WaifuError::api(status, message));
+    }
+    let suggestions: Vec<Suggestion> = serde_json::from_slice(body)?;
+
+    Ok(suggestions
+        .into_iter()
+        .map(|suggestion| suggestion.value)
+        .collect())
+}
+
+/// A search for one page of a booru's posts, which each booru turns into its
+/// own URL
+///
+/// ```
+/// use waifu::api::{danbooru, Booru, Query};
+/// use waifu::Rating;
+///
+/// let query = Query::builder()
+///     .tags(["scenery", "sky"])
+///     .rating(Rating::General)
+///     .min_score(10)
+///     .limit(5)
+///     .build();
+/// let url = danbooru::Api::new().url(&query);
+/// assert!(url.ends_with("tags=scenery%20sky%20rating:g%20score:%3E%3D10"));
+/// ```
+#[derive(Debug, Clone)]
+pub struct Query {
+    /// Tags and metatags, each as the booru writes them, e.g. "order:score"
+    pub tags: Vec<String>,
+    /// Only posts with these ratings, or any when it's empty
+    pub rating: Vec<Rating>,
+    pub min_score: Option<i32>,
+    /// How many posts go on a page
+    pub limit: u32,
+    /// Which page of them, the first being 0
+    pub page: u32,
+}
+
+impl Query {
+    pub fn builder() -> QueryBuilder {
+        QueryBuilder::default()
+    }
+
+    /// The score the posts need as a metatag, the same on all the boorus
+    #[cfg(feature = "json")]
+    fn score_term(&self) -> Option<String> {
+        self.min_score.map(|score| format!("score:>={}", score))
+    }
+}
+
+impl Default for Query {
+    fn default() -> Self {
+        Query {
+            tags: Vec::new(),
+            rating: Vec::new(),
+            min_score: None,
+            limit: 20,
+            page: 0,
+        }
+    }
+}
+
+/// Makes a Query a part at a time, starting from the default one
+#[derive(Debug, Clone, Default)]
+pub struct QueryBuilder {
+    query: Query,
+}
+
+impl QueryBuilder {
+    /// Search for these tags too
+    pub fn tags<T: Into<String>>(mut self, tags: impl IntoIterator<Item = T>) -> Self {
+        self.query.tags.extend(tags.into_iter().map(Into::into));
+        self
+    }
+
+    /// Let posts with this rating in too
+    pub fn rating(mut self, rating: Rating) -> Self {
+        if !self.query.rating.contains(&rating) {
+            self.query.rating.push(rating);
+        }
+        self
+    }
+
+    pub fn min_score(mut self, score: i32) -> Self {
+        self.query.min_score = Some(score);
+        self
+    }
+
+    pub fn limit(mut self, limit: u32) -> Self {
+        self.query.limit = limit;
+        self
+    }
+
+    pub fn page(mut self, page: u32) -> Self {
+        self.query.page = page;
+        self
+    }
+
+    pub fn build(self) -> Query {
+        self.query
+    }
+}
+
+/// A booru's API. A new one only has to say where to search and how to read
+/// what it answers, sending the request is left to its Http
+pub trait Booru {
+    /// The API URL of the query
+    fn url(&self, query: &Query) -> String;
+
+    /// The posts in what the API answered
+    fn parse(&self, status: StatusCode, body: &[u8]) -> Result<Vec<Post>, WaifuError>;
+
+    /// The API URL of the tags starting with the prefix, for completing them
+    fn complete_url(&self, prefix: &str) -> String;
+
+    /// The tags in what the API's autocomplete answered, the most used first
+    fn parse_completions(&self, status: StatusCode, body: &[u8])
+        -> Result<Vec<String>, WaifuError>;
+
+    /// What sends its requests
+    fn http(&self) -> &dyn Http;
+
+    /// The posts the query finds, none when there are none
+    fn search(&self, query: &Query) -> Result<Vec<Post>, WaifuError> {
+        let (status, body) = self.http().get(&self.url(query))?;
+        self.parse(status, &body)
+    }
+
+    /// The posts on every page the query finds from its page on, searching for
+    /// each page once the one before has run out
+    fn search_iter(&self, query: Query) -> SearchIter<'_, Self> {
+        SearchIter {
+            booru: self,
+            query,
+            posts: Vec::new().into_iter(),
+            done: false,
+        }
+    }
+}
+
+/// The posts of a search page after page, from Booru::search_iter. It stops at
+/// the first page that isn't full, or after an error
+pub struct SearchIter<'a, B: ?Sized> {
+    booru: &'a B,
+    /// The next page to search for
+    query: Query,
+    posts: std::vec::IntoIter<Post>,
+    done: bool,
+}
+
+impl<B: Booru + ?Sized> Iterator for SearchIter<'_, B> {
+    type Item = Result<Post, WaifuError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(post) = self.posts.next() {
+                return Some(Ok(post));
+            }
+            if self.done {
+                return None;
+            }
+
+            match self.booru.search(&self.query) {
+                Ok(posts) => {
+                    self.done = posts.len() < self.query.limit as usize;
+                    self.query.page += 1;
+                    self.posts = posts.into_iter();
+                }
+                Err(error) => {
+                    self.done = true;
+                    return Some(Err(error));
+                }
+            }
+        }
+    }
+}
+
+/// What a booru post has to be like to be picked, besides the tags searched for
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+#[derive(Debug, Clone, Default)]
+pub struct Filters {
+    /// Leave out posts with any of these tags, separated by spaces or commas
+    pub exclude: Option<String>,
+    /// Only posts with at least one of these tags, along with any others
+    pub any_of: Option<String>,
+    pub min_score: Option<i32>,
+    /// In pixels
+    pub min_width: Option<u32>,
+    pub min_height: Option<u32>,
+    pub orientation: Option<Orientation>,
+    /// In bytes. Safebooru doesn't say how big its files are, so this is for Danbooru
+    pub max_filesize: Option<u64>,
+    pub filetype: Option<FileType>,
+}
+
+/// The kinds of image file the boorus have
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FileType {
+    Png,
+    Jpg,
+    Gif,
+}
+
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+impl FileType {
+    /// The extension the boorus give files of this type
+    pub fn extension(self) -> &'static str {
+        match self {
+            FileType::Png => "png",
+            FileType::Jpg => "jpg",
+            FileType::Gif => "gif",
+        }
+    }
+}
+
+/// Which way round an image is
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Orientation {
+    /// Wider than it's high
+    Landscape,
+    /// Higher than it's wide
+    Portrait,
+    Square,
+}
+
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+impl Filters {
+    /// Whether a post's file will do, for what the booru couldn't search for. The
+    /// size is left out when it isn't known
+    pub fn fits(&self, width: u32, height: u32, file: &str, size: Option<u64>) -> bool {
+        // Rounded like Danbooru's ratio:, so square takes in ones a pixel or two off
+        let ratio = (f64::from(width) / f64::from(height.max(1)) * 100.0).round() / 100.0;
+        let shape = match self.orientation {
+            None => true,
+            Some(Orientation::Landscape) => ratio > 1.0,
+            Some(Orientation::Portrait) => ratio < 1.0,
+            Some(Orientation::Square) => ratio == 1.0,
+        };
+
+        let path = file.split(['?', '#']).next().unwrap_or(file);
+        let extension = Path::new(path)
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_ascii_lowercase().replace("jpeg", "jpg"));
+
+        shape
+            && self.min_width.is_none_or(|min| width >= min)
+            && self.min_height.is_none_or(|min| height >= min)
+            && self
+                .max_filesize
+                .zip(size)
+                .is_none_or(|(max, size)| size <= max)
+            && self
+                .filetype
+                .is_none_or(|filetype| extension.as_deref() == Some(filetype.extension()))
+    }
+}
+
+/// Most times a search is made again when every post it found was shown recently
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+pub const MAX_SEARCHES_AGAIN: u32 = 3;
+
+/// The posts found by a search. They're kept for the TTL it's run with, so
+/// running the same search again soon after takes the next of them rather than
+/// asking the API
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+pub struct Search<T> {
+    /// What the posts left are kept under. Those read with samples point at
+    /// them rather than the originals, so they're kept apart
+    kept_as: String,
+    ttl: Duration,
+    pub posts: Vec<T>,
+    made: SystemTime,
+}
+
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+impl<T: Serialize + DeserializeOwned> Search<T> {
+    /// The posts left from the last search with this URL within the TTL, or
+    /// else those `fetch` finds
+    pub fn run(
+        url: String,
+        samples: bool,
+        ttl: Duration,
+        fetch: impl FnOnce(String) -> Result<Vec<T>, WaifuError>,
+    ) -> Result<Search<T>, WaifuError> {
+        let kept_as = if samples {
+            format!("{}#samples", url)
+        } else {
+            url.clone()
+        };
+        // Seeded picks have to be made from what the search finds every time
+        let left = cache::get_search(&kept_as, ttl)
+            .filter(|_| !random::seeded())
+            .and_then(|(bytes, made)| Some((serde_json::from_slice::<Vec<T>>(&bytes).ok()?, made)))
+            .filter(|(posts, _)| !posts.is_empty());
+        if let Some((posts, made)) = left {
+            tracing::debug!("Using the {} posts left from the last search", posts.len());
+            return Ok(Search {
+                kept_as,
+                ttl,
+                posts,
+                made,
+            });
+        }
+
+        let posts = fetch(url)?;
+        Ok(Search {
+            kept_as,
+            ttl,
+            posts,
+            made: SystemTime::now(),
+        })
+    }
+
+    /// Search again, for when none of the posts will do. The ones found take the
+    /// place of these, including for next time
+    pub fn search_again(
+        &mut self,
+        url: String,
+        fetch: impl FnOnce(String) -> Result<Vec<T>, WaifuError>,
+    ) -> Result<(), WaifuError> {
+        tracing::debug!("Searching again, with {}", url);
+        self.posts = fetch(url)?;
+        self.made = SystemTime::now();
+
+        Ok(())
+    }
+
+    /// Take out the post that's going to be shown, keeping the rest for next time
+    pub fn take(mut self, index: usize) -> T {
+        let post = self.posts.remove(index);
+        if !self.ttl.is_zero() {
+            if let Ok(bytes) = serde_json::to_vec(&self.posts) {
+                let _ = cache::put_search(&self.kept_as, &bytes, self.made);
+            }
+        }
+
+        post
+    }
+}
+
+/// The URL with API keys swapped for asterisks, to be safe to print
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+pub fn mask_credentials(url: &str) -> String {
+    let keys = Regex::new(r"([?&]api_key=)[^&]*").unwrap();
+
+    keys.replace_all(url, "${1}***").to_string()
+}
+
+/// Most tags suggested in place of one a search found nothing for
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+const SUGGESTIONS: usize = 5;
+
+/// Tags the booru has in place of one it doesn't, the closest first
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+pub struct Suggestion {
+    pub tag: String,
+    pub known: Vec<String>,
+    /// The known tag that's close enough for the searched one to be a typo of it
+    pub correction: Option<String>,
+}
+
+/// Tags the booru has that start like the searched ones it doesn't, for when a
+/// search finds nothing. Asked for with the whole tag, then with its first half
+/// when that finds nothing. Meta tags like rating:s are left alone
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+pub fn suggest_tags(
+    tags: &str,
+    complete: impl Fn(&str) -> Result<Vec<String>, WaifuError>,
+) -> Result<Vec<Suggestion>, WaifuError> {
+    let mut suggested = Vec::new();
+    for tag in search_tags(tags) {
+        let mut found = complete(tag)?;
+        if found.is_empty() {
+            let half = tag.chars().count().div_ceil(2).max(3);
+            let prefix: String = tag.chars().take(half).collect();
+            if prefix != tag {
+                found = complete(&prefix)?;
+            }
+        }
+        if found.is_empty() || found.iter().any(|known| known == tag) {
+            continue;
+        }
+
+        // Sorting is stable, so equally close tags stay the most used first
+        found.sort_by_key(|known| strsim::damerau_levenshtein(tag, known));
+        found.truncate(SUGGESTIONS);
+        // One slip in short tags, and two in longer ones
+        let typos = if tag.chars().count() < 5 { 1 } else { 2 };
+        let correction = found
+            .first()
+            .filter(|known| strsim::damerau_levenshtein(tag, known) <= typos)
+            .cloned();
+        suggested.push(Suggestion {
+            tag: tag.to_string(),
+            known: found,
+            correction,
+        });
+    }
+
+    Ok(suggested)
+}
+
+/// The tags of a search that name things, without a leading - and leaving out
+/// meta tags like rating:s and wildcards
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+fn search_tags(tags: &str) -> impl Iterator<Item = &str> {
+    tags.split(|c: char| c == ',' || c.is_whitespace())
+        .map(|tag| tag.trim_start_matches('-'))
+        .filter(|tag| !tag.is_empty() && !tag.contains([':', '*']))
+}
+
+/// The tags to leave out, negated to go after the others in the search
+pub fn excluded_tags(exclude: Option<&str>) -> Vec<String> {
+    split_tags(exclude)
+        .into_iter()
+        .map(|tag| format!("-{}", tag.trim_start_matches('-')))
+        .collect()
+}
+
+/// The tags in a list of them separated by spaces or commas, like -t takes
+pub fn split_tags(tags: Option<&str>) -> Vec<&str> {
+    tags.unwrap_or_default()
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|tag| !tag.is_empty())
+        .collect()
+}
+
+/// What's escaped in a tag for a URL. The punctuation of wildcards and meta
+/// tags like rating:s,q and ~tag is fine in a query string, and kept readable
+const TAG: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'_')
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'~')
+    .remove(b'*')
+    .remove(b':')
+    .remove(b',')
+    .remove(b'(')
+    .remove(b')')
+    .remove(b'!')
+    .remove(b'\'');
+
+/// The search terms as the tags= of an API URL
+pub fn encode_tags(terms: &[impl AsRef<str>]) -> String {
+    terms
+        .iter()
+        .map(|term| utf8_percent_encode(term.as_ref(), TAG).to_string())
+        .collect::<Vec<_>>()
+        .join("%20")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_queries() {
+        let query = Query::builder()
+            .tags(["scenery"])
+            .tags(vec!["sky".to_string()])
+            .rating(Rating::General)
+            .rating(Rating::General)
+            .min_score(-3)
+            .page(4)
+            .build();
+
+        assert_eq!(query.tags, ["scenery", "sky"]);
+        assert_eq!(query.rating, [Rating::General]);
+        assert_eq!(query.min_score, Some(-3));
+        assert_eq!((query.limit, query.page), (20, 4));
+    }
+
+    #[test]
+    fn encodes_tags() {
+        assert_eq!(encode_tags(&[] as &[&str]), "");
+        assert_eq!(
+            encode_tags(&["cat_ears", "~rating:s,q", "-(artist)", "フリーレン"]),
+            "cat_ears%20~rating:s,q%20-(artist)%20%E3%83%95%E3%83%AA%E3%83%BC%E3%83%AC%E3%83%B3"
+        );
+        assert_eq!(
+            encode_tags(&["a&b", "100%", "#1", "x+y", "a b"]),
+            "a%26b%20100%25%20%231%20x%2By%20a%20b"
+        );
+    }
 }
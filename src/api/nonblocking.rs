@@ -0,0 +1,190 @@
+use rand::Rng;
+use reqwest::StatusCode;
+use std::future::Future;
+
+use crate::api::{Booru, Post, Query, USER_AGENT};
+use crate::error::WaifuError;
+pub use crate::retry::{Backoff, Retry};
+
+/// Sends a booru's API requests without blocking, like Http does otherwise
+pub trait AsyncHttp {
+    /// The status and body of what the URL answered
+    fn get(
+        &self,
+        url: &str,
+    ) -> impl Future<Output = Result<(StatusCode, Vec<u8>), WaifuError>> + Send;
+}
+
+/// Sends requests with an async reqwest client, on the tokio runtime it's
+/// awaited in. It tries them again and keeps within the rate limit the way the
+/// blocking one does, and sends the login given along to its booru
+pub struct Web {
+    client: reqwest::Client,
+    retry: Retry,
+    login: Option<(String, String, String)>,
+}
+
+impl Web {
+    /// A client with waifu's user agent, trying failed requests twice more
+    pub fn new() -> Result<Self, WaifuError> {
+        let client = reqwest::Client::builder().user_agent(USER_AGENT).build()?;
+        Ok(Web::with_client(client))
+    }
+
+    /// Send the requests with this client instead, e.g. one the program
+    /// already has
+    pub fn with_client(client: reqwest::Client) -> Self {
+        Web {
+            client,
+            retry: Retry::default(),
+            login: None,
+        }
+    }
+
+    /// Try requests again and keep within a rate limit like this instead
+    pub fn with_retry(self, retry: Retry) -> Self {
+        Web { retry, ..self }
+    }
+
+    /// Log in as this user with their API key on the booru at `base_url`, e.g.
+    /// https://danbooru.donmai.us. Requests to other sites go without
+    pub fn with_login(self, base_url: &str, username: &str, api_key: &str) -> Self {
+        let login = (
+            base_url.to_string(),
+            username.to_string(),
+            api_key.to_string(),
+        );
+        Web {
+            login: Some(login),
+            ..self
+        }
+    }
+
+    fn request(&self, url: &str) -> reqwest::RequestBuilder {
+        let request = self.client.get(url);
+        match &self.login {
+            Some((base_url, user, key)) if url.starts_with(base_url.as_str()) => {
+                request.basic_auth(user, Some(key))
+            }
+            _ => request,
+        }
+    }
+}
+
+impl AsyncHttp for Web {
+    async fn get(&self, url: &str) -> Result<(StatusCode, Vec<u8>), WaifuError> {
+        let response = send(&self.retry, self.request(url)).await?;
+        let status = response.status();
+        let body = response.bytes().await?.to_vec();
+
+        Ok((status, body))
+    }
+}
+
+/// Send a request like net::send does, awaiting the waits rather than sleeping
+pub(crate) async fn send(
+    retry: &Retry,
+    request: reqwest::RequestBuilder,
+) -> Result<reqwest::Response, WaifuError> {
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+        let (client, request) = request
+            .try_clone()
+            .ok_or("This request can't be sent more than once")?
+            .build_split();
+        let request = request?;
+        if let Some(wait) = retry.slot(request.url()) {
+            tokio::time::sleep(wait).await;
+        }
+
+        match client.execute(request).await {
+            Ok(response) => {
+                let (status, headers, url) =
+                    (response.status(), response.headers(), response.url());
+                match retry.after_answer(attempt, status, headers, url)? {
+                    Some(wait) => tokio::time::sleep(wait).await,
+                    None => return Ok(response),
+                }
+            }
+            Err(error) => tokio::time::sleep(retry.after_error(attempt, error)?).await,
+        }
+    }
+}
+
+/// The posts the query finds, like Booru::search but without blocking
+pub async fn search(
+    booru: &(impl Booru + Sync),
+    http: &impl AsyncHttp,
+    query: &Query,
+) -> Result<Vec<Post>, WaifuError> {
+    let (status, body) = http.get(&booru.url(query)).await?;
+    booru.parse(status, &body)
+}
+
+/// A random one of the posts the query finds, None when it finds none
+pub async fn random_post(
+    booru: &(impl Booru + Sync),
+    http: &impl AsyncHttp,
+    query: &Query,
+) -> Result<Option<Post>, WaifuError> {
+    let mut posts = search(booru, http, query).await?;
+    if posts.is_empty() {
+        return Ok(None);
+    }
+    let index = rand::thread_rng().gen_range(0..posts.len());
+
+    Ok(Some(posts.swap_remove(index)))
+}
+
+/// The post with the ID, like danbooru::grab_post but without blocking. None
+/// when the booru has no such post
+pub async fn grab_post(
+    booru: &(impl Booru + Sync),
+    http: &impl AsyncHttp,
+    id: u32,
+) -> Result<Option<Post>, WaifuError> {
+    first(booru, http, format!("id:{}", id)).await
+}
+
+/// The post whose file has the MD5 hash, like danbooru::grab_by_md5 but
+/// without blocking
+pub async fn grab_by_md5(
+    booru: &(impl Booru + Sync),
+    http: &impl AsyncHttp,
+    md5: &str,
+) -> Result<Option<Post>, WaifuError> {
+    first(booru, http, format!("md5:{}", md5.to_lowercase())).await
+}
+
+async fn first(
+    booru: &(impl Booru + Sync),
+    http: &impl AsyncHttp,
+    tag: String,
+) -> Result<Option<Post>, WaifuError> {
+    let query = Query::builder().tags([tag]).limit(1).build();
+    Ok(search(booru, http, &query).await?.into_iter().next())
+}
+
+/// The tags starting with the prefix, like danbooru::complete_tag but without
+/// blocking. A `-` in front is kept on each, for leaving the tag out
+pub async fn complete_tag(
+    booru: &(impl Booru + Sync),
+    http: &impl AsyncHttp,
+    prefix: &str,
+) -> Result<Vec<String>, WaifuError> {
+    let (negated, prefix) = match prefix.strip_prefix('-') {
+        Some(prefix) => ("-", prefix),
+        None => ("", prefix),
+    };
+    if prefix.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let (status, body) = http.get(&booru.complete_url(prefix)).await?;
+    Ok(booru
+        .parse_completions(status, &body)?
+        .into_iter()
+        .map(|tag| format!("{}{}", negated, tag))
+        .collect())
+}
@@ -1,139 +1,294 @@
-use colored::Colorize;
+use http::StatusCode;
+#[cfg(feature = "safebooru")]
 use rand::distributions::{Distribution, Uniform};
+#[cfg(feature = "safebooru")]
+use rand::Rng;
+#[cfg(feature = "safebooru")]
+use regex::Regex;
+#[cfg(feature = "safebooru")]
+use reqwest::blocking::Client;
 use serde_json::Value;
-use std::{error::Error, fmt};
-
-use crate::api::reformat_search_tags;
-use crate::app::Safebooru;
-
-pub fn grab_random_image(args: Safebooru) -> String {
-    let request_url = evaluate_arguments(&args);
-    let data = match fetch_api_data(request_url) {
-        Ok(json_data) => json_data,
-        Err(error) => {
-            eprintln!("{}\n", error);
-            if args.questionable {
-                println!(
-                    "{}: Couldn't fetch API data. There's probably no questionable images associated with your tag(s).",
-                    "help".green()
-                );
+#[cfg(feature = "safebooru")]
+use std::collections::BTreeSet;
+#[cfg(feature = "safebooru")]
+use std::time::Duration;
+
+use crate::api::{encode_tags, parse_suggestions, Booru, Http, Post, Query};
+#[cfg(feature = "safebooru")]
+use crate::api::{excluded_tags, split_tags, Filters, Search, MAX_SEARCHES_AGAIN};
+use crate::error::WaifuError;
+#[cfg(feature = "safebooru")]
+use crate::exit;
+#[cfg(feature = "safebooru")]
+use crate::net;
+#[cfg(feature = "safebooru")]
+use crate::random;
+use crate::rating::Rating;
+#[cfg(feature = "safebooru")]
+use crate::retry::Retry;
+#[cfg(feature = "safebooru")]
+use crate::video;
+
+/// A search for a random post, like `waifu safe` makes
+#[cfg(feature = "safebooru")]
+#[derive(Debug, Clone, Default)]
+pub struct RandomSearch {
+    /// Tags and metatags, separated by spaces or commas
+    pub tags: Option<String>,
+    /// Only questionable posts. Safebooru has a few it hasn't cleaned out yet
+    pub questionable: bool,
+    pub filters: Filters,
+}
+
+#[cfg(feature = "safebooru")]
+impl Api {
+    /// Pick a random post. Video posts are passed over unless `videos` is set, and so
+    /// are the `shown` ones, looking through older posts when nothing else is found
+    pub fn grab_random_image(
+        &self,
+        search: &RandomSearch,
+        videos: bool,
+        shown: &BTreeSet<u32>,
+    ) -> Result<Post, WaifuError> {
+        let request_url = self.search_url(search);
+        let fetch_failed = |error: WaifuError| {
+            let help = if search.questionable {
+                "Couldn't fetch API data. There's probably no questionable images associated with your tag(s)."
             } else {
-                println!(
-                    "{}: Couldn't fetch API data. Try checking your tag(s) for errors.",
-                    "help".green()
-                );
+                "Couldn't fetch API data. Try checking your tag(s) for errors."
+            };
+            exit::with_help(error, help)
+        };
+        let fetch = |url: String| self.fetch_search(url);
+        let ttl = self.http.search_ttl;
+        let mut found =
+            Search::run(request_url.clone(), self.samples, ttl, fetch).map_err(fetch_failed)?;
+
+        let usable = |image: &Post| videos || !video::is_video(&image.url);
+        // Safebooru can't search for a shape or a type of file, and doesn't say how big
+        // the files are
+        let fits = |image: &Post| {
+            let filters = &search.filters;
+            filters.fits(image.width, image.height, &image.url, None)
+        };
+        let mut indices = Vec::new();
+        for page in 1.. {
+            indices = (0..found.posts.len())
+                .filter(|&index| usable(&found.posts[index]) && fits(&found.posts[index]))
+                .filter(|&index| !shown.contains(&found.posts[index].id))
+                .collect();
+            let any_usable = found.posts.iter().any(usable);
+            if !indices.is_empty() || !any_usable || page > MAX_SEARCHES_AGAIN {
+                break;
             }
-
-            std::process::exit(1);
+            // Another random page may have some that will do
+            found
+                .search_again(request_url.clone(), fetch)
+                .map_err(fetch_failed)?;
+        }
+        if indices.is_empty() {
+            let fitting = found.posts.iter().any(|image| usable(image) && fits(image));
+            let message = if !shown.is_empty() && fitting {
+                "Only images that were shown recently were found for the given tags."
+            } else if found.posts.iter().any(usable) {
+                "No images that fit the filters given were found for the given tags."
+            } else {
+                "No images found for the given tags."
+            };
+            return Err(WaifuError::NoResults(message.into()));
         }
-    };
 
-    if data.is_empty() {
-        eprintln!("No images found for the given tags.");
-        std::process::exit(1);
+        let random_number = Uniform::from(0..indices.len());
+        Ok(found.take(indices[random_number.sample(&mut random::rng())]))
     }
 
-    let mut rng = rand::thread_rng();
-    let random_number = Uniform::from(0..data.len());
-    let index = random_number.sample(&mut rng);
+    /// The API URL the search is made with. Another site than Safebooru itself,
+    /// like the mirror, has posts of every rating, where Safebooru only has safe
+    /// ones, so those rated above what was asked for are left out there
+    pub fn search_url(&self, search: &RandomSearch) -> String {
+        let RandomSearch {
+            questionable,
+            tags,
+            filters,
+        } = search;
+
+        let mut terms: Vec<String> = split_tags(tags.as_deref())
+            .into_iter()
+            .map(String::from)
+            .collect();
+
+        terms.extend(excluded_tags(filters.exclude.as_deref()));
+        // Safebooru can't search for one tag or another, so after the # are the
+        // ones to search for one by one
+        let any_of = split_tags(filters.any_of.as_deref());
+        if let [tag] = any_of.as_slice() {
+            terms.push(tag.to_string());
+        }
+        if let Some(width) = filters.min_width {
+            terms.push(format!("width:>={}", width));
+        }
+        if let Some(height) = filters.min_height {
+            terms.push(format!("height:>={}", height));
+        }
+        // With questionable the search is for rating:questionable already
+        if self.host != SAFEBOORU && !questionable {
+            terms.extend(["-rating:questionable", "-rating:explicit"].map(String::from));
+        }
 
-    let image = &data[index];
+        let mut query = Query::builder().tags(terms).limit(PAGE_SIZE);
+        if *questionable {
+            query = query.rating(Rating::Questionable);
+        }
+        if let Some(score) = filters.min_score {
+            query = query.min_score(score);
+        }
+        // No key needed for access
+        let mut api = self.url(&query.build());
+        if any_of.len() > 1 {
+            let any_of: Vec<String> = any_of.iter().map(|tag| encode_tags(&[tag])).collect();
+            api.push_str(&format!("#{}", any_of.join("~")));
+        }
 
-    // Prefer API-provided file_url when available (avoids missing directory issues)
-    let image_url = if let Some(url) = item_file_url(image) {
-        url
-    } else {
-        format!(
-            "https://safebooru.org/images/{dir}/{img}?{id}",
-            dir = image.directory,
-            img = image.image,
-            id = image.id
-        )
-    };
+        api
+    }
 
-    if args.details {
-        let ImageData {
-            rating,
-            width,
-            height,
-            tags,
-            ..
-        } = image;
-
-        let details = ImageInfo {
-            url: &image_url,
-            rating,
-            width: *width,
-            height: *height,
-            tags: tags.split(' ').collect(),
-        };
+    /// Tags starting with the prefix, the most used first, from the site's
+    /// autocomplete
+    pub fn complete_tag(&self, prefix: &str) -> Result<Vec<String>, WaifuError> {
+        let (status, body) = self.http.get(&self.complete_url(prefix))?;
+        self.parse_completions(status, &body)
+    }
 
-        if let Err(error) = print_image_details(details) {
-            eprintln!("{}\n", error);
-            println!(
-                "{}: There was an error when printing the tags. Please try again later.",
-                "help".green()
-            );
-            std::process::exit(1);
-        }
+    /// Look up a single post by its ID, along with its details
+    pub fn grab_post(&self, id: u32) -> Result<Post, WaifuError> {
+        let missing = format!("There's no post #{} on {}", id, self.host);
+        self.grab_one(&format!("id={}", id), missing)
     }
 
-    image_url
-}
+    /// Look up the post whose file has this MD5, like grab_post does by ID
+    pub fn grab_by_md5(&self, md5: &str) -> Result<Post, WaifuError> {
+        let missing = format!("No post on {} has the MD5 {}", self.host, md5);
+        self.grab_one(&format!("tags=md5:{}", md5), missing)
+    }
 
-fn evaluate_arguments(args: &Safebooru) -> String {
-    let Safebooru {
-        questionable, tags, ..
-    } = args;
+    /// The first post the query finds and its details, with the error saying
+    /// what's missing when there's none
+    fn grab_one(&self, query: &str, missing: String) -> Result<Post, WaifuError> {
+        let mut request_url = format!(
+            "https://{}/index.php?page=dapi&s=post&q=index&json=1&{}",
+            self.host, query
+        );
+        // Gelbooru wants an API key for its API, Safebooru doesn't
+        if let Some((user, key)) = &self.http.api_key {
+            if self.host != SAFEBOORU {
+                request_url.push_str(&format!("&api_key={}&user_id={}", key, user));
+            }
+        }
 
-    let tags = match tags {
-        Some(search_items) => search_items,
-        None => "",
-    };
+        let data = self.fetch_api_data(request_url)?;
+        data.into_iter()
+            .next()
+            .ok_or(WaifuError::NoResults(missing))
+    }
 
-    let search_tags = String::from(tags);
-    let mut tags = reformat_search_tags(search_tags);
+    /// The posts on a random page of each search `search_url` stands for,
+    /// together
+    fn fetch_search(&self, search: String) -> Result<Vec<Post>, WaifuError> {
+        let mut posts: Vec<Post> = Vec::new();
+        for url in search_urls(&search) {
+            for post in self.fetch_random_page(&url)? {
+                if !posts.iter().any(|other| other.id == post.id) {
+                    posts.push(post);
+                }
+            }
+        }
 
-    if *questionable {
-        tags.push_str("%20rating:questionable");
+        Ok(posts)
     }
 
-    let tags = format!("&tags={}", tags);
-    // No key needed for access
-    let mut api =
-        String::from("https://safebooru.org/index.php?page=dapi&s=post&q=index&limit=100&json=1");
-    api.push_str(&tags);
+    /// A page picked at random out of all those the search finds, so older posts
+    /// come up as often as the newest ones
+    fn fetch_random_page(&self, url: &str) -> Result<Vec<Post>, WaifuError> {
+        let count = self.fetch_count(url)?.min(MAX_OFFSET);
+        if count == 0 {
+            return Ok(Vec::new());
+        }
+        let page = random::rng().gen_range(0..count.div_ceil(PAGE_SIZE));
+        tracing::debug!("Searching page {} of the {} posts found", page, count);
+
+        self.fetch_api_data(format!("{}&pid={}", url, page))
+    }
 
-    api
-}
+    /// How many posts the search finds in all. Only the XML of the API says so,
+    /// the JSON is just the posts
+    fn fetch_count(&self, url: &str) -> Result<u32, WaifuError> {
+        let url = url.replacen(&format!("&limit={}&json=1", PAGE_SIZE), "&limit=0", 1);
+        let response = self.http.send(self.http.client()?.get(&url))?;
+        if !response.status().is_success() {
+            let message = "Safebooru returned non-success status.";
+            return Err(WaifuError::api(response.status(), message));
+        }
+        let status = response.status();
+        let text = response.text()?;
+
+        let count = Regex::new(r#"<posts[^>]*\scount="(\d+)""#)?
+            .captures(&text)
+            .and_then(|captures| captures[1].parse().ok())
+            .ok_or_else(|| {
+                WaifuError::api(status, "Safebooru didn't say how many posts it found")
+            })?;
+        Ok(count)
+    }
 
-#[derive(Debug)]
-struct ImageData {
-    directory: String,
-    image: String,
-    id: u32,
-    rating: String,
-    width: u32,
-    height: u32,
-    tags: String,
-    file_url: Option<String>,
+    fn fetch_api_data(&self, url: String) -> Result<Vec<Post>, WaifuError> {
+        let (status, body) = self.http.get(&url)?;
+        self.parse_api_data(status, &body)
+    }
 }
 
-// Helper to extract file_url when present in the serialized map
-fn item_file_url(image: &ImageData) -> Option<String> {
-    image.file_url.clone()
+pub const SAFEBOORU: &str = "safebooru.org";
+
+/// Runs the same software as Safebooru, and has its posts among others
+#[cfg(feature = "safebooru")]
+pub const MIRROR: &str = "tbib.org";
+
+/// Whether Safebooru is down or turning requests away, rather than finding
+/// nothing or being asked for something it can't do. Its errors come as HTML
+/// pages, often with a success status
+#[cfg(feature = "safebooru")]
+pub fn unavailable(error: &WaifuError) -> bool {
+    match error.without_help() {
+        WaifuError::Network(_) | WaifuError::Http(_) => true,
+        WaifuError::Api { status, .. } => {
+            status.is_server_error()
+                || status.is_success()
+                || *status == StatusCode::TOO_MANY_REQUESTS
+                || *status == StatusCode::FORBIDDEN
+        }
+        _ => false,
+    }
 }
 
-#[derive(Debug)]
-struct ResponseError(String);
+/// The URLs to search Safebooru with for what `search_url` gave, one for
+/// each tag of --any-of
+#[cfg(feature = "safebooru")]
+pub fn search_urls(search: &str) -> Vec<String> {
+    let Some((url, tags)) = search.split_once('#') else {
+        return vec![search.to_string()];
+    };
 
-impl fmt::Display for ResponseError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.0)
-    }
+    tags.split('~')
+        .map(|tag| format!("{}%20{}", url, tag))
+        .collect()
 }
 
-impl Error for ResponseError {}
+/// How many posts each search for a page finds
+#[cfg(feature = "safebooru")]
+const PAGE_SIZE: u32 = 100;
+
+/// The most posts into a search the API goes, past them it finds none
+#[cfg(feature = "safebooru")]
+const MAX_OFFSET: u32 = 20000;
 
 fn parse_u32(value: Option<&Value>) -> u32 {
     match value {
@@ -143,115 +298,404 @@ fn parse_u32(value: Option<&Value>) -> u32 {
     }
 }
 
-fn fetch_api_data(url: String) -> Result<Vec<ImageData>, Box<dyn Error>> {
-    use reqwest::blocking::Client;
-    use std::time::Duration;
-
-    let client = Client::builder()
-        .timeout(Duration::from_secs(15))
-        .user_agent("Mozilla/5.0 (compatible; waifu/1.0; +https://github.com/lenkat101/waifu)")
-        .build()?;
-    let response = client.get(&url).send()?;
-    let status = response.status();
-    let text = response.text()?;
-
-    if text.trim_start().starts_with('<') {
-        let message = "Safebooru returned HTML or an unexpected response.";
-        return Err(Box::new(ResponseError(message.into())));
-    }
-
-    if !status.is_success() {
-        let message = format!("{}: Safebooru returned non-success status.", status);
-        return Err(Box::new(ResponseError(message)));
-    }
-
-    let raw: Value = serde_json::from_str(&text)
-        .map_err(|e| ResponseError(format!("Failed to parse JSON: {}", e)))?;
-    let arr = raw
-        .as_array()
-        .ok_or_else(|| ResponseError("Unexpected JSON structure".into()))?;
-
-    let mut data = Vec::new();
-    for item in arr {
-        let directory = item
-            .get("directory")
-            .and_then(Value::as_str)
-            .unwrap_or("")
-            .to_string();
-        let image = item
-            .get("image")
-            .and_then(Value::as_str)
-            .unwrap_or("")
-            .to_string();
-        let id = parse_u32(item.get("id"));
-        let rating = item
-            .get("rating")
-            .and_then(Value::as_str)
-            .unwrap_or("")
-            .to_string();
-        let width = parse_u32(item.get("width"));
-        let height = parse_u32(item.get("height"));
-        let tags = item
-            .get("tags")
-            .and_then(Value::as_str)
-            .unwrap_or("")
-            .to_string();
-        let file_url = item
-            .get("file_url")
-            .and_then(Value::as_str)
-            .map(|s| s.to_string());
-
-        data.push(ImageData {
-            directory,
-            image,
-            id,
-            rating,
-            width,
-            height,
-            tags,
-            file_url,
-        });
+/// The API of Safebooru, or of another site running the same software, which
+/// reads what waifu's own Http or another gets from it
+pub struct Api<H: Http = Web> {
+    host: String,
+    samples: bool,
+    http: H,
+}
+
+impl Api {
+    pub fn new(host: &str) -> Self {
+        Api::with_http(host, Web::new())
+    }
+}
+
+impl<H: Http> Api<H> {
+    /// The API on the host, with its requests sent by `http`
+    pub fn with_http(host: &str, http: H) -> Self {
+        Api {
+            host: host.to_string(),
+            samples: false,
+            http,
+        }
+    }
+
+    /// Whether posts link the smaller sample of a file rather than the full size
+    /// one, when there's one
+    pub fn with_samples(self, samples: bool) -> Self {
+        Api { samples, ..self }
+    }
+
+    /// The host the API is on
+    pub fn host(&self) -> &str {
+        &self.host
     }
 
-    Ok(data)
+    /// The posts in what the host's API answered
+    fn parse_api_data(&self, status: StatusCode, body: &[u8]) -> Result<Vec<Post>, WaifuError> {
+        let host = self.host.as_str();
+        let text = String::from_utf8_lossy(body);
+
+        // Searches that find nothing come back empty rather than as an empty list
+        if status.is_success() && text.trim().is_empty() {
+            return Ok(Vec::new());
+        }
+
+        if text.trim_start().starts_with('<') {
+            let message = "Safebooru returned HTML or an unexpected response.";
+            return Err(WaifuError::api(status, message));
+        }
+
+        if !status.is_success() {
+            let message = "Safebooru returned non-success status.";
+            return Err(WaifuError::api(status, message));
+        }
+
+        let raw: Value = serde_json::from_str(&text)
+            .map_err(|e| WaifuError::api(status, format!("Failed to parse JSON: {}", e)))?;
+        // Gelbooru wraps the posts in an object, and leaves them out when there are none
+        let arr = match &raw {
+            Value::Array(arr) => arr.as_slice(),
+            Value::Object(object) => object
+                .get("post")
+                .and_then(Value::as_array)
+                .map_or(&[][..], Vec::as_slice),
+            _ => return Err(WaifuError::api(status, "Unexpected JSON structure")),
+        };
+
+        let mut data = Vec::new();
+        for item in arr {
+            let text = |key: &str| item.get(key).and_then(Value::as_str).unwrap_or("");
+            let id = parse_u32(item.get("id"));
+            // Prefer API-provided file_url when available (avoids missing directory issues)
+            let url = match item.get("file_url").and_then(Value::as_str) {
+                Some(url) => url.to_string(),
+                None => format!(
+                    "https://{}/images/{}/{}?{}",
+                    host,
+                    text("directory"),
+                    text("image"),
+                    id
+                ),
+            };
+            // Only files bigger than a sample have one, always a JPEG named after them
+            let has_sample = matches!(item.get("sample"), Some(Value::Bool(true)))
+                || parse_u32(item.get("sample")) == 1;
+            let sample = match text("sample_url") {
+                _ if !self.samples || !has_sample => None,
+                "" => text("image").rsplit_once('.').map(|(name, _)| {
+                    format!(
+                        "https://{}/samples/{}/sample_{}.jpg?{}",
+                        host,
+                        text("directory"),
+                        name,
+                        id
+                    )
+                }),
+                sample_url => Some(sample_url.to_string()),
+            };
+            let (url, variant) = match sample {
+                Some(sample) => (sample, Some("sample".to_string())),
+                None => (url, None),
+            };
+
+            data.push(Post {
+                site: host.split('.').next().unwrap_or(host).to_string(),
+                id,
+                url,
+                variant,
+                rating: text("rating").chars().next().and_then(Rating::from_letter),
+                width: parse_u32(item.get("width")),
+                height: parse_u32(item.get("height")),
+                file_size: None,
+                tags: text("tags").split_whitespace().map(String::from).collect(),
+                // Safebooru doesn't split out artist and character tags, or say
+                // where the art is from
+                artist: None,
+                character: None,
+                source: None,
+                page: format!("https://{}/index.php?page=post&s=view&id={}", host, id),
+            });
+        }
+
+        Ok(data)
+    }
 }
 
-struct ImageInfo<'a> {
-    url: &'a str,
-    rating: &'a str,
-    width: u32,
-    height: u32,
-    tags: Vec<&'a str>,
+impl<H: Http> Booru for Api<H> {
+    fn url(&self, query: &Query) -> String {
+        let mut terms = query.tags.clone();
+        // It can't search for one rating or another, only leave out the rest
+        match query.rating.as_slice() {
+            [] => (),
+            [rating] => terms.push(format!("rating:{}", rating.name())),
+            ratings => terms.extend(
+                Rating::ALL
+                    .iter()
+                    .filter(|rating| !ratings.contains(rating))
+                    .map(|rating| format!("-rating:{}", rating.name())),
+            ),
+        }
+        terms.extend(query.score_term());
+
+        let mut url = format!(
+            "https://{}/index.php?page=dapi&s=post&q=index&limit={}&json=1",
+            self.host, query.limit
+        );
+        // The first page goes without, so a search for a random page can add its own
+        if query.page > 0 {
+            url.push_str(&format!("&pid={}", query.page));
+        }
+        url.push_str(&format!("&tags={}", encode_tags(&terms)));
+
+        url
+    }
+
+    fn parse(&self, status: StatusCode, body: &[u8]) -> Result<Vec<Post>, WaifuError> {
+        self.parse_api_data(status, body)
+    }
+
+    fn complete_url(&self, prefix: &str) -> String {
+        format!(
+            "https://{}/autocomplete.php?q={}",
+            self.host,
+            encode_tags(&[prefix])
+        )
+    }
+
+    fn parse_completions(
+        &self,
+        status: StatusCode,
+        body: &[u8],
+    ) -> Result<Vec<String>, WaifuError> {
+        parse_suggestions("Safebooru", status, body)
+    }
+
+    fn http(&self) -> &dyn Http {
+        &self.http
+    }
+}
+
+/// Sends requests with waifu's own client, caching what it can
+#[cfg(feature = "safebooru")]
+#[derive(Clone, Default)]
+pub struct Web {
+    client: Option<Client>,
+    retry: Retry,
+    api_key: Option<(String, String)>,
+    revalidate: bool,
+    search_ttl: Duration,
 }
 
-fn print_image_details(info: ImageInfo) -> Result<(), Box<dyn std::error::Error>> {
-    use std::io::{self, Write};
+#[cfg(feature = "safebooru")]
+impl Web {
+    pub fn new() -> Self {
+        Web::default()
+    }
 
-    let ImageInfo {
-        url,
-        rating,
-        width,
-        height,
-        tags,
-    } = info;
+    /// Send the requests with this client, e.g. one going through a proxy
+    pub fn with_client(client: Client) -> Self {
+        Web {
+            client: Some(client),
+            ..Web::default()
+        }
+    }
 
-    println!("✉️ {title}: {}", url, title = "Link".cyan());
-    println!("⚖️ {title}: {}", rating, title = "Rating".cyan());
-    println!(
-        "📐 {title}: {w} x {h}",
-        title = "Dimensions".cyan(),
-        w = width,
-        h = height
-    );
+    /// Try requests again and keep to the rate limit like this
+    pub fn with_retry(self, retry: Retry) -> Self {
+        Web { retry, ..self }
+    }
 
-    let stdout = io::stdout();
-    let lock = stdout.lock();
-    let mut buffer = io::BufWriter::new(lock);
+    /// Look posts up with this user ID and API key, which Gelbooru wants for its
+    /// API. Safebooru itself is never sent them
+    pub fn with_api_key(self, user_id: &str, api_key: &str) -> Self {
+        Web {
+            api_key: Some((user_id.to_string(), api_key.to_string())),
+            ..self
+        }
+    }
+
+    /// With `revalidate`, keep API responses to use again when they haven't
+    /// changed. Searches are kept for `search_ttl` to pick from again
+    pub fn with_cache(self, revalidate: bool, search_ttl: Duration) -> Self {
+        Web {
+            revalidate,
+            search_ttl,
+            ..self
+        }
+    }
 
-    write!(buffer, "🏷️ {}:", "Tags".cyan())?;
-    tags.iter().try_for_each(|tag| write!(buffer, " {}", tag))?;
+    fn client(&self) -> Result<&Client, WaifuError> {
+        match &self.client {
+            Some(client) => Ok(client),
+            None => net::default_client(),
+        }
+    }
+
+    fn send(
+        &self,
+        request: reqwest::blocking::RequestBuilder,
+    ) -> Result<reqwest::blocking::Response, WaifuError> {
+        net::send_with(&self.retry, request)
+    }
+}
+
+#[cfg(feature = "safebooru")]
+impl Http for Web {
+    fn get(&self, url: &str) -> Result<(StatusCode, Vec<u8>), WaifuError> {
+        let request = self.client()?.get(url);
+        net::send_revalidated(url, request, &self.retry, self.revalidate)
+    }
+}
 
-    writeln!(buffer)?;
+/// The site's API answers, read into posts by Booru::parse
+#[cfg(not(feature = "safebooru"))]
+#[derive(Default)]
+pub struct Web;
 
-    Ok(())
+#[cfg(not(feature = "safebooru"))]
+impl Web {
+    pub fn new() -> Self {
+        Web
+    }
+}
+
+// Without the command there's no client, the program gets the URL itself and
+// has Booru::parse read what it answered
+#[cfg(not(feature = "safebooru"))]
+impl Http for Web {
+    fn get(&self, url: &str) -> Result<(StatusCode, Vec<u8>), WaifuError> {
+        Err(super::no_client(url))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Answers every request with the same status and body
+    struct Canned(StatusCode, &'static str);
+
+    impl Http for Canned {
+        fn get(&self, _url: &str) -> Result<(StatusCode, Vec<u8>), WaifuError> {
+            Ok((self.0, self.1.as_bytes().to_vec()))
+        }
+    }
+
+    fn search(host: &str, status: StatusCode, body: &'static str) -> Result<Vec<Post>, WaifuError> {
+        Api::with_http(host, Canned(status, body)).search(&Query::default())
+    }
+
+    #[test]
+    fn reads_a_search() {
+        let posts = search(
+            "safebooru.org",
+            StatusCode::OK,
+            r#"[{"id": 3, "directory": "ab", "image": "cd.png", "rating": "general",
+                 "width": "800", "height": 600, "tags": " scenery  sky "}]"#,
+        )
+        .unwrap();
+
+        assert_eq!(posts.len(), 1);
+        let post = &posts[0];
+        assert_eq!(post.site, "safebooru");
+        assert_eq!(post.id, 3);
+        assert_eq!(post.url, "https://safebooru.org/images/ab/cd.png?3");
+        assert_eq!(post.rating, Some(Rating::General));
+        assert_eq!((post.width, post.height), (800, 600));
+        assert_eq!(post.tags, ["scenery", "sky"]);
+    }
+
+    #[test]
+    fn reads_gelbooru_posts() {
+        let posts = search(
+            "gelbooru.com",
+            StatusCode::OK,
+            r#"{"@attributes": {"limit": 20, "offset": 0, "count": 1},
+                "post": [{"id": 9, "file_url": "https://img3.gelbooru.com/images/ab/cd.jpg",
+                          "tags": "cat"}]}"#,
+        )
+        .unwrap();
+
+        assert_eq!(posts.len(), 1);
+        assert_eq!(posts[0].site, "gelbooru");
+        assert_eq!(posts[0].id, 9);
+    }
+
+    #[test]
+    fn finds_nothing_when_gelbooru_leaves_the_posts_out() {
+        let body = r#"{"@attributes": {"limit": 20, "offset": 0, "count": 0}}"#;
+        assert!(search("gelbooru.com", StatusCode::OK, body)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn finds_nothing_in_an_empty_body() {
+        assert!(search("safebooru.org", StatusCode::OK, "")
+            .unwrap()
+            .is_empty());
+        assert!(search("safebooru.org", StatusCode::OK, "[]")
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn fails_on_an_html_page() {
+        let error = search(
+            "safebooru.org",
+            StatusCode::OK,
+            "<html><head><title>Cloudflare</title></head></html>",
+        )
+        .unwrap_err();
+        assert!(matches!(error, WaifuError::Api { .. }), "{:?}", error);
+
+        let error = search("safebooru.org", StatusCode::BAD_GATEWAY, "Bad gateway").unwrap_err();
+        assert!(
+            matches!(error, WaifuError::Api { status, .. } if status == StatusCode::BAD_GATEWAY)
+        );
+    }
+
+    #[test]
+    fn prefers_the_file_url() {
+        let posts = search(
+            "safebooru.org",
+            StatusCode::OK,
+            r#"[{"id": 1, "directory": "ab", "image": "cd.png",
+                 "file_url": "https://safebooru.org//images/ab/cd.png"},
+                {"id": 2, "directory": "ef", "image": "gh.jpg"}]"#,
+        )
+        .unwrap();
+
+        assert_eq!(posts[0].url, "https://safebooru.org//images/ab/cd.png");
+        assert_eq!(posts[1].url, "https://safebooru.org/images/ef/gh.jpg?2");
+    }
+
+    #[test]
+    fn encodes_the_query() {
+        let query = Query::builder()
+            .tags(["fate/stay_night", "c++"])
+            .rating(Rating::General)
+            .limit(5)
+            .page(2)
+            .build();
+        assert_eq!(
+            Api::new("safebooru.org").url(&query),
+            "https://safebooru.org/index.php?page=dapi&s=post&q=index&limit=5&json=1&pid=2\
+             &tags=fate%2Fstay_night%20c%2B%2B%20rating:general"
+        );
+
+        // It can only leave out the other ratings
+        let query = Query::builder()
+            .rating(Rating::General)
+            .rating(Rating::Sensitive)
+            .build();
+        assert_eq!(
+            Api::new("safebooru.org").url(&query),
+            "https://safebooru.org/index.php?page=dapi&s=post&q=index&limit=20&json=1\
+             &tags=-rating:questionable%20-rating:explicit"
+        );
+    }
 }
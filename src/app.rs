@@ -1,21 +1,479 @@
-use clap::{Args, Parser, Subcommand, ValueHint};
+use clap::{Args, Parser, Subcommand, ValueEnum, ValueHint};
 use is_terminal::IsTerminal;
-use std::error::Error;
-use std::path::PathBuf;
-use viuer::{print, print_from_file};
+#[cfg(feature = "json")]
+use serde::Serialize;
+use std::cell::Cell;
+use std::collections::BTreeMap;
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+use std::collections::BTreeSet;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+#[cfg(feature = "json")]
+use std::sync::OnceLock;
+use std::sync::{Mutex, PoisonError};
+use std::time::Duration;
+
+#[cfg(feature = "danbooru")]
+use crate::api::danbooru;
+#[cfg(feature = "safebooru")]
+use crate::api::safebooru;
+use crate::api::Post;
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+use crate::api::{self, FileType, Orientation};
+#[cfg(feature = "network")]
+use crate::auth;
+use crate::browser;
+#[cfg(feature = "network")]
+use crate::cache;
+use crate::clipboard;
+use crate::config;
+#[cfg(feature = "json")]
+use crate::dirs;
+use crate::doctor;
+use crate::error::WaifuError;
+use crate::exit;
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+use crate::exit::Code;
+#[cfg(feature = "json")]
+use crate::favorites;
+#[cfg(feature = "json")]
+use crate::history::{self, Entry, Last, Shown};
+use crate::hook;
+use crate::local;
+#[cfg(feature = "network")]
+use crate::net;
+use crate::palette;
+#[cfg(all(feature = "network", feature = "async"))]
+use crate::pipeline;
+use crate::random;
+#[cfg(feature = "animation")]
+use crate::render::animation;
+use crate::render::{
+    self,
+    dither::Dither,
+    layout::{Fit, Size},
+    tmux::Passthrough,
+    transform::{self, Background, Censor, Crop, Filter},
+    ColorDepth, Protocol, Renderer, Settings,
+};
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+use crate::serve;
+use crate::theme;
+#[cfg(feature = "network")]
+use crate::upload;
+use crate::upscale;
+use crate::video;
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+use crate::wallpaper;
+#[cfg(feature = "network")]
+use crate::webhook;
 
 const MAX_IMAGE_BYTES: usize = 20 * 1024 * 1024; // 20 MiB hard cap to avoid OOM
 
 #[derive(Parser, Debug)]
-#[command(about = "View random anime fanart in your terminal")]
+#[command(
+    about = "View random anime fanart in your terminal",
+    args_override_self = true
+)]
 struct Cli {
-    /// Resize the image to a provided height
+    /// Resize the image to a provided height, in cells or as a percentage
+    /// of the terminal (e.g. 80%)
     #[arg(short = 'H', long)]
-    height: Option<u32>,
+    height: Option<Size>,
 
-    /// Resize the image to a provided width
+    /// Resize the image to a provided width, in cells or as a percentage
+    /// of the terminal (e.g. 50%)
     #[arg(short = 'W', long)]
-    width: Option<u32>,
+    width: Option<Size>,
+
+    /// Size the image against the whole terminal, or the box given by --width/--height
+    #[arg(long, value_enum)]
+    fit: Option<Fit>,
+
+    /// Exact width of the image in pixels. Precise on kitty and iTerm,
+    /// converted to cells everywhere else
+    #[arg(long, conflicts_with_all = ["width", "height", "fit", "scale"])]
+    pixel_width: Option<u32>,
+
+    /// Exact height of the image in pixels. Precise on kitty and iTerm,
+    /// converted to cells everywhere else
+    #[arg(long, conflicts_with_all = ["width", "height", "fit", "scale"])]
+    pixel_height: Option<u32>,
+
+    /// Draw the image at a fraction of the size it would otherwise get, e.g. 0.5
+    #[arg(long, value_parser = render::layout::parse_scale)]
+    scale: Option<f64>,
+
+    /// Never let the image get wider than this, in cells or as a percentage of the terminal
+    #[arg(long)]
+    max_width: Option<Size>,
+
+    /// Never let the image get taller than this, in cells or as a percentage of the terminal
+    #[arg(long)]
+    max_height: Option<Size>,
+
+    /// Don't blow small images up past their own resolution
+    #[arg(long)]
+    no_upscale: bool,
+
+    /// Center the image horizontally, and vertically too with --absolute
+    #[arg(long)]
+    center: bool,
+
+    /// Height of a terminal cell divided by its width, for fonts that aren't twice as tall as they are wide
+    #[arg(long, value_name = "RATIO", value_parser = render::layout::parse_scale)]
+    cell_aspect: Option<f64>,
+
+    /// Only show part of the image, given in pixels as WIDTHxHEIGHT+X+Y
+    #[arg(long, value_name = "GEOMETRY", conflicts_with = "crop_center")]
+    crop: Option<Crop>,
+
+    /// Only show the middle of the image, given in pixels as WIDTHxHEIGHT
+    #[arg(long, value_name = "SIZE", value_parser = Crop::centered)]
+    crop_center: Option<Crop>,
+
+    /// Adjust the image's colors before drawing it. Can be repeated or comma separated
+    #[arg(long, value_enum, value_delimiter = ',')]
+    filter: Vec<Filter>,
+
+    /// Make the image brighter or darker, as a percentage from -100 to 100
+    #[arg(
+        long,
+        default_value_t = 0,
+        allow_negative_numbers = true,
+        value_parser = clap::value_parser!(i32).range(-100..=100)
+    )]
+    brightness: i32,
+
+    /// Raise or lower the image's contrast, as a percentage from -100 to 100
+    #[arg(
+        long,
+        default_value_t = 0,
+        allow_negative_numbers = true,
+        value_parser = clap::value_parser!(i32).range(-100..=100)
+    )]
+    contrast: i32,
+
+    /// Make colors more vivid or more washed out, as a percentage from -100 (gray) to 100
+    #[arg(
+        long,
+        default_value_t = 0,
+        allow_negative_numbers = true,
+        value_parser = clap::value_parser!(i32).range(-100..=100)
+    )]
+    saturation: i32,
+
+    /// What to draw behind transparent parts of the image: a color like '#1e1e2e',
+    /// 'checker' for a checkerboard, or 'terminal' to let the terminal background through
+    #[arg(long, value_name = "BACKGROUND")]
+    background: Option<Background>,
+
+    /// Obscure questionable and explicit images from Safebooru and Danbooru,
+    /// while still printing their details
+    #[arg(long, value_enum)]
+    censor: Option<Censor>,
+
+    /// Write the artist, character and source of Safebooru and Danbooru
+    /// images across their bottom, so screenshots keep the attribution
+    #[arg(long)]
+    caption: bool,
+
+    /// Print a QR code of the post's page beneath the image, or of the image's
+    /// URL when it isn't from a booru, to open it on a phone
+    #[arg(long, conflicts_with = "stdout")]
+    qr: bool,
+
+    /// Copy the image's URL, the post's tags or its source to the clipboard,
+    /// through the terminal over SSH
+    #[arg(long, value_enum, value_name = "WHAT")]
+    copy: Option<clipboard::Field>,
+
+    /// Open the post's page in the browser once the image is shown, to favorite
+    /// or comment on it there, or the image's URL when it isn't from a booru
+    #[arg(long)]
+    open: bool,
+
+    /// Post the image's URL and attribution to a Discord or Slack webhook too,
+    /// e.g. for a channel that gets a new one every day from cron
+    #[cfg(feature = "network")]
+    #[arg(long, value_name = "URL")]
+    webhook: Option<String>,
+
+    /// Hand each image shown to the downloader_cmd from the config file too,
+    /// e.g. 'gallery-dl {url}', to archive it with a tool made for that
+    #[arg(long)]
+    download_via_hook: bool,
+
+    /// Run images through an external upscaler before showing them, e.g.
+    /// 'waifu2x-ncnn-vulkan -i {in} -o {out}'
+    #[arg(long, value_name = "COMMAND")]
+    upscale_cmd: Option<String>,
+
+    /// Show video posts and files by grabbing a still frame of them with an
+    /// external program, e.g. 'ffmpeg -loglevel error -i {in} -frames:v 1 {out}'.
+    /// Without it, videos are skipped when picking random posts
+    #[arg(long, value_name = "COMMAND")]
+    poster_cmd: Option<String>,
+
+    /// Send every request through this proxy, e.g. socks5://127.0.0.1:9050 or
+    /// http://proxy.example.com:8080. Otherwise http_proxy, https_proxy and
+    /// all_proxy are used when they're set
+    #[cfg(feature = "network")]
+    #[arg(long, value_name = "URL")]
+    proxy: Option<String>,
+
+    /// Send an extra header with every request, e.g. 'Authorization: Bearer ...'.
+    /// Can be given several times
+    #[cfg(feature = "network")]
+    #[arg(long = "header", value_name = "NAME: VALUE", value_parser = net::parse_header)]
+    headers: Vec<(reqwest::header::HeaderName, reqwest::header::HeaderValue)>,
+
+    /// Send cookies from a cookies.txt file, like the ones browser extensions
+    /// export, e.g. to get past Cloudflare or stay logged in
+    #[cfg(feature = "network")]
+    #[arg(long, value_name = "FILE", value_hint = ValueHint::FilePath)]
+    cookie_file: Option<PathBuf>,
+
+    /// Trust the certificate authorities in this PEM file too, e.g. a
+    /// TLS-intercepting proxy's or a self-hosted booru's private CA
+    #[cfg(feature = "network")]
+    #[arg(long, value_name = "FILE", value_hint = ValueHint::FilePath)]
+    cacert: Option<PathBuf>,
+
+    /// Don't check certificates at all. Anyone in between can read and change
+    /// what's fetched, so only use it when there's no other way
+    #[cfg(feature = "network")]
+    #[arg(long)]
+    insecure: bool,
+
+    /// Give up on a request after this long, e.g. 30s or 2m
+    #[cfg(feature = "network")]
+    #[arg(long, value_name = "DURATION", default_value = "20s", value_parser = humantime::parse_duration)]
+    timeout: Duration,
+
+    /// Times to try a request again after a connection error or timeout, or
+    /// after the site said to slow down
+    #[cfg(feature = "network")]
+    #[arg(long, value_name = "N", default_value_t = 2)]
+    retries: u32,
+
+    /// How the wait between tries grows
+    #[cfg(feature = "network")]
+    #[arg(long, value_enum, default_value_t = net::Backoff::Linear)]
+    backoff: net::Backoff,
+
+    /// Send at most this many requests a second to any one site, so slideshows
+    /// and long lists don't get you banned. 0 turns the limit off
+    #[cfg(feature = "network")]
+    #[arg(long, value_name = "N", default_value = "2", value_parser = net::parse_rate)]
+    rate_limit: f64,
+
+    /// Identify as this User-Agent instead of waifu's own, for sites that want
+    /// to know who's asking or filter on it
+    #[cfg(feature = "network")]
+    #[arg(long, value_name = "STRING", default_value = net::USER_AGENT, hide_default_value = true)]
+    user_agent: String,
+
+    /// Send Danbooru's requests to another site running it, like
+    /// https://testbooru.donmai.us, aibooru.online or one of your own. The saved
+    /// Danbooru login isn't sent to it; searches log in to it with the
+    /// --username and --key given to `waifu dan`
+    #[cfg(feature = "danbooru")]
+    #[arg(long, value_name = "URL")]
+    base_url: Option<reqwest::Url>,
+
+    /// How long downloaded images are kept and reused for, e.g. 12h or 7days
+    #[cfg(feature = "network")]
+    #[arg(long, value_name = "DURATION", default_value = "1day", value_parser = humantime::parse_duration)]
+    cache_ttl: Duration,
+
+    /// Most space cached images can take up, e.g. 200M or 2G. The least
+    /// recently used ones make way for new ones
+    #[cfg(feature = "network")]
+    #[arg(long, value_name = "SIZE", default_value = "500M", value_parser = cache::parse_size)]
+    cache_size: u64,
+
+    /// How long the posts found by a search are kept, so running the same search
+    /// again soon after shows the next of them without asking the API again.
+    /// 0 searches every time
+    #[cfg(any(feature = "danbooru", feature = "safebooru"))]
+    #[arg(long, value_name = "DURATION", default_value = "5m", value_parser = humantime::parse_duration)]
+    search_ttl: Duration,
+
+    /// Always download images and search again instead of reusing cached copies
+    #[cfg(feature = "network")]
+    #[arg(long)]
+    no_cache: bool,
+
+    /// Send this Referer header when downloading images, for sites that won't serve
+    /// them without one. pixiv's is sent for i.pximg.net links already
+    #[cfg(feature = "network")]
+    #[arg(long, value_name = "URL")]
+    referer: Option<String>,
+
+    /// Write the image to stdout as a file instead of drawing it, for piping
+    /// into other programs. Crops, filters and the like are still applied
+    #[arg(long, value_enum, value_name = "FORMAT", conflicts_with = "viewer")]
+    stdout: Option<render::output::Format>,
+
+    /// Don't add the images shown to the history, or keep them for `waifu last`
+    #[cfg(feature = "json")]
+    #[arg(long)]
+    no_history: bool,
+
+    /// When to color the details and history. Auto colors them on a terminal,
+    /// unless NO_COLOR is set
+    #[arg(long, value_enum, value_name = "WHEN", default_value_t = Color::Auto)]
+    color: Color,
+
+    /// How --details and `history show` print a post's details. Json prints
+    /// each as one object on a line, for scripts and status bars
+    #[cfg(feature = "json")]
+    #[arg(long, value_enum, value_name = "FORMAT", default_value_t = Format::Text)]
+    format: Format,
+
+    /// Print post details with a template instead, e.g. '{artist} — {character} ({rating})'.
+    /// It can use {site}, {id}, {source}, {artist}, {character}, {url}, {rating},
+    /// {width}, {height}, {tags} and, in the history, {time}. Images from files
+    /// and URLs have {path}, {url}, {format}, {content_type}, {width}, {height} and {size}
+    #[cfg(feature = "json")]
+    #[arg(long, value_name = "TEMPLATE", conflicts_with_all = ["format", "no_image"])]
+    details_format: Option<String>,
+
+    /// Have --details list the tags by category, colored, with how many posts
+    /// each has, looked up on Danbooru
+    #[cfg(feature = "danbooru")]
+    #[arg(long)]
+    tag_info: bool,
+
+    /// Log what's happening to stderr: -v for requests, timings and the protocol
+    /// used, -vv for everything else
+    #[arg(short, long, action = clap::ArgAction::Count, conflicts_with = "quiet")]
+    verbose: u8,
+
+    /// Write the log to this file instead of stderr, with everything -v logs
+    /// even when it isn't given
+    #[arg(long, value_name = "PATH", value_hint = ValueHint::FilePath)]
+    log_file: Option<PathBuf>,
+
+    /// Keep what was downloaded when it isn't an image, along with the URL it
+    /// came from, in the state directory, e.g. ~/.local/state/waifu/dumps
+    #[cfg(feature = "json")]
+    #[arg(long)]
+    debug_dump: bool,
+
+    /// Leave out hints and messages saying what was done, printing nothing but
+    /// errors to stderr, for scripts and prompts
+    #[arg(short, long)]
+    quiet: bool,
+
+    /// Pass over booru posts the history has from this long ago or less, e.g. 7days,
+    /// searching again when that leaves nothing
+    #[cfg(any(feature = "danbooru", feature = "safebooru"))]
+    #[arg(long, value_name = "DURATION", value_parser = humantime::parse_duration, num_args = 0..=1, require_equals = true, default_missing_value = "30days")]
+    no_repeat: Option<Duration>,
+
+    /// Show the same image all day, picked with a seed made from the date in
+    /// UTC and the search, e.g. for the shell's greeting. The day's picks are
+    /// kept too, so they stay put as new posts come in, and --no-repeat doesn't
+    /// pass them over
+    #[cfg(any(feature = "danbooru", feature = "safebooru"))]
+    #[arg(long, conflicts_with_all = ["dry_run", "seed"])]
+    daily: bool,
+
+    /// Make the random picks with this seed, so the same command picks the
+    /// same images again, as long as the booru or directory has the same ones
+    #[arg(long, value_name = "NUMBER")]
+    seed: Option<u64>,
+
+    /// Print the API URL a booru search would ask for, with API keys masked,
+    /// instead of sending it
+    #[cfg(any(feature = "danbooru", feature = "safebooru"))]
+    #[arg(long, conflicts_with_all = ["stdout", "no_image"])]
+    dry_run: bool,
+
+    /// Print the image URL of the random post instead of showing it, for feeding
+    /// to mpv, feh or a wallpaper setter
+    #[cfg(any(feature = "danbooru", feature = "safebooru"))]
+    #[arg(long, conflicts_with_all = ["stdout", "dry_run", "no_image"])]
+    url_only: bool,
+
+    /// Download the booru's smaller sample of each post rather than the
+    /// original, which is plenty for a terminal and a fraction of the size, for
+    /// slow or metered connections
+    #[cfg(any(feature = "danbooru", feature = "safebooru"))]
+    #[arg(long)]
+    low_bandwidth: bool,
+
+    /// Describe images in plain sentences along with where they're from instead
+    /// of showing them: a post's character, artist, rating, size and first tags,
+    /// or a file's format and size. For screen readers, terminals without
+    /// graphics and logs
+    #[arg(long, conflicts_with_all = ["stdout", "viewer"])]
+    no_image: bool,
+
+    /// Show only the first frame of animated images
+    #[arg(long)]
+    no_animation: bool,
+
+    /// Play animations this many times instead of looping them until interrupted
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    loops: Option<u32>,
+
+    /// Horizontal offset of the image, in terminal cells
+    #[arg(short = 'x', long, default_value_t = 0)]
+    x: u16,
+
+    /// Vertical offset of the image, in terminal cells. Relative to the cursor
+    /// (and allowed to be negative) unless --absolute is passed
+    #[arg(short = 'y', long, default_value_t = 0, allow_negative_numbers = true)]
+    y: i16,
+
+    /// Measure the offsets from the top left corner of the terminal instead of the cursor
+    #[arg(long)]
+    absolute: bool,
+
+    /// Draw the image with braille characters, fitting 2x4 dots in every
+    /// terminal cell for a much finer picture than half blocks
+    #[arg(long, conflicts_with = "ascii")]
+    braille: bool,
+
+    /// Draw the image with plain ASCII characters and no color, for serial
+    /// consoles, logs and terminals without unicode support
+    #[arg(long, conflicts_with = "braille")]
+    ascii: bool,
+
+    /// Draw braille output without color
+    #[arg(long)]
+    monochrome: bool,
+
+    /// Number of colors the terminal can show. Detected from COLORTERM,
+    /// TERM and terminfo when not given
+    #[arg(long, value_enum)]
+    colors: Option<ColorDepth>,
+
+    /// Dithering used when colors are reduced for half block output
+    #[arg(long, value_enum)]
+    dither: Option<Dither>,
+
+    /// Reduce half block output to a palette of this many colors picked from the image
+    #[arg(long, value_parser = clap::value_parser!(u16).range(2..=256))]
+    palette_size: Option<u16>,
+
+    /// Graphics protocol used to draw the image
+    #[arg(long, value_enum, default_value_t = Protocol::Auto)]
+    protocol: Protocol,
+
+    /// Open the image in an external program instead of drawing it in the
+    /// terminal. `{}` is replaced by the image's path, e.g. 'feh --fullscreen {}'
+    #[arg(long, value_name = "COMMAND")]
+    viewer: Option<String>,
+
+    /// Tunnel kitty/iTerm graphics through tmux to the outer terminal.
+    /// NOTE: tmux 3.3 and later need `set -g allow-passthrough on`
+    #[arg(long, value_enum, default_value_t = Passthrough::Auto)]
+    tmux_passthrough: Passthrough,
 
     #[command(subcommand)]
     subcommand: Option<Commands>,
@@ -23,22 +481,167 @@ struct Cli {
 
 #[derive(Subcommand, Debug)]
 enum Commands {
+    #[cfg(feature = "safebooru")]
     #[command(name = "safe")]
     Safebooru(Safebooru),
 
+    #[cfg(feature = "danbooru")]
     #[command(name = "dan")]
-    Danbooru(Danbooru),
+    Danbooru(DanbooruCommand),
+
+    #[cfg(feature = "danbooru")]
+    #[command(name = "char")]
+    Character(Character),
+
+    #[cfg(feature = "danbooru")]
+    #[command(name = "artist")]
+    Artist(Artist),
+
+    #[cfg(feature = "danbooru")]
+    #[command(name = "pool")]
+    Pool(Pool),
 
+    #[cfg(feature = "network")]
     #[command(name = "url")]
     Url(Url),
 
     #[command(name = "file")]
     File(File),
+
+    #[command(name = "dir")]
+    Dir(Dir),
+
+    #[command(name = "play")]
+    Play(Play),
+
+    #[command(name = "palette")]
+    Palette(Palette),
+
+    #[cfg(feature = "danbooru")]
+    #[command(name = "tag")]
+    Tag(Tag),
+
+    /// Print what a tag means, from its Danbooru wiki page
+    #[cfg(feature = "danbooru")]
+    #[command(name = "wiki")]
+    Wiki { tag: String },
+
+    /// List the tags most often found with a tag, to narrow down a search
+    #[cfg(feature = "danbooru")]
+    #[command(name = "related")]
+    Related {
+        tag: String,
+
+        /// How many to list
+        #[arg(short = 'n', long, default_value_t = 20, value_parser = clap::value_parser!(u32).range(1..=1000))]
+        limit: u32,
+    },
+
+    #[cfg(feature = "json")]
+    #[command(name = "history")]
+    History(History),
+
+    #[cfg(feature = "network")]
+    #[command(name = "cache")]
+    Cache(Cache),
+
+    #[cfg(feature = "json")]
+    #[command(name = "fav")]
+    Favorites(Favorites),
+
+    #[cfg(feature = "danbooru")]
+    #[command(name = "vote")]
+    Vote(Vote),
+
+    /// Show the last image again, with the options it had unless others are given
+    #[cfg(feature = "json")]
+    #[command(name = "last")]
+    Last,
+
+    #[cfg(any(feature = "danbooru", feature = "safebooru"))]
+    #[command(name = "motd")]
+    Motd(Motd),
+
+    #[cfg(any(feature = "danbooru", feature = "safebooru"))]
+    #[command(name = "daemon")]
+    Daemon(Daemon),
+
+    #[cfg(any(feature = "danbooru", feature = "safebooru"))]
+    #[command(name = "wallpaper")]
+    Wallpaper(Wallpaper),
+
+    #[cfg(any(feature = "danbooru", feature = "safebooru"))]
+    #[command(name = "serve")]
+    Serve(Serve),
+
+    #[cfg(feature = "network")]
+    #[command(name = "share")]
+    Share(Share),
+
+    #[cfg(feature = "config")]
+    #[command(name = "config")]
+    Config(Config),
+
+    #[cfg(feature = "network")]
+    #[command(name = "auth")]
+    Auth(Auth),
+
+    /// Answer a few questions to make a new config file
+    #[cfg(feature = "config")]
+    #[command(name = "setup")]
+    Setup,
+
+    /// Print what waifu makes of the terminal, whether the boorus can be reached
+    /// and which logins it has, e.g. to find out why images come out as blocks
+    #[command(name = "doctor")]
+    Doctor,
+
+    #[command(name = "completions")]
+    Completions(Completions),
+
+    /// Print a man page for waifu, e.g. `waifu man > /usr/local/share/man/man1/waifu.1`
+    #[command(name = "man")]
+    Man,
+
+    /// Print the Danbooru tags starting with a prefix, for the shell completions
+    #[cfg(feature = "danbooru")]
+    #[command(name = "complete-tag", hide = true)]
+    CompleteTag { prefix: String },
+}
+
+/// When to write colors
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum Color {
+    Auto,
+    Always,
+    Never,
+}
+
+/// How to print post details
+#[cfg(feature = "json")]
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum Format {
+    Text,
+    Json,
+}
+
+impl Color {
+    fn enabled(self) -> bool {
+        match self {
+            Color::Auto => {
+                std::io::stdout().is_terminal()
+                    && std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+            }
+            Color::Always => true,
+            Color::Never => false,
+        }
+    }
 }
 
 /// Look at random images from Safebooru
-#[derive(Args, Debug)]
-pub struct Safebooru {
+#[cfg(feature = "safebooru")]
+#[derive(Args, Debug, Clone)]
+struct Safebooru {
     /// Show data related to image (url, rating, dimensions, tags)
     #[arg(short, long)]
     pub details: bool,
@@ -48,39 +651,131 @@ pub struct Safebooru {
     pub questionable: bool,
 
     /// Search for an image based on Safebooru tags.
-    /// Pass as a string separated by spaces or commas.         
+    /// Pass as a string separated by spaces or commas, or - to read them from stdin.
     /// Look at Safebooru's cheatsheet for a full list of search options
     #[arg(short, long)]
     pub tags: Option<String>,
+
+    /// How many random posts to show, one after another. With --url-only each
+    /// is printed as soon as it's picked, as one line of JSON with --format json
+    #[arg(short = 'n', long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    pub count: u32,
+
+    /// Search again with the closest tags Safebooru has in place of misspelt
+    /// ones, without asking first
+    #[arg(long)]
+    pub fix_tags: bool,
+
+    /// Search with a preset's tags along with any others: scenery, cozy, seasonal
+    /// or chibi, or one of those in the config file
+    #[arg(long, value_name = "NAME")]
+    pub preset: Option<String>,
+
+    /// Show the post with this ID, and its details, instead of a random one
+    #[arg(long, conflicts_with_all = ["tags", "preset", "count", "fix_tags"])]
+    pub id: Option<u32>,
+
+    /// Show the post whose file has this MD5, and its details, to find where an
+    /// image came from. Given a path, the file's MD5 is worked out
+    #[arg(long, value_name = "HASH|PATH", conflicts_with_all = ["tags", "preset", "count", "fix_tags", "id"])]
+    pub md5: Option<String>,
+
+    /// Search this site with the same API instead when Safebooru is down or
+    /// turning requests away, leaving out the posts rated above what's asked
+    /// for. tbib.org unless the config file says otherwise, none for nowhere
+    #[arg(long, value_name = "HOST")]
+    pub mirror: Option<String>,
+
+    #[command(flatten)]
+    pub filters: Filters,
 }
 
 /// Look at random images from Danbooru
-#[derive(Args, Debug)]
-pub struct Danbooru {
+#[cfg(feature = "danbooru")]
+#[derive(Args, Debug, Clone)]
+struct Danbooru {
     /// Show data related to image (artist, source, character, url, rating, dimensions, tags)
     #[arg(short, long)]
     pub details: bool,
 
-    /// Only display images lacking sexual content. Includes lingerie,
-    /// swimsuits, innocent romance, etc. NOTE: this doesn't mean "safe
-    /// for work."
-    #[arg(short, long, conflicts_with_all = ["questionable", "explicit"])]
+    /// Only display images with nothing sexual or suggestive at all, the ones
+    /// to pick for safe for work
+    #[arg(short, long)]
+    pub general: bool,
+
+    /// Only display images with mild sexual content, what Danbooru calls
+    /// sensitive. Includes lingerie, swimsuits, innocent romance, etc. NOTE:
+    /// this doesn't mean "safe for work."
+    #[arg(short, long)]
     pub safe: bool,
 
     /// Only display images with some nox-explicit nudity or sexual content
-    #[arg(short, long, conflicts_with_all = ["safe", "explicit"])]
+    #[arg(short, long)]
     pub questionable: bool,
 
     /// Only display images with explicit sexual content
-    #[arg(short, long, conflicts_with_all = ["safe", "questionable"])]
+    #[arg(short, long)]
     pub explicit: bool,
 
+    /// Only display images with one of these ratings, e.g. g,s. Giving more
+    /// than one of --general, --safe, --questionable and --explicit does the same
+    #[arg(long, value_enum, value_delimiter = ',', value_name = "RATINGS")]
+    pub rating: Vec<config::Rating>,
+
     /// Search for an image based on Danbooru tags.
-    /// Pass as a string separated by spaces or commas.         
+    /// Pass as a string separated by spaces or commas, or - to read them from stdin.
     /// Look at Danbooru's cheatsheet for a full list of search options
     #[arg(short, long)]
     pub tags: Option<String>,
 
+    /// How many random posts to show, one after another. With --url-only each
+    /// is printed as soon as it's picked, as one line of JSON with --format json
+    #[arg(short = 'n', long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    pub count: u32,
+
+    /// Search again with the closest tags Danbooru has in place of misspelt
+    /// ones, without asking first
+    #[arg(long)]
+    pub fix_tags: bool,
+
+    /// Search with a preset's tags along with any others: scenery, cozy, seasonal
+    /// or chibi, or one of those in the config file
+    #[arg(long, value_name = "NAME")]
+    pub preset: Option<String>,
+
+    /// Show the post with this ID, and its details, instead of a random one
+    #[arg(long, conflicts_with_all = ["tags", "preset", "count", "fix_tags"])]
+    pub id: Option<u32>,
+
+    /// Show the post whose file has this MD5, and its details, to find where an
+    /// image came from. Given a path, the file's MD5 is worked out
+    #[arg(long, value_name = "HASH|PATH", conflicts_with_all = ["tags", "preset", "count", "fix_tags", "id"])]
+    pub md5: Option<String>,
+
+    #[command(flatten)]
+    pub filters: Filters,
+
+    /// Without a login Danbooru only searches for 2 tags at once. Over that,
+    /// search for the ones with the fewest posts and check the posts found for
+    /// the rest
+    #[arg(long)]
+    pub narrow_tags: bool,
+
+    /// Print the post's notes below the image, with where on it they are. They're
+    /// mostly translations of the text in it
+    #[arg(long)]
+    pub notes: bool,
+
+    /// Print the newest comments on the post below it, 5 unless given how many.
+    /// They sometimes say where it's from or what's going on in it
+    #[arg(long, value_name = "N", num_args = 0..=1, default_missing_value = "5")]
+    pub comments: Option<u32>,
+
+    /// Pick from the posts a Danbooru user has favorited, e.g. your own, along
+    /// with any tags. Their favorites have to be public
+    #[arg(long, value_name = "USER", conflicts_with_all = ["id", "md5"])]
+    pub from_favorites: Option<String>,
+
     /// Pass your Danbooru username for authentication.
     /// NOTE: This doesn't set a persistent environmental variable and
     /// instead only works for one session
@@ -92,218 +787,5091 @@ pub struct Danbooru {
     /// instead only works for one session
     #[arg(short, long, requires = "username")]
     pub key: Option<String>,
+
+    /// Browse this way instead of searching, from `waifu dan popular` and the like
+    #[arg(skip)]
+    pub explore: Option<danbooru::Explore>,
+}
+
+/// Look at random images from Danbooru
+#[cfg(feature = "danbooru")]
+#[derive(Args, Debug)]
+struct DanbooruCommand {
+    #[command(flatten)]
+    search: Danbooru,
+
+    #[command(subcommand)]
+    explore: Option<Explore>,
+}
+
+/// Other ways to browse Danbooru than a random search
+#[cfg(feature = "danbooru")]
+#[derive(Subcommand, Debug, Clone)]
+enum Explore {
+    /// Show a random one of the posts trending on Danbooru, picked from its
+    /// most popular ones of the day, week or month
+    #[command(name = "popular")]
+    Popular {
+        /// How far back to look for popular posts
+        #[arg(long, value_enum, default_value_t = danbooru::Period::Day)]
+        period: danbooru::Period,
+    },
+}
+
+#[cfg(feature = "danbooru")]
+impl From<Explore> for danbooru::Explore {
+    fn from(explore: Explore) -> Self {
+        match explore {
+            Explore::Popular { period } => danbooru::Explore::Popular { period },
+        }
+    }
 }
 
-/// View an image from a url
+/// View images from urls
+#[cfg(feature = "network")]
 #[derive(Args, Debug)]
 struct Url {
-    /// The URL of an image (e.g. https://i.redd.it/7tycieudz3c61.png)
-    image_url: String,
+    /// The URLs of one or more images (e.g. https://i.redd.it/7tycieudz3c61.png),
+    /// shown in the order given. Danbooru, Safebooru and Gelbooru post pages work too
+    #[arg(required = true)]
+    image_urls: Vec<String>,
+
+    /// Show data related to images from post pages (url, rating, dimensions, tags),
+    /// or for other images the URL they ended up at, their content type, format,
+    /// dimensions and size
+    #[arg(short, long)]
+    details: bool,
+
+    /// Look up where each image came from on SauceNAO, and print the closest
+    /// match's source, artist and how similar it is
+    #[arg(long)]
+    sauce: bool,
+
+    #[command(flatten)]
+    slideshow: Slideshow,
 }
 
-/// View an image from your file system
+/// View images from your file system
 #[derive(Args, Debug)]
 struct File {
-    /// The path to an image file (e.g. ~/Pictures/your-image.jpg)
-    #[arg(value_hint = ValueHint::FilePath)]
-    file_path: PathBuf,
+    /// Paths of one or more image files, or glob patterns matching them
+    /// (e.g. ~/Pictures/your-image.jpg or '~/Pictures/*.png'), shown in order.
+    /// - is the image piped to stdin
+    #[arg(required = true, value_hint = ValueHint::FilePath)]
+    file_paths: Vec<PathBuf>,
+
+    /// Show each image's format, dimensions and file size
+    #[arg(short, long)]
+    details: bool,
+
+    /// Look up where each image came from on SauceNAO, and print the closest
+    /// match's source, artist and how similar it is
+    #[cfg(feature = "network")]
+    #[arg(long)]
+    sauce: bool,
+
+    #[command(flatten)]
+    slideshow: Slideshow,
 }
 
-pub fn run() -> Result<(), Box<dyn Error>> {
-    let args = Cli::parse();
-    let result: Result<(), Box<dyn Error>>;
+/// View a random image from a directory on your file system,
+/// or all of them in shuffled order with --slideshow
+#[derive(Args, Debug)]
+struct Dir {
+    /// The directory to pick from (e.g. ~/Pictures/fanart)
+    #[arg(value_hint = ValueHint::DirPath)]
+    dir_path: PathBuf,
 
-    let Cli { width, height, .. } = args;
+    /// Include images in subdirectories too
+    #[arg(short, long)]
+    recursive: bool,
 
-    let config = viuer::Config {
-        width,
-        height,
-        absolute_offset: false,
-        ..Default::default()
-    };
+    /// Keep watching the directory and show each new image dropped into it
+    #[arg(short, long, conflicts_with = "slideshow")]
+    watch: bool,
 
-    // Read from stdin when data is actually present
-    if !std::io::stdin().is_terminal() {
-        use std::io::{stdin, Read};
-        let mut buf = Vec::new();
-        let _ = stdin().read_to_end(&mut buf)?;
-        if !buf.is_empty() {
-            if buf.len() > MAX_IMAGE_BYTES {
-                return Err(format!(
-                    "Input image too large ({} bytes > {} bytes)",
-                    buf.len(),
-                    MAX_IMAGE_BYTES
-                )
-                .into());
-            }
-            let image = image::load_from_memory(&buf)?;
-            print(&image, &config)?;
-            return Ok(());
-        }
-        // If stdin is empty, fall through to normal subcommand handling
-    }
+    #[command(flatten)]
+    slideshow: Slideshow,
+}
 
-    if let Some(subcommand) = args.subcommand {
-        match subcommand {
-            Commands::Danbooru(args) => {
-                let dan_args = Danbooru { ..args };
-                let dan_args = Commands::Danbooru(dan_args);
-                result = show_random_image(dan_args, config);
-            }
-            Commands::Safebooru(args) => {
-                let safe_args = Safebooru { ..args };
-                let safe_args = Commands::Safebooru(safe_args);
-                result = show_random_image(safe_args, config);
-            }
-            Commands::File(file) => {
-                result = show_image_with_path(file.file_path, config);
-            }
-            Commands::Url(url) => {
-                result = show_image_with_url(url.image_url, config);
-            }
-        };
-    } else {
-        let default_options = Safebooru {
-            details: false,
-            questionable: false,
-            tags: None,
-        };
+/// Show every image in a list of URLs and paths, one per line
+#[derive(Args, Debug)]
+struct Play {
+    /// File with the list, or - to read it from stdin. Empty lines and ones
+    /// starting with # are skipped
+    #[arg(value_hint = ValueHint::FilePath)]
+    list: PathBuf,
+
+    #[command(flatten)]
+    slideshow: Slideshow,
+}
+
+/// Show an image straight from the cache and pick the next one in the background,
+/// so it can go in your shell's startup file without slowing it down
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+#[derive(Args, Debug)]
+struct Motd {
+    /// Where the next images come from, Safebooru unless it's given
+    #[command(subcommand)]
+    source: Option<Source>,
+}
 
-        let default = Commands::Safebooru(default_options);
+/// Keep a few images from a search downloaded, so `waifu safe` or `waifu dan` with
+/// the same search shows one straight away
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+#[derive(Args, Debug)]
+struct Daemon {
+    /// How many images to keep ready
+    #[arg(short = 'n', long, default_value_t = 5, value_parser = clap::value_parser!(u32).range(1..))]
+    pool: u32,
 
-        result = show_random_image(default, config);
-    }
+    /// How often to check whether more are needed, e.g. 30s or 5m
+    #[arg(short, long, default_value = "1m", value_parser = humantime::parse_duration)]
+    interval: Duration,
 
-    result
+    /// Where the images come from, Safebooru unless it's given
+    #[command(subcommand)]
+    source: Option<Source>,
 }
 
-fn show_random_image(args: Commands, config: viuer::Config) -> Result<(), Box<dyn Error>> {
-    use crate::api::{danbooru, safebooru};
+/// Set a random image as the desktop wallpaper, with swaymsg, gsettings, swaybg
+/// or feh on Linux, and the system's own settings on macOS and Windows
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+#[derive(Args, Debug)]
+struct Wallpaper {
+    /// Only set it on this monitor, e.g. DP-1 with sway or swaybg, or the
+    /// number of the display on macOS
+    #[arg(long, value_name = "NAME")]
+    monitor: Option<String>,
 
-    let image_url = match args {
-        Commands::Danbooru(args) => danbooru::grab_random_image(args),
-        Commands::Safebooru(args) => safebooru::grab_random_image(args),
-        _ => panic!(
-            "Invalid subcommand passed to show_random_image. \
-                Only valid ones are 'Danbooru' and 'Safebooru'."
-        ),
-    };
+    /// Make it the lock screen image too, in swaylock's config file, GNOME's
+    /// settings or Windows' lock screen. macOS shows the wallpaper there already
+    #[arg(long)]
+    lockscreen: bool,
+
+    /// Keep running, setting another every so often, e.g. 30m or 1h
+    #[arg(long, value_name = "INTERVAL", value_parser = humantime::parse_duration)]
+    rotate: Option<Duration>,
+
+    /// With --rotate, set another only when the last has been up that long and
+    /// then exit, to run from cron or a timer instead
+    #[arg(long, requires = "rotate")]
+    once: bool,
+
+    /// List the wallpapers set before, newest first
+    #[arg(long, conflicts_with_all = ["rotate", "again"])]
+    list: bool,
+
+    /// Set the wallpaper numbered this by --list again
+    #[arg(long, value_name = "N", conflicts_with = "rotate", value_parser = clap::value_parser!(u32).range(1..))]
+    again: Option<u32>,
 
-    show_image_with_url(image_url, config)
+    /// Where the image comes from, Safebooru unless it's given
+    #[command(subcommand)]
+    source: Option<Source>,
 }
 
-fn show_image_with_url(image_url: String, config: viuer::Config) -> Result<(), Box<dyn Error>> {
-    use reqwest::blocking::Client;
-    use reqwest::header;
-    use std::fs::File;
-    use std::io::Write;
-    use std::time::Duration;
-
-    let client = Client::builder()
-        .connect_timeout(Duration::from_secs(10))
-        .timeout(Duration::from_secs(20))
-        .build()?;
-
-    // Simple retry for transient errors
-    #[allow(unused_assignments)]
-    let mut last_err: Option<String> = None;
-    let bytes = {
-        let mut attempts = 0;
-        loop {
-            attempts += 1;
-            let resp = client.get(&image_url).send();
-            match resp {
-                Ok(resp) => {
-                    let status = resp.status();
-                    let ct = resp
-                        .headers()
-                        .get(header::CONTENT_TYPE)
-                        .and_then(|v| v.to_str().ok())
-                        .unwrap_or("")
-                        .to_string();
-
-                    if !status.is_success() || (!ct.is_empty() && !ct.starts_with("image/")) {
-                        let mut path = std::env::temp_dir();
-                        path.push("waifu_fetch_error.bin");
-                        if let Ok(mut f) = File::create(&path) {
-                            if let Ok(buf) = resp.bytes() {
-                                let _ = f.write_all(&buf);
-                            }
-                        }
-                        return Err(format!(
-                            "Failed to fetch image: HTTP {} (content-type: {}). Saved bytes to {}",
-                            status,
-                            if ct.is_empty() { "unknown" } else { &ct },
-                            path.display()
-                        )
-                        .into());
-                    }
+/// Serve a web page with a random image that changes every so often, and its
+/// details as JSON at /post.json, for devices without a terminal to show it on
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+#[derive(Args, Debug)]
+struct Serve {
+    /// The port to listen on
+    #[arg(short, long, default_value_t = 8080)]
+    port: u16,
 
-                    if let Some(len) = resp.headers().get(header::CONTENT_LENGTH) {
-                        if let Some(len) = len.to_str().ok().and_then(|s| s.parse::<usize>().ok()) {
-                            if len > MAX_IMAGE_BYTES {
-                                return Err(format!(
-                                    "Image too large ({} bytes > {} bytes)",
-                                    len, MAX_IMAGE_BYTES
-                                )
-                                .into());
-                            }
-                        }
-                    }
+    /// The address to listen on. The default keeps it to this computer, and
+    /// 0.0.0.0 lets other devices on the network get to it
+    #[arg(long, default_value = "127.0.0.1")]
+    bind: std::net::IpAddr,
 
-                    let body = resp.bytes()?;
-                    if body.len() > MAX_IMAGE_BYTES {
-                        return Err(format!(
-                            "Image too large ({} bytes > {} bytes)",
-                            body.len(),
-                            MAX_IMAGE_BYTES
-                        )
-                        .into());
-                    }
-                    break body;
-                }
-                Err(e) => {
-                    last_err = Some(e.to_string());
-                    if attempts >= 3 {
-                        return Err(format!(
-                            "Failed to fetch image after {} attempts: {}",
-                            attempts,
-                            last_err.unwrap_or_else(|| "unknown error".into())
-                        )
-                        .into());
-                    }
-                    std::thread::sleep(std::time::Duration::from_millis(200 * attempts as u64));
-                }
-            }
-        }
-    };
+    /// How often to pick another, e.g. 30s or 5m, which the page reloads after too
+    #[arg(short, long, default_value = "1m", value_parser = humantime::parse_duration)]
+    interval: Duration,
 
-    let image = match image::load_from_memory(&bytes) {
-        Ok(img) => img,
-        Err(e) => {
-            let mut path = std::env::temp_dir();
-            path.push("waifu_fetch_error.bin");
-            if let Ok(mut f) = File::create(&path) {
-                let _ = f.write_all(&bytes);
-            }
-            return Err(format!(
-                "Failed to decode image: {}. Saved bytes to {}",
-                e,
-                path.display()
-            )
-            .into());
-        }
-    };
+    /// Where the images come from, Safebooru unless it's given
+    #[command(subcommand)]
+    source: Option<Source>,
+}
 
-    print(&image, &config)?;
+/// A booru to pick images from. Everything that shows random posts takes one
+/// of these rather than Commands, so there's no other command to turn away
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+#[derive(Subcommand, Debug, Clone)]
+enum Source {
+    #[cfg(feature = "safebooru")]
+    #[command(name = "safe")]
+    Safebooru(Safebooru),
 
-    Ok(())
+    #[cfg(feature = "danbooru")]
+    #[command(name = "dan")]
+    Danbooru(Danbooru),
 }
 
-fn show_image_with_path(image_path: PathBuf, config: viuer::Config) -> Result<(), Box<dyn Error>> {
-    print_from_file(image_path, &config)?;
+/// What a booru post has to be like to be picked, besides the tags searched for
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+#[derive(Args, Debug, Clone, Default)]
+struct Filters {
+    /// Leave out posts with any of these tags, separated by spaces or commas.
+    /// Aliases work here too
+    #[arg(short = 'x', long, value_name = "TAGS")]
+    pub exclude: Option<String>,
 
-    Ok(())
+    /// Only pick posts with at least one of these tags, separated by spaces or
+    /// commas, along with any others searched for
+    #[arg(long, value_name = "TAGS")]
+    pub any_of: Option<String>,
+
+    /// Only pick posts scored at least this high, to skew toward well-received art
+    #[arg(long, value_name = "N", allow_negative_numbers = true)]
+    pub min_score: Option<i32>,
+
+    /// Only pick images at least this many pixels wide, e.g. 1920 for wallpapers
+    #[arg(long, value_name = "PIXELS")]
+    pub min_width: Option<u32>,
+
+    /// Only pick images at least this many pixels high
+    #[arg(long, value_name = "PIXELS")]
+    pub min_height: Option<u32>,
+
+    /// Only pick images of this shape
+    #[arg(long, value_enum)]
+    pub orientation: Option<Orientation>,
+
+    /// Only pick files this big at most, e.g. 5M, to spare a slow connection.
+    /// Safebooru doesn't say how big its files are, so this is for Danbooru
+    #[arg(long, value_name = "SIZE", value_parser = cache::parse_size)]
+    pub max_filesize: Option<u64>,
+
+    /// Only pick files of this type
+    #[arg(long, value_enum)]
+    pub filetype: Option<FileType>,
 }
 
-// Removed old stdin helper; stdin is handled inline in run()
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+impl From<&Filters> for api::Filters {
+    fn from(filters: &Filters) -> Self {
+        api::Filters {
+            exclude: filters.exclude.clone(),
+            any_of: filters.any_of.clone(),
+            min_score: filters.min_score,
+            min_width: filters.min_width,
+            min_height: filters.min_height,
+            orientation: filters.orientation,
+            max_filesize: filters.max_filesize,
+            filetype: filters.filetype,
+        }
+    }
+}
+
+/// How to go through several images
+#[derive(Args, Debug)]
+struct Slideshow {
+    /// Show the images one at a time, clearing the terminal in between
+    #[arg(short = 's', long = "slideshow", id = "slideshow")]
+    enabled: bool,
+
+    /// How long each image stays up in a slideshow, e.g. 30s or 2m
+    #[arg(short, long, default_value = "10s", value_parser = humantime::parse_duration, requires = "slideshow")]
+    interval: Duration,
+
+    /// Start over once a slideshow has been through every image
+    #[arg(long, requires = "slideshow")]
+    repeat: bool,
+}
+
+/// Look at random images of a character from Danbooru, found by part of their
+/// name, e.g. koishi for komeiji_koishi
+#[cfg(feature = "danbooru")]
+#[derive(Args, Debug)]
+struct Character {
+    /// The character's name, or some of it
+    name: String,
+
+    #[command(flatten)]
+    search: Danbooru,
+}
+
+/// Print an artist's profile from Danbooru, found by part of their name, and
+/// show a random work of theirs
+#[cfg(feature = "danbooru")]
+#[derive(Args, Debug)]
+struct Artist {
+    /// The artist's name, or some of it
+    name: String,
+
+    #[command(flatten)]
+    search: Danbooru,
+}
+
+/// Look at a Danbooru pool, like a comic or a set of images, by its ID or name.
+/// Prints what the pool is and shows one of its pages at random
+#[cfg(feature = "danbooru")]
+#[derive(Args, Debug)]
+struct Pool {
+    /// The pool's ID, or some of its name
+    pool: String,
+
+    /// Show data related to the page's image
+    #[arg(short, long)]
+    details: bool,
+
+    /// Save every image in the pool to this directory, in order, instead of
+    /// showing one
+    #[arg(long, value_name = "DIR", value_hint = ValueHint::DirPath)]
+    download: Option<PathBuf>,
+}
+
+/// Look up Danbooru's tags, to find the right one before searching
+#[cfg(feature = "danbooru")]
+#[derive(Args, Debug)]
+struct Tag {
+    #[command(subcommand)]
+    action: TagAction,
+}
+
+#[cfg(feature = "danbooru")]
+#[derive(Subcommand, Debug)]
+enum TagAction {
+    /// List the tags matching a pattern with their categories and post counts,
+    /// the most used first
+    Search {
+        /// Part of a tag, or a pattern with * for anything, e.g. 'hatsune*'
+        pattern: String,
+
+        /// How many to list
+        #[arg(short = 'n', long, default_value_t = 20, value_parser = clap::value_parser!(u32).range(1..=1000))]
+        limit: u32,
+    },
+}
+
+/// List the images you've been shown, or show one of them again
+#[cfg(feature = "json")]
+#[derive(Args, Debug)]
+struct History {
+    #[command(subcommand)]
+    action: Option<HistoryAction>,
+}
+
+#[cfg(feature = "json")]
+#[derive(Subcommand, Debug)]
+enum HistoryAction {
+    /// List the latest images, newest first (the default)
+    List {
+        /// How many to list
+        #[arg(short = 'n', long, default_value_t = 20)]
+        limit: usize,
+    },
+
+    /// Print an image's details and show it again
+    Show {
+        /// Which image, counting back from 1 for the latest
+        #[arg(value_parser = clap::value_parser!(u32).range(1..))]
+        n: u32,
+    },
+
+    /// Count the artists, characters, tags and sites seen most often
+    Stats {
+        /// How many of each to list
+        #[arg(short = 'n', long, default_value_t = 10)]
+        top: usize,
+    },
+
+    /// Write out the whole history, oldest first, to look at or back up elsewhere
+    Export {
+        #[arg(short, long, value_enum, default_value_t = history::ExportFormat::Json)]
+        format: history::ExportFormat,
+
+        /// Write it to a file instead of printing it
+        #[arg(short, long, value_hint = ValueHint::FilePath)]
+        output: Option<PathBuf>,
+    },
+
+    /// Forget every image in the history
+    Clear,
+}
+
+/// Keep images you liked, to find and show them again
+#[cfg(feature = "json")]
+#[derive(Args, Debug)]
+struct Favorites {
+    #[command(subcommand)]
+    action: Option<FavoritesAction>,
+}
+
+/// Vote a Danbooru post you were shown up or down, with the login from
+/// `waifu auth login dan`
+#[cfg(feature = "danbooru")]
+#[derive(Args, Debug)]
+#[command(group(clap::ArgGroup::new("way").required(true).args(["upvote", "downvote"])))]
+struct Vote {
+    /// Which image of the history, counting back from 1 for the latest
+    #[arg(default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    n: u32,
+
+    /// Vote the post up
+    #[arg(long)]
+    upvote: bool,
+
+    /// Vote the post down
+    #[arg(long)]
+    downvote: bool,
+}
+
+#[cfg(feature = "json")]
+#[derive(Subcommand, Debug)]
+enum FavoritesAction {
+    /// Add the image that was just shown, or one further back in the history
+    Add {
+        /// Which image of the history, counting back from 1 for the latest
+        #[arg(default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+        n: u32,
+
+        /// Favorite the post on Danbooru too, with the login from `waifu auth login dan`
+        #[cfg(feature = "danbooru")]
+        #[arg(long)]
+        remote: bool,
+    },
+
+    /// List the favorites, newest first (the default)
+    List,
+
+    /// Print a favorite's details and show it
+    Show {
+        /// Which favorite, counting back from 1 for the newest
+        #[arg(value_parser = clap::value_parser!(u32).range(1..))]
+        n: u32,
+    },
+
+    /// Take an image out of the favorites
+    Remove {
+        /// Which favorite, counting back from 1 for the newest
+        #[arg(value_parser = clap::value_parser!(u32).range(1..))]
+        n: u32,
+
+        /// Take the post out of your favorites on Danbooru too
+        #[cfg(feature = "danbooru")]
+        #[arg(long)]
+        remote: bool,
+    },
+}
+
+/// Look at or change the config file without editing it by hand
+#[cfg(feature = "config")]
+#[derive(Args, Debug)]
+struct Config {
+    #[command(subcommand)]
+    action: ConfigAction,
+}
+
+#[cfg(feature = "config")]
+#[derive(Subcommand, Debug)]
+enum ConfigAction {
+    /// Print the value of a key, e.g. default.tags
+    Get { key: String },
+
+    /// Set a key, e.g. `waifu config set default.tags "scenery"`
+    Set { key: String, value: String },
+
+    /// Print where the config file is
+    Path,
+}
+
+/// Keep logins for the boorus and SauceNAO in the system keyring rather than
+/// in environment variables
+#[cfg(feature = "network")]
+#[derive(Args, Debug)]
+struct Auth {
+    #[command(subcommand)]
+    action: AuthAction,
+}
+
+#[cfg(feature = "network")]
+#[derive(Subcommand, Debug)]
+enum AuthAction {
+    /// Ask for a username and API key, and save them in the keyring
+    Login { site: auth::Site },
+
+    /// Remove a saved login from the keyring
+    Logout { site: auth::Site },
+}
+
+/// Print a script that tab completes waifu's options and subcommands, e.g.
+/// `waifu completions bash > ~/.local/share/bash-completion/completions/waifu`
+#[derive(Args, Debug)]
+struct Completions {
+    shell: clap_complete::Shell,
+}
+
+/// Look at how much space cached images take up, or remove them
+#[cfg(feature = "network")]
+#[derive(Args, Debug)]
+struct Cache {
+    #[command(subcommand)]
+    action: Option<CacheAction>,
+}
+
+#[cfg(feature = "network")]
+#[derive(Subcommand, Debug)]
+enum CacheAction {
+    /// Print the number and size of cached images (the default)
+    Stats,
+
+    /// Remove images older than --cache-ttl, then the least recently used ones
+    /// until the cache fits in --cache-size
+    Prune,
+
+    /// Remove every cached image
+    Clear,
+}
+
+/// Upload the last image shown, or another one, and print the link to it
+#[cfg(feature = "network")]
+#[derive(Args, Debug)]
+struct Share {
+    /// Path or URL of the image to upload instead
+    #[arg(value_hint = ValueHint::AnyPath)]
+    image: Option<String>,
+
+    /// Where to upload it. Imgur takes an app's client ID, from
+    /// `waifu auth login imgur`
+    #[arg(long, value_enum, default_value_t = upload::Host::Catbox)]
+    to: upload::Host,
+}
+
+/// Show an image and print its dominant colors, for theming your terminal after it
+#[derive(Args, Debug)]
+struct Palette {
+    /// Path or URL of the image. A random Safebooru image is used when left out
+    #[arg(value_hint = ValueHint::AnyPath)]
+    source: Option<String>,
+
+    /// Number of colors to extract
+    #[arg(short = 'n', long, default_value_t = 16, value_parser = clap::value_parser!(u16).range(2..=256))]
+    size: u16,
+
+    /// How to print the palette
+    #[arg(short, long, value_enum, default_value_t = palette::Format::Swatches)]
+    format: palette::Format,
+
+    /// Write the palette to a file instead of printing it
+    #[arg(short, long, value_hint = ValueHint::FilePath)]
+    output: Option<PathBuf>,
+}
+
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+/// Whether --quiet was given, so only errors are printed
+pub fn quiet() -> bool {
+    QUIET.load(Ordering::Relaxed)
+}
+
+#[cfg(feature = "json")]
+static DEBUG_DUMP: AtomicBool = AtomicBool::new(false);
+
+/// Whether --debug-dump was given, so downloads that aren't images are kept
+#[cfg(feature = "json")]
+fn debug_dump() -> bool {
+    DEBUG_DUMP.load(Ordering::Relaxed)
+}
+
+#[cfg(feature = "json")]
+static JSON: AtomicBool = AtomicBool::new(false);
+
+/// Whether --format json was given, so details are printed as JSON
+#[cfg(feature = "json")]
+pub fn json() -> bool {
+    JSON.load(Ordering::Relaxed)
+}
+
+#[cfg(feature = "danbooru")]
+static TAG_INFO: AtomicBool = AtomicBool::new(false);
+
+/// Whether --tag-info was given, so details have each tag's category and post count
+#[cfg(feature = "danbooru")]
+pub fn tag_info() -> bool {
+    TAG_INFO.load(Ordering::Relaxed)
+}
+
+#[cfg(feature = "json")]
+static DETAILS_FORMAT: OnceLock<String> = OnceLock::new();
+
+/// The template --details-format gave for printing details
+#[cfg(feature = "json")]
+pub fn details_format() -> Option<&'static str> {
+    DETAILS_FORMAT.get().map(String::as_str)
+}
+
+/// Whether details are printed as --format json or --details-format ask rather than as text
+#[cfg(feature = "json")]
+fn formatted_details() -> bool {
+    json() || details_format().is_some()
+}
+
+static NO_IMAGE: AtomicBool = AtomicBool::new(false);
+
+/// Whether --no-image was given, so images are described in sentences rather
+/// than drawn
+pub fn no_image() -> bool {
+    NO_IMAGE.load(Ordering::Relaxed)
+}
+
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+static LOW_BANDWIDTH: AtomicBool = AtomicBool::new(false);
+
+/// Whether --low-bandwidth was given, so posts point at the boorus' smaller
+/// samples of their files
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+fn low_bandwidth() -> bool {
+    LOW_BANDWIDTH.load(Ordering::Relaxed)
+}
+
+#[cfg(feature = "danbooru")]
+static DANBOORU_URL: OnceLock<String> = OnceLock::new();
+
+/// Where Danbooru is, the site --base-url gave or else Danbooru itself
+#[cfg(feature = "danbooru")]
+pub fn danbooru_url() -> &'static str {
+    DANBOORU_URL
+        .get()
+        .map_or(danbooru::DANBOORU, String::as_str)
+}
+
+/// Whether --url-only prints the details instead of the URL, which they have in them
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+fn url_in_details() -> bool {
+    formatted_details() || no_image()
+}
+
+/// Log to stderr at the level -v asks for, or to the log file
+fn start_logging(verbose: u8, log_file: Option<&Path>) -> Result<(), WaifuError> {
+    use std::sync::Arc;
+    use tracing::Level;
+    use tracing_subscriber::prelude::*;
+
+    let level = match (verbose, log_file) {
+        (0, None) => return Ok(()),
+        (0 | 1, _) => Level::INFO,
+        (2, _) => Level::DEBUG,
+        _ => Level::TRACE,
+    };
+    // Only waifu's own logs, not those of everything it uses
+    let filter = tracing_subscriber::filter::Targets::new().with_target("waifu", level);
+    let logs = tracing_subscriber::fmt().with_max_level(level);
+
+    match log_file {
+        Some(path) => {
+            let file = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .map_err(|e| format!("Couldn't open the log file {}: {}", path.display(), e))?;
+            logs.with_writer(Arc::new(file))
+                .with_ansi(false)
+                .finish()
+                .with(filter)
+                .init();
+        }
+        None => logs
+            .with_writer(std::io::stderr)
+            .with_ansi(std::io::stderr().is_terminal())
+            .finish()
+            .with(filter)
+            .init(),
+    }
+
+    Ok(())
+}
+
+/// Print a message saying what was done, unless --quiet was given
+fn tell(message: impl std::fmt::Display) {
+    if !quiet() {
+        println!("{}", message);
+    }
+}
+
+pub fn run() -> Result<(), WaifuError> {
+    // Only changed once the setup or `waifu last` have something else
+    #[cfg_attr(not(feature = "config"), allow(unused_mut))]
+    let (mut config_file, mut loaded) = load_config();
+    #[cfg_attr(not(feature = "json"), allow(unused_mut))]
+    let mut args = parse(std::env::args_os(), &config_file)?.unwrap_or_else(|e| exit::usage(e));
+    // Logging can only be started once, and is wanted for the setup too
+    start_logging(args.verbose, args.log_file.as_deref())?;
+    #[cfg(feature = "config")]
+    if first_run(&args)? {
+        if setup(true)? {
+            (config_file, loaded) = load_config();
+            args = parse(std::env::args_os(), &config_file)?.unwrap_or_else(|e| exit::usage(e));
+        } else {
+            config::create(&[])?;
+        }
+    }
+    // A broken config file can still be fixed with `waifu config`
+    #[cfg(feature = "config")]
+    let loaded = match args.subcommand {
+        Some(Commands::Config(_)) => Ok(()),
+        _ => loaded,
+    };
+    loaded?;
+    let result: Result<(), WaifuError>;
+
+    #[cfg(feature = "json")]
+    let last = match args.subcommand {
+        Some(Commands::Last) => {
+            let last = history::load_last()?.ok_or("No image has been shown yet")?;
+            // Nothing but `waifu last` means the options from last time
+            if std::env::args_os().len() == 2 {
+                let argv = std::iter::once("waifu".into()).chain(last.args.iter().map(Into::into));
+                args = parse(argv, &config_file)?.map_err(|e| {
+                    format!(
+                        "Couldn't reuse the options the image was shown with, pass them again: {}",
+                        e
+                    )
+                })?;
+            }
+            Some(last)
+        }
+        _ => None,
+    };
+
+    store_options(&args, &config_file)?;
+    #[cfg(feature = "network")]
+    configure_net(&args)?;
+
+    let Cli {
+        width,
+        height,
+        fit,
+        pixel_width,
+        pixel_height,
+        scale,
+        max_width,
+        max_height,
+        no_upscale,
+        center,
+        cell_aspect,
+        crop,
+        crop_center,
+        filter,
+        brightness,
+        contrast,
+        saturation,
+        background,
+        censor,
+        caption,
+        qr,
+        copy,
+        open,
+        #[cfg(feature = "network")]
+        ref webhook,
+        download_via_hook,
+        ref upscale_cmd,
+        ref poster_cmd,
+        #[cfg(feature = "network")]
+        ref referer,
+        #[cfg(feature = "network")]
+        cache_ttl,
+        #[cfg(feature = "network")]
+        cache_size,
+        #[cfg(feature = "network")]
+        no_cache,
+        stdout,
+        #[cfg(feature = "json")]
+        no_history,
+        #[cfg(any(feature = "danbooru", feature = "safebooru"))]
+        dry_run,
+        #[cfg(any(feature = "danbooru", feature = "safebooru"))]
+        url_only,
+        #[cfg(any(feature = "danbooru", feature = "safebooru"))]
+        no_image,
+        #[cfg(any(feature = "danbooru", feature = "safebooru"))]
+        no_repeat,
+        #[cfg(any(feature = "danbooru", feature = "safebooru"))]
+        daily,
+        seed,
+        no_animation,
+        loops,
+        x,
+        y,
+        absolute,
+        braille,
+        ascii,
+        monochrome,
+        colors,
+        dither,
+        palette_size,
+        protocol,
+        tmux_passthrough,
+        ref viewer,
+        ..
+    } = args;
+
+    if absolute && y < 0 {
+        return Err("The vertical offset can't be negative with --absolute".into());
+    }
+    if let Some(seed) = seed {
+        random::seed(seed);
+    }
+
+    let colors = colors.unwrap_or_else(render::detect_colors);
+
+    let (term_w, term_h) = viuer::terminal_size();
+    let width = width.map(|w| w.resolve(term_w as u32));
+    let height = height.map(|h| h.resolve(term_h as u32));
+
+    let mut config = viuer::Config {
+        width,
+        height,
+        x,
+        y,
+        absolute_offset: absolute,
+        transparent: background == Some(Background::Terminal),
+        truecolor: colors == ColorDepth::Truecolor,
+        ..Default::default()
+    };
+    render::configure(&mut config, protocol);
+
+    let renderer = if braille {
+        Renderer::Braille
+    } else if ascii {
+        Renderer::Ascii
+    } else {
+        Renderer::Viuer
+    };
+
+    let download_cmd =
+        match (download_via_hook, &config_file.downloader_cmd) {
+            (false, _) => None,
+            (true, Some(command)) => Some(command.clone()),
+            (true, None) => return Err(
+                "--download-via-hook runs the downloader_cmd from the config file, which isn't set"
+                    .into(),
+            ),
+        };
+
+    let mut settings = Settings {
+        config,
+        renderer,
+        monochrome,
+        colors,
+        dither,
+        palette_size,
+        protocol,
+        tmux_passthrough,
+        viewer: viewer.clone(),
+        fit,
+        center,
+        max_width: max_width.map(|w| w.resolve(term_w as u32)),
+        max_height: max_height.map(|h| h.resolve(term_h as u32)),
+        no_upscale,
+        scale,
+        pixel_width,
+        pixel_height,
+        crop: crop.or(crop_center),
+        cell_aspect,
+        brightness,
+        contrast,
+        saturation,
+        filters: filter,
+        background,
+        censor,
+        caption,
+        qr,
+        copy,
+        open,
+        #[cfg(feature = "network")]
+        webhook: webhook.clone(),
+        download_cmd,
+        upscale_cmd: upscale_cmd.clone(),
+        poster_cmd: poster_cmd.clone(),
+        #[cfg(feature = "network")]
+        referer: referer.clone(),
+        #[cfg(feature = "network")]
+        cache_ttl: (!no_cache).then_some(cache_ttl),
+        #[cfg(feature = "network")]
+        cache_size,
+        stdout,
+        #[cfg(feature = "json")]
+        history: !no_history,
+        #[cfg(any(feature = "danbooru", feature = "safebooru"))]
+        dry_run,
+        #[cfg(any(feature = "danbooru", feature = "safebooru"))]
+        url_only: url_only || no_image,
+        #[cfg(any(feature = "danbooru", feature = "safebooru"))]
+        no_repeat,
+        #[cfg(any(feature = "danbooru", feature = "safebooru"))]
+        daily,
+        animate: !no_animation,
+        loops,
+    };
+
+    #[cfg(feature = "json")]
+    if let Some(last) = last {
+        // Showing it again doesn't make it any newer
+        settings.history = false;
+        return show_last(last, &settings);
+    }
+
+    // Details and palettes are printed to stdout too, and would end up in the image data,
+    // as would more than one image
+    let text_on_stdout = match &args.subcommand {
+        #[cfg(feature = "danbooru")]
+        Some(Commands::Danbooru(DanbooruCommand { search: args, .. })) => {
+            args.details
+                || args.notes
+                || args.comments.is_some()
+                || args.count > 1
+                || args.id.is_some()
+                || args.md5.is_some()
+        }
+        #[cfg(feature = "danbooru")]
+        Some(Commands::Character(args)) => {
+            let search = &args.search;
+            search.details || search.notes || search.comments.is_some() || search.count > 1
+        }
+        // The profile's printed first
+        #[cfg(feature = "danbooru")]
+        Some(Commands::Artist(_)) => true,
+        #[cfg(feature = "danbooru")]
+        Some(Commands::Pool(_)) => true,
+        #[cfg(feature = "safebooru")]
+        Some(Commands::Safebooru(args)) => {
+            args.details || args.count > 1 || args.id.is_some() || args.md5.is_some()
+        }
+        #[cfg(feature = "network")]
+        Some(Commands::Url(args)) => args.details || args.sauce,
+        #[cfg(feature = "network")]
+        Some(Commands::File(args)) => args.details || args.sauce,
+        #[cfg(not(feature = "network"))]
+        Some(Commands::File(args)) => args.details,
+        Some(Commands::Palette(args)) => args.output.is_none(),
+        #[cfg(feature = "danbooru")]
+        Some(Commands::Tag(_)) => true,
+        #[cfg(feature = "danbooru")]
+        Some(Commands::Wiki { .. }) => true,
+        #[cfg(feature = "danbooru")]
+        Some(Commands::Related { .. }) => true,
+        #[cfg(feature = "json")]
+        Some(Commands::History(_)) => true,
+        #[cfg(feature = "json")]
+        Some(Commands::Favorites(_)) => true,
+        #[cfg(feature = "danbooru")]
+        Some(Commands::Vote(_)) => true,
+        #[cfg(feature = "config")]
+        Some(Commands::Config(_)) => true,
+        #[cfg(feature = "network")]
+        Some(Commands::Auth(_)) => true,
+        #[cfg(feature = "config")]
+        Some(Commands::Setup) => true,
+        Some(Commands::Doctor) => true,
+        Some(Commands::Completions(_)) => true,
+        #[cfg(feature = "danbooru")]
+        Some(Commands::CompleteTag { .. }) => true,
+        Some(Commands::Man) => true,
+        #[cfg(feature = "network")]
+        Some(Commands::Cache(_)) => true,
+        _ => false,
+    };
+    if text_on_stdout && settings.stdout.is_some() {
+        return Err(
+            "--stdout can't be combined with --details or -n, or with palette unless it has --output"
+                .into(),
+        );
+    }
+
+    // Read from stdin when data is actually present, and meant to be an image
+    let stdin_taken = match &args.subcommand {
+        Some(Commands::Play(play)) => play.list == Path::new("-"),
+        #[cfg(feature = "danbooru")]
+        Some(Commands::Danbooru(DanbooruCommand {
+            search: Danbooru { tags, .. },
+            ..
+        }))
+        | Some(Commands::Character(Character {
+            search: Danbooru { tags, .. },
+            ..
+        }))
+        | Some(Commands::Artist(Artist {
+            search: Danbooru { tags, .. },
+            ..
+        })) => tags.as_deref() == Some("-"),
+        #[cfg(feature = "safebooru")]
+        Some(Commands::Safebooru(Safebooru { tags, .. })) => tags.as_deref() == Some("-"),
+        // Waiting on stdin would hold up the shell it starts with
+        #[cfg(any(feature = "danbooru", feature = "safebooru"))]
+        Some(Commands::Motd(_)) | Some(Commands::Daemon(_)) => true,
+        #[cfg(feature = "danbooru")]
+        Some(Commands::CompleteTag { .. }) => true,
+        Some(Commands::Completions(_)) | Some(Commands::Man) => true,
+        // The answers are typed in there
+        #[cfg(feature = "network")]
+        Some(Commands::Auth(_)) => true,
+        #[cfg(feature = "config")]
+        Some(Commands::Setup) => true,
+        _ => false,
+    };
+    if !stdin_taken && !std::io::stdin().is_terminal() {
+        use std::io::{stdin, Read};
+        let mut buf = Vec::new();
+        let _ = stdin().read_to_end(&mut buf)?;
+        if !buf.is_empty() {
+            if buf.len() > MAX_IMAGE_BYTES {
+                return Err(format!(
+                    "Input image too large ({} bytes > {} bytes)",
+                    buf.len(),
+                    MAX_IMAGE_BYTES
+                )
+                .into());
+            }
+            if let Some(Commands::File(File { details: true, .. })) = &args.subcommand {
+                ImageDetails::of(&buf)?.print()?;
+            }
+            if describe_instead(&buf, ImageDetails::default())? {
+                return Ok(());
+            }
+            buf = poster_frame(buf, &settings)?;
+            if let Some(command) = &settings.upscale_cmd {
+                buf = upscale::run(command, &buf)?;
+            }
+            if let Some(viewer) = &settings.viewer {
+                return render::viewer::open_bytes(viewer, &buf);
+            }
+            return print_bytes(&buf, &settings);
+        }
+        // If stdin is empty, fall through to normal subcommand handling
+    }
+
+    if let Some(subcommand) = args.subcommand {
+        match subcommand {
+            #[cfg(feature = "danbooru")]
+            Commands::Danbooru(DanbooruCommand {
+                search: mut args,
+                explore,
+            }) => {
+                let searched = args.tags.is_some()
+                    || args.preset.is_some()
+                    || args.from_favorites.is_some()
+                    || args.filters.exclude.is_some()
+                    || args.filters.any_of.is_some();
+                if explore.is_some() && (searched || args.id.is_some() || args.md5.is_some()) {
+                    return Err(
+                        "Popular posts can't be searched with --tags, --preset, --exclude, \
+                                --any-of or --from-favorites, or picked with --id or --md5"
+                            .into(),
+                    );
+                }
+                args.explore = explore.map(Into::into);
+                args.with_defaults(&config_file.default);
+                args.tags = config_file.expand_aliases(tags_from_stdin(args.tags)?)?;
+                args.filters.exclude = config_file.expand_aliases(args.filters.exclude)?;
+                args.filters.any_of = config_file.expand_aliases(args.filters.any_of)?;
+                args.tags = config_file.apply_preset(
+                    args.preset.as_deref(),
+                    config::Source::Dan,
+                    args.tags,
+                )?;
+                result = show_random_image(Source::Danbooru(args), &settings);
+            }
+            #[cfg(feature = "danbooru")]
+            Commands::Character(Character { name, search }) => {
+                result = show_tagged(&name, "character", search, &config_file, &settings);
+            }
+            #[cfg(feature = "danbooru")]
+            Commands::Artist(Artist { name, search }) => {
+                result = show_tagged(&name, "artist", search, &config_file, &settings);
+            }
+            #[cfg(feature = "danbooru")]
+            Commands::Pool(args) => {
+                result = show_pool(args, &settings);
+            }
+            #[cfg(feature = "safebooru")]
+            Commands::Safebooru(mut args) => {
+                args.with_defaults(&config_file.default);
+                args.tags = config_file.expand_aliases(tags_from_stdin(args.tags)?)?;
+                args.filters.exclude = config_file.expand_aliases(args.filters.exclude)?;
+                args.filters.any_of = config_file.expand_aliases(args.filters.any_of)?;
+                args.tags = config_file.apply_preset(
+                    args.preset.as_deref(),
+                    config::Source::Safe,
+                    args.tags,
+                )?;
+                result = show_random_image(Source::Safebooru(args), &settings);
+            }
+            Commands::File(file) => {
+                result = local::expand(&file.file_paths).and_then(|image_paths| {
+                    if image_paths.len() > 1 || file.slideshow.enabled {
+                        play_once(&mut settings);
+                    }
+                    show_images_with_paths(image_paths, &file, &settings)
+                });
+            }
+            Commands::Play(play) => {
+                play_once(&mut settings);
+                result = show_images_from_list(play, &settings);
+            }
+            Commands::Dir(dir) => {
+                if dir.slideshow.enabled || dir.watch {
+                    play_once(&mut settings);
+                }
+                result = show_image_from_dir(dir, &settings);
+            }
+            #[cfg(feature = "network")]
+            Commands::Url(url) => {
+                if url.image_urls.len() > 1 || url.slideshow.enabled {
+                    play_once(&mut settings);
+                }
+                result = show_images_with_urls(url, &settings);
+            }
+            Commands::Palette(args) => {
+                result = show_palette(args, &settings);
+            }
+            #[cfg(feature = "danbooru")]
+            Commands::Tag(args) => {
+                result = manage_tags(args);
+            }
+            #[cfg(feature = "danbooru")]
+            Commands::Wiki { tag } => {
+                result = show_wiki(&tag);
+            }
+            #[cfg(feature = "danbooru")]
+            Commands::Related { tag, limit } => {
+                result = show_related(&tag, limit as usize);
+            }
+            #[cfg(feature = "json")]
+            Commands::History(args) => {
+                result = show_history(args, &settings);
+            }
+            #[cfg(feature = "network")]
+            Commands::Cache(args) => {
+                result = manage_cache(args, cache_ttl, cache_size);
+            }
+            #[cfg(feature = "json")]
+            Commands::Favorites(args) => {
+                result = manage_favorites(args, &settings);
+            }
+            #[cfg(feature = "danbooru")]
+            Commands::Vote(args) => {
+                result = vote(args);
+            }
+            #[cfg(feature = "config")]
+            Commands::Config(args) => {
+                result = manage_config(args);
+            }
+            #[cfg(feature = "network")]
+            Commands::Auth(args) => {
+                result = manage_auth(args);
+            }
+            Commands::Doctor => {
+                result = doctor::run(&settings, &config_file);
+            }
+            #[cfg(feature = "config")]
+            Commands::Setup => {
+                result = setup(false).map(|_| ());
+            }
+            Commands::Completions(args) => {
+                result = print_completions(args.shell);
+            }
+            Commands::Man => {
+                result = print_man_page();
+            }
+            #[cfg(feature = "danbooru")]
+            Commands::CompleteTag { prefix } => {
+                result = danbooru_api()
+                    .and_then(|api| api.complete_tag(&prefix))
+                    .map(|tags| {
+                        for tag in tags {
+                            println!("{}", tag);
+                        }
+                    });
+            }
+            #[cfg(any(feature = "danbooru", feature = "safebooru"))]
+            Commands::Motd(args) => {
+                let source = Source::or_default(args.source, &config_file)?;
+                result = show_motd(source, &settings);
+            }
+            #[cfg(any(feature = "danbooru", feature = "safebooru"))]
+            Commands::Daemon(mut args) => {
+                let source = Source::or_default(args.source.take(), &config_file)?;
+                result = run_daemon(args, source, &settings);
+            }
+            #[cfg(any(feature = "danbooru", feature = "safebooru"))]
+            Commands::Wallpaper(mut args) => {
+                let source = Source::or_default(args.source.take(), &config_file)?;
+                result = set_wallpaper(args, source, &settings);
+            }
+            #[cfg(any(feature = "danbooru", feature = "safebooru"))]
+            Commands::Serve(mut args) => {
+                let source = Source::or_default(args.source.take(), &config_file)?;
+                result = serve_images(args, source, &settings);
+            }
+            #[cfg(feature = "network")]
+            Commands::Share(args) => {
+                result = upload_image(args, &settings);
+            }
+            #[cfg(feature = "json")]
+            Commands::Last => unreachable!("`waifu last` is handled before anything else"),
+        };
+    } else {
+        result = show_default(&config_file, &settings);
+    }
+
+    result
+}
+
+/// A random image from the booru the config file has as the default
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+fn show_default(config: &config::Config, settings: &Settings) -> Result<(), WaifuError> {
+    let default = Source::or_default(None, config)?;
+    show_random_image(default, settings)
+}
+
+// There's nowhere to pick one from without a booru
+#[cfg(not(any(feature = "danbooru", feature = "safebooru")))]
+fn show_default(_: &config::Config, _: &Settings) -> Result<(), WaifuError> {
+    Err(
+        "waifu was built without any boorus to pick random images from, so show a file with \
+         `waifu file` or pipe one in instead"
+            .into(),
+    )
+}
+
+/// Set up the client everything's downloaded with from the options given for it
+#[cfg(feature = "network")]
+fn configure_net(args: &Cli) -> Result<(), WaifuError> {
+    let cookies = match &args.cookie_file {
+        Some(path) => Some(std::sync::Arc::new(net::load_cookies(path)?)),
+        None => None,
+    };
+    net::configure(net::Options {
+        proxy: args.proxy.clone(),
+        headers: args.headers.clone(),
+        cookies,
+        ca_certs: match &args.cacert {
+            Some(path) => net::load_certs(path)?,
+            None => Vec::new(),
+        },
+        insecure: args.insecure,
+        timeout: args.timeout,
+        retries: args.retries,
+        backoff: args.backoff,
+        rate_limit: args.rate_limit,
+        user_agent: args.user_agent.clone(),
+        #[cfg(any(feature = "danbooru", feature = "safebooru"))]
+        revalidate: !args.no_cache,
+        #[cfg(any(feature = "danbooru", feature = "safebooru"))]
+        search_ttl: if args.no_cache {
+            Duration::ZERO
+        } else {
+            args.search_ttl
+        },
+    });
+    #[cfg(feature = "danbooru")]
+    if let Some(url) = &args.base_url {
+        let _ = DANBOORU_URL.set(url.as_str().trim_end_matches('/').to_string());
+    }
+
+    Ok(())
+}
+
+/// Keep the options the rest of the run goes by. Only done once they're settled,
+/// after the setup and `waifu last` may have parsed them again
+fn store_options(args: &Cli, config_file: &config::Config) -> Result<(), WaifuError> {
+    QUIET.store(args.quiet, Ordering::Relaxed);
+    #[cfg(feature = "json")]
+    DEBUG_DUMP.store(args.debug_dump, Ordering::Relaxed);
+    #[cfg(feature = "json")]
+    JSON.store(args.format == Format::Json, Ordering::Relaxed);
+    #[cfg(feature = "danbooru")]
+    TAG_INFO.store(args.tag_info, Ordering::Relaxed);
+    NO_IMAGE.store(args.no_image, Ordering::Relaxed);
+    #[cfg(any(feature = "danbooru", feature = "safebooru"))]
+    LOW_BANDWIDTH.store(args.low_bandwidth, Ordering::Relaxed);
+    #[cfg(feature = "json")]
+    if let Some(template) = &args.details_format {
+        check_template(template)?;
+        DETAILS_FORMAT.get_or_init(|| template.clone());
+    }
+    colored::control::set_override(args.color.enabled());
+    theme::configure(&config_file.theme);
+
+    Ok(())
+}
+
+/// Parse the command line, with the options from the config file going first and
+/// those from the environment next, so the ones given on the command line win
+fn parse(
+    argv: impl IntoIterator<Item = OsString>,
+    config: &config::Config,
+) -> Result<Result<Cli, clap::Error>, WaifuError> {
+    use clap::CommandFactory;
+
+    let mut argv = argv.into_iter();
+    let argv = argv
+        .next()
+        .into_iter()
+        .chain(config.args()?)
+        .chain(config::env_args(&Cli::command())?)
+        .chain(argv);
+
+    Ok(Cli::try_parse_from(argv))
+}
+
+#[cfg(feature = "safebooru")]
+impl Default for Safebooru {
+    /// A search for anything, like `waifu safe` alone
+    fn default() -> Self {
+        Safebooru {
+            details: false,
+            questionable: false,
+            tags: None,
+            count: 1,
+            fix_tags: false,
+            preset: None,
+            id: None,
+            md5: None,
+            mirror: None,
+            filters: Filters::default(),
+        }
+    }
+}
+
+#[cfg(feature = "safebooru")]
+impl Safebooru {
+    /// Search with the tags and rating from the config file, unless this
+    /// search gives its own or the config is for Danbooru. The mirror is
+    /// Safebooru's own setting, so it's taken either way
+    fn with_defaults(&mut self, defaults: &config::Defaults) {
+        if self.mirror.is_none() {
+            self.mirror = defaults.mirror.clone();
+        }
+        if defaults.source.unwrap_or(config::Source::Safe) != config::Source::Safe {
+            return;
+        }
+        if self.tags.is_none() && self.preset.is_none() {
+            self.tags = defaults.tags.clone();
+        }
+        // Safebooru has nothing explicit, and everything else is safe
+        if defaults.rating == Some(config::Rating::Questionable) {
+            self.questionable = true;
+        }
+    }
+
+    /// What the API is asked to search for
+    fn search(&self) -> safebooru::RandomSearch {
+        safebooru::RandomSearch {
+            tags: self.tags.clone(),
+            questionable: self.questionable,
+            filters: (&self.filters).into(),
+        }
+    }
+
+    /// Where to search when Safebooru doesn't answer, if anywhere
+    fn mirror(&self) -> Option<&str> {
+        match self.mirror.as_deref() {
+            None => Some(safebooru::MIRROR),
+            Some("none" | "") => None,
+            Some(host) => Some(host),
+        }
+    }
+}
+
+/// The API of Safebooru, or of another site running the same software, with
+/// the network options given. Sites other than Safebooru get the Gelbooru login
+#[cfg(feature = "safebooru")]
+fn safebooru_api(host: &str) -> Result<safebooru::Api, WaifuError> {
+    let mut web = safebooru::Web::with_client(net::client()?.clone())
+        .with_retry(net::retry())
+        .with_cache(net::revalidate(), net::search_ttl());
+    if host != safebooru::SAFEBOORU {
+        if let auth::Credentials {
+            api_key: Some(key),
+            user_id: Some(user),
+            ..
+        } = auth::credentials(auth::Site::Gelbooru)
+        {
+            web = web.with_api_key(&user, &key);
+        }
+    }
+
+    Ok(safebooru::Api::with_http(host, web).with_samples(low_bandwidth()))
+}
+
+/// Do what `attempt` does on Safebooru, and on the mirror instead when
+/// Safebooru doesn't answer, saying so with what it's `doing` there
+#[cfg(feature = "safebooru")]
+fn or_mirror<T>(
+    mirror: Option<&str>,
+    doing: &str,
+    attempt: impl Fn(&safebooru::Api) -> Result<T, WaifuError>,
+) -> Result<T, WaifuError> {
+    match (attempt(&safebooru_api(safebooru::SAFEBOORU)?), mirror) {
+        (Err(error), Some(mirror)) if safebooru::unavailable(&error) => {
+            if !quiet() {
+                eprintln!("{}\n{} {} instead", error, doing, mirror);
+            }
+            attempt(&safebooru_api(mirror)?)
+        }
+        (result, _) => result,
+    }
+}
+
+/// Pick a random post from Safebooru, or from the mirror when Safebooru doesn't
+/// answer. When nothing is found `ask` is asked whether to search for the tags
+/// corrected instead
+#[cfg(feature = "safebooru")]
+fn pick_safebooru_post(
+    args: &Safebooru,
+    videos: bool,
+    shown: &BTreeSet<u32>,
+    ask: impl Fn(&str) -> bool,
+) -> Result<Post, WaifuError> {
+    let mut search = args.search();
+    loop {
+        let picked = or_mirror(args.mirror(), "Searching", |api| {
+            api.grab_random_image(&search, videos, shown)
+        });
+        let Err(error @ WaifuError::NoResults(_)) = picked else {
+            return picked;
+        };
+        let complete =
+            |prefix: &str| or_mirror(args.mirror(), "Trying", |api| api.complete_tag(prefix));
+        match correct_tags(search.tags.as_deref(), args.fix_tags, complete, &ask) {
+            Some(tags) => search.tags = Some(tags),
+            None => return Err(error),
+        }
+    }
+}
+
+/// Look up a post on Safebooru by its ID, for `waifu safe --id`. The mirror
+/// numbers its posts its own way, so it can't stand in for Safebooru here
+#[cfg(feature = "safebooru")]
+fn grab_safebooru_post(args: &Safebooru, id: u32) -> Result<Post, WaifuError> {
+    let post = safebooru_api(safebooru::SAFEBOORU)?.grab_post(id);
+    post.map_err(|error| match args.mirror() {
+        Some(mirror) if safebooru::unavailable(&error) => {
+            let help = format!(
+                "{} numbers its posts differently, so look the image up there by its --md5 instead",
+                mirror
+            );
+            exit::with_help(error, help)
+        }
+        _ => error,
+    })
+}
+
+/// Look up a post on Safebooru by the MD5 of its file, for `waifu safe --md5`,
+/// or on the mirror when Safebooru doesn't answer
+#[cfg(feature = "safebooru")]
+fn find_safebooru_md5(args: &Safebooru, md5: &str) -> Result<Post, WaifuError> {
+    or_mirror(args.mirror(), "Trying", |api| api.grab_by_md5(md5))
+}
+
+#[cfg(feature = "danbooru")]
+impl Default for Danbooru {
+    /// A search for anything, like `waifu dan` alone
+    fn default() -> Self {
+        Danbooru {
+            details: false,
+            general: false,
+            safe: false,
+            questionable: false,
+            explicit: false,
+            rating: Vec::new(),
+            tags: None,
+            count: 1,
+            fix_tags: false,
+            preset: None,
+            id: None,
+            md5: None,
+            filters: Filters::default(),
+            narrow_tags: false,
+            notes: false,
+            comments: None,
+            from_favorites: None,
+            username: None,
+            key: None,
+            explore: None,
+        }
+    }
+}
+
+#[cfg(feature = "danbooru")]
+impl Danbooru {
+    /// Danbooru's API, logged in with the --username and --key given, or else
+    /// the saved login
+    fn api(&self) -> Result<danbooru::Api, WaifuError> {
+        match self.username.clone().zip(self.key.clone()) {
+            Some(login) => danbooru_as(Some(login)),
+            None => danbooru_api(),
+        }
+    }
+
+    /// What the API is asked to search for
+    fn search(&self) -> danbooru::RandomSearch {
+        danbooru::RandomSearch {
+            tags: self.tags.clone(),
+            ratings: self.ratings(),
+            filters: (&self.filters).into(),
+            narrow_tags: self.narrow_tags,
+            from_favorites: self.from_favorites.clone(),
+            explore: self.explore.clone(),
+        }
+    }
+
+    /// Search with the tags and rating from the config file, unless this
+    /// search gives its own or the config is for Safebooru
+    fn with_defaults(&mut self, defaults: &config::Defaults) {
+        if defaults.source != Some(config::Source::Dan) {
+            return;
+        }
+        if self.tags.is_none() && self.preset.is_none() && self.explore.is_none() {
+            self.tags = defaults.tags.clone();
+        }
+        if self.ratings().is_empty() {
+            self.rating.extend(defaults.rating);
+        }
+    }
+
+    /// The ratings asked for, in order from general to explicit
+    pub fn ratings(&self) -> Vec<config::Rating> {
+        let flags = [
+            (self.general, config::Rating::General),
+            (self.safe, config::Rating::Sensitive),
+            (self.questionable, config::Rating::Questionable),
+            (self.explicit, config::Rating::Explicit),
+        ];
+        let mut ratings: Vec<config::Rating> = flags
+            .into_iter()
+            .filter(|(given, _)| *given)
+            .map(|(_, rating)| rating)
+            .chain(self.rating.iter().copied())
+            .collect();
+        ratings.sort();
+        ratings.dedup();
+        ratings
+    }
+}
+
+/// Danbooru's API at the site --base-url gave, with the network options and
+/// the saved login. The login is only ever sent to Danbooru itself
+#[cfg(feature = "danbooru")]
+fn danbooru_api() -> Result<danbooru::Api, WaifuError> {
+    if danbooru_url() != danbooru::DANBOORU {
+        return danbooru_as(None);
+    }
+    let login = auth::credentials(auth::Site::Danbooru);
+
+    danbooru_as(login.username.zip(login.api_key))
+}
+
+/// Danbooru's API at the site --base-url gave, logged in as the user with
+/// their API key when there's one
+#[cfg(feature = "danbooru")]
+fn danbooru_as(login: Option<(String, String)>) -> Result<danbooru::Api, WaifuError> {
+    let mut web = danbooru::Web::with_client(net::client()?.clone())
+        .with_retry(net::retry())
+        .with_cache(net::revalidate(), net::search_ttl());
+    if let Some((username, api_key)) = login {
+        web = web.with_login(&username, &api_key);
+    }
+
+    Ok(danbooru::Api::with_http(web)
+        .at(danbooru_url())
+        .with_samples(low_bandwidth()))
+}
+
+/// Pick a random post from Danbooru. When nothing is found `ask` is asked
+/// whether to search for the tags corrected instead
+#[cfg(feature = "danbooru")]
+fn pick_danbooru_post(
+    args: &Danbooru,
+    videos: bool,
+    shown: &BTreeSet<u32>,
+    ask: impl Fn(&str) -> bool,
+) -> Result<Post, WaifuError> {
+    let api = args.api()?;
+    let mut search = args.search();
+    loop {
+        let picked = api.grab_random_image(&search, videos, shown);
+        let Err(error @ WaifuError::NoResults(_)) = picked else {
+            return picked;
+        };
+        let complete = |prefix: &str| api.complete_tag(prefix);
+        match correct_tags(search.tags.as_deref(), args.fix_tags, complete, &ask) {
+            Some(tags) => search.tags = Some(tags),
+            None => return Err(error),
+        }
+    }
+}
+
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+impl Source {
+    /// The search given, or else the one the config file has as the default,
+    /// with its aliases expanded
+    fn or_default(source: Option<Source>, config: &config::Config) -> Result<Source, WaifuError> {
+        let defaults = &config.default;
+        let mut source = source.unwrap_or(match defaults.source {
+            #[cfg(feature = "danbooru")]
+            Some(config::Source::Dan) => Source::Danbooru(Danbooru::default()),
+            #[cfg(feature = "safebooru")]
+            _ => Source::Safebooru(Safebooru::default()),
+            #[cfg(not(feature = "safebooru"))]
+            _ => Source::Danbooru(Danbooru::default()),
+        });
+        match &mut source {
+            #[cfg(feature = "safebooru")]
+            Source::Safebooru(args) => {
+                args.with_defaults(defaults);
+                let tags = config.expand_aliases(args.tags.take())?;
+                args.filters.exclude = config.expand_aliases(args.filters.exclude.take())?;
+                args.filters.any_of = config.expand_aliases(args.filters.any_of.take())?;
+                args.tags =
+                    config.apply_preset(args.preset.as_deref(), config::Source::Safe, tags)?;
+            }
+            #[cfg(feature = "danbooru")]
+            Source::Danbooru(args) => {
+                args.with_defaults(defaults);
+                let tags = config.expand_aliases(args.tags.take())?;
+                args.filters.exclude = config.expand_aliases(args.filters.exclude.take())?;
+                args.filters.any_of = config.expand_aliases(args.filters.any_of.take())?;
+                args.tags =
+                    config.apply_preset(args.preset.as_deref(), config::Source::Dan, tags)?;
+            }
+        }
+
+        Ok(source)
+    }
+
+    /// The booru's name, as the history has it
+    fn site(&self) -> &'static str {
+        match self {
+            #[cfg(feature = "danbooru")]
+            Source::Danbooru(_) => "danbooru",
+            #[cfg(feature = "safebooru")]
+            Source::Safebooru(_) => "safebooru",
+        }
+    }
+}
+
+/// Swap `-t -` for the tags piped in on stdin, which can span several lines
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+fn tags_from_stdin(tags: Option<String>) -> Result<Option<String>, WaifuError> {
+    use std::io::Read;
+
+    if tags.as_deref() != Some("-") {
+        return Ok(tags);
+    }
+
+    let mut text = String::new();
+    std::io::stdin()
+        .read_to_string(&mut text)
+        .map_err(|e| format!("Couldn't read tags from stdin: {}", e))?;
+    let tags = text.split_whitespace().collect::<Vec<_>>().join(" ");
+
+    Ok((!tags.is_empty()).then_some(tags))
+}
+
+/// Show a random image with the character or artist tag the name matches, along
+/// with any other tags given. An artist's profile is printed first
+#[cfg(feature = "danbooru")]
+fn show_tagged(
+    name: &str,
+    category: &str,
+    mut args: Danbooru,
+    config: &config::Config,
+    settings: &Settings,
+) -> Result<(), WaifuError> {
+    let tag = danbooru_api()?.resolve_tag(name, category)?;
+    if category == "artist" && !settings.url_only {
+        print_artist_profile(&tag.name)?;
+    } else if !quiet() && tag.name != name {
+        eprintln!("Showing {}", tag.name);
+    }
+
+    let tags = config.expand_aliases(tags_from_stdin(args.tags.take())?)?;
+    let tags = config.apply_preset(args.preset.as_deref(), config::Source::Dan, tags)?;
+    args.filters.exclude = config.expand_aliases(args.filters.exclude.take())?;
+    args.filters.any_of = config.expand_aliases(args.filters.any_of.take())?;
+    args.tags = Some(match tags {
+        Some(tags) => format!("{} {}", tag.name, tags),
+        None => tag.name,
+    });
+    args.with_defaults(&config.default);
+    show_random_image(Source::Danbooru(args), settings)
+}
+
+#[cfg(feature = "danbooru")]
+fn show_pool(args: Pool, settings: &Settings) -> Result<(), WaifuError> {
+    use rand::Rng;
+
+    let pool = danbooru_api()?.find_pool(&args.pool)?;
+    if json() {
+        println!("{}", serde_json::to_string(&pool)?);
+    } else {
+        let name = pool.name.replace('_', " ");
+        println!("📚 {}: {}", theme::label("Pool"), name);
+        println!(
+            "🗂️ {}: {}, {} posts",
+            theme::label("Kind"),
+            pool.category,
+            pool.post_ids.len()
+        );
+        println!(
+            "✉️ {}: {}/pools/{}",
+            theme::label("Link"),
+            danbooru_url(),
+            pool.id
+        );
+        if !pool.description.is_empty() {
+            println!("\n{}\n", pool.description);
+        }
+    }
+
+    if let Some(dir) = args.download {
+        std::fs::create_dir_all(&dir)
+            .map_err(|e| format!("Couldn't make {}: {}", dir.display(), e))?;
+        let posts = danbooru_api()?.pool_posts(&pool)?;
+        let digits = posts.len().to_string().len();
+        for (i, post) in posts.iter().enumerate() {
+            let bytes = download(&post.url, settings)?;
+            let extension = post
+                .url
+                .rsplit('/')
+                .next()
+                .and_then(|name| name.rsplit_once('.'))
+                .map_or("jpg", |(_, extension)| extension);
+            let name = format!("{:0digits$}_{}.{}", i + 1, post.id, extension);
+            let path = dir.join(&name);
+            std::fs::write(&path, bytes)
+                .map_err(|e| format!("Couldn't save {}: {}", path.display(), e))?;
+            tell(format!("Saved {} ({} of {})", name, i + 1, posts.len()));
+        }
+        return Ok(());
+    }
+
+    if pool.post_ids.is_empty() {
+        return Err(WaifuError::NoResults("The pool has no posts".into()));
+    }
+    let page = random::rng().gen_range(0..pool.post_ids.len());
+    let id = pool.post_ids[page];
+    if !formatted_details() {
+        println!(
+            "📄 {}: {} of {}",
+            theme::label("Page"),
+            page + 1,
+            pool.post_ids.len()
+        );
+    }
+    let post = danbooru_api()?.grab_post(id)?;
+    if args.details {
+        PostDetails::from(&post).print()?;
+    }
+    show_image_with_url(&post.url, settings, Some(&post))?;
+    remember(Entry::from_post(&post), settings);
+
+    Ok(())
+}
+
+#[cfg(feature = "danbooru")]
+fn print_artist_profile(tag: &str) -> Result<(), WaifuError> {
+    let profile = danbooru_api()?.artist_profile(tag)?;
+    if json() {
+        println!("{}", serde_json::to_string(&profile)?);
+        return Ok(());
+    }
+
+    println!("🎨 {}: {}", theme::label("Artist"), tag);
+    let Some(profile) = profile else {
+        return Ok(());
+    };
+    if !profile.other_names.is_empty() {
+        println!(
+            "🪪 {}: {}",
+            theme::label("Also known as"),
+            profile.other_names.join(", ")
+        );
+    }
+    if let Some(group) = &profile.group_name {
+        println!("👥 {}: {}", theme::label("Group"), group);
+    }
+    for url in &profile.urls {
+        println!("🔗 {}: {}", theme::label("Link"), url);
+    }
+
+    Ok(())
+}
+
+/// Print "did you mean" for the tags a search found nothing for, and the tags
+/// to search for again when they look like typos of ones the booru has. That's
+/// without asking with `fix`, otherwise only when `ask` says yes to the
+/// question. Nothing when the booru can't be asked
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+fn correct_tags(
+    tags: Option<&str>,
+    fix: bool,
+    complete: impl Fn(&str) -> Result<Vec<String>, WaifuError>,
+    ask: impl Fn(&str) -> bool,
+) -> Option<String> {
+    use colored::Colorize;
+
+    let tags = tags?;
+    if quiet() && !fix {
+        return None;
+    }
+    let suggested = match api::suggest_tags(tags, complete) {
+        Ok(suggested) => suggested,
+        Err(error) => {
+            tracing::debug!("Couldn't look up tags to suggest: {}", error);
+            return None;
+        }
+    };
+
+    if !quiet() {
+        for suggestion in &suggested {
+            eprintln!(
+                "{}: Nothing is tagged {}. Did you mean: {}?",
+                "help".green(),
+                suggestion.tag,
+                suggestion.known.join(", ")
+            );
+        }
+    }
+
+    let corrections: Vec<_> = suggested
+        .iter()
+        .filter_map(|suggestion| Some((&suggestion.tag, suggestion.correction.as_ref()?)))
+        .collect();
+    if corrections.is_empty() {
+        return None;
+    }
+    let corrected = tags
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|tag| !tag.is_empty())
+        .map(|tag| {
+            let name = tag.trim_start_matches('-');
+            match corrections.iter().find(|(typo, _)| *typo == name) {
+                Some((_, correction)) => {
+                    format!("{}{}", &tag[..tag.len() - name.len()], correction)
+                }
+                None => tag.to_string(),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    if fix {
+        if !quiet() {
+            eprintln!("Searching for {} instead\n", corrected);
+        }
+        return Some(corrected);
+    }
+    ask(&format!("Search for {} instead?", corrected)).then_some(corrected)
+}
+
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+fn show_random_image(args: Source, settings: &Settings) -> Result<(), WaifuError> {
+    if settings.dry_run {
+        let url = match &args {
+            #[cfg(feature = "danbooru")]
+            Source::Danbooru(Danbooru { id: Some(id), .. }) => {
+                format!("{}/posts/{}.json", danbooru_url(), id)
+            }
+            #[cfg(feature = "safebooru")]
+            Source::Safebooru(Safebooru { id: Some(id), .. }) => format!(
+                "https://{}/index.php?page=dapi&s=post&q=index&json=1&id={}",
+                safebooru::SAFEBOORU,
+                id
+            ),
+            #[cfg(feature = "danbooru")]
+            Source::Danbooru(Danbooru { md5: Some(md5), .. }) => {
+                format!("{}/posts.json?tags=md5:{}", danbooru_url(), md5_of(md5)?)
+            }
+            #[cfg(feature = "safebooru")]
+            Source::Safebooru(Safebooru { md5: Some(md5), .. }) => format!(
+                "https://{}/index.php?page=dapi&s=post&q=index&json=1&tags=md5:{}",
+                safebooru::SAFEBOORU,
+                md5_of(md5)?
+            ),
+            #[cfg(feature = "danbooru")]
+            Source::Danbooru(args) => args.api()?.search_url(&args.search()),
+            // Each tag of --any-of is searched for on its own
+            #[cfg(feature = "safebooru")]
+            Source::Safebooru(args) => {
+                let search = safebooru_api(safebooru::SAFEBOORU)?.search_url(&args.search());
+                safebooru::search_urls(&search).join("\n")
+            }
+        };
+        println!("{}", crate::api::mask_credentials(&url));
+        return Ok(());
+    }
+
+    let (count, site) = match &args {
+        #[cfg(feature = "danbooru")]
+        Source::Danbooru(args) => (args.count, "danbooru"),
+        #[cfg(feature = "safebooru")]
+        Source::Safebooru(args) => (args.count, "safebooru"),
+    };
+    // Along with the ones --no-repeat leaves out, so -n never shows a post twice.
+    // The day's posts are shown however recently they were
+    let mut shown = if settings.daily {
+        BTreeSet::new()
+    } else {
+        recently_shown(site, settings)
+    };
+    let mut daily = settings.daily.then(|| start_daily(&args)).transpose()?;
+    // The next post is picked and downloaded while this one is drawn, unless
+    // there's something printed about it that would come out in between
+    let quiet_posts = match &args {
+        #[cfg(feature = "danbooru")]
+        Source::Danbooru(args) => !args.details && !args.notes && args.comments.is_none(),
+        #[cfg(feature = "safebooru")]
+        Source::Safebooru(args) => !args.details,
+    };
+    let mut next = None;
+    for i in 0..count {
+        let pick_next = quiet_posts && !settings.url_only && i + 1 < count;
+        let kept = daily
+            .as_ref()
+            .and_then(|daily| daily.posts.get(i as usize))
+            .and_then(|entry| entry.id);
+        let entry = match kept {
+            Some(id) => show_kept(&args, id, settings)?,
+            None => {
+                let entry = show_random_post(&args, settings, &shown, &mut next, pick_next)?;
+                if let (Some(daily), Some(entry)) = (&mut daily, &entry) {
+                    daily.keep(entry);
+                }
+                entry
+            }
+        };
+        if let Some(id) = entry.and_then(|entry| entry.id) {
+            #[cfg(feature = "danbooru")]
+            #[cfg_attr(not(feature = "safebooru"), allow(irrefutable_let_patterns))]
+            if let Source::Danbooru(args) = &args {
+                if args.notes {
+                    print_notes(id)?;
+                }
+                if let Some(newest) = args.comments {
+                    print_comments(id, newest)?;
+                }
+            }
+            shown.insert(id);
+        }
+    }
+
+    Ok(())
+}
+
+/// Seed the picks with the day and the search, and find the posts picked for
+/// them already today
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+fn start_daily(args: &Source) -> Result<history::Daily, WaifuError> {
+    // Logins don't change what's found, and aren't written down
+    let search = crate::api::mask_credentials(&search_of(args)?);
+    let day = humantime::format_rfc3339(std::time::SystemTime::now()).to_string();
+    let day = &day[..10];
+    random::seed(random::seed_from(&format!("{} {}", day, search)));
+
+    Ok(history::Daily::load(day, &search).unwrap_or_else(|error| {
+        eprintln!("Couldn't read the images picked today: {}", error);
+        history::Daily::new(day, &search)
+    }))
+}
+
+/// Show a post `--daily` picked earlier in the day. It's looked up again, as
+/// its details may be printed
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+fn show_kept(args: &Source, id: u32, settings: &Settings) -> Result<Option<Entry>, WaifuError> {
+    let details = settings.url_only && url_in_details();
+    let post = match args {
+        #[cfg(feature = "danbooru")]
+        Source::Danbooru(_) => danbooru_api()?.grab_post(id)?,
+        #[cfg(feature = "safebooru")]
+        Source::Safebooru(args) => grab_safebooru_post(args, id)?,
+    };
+    if prints_details(args) || details {
+        PostDetails::from(&post).print()?;
+    }
+    let entry = Entry::from_post(&post);
+    if settings.url_only {
+        if !details {
+            println!("{}", post.url);
+        }
+        return Ok(Some(entry));
+    }
+
+    show_image_with_url(&post.url, settings, Some(&post))?;
+    remember(entry.clone(), settings);
+    Ok(Some(entry))
+}
+
+/// The API URL the arguments search with, which stands for the search
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+fn search_of(args: &Source) -> Result<String, WaifuError> {
+    Ok(match args {
+        #[cfg(feature = "danbooru")]
+        Source::Danbooru(args) => args.api()?.search_url(&args.search()),
+        #[cfg(feature = "safebooru")]
+        Source::Safebooru(args) => safebooru_api(safebooru::SAFEBOORU)?.search_url(&args.search()),
+    })
+}
+
+/// Print the notes on a Danbooru post, with where they are on the image
+#[cfg(feature = "danbooru")]
+fn print_notes(id: u32) -> Result<(), WaifuError> {
+    let notes = danbooru_api()?.notes(id)?;
+    if json() {
+        println!("{}", serde_json::to_string(&notes)?);
+        return Ok(());
+    }
+    if notes.is_empty() {
+        tell("The post has no notes");
+    }
+    for note in &notes {
+        let at = format!("({}, {}) {}×{}", note.x, note.y, note.width, note.height);
+        let mut lines = note.body.lines();
+        println!(
+            "📝 {}: {}",
+            theme::label(&at),
+            lines.next().unwrap_or_default()
+        );
+        for line in lines {
+            println!("   {}", line);
+        }
+    }
+
+    Ok(())
+}
+
+/// Print the newest comments on a Danbooru post, in the order they were left
+#[cfg(feature = "danbooru")]
+fn print_comments(id: u32, newest: u32) -> Result<(), WaifuError> {
+    let comments = danbooru_api()?.comments(id, newest)?;
+    if json() {
+        println!("{}", serde_json::to_string(&comments)?);
+        return Ok(());
+    }
+    if comments.is_empty() {
+        tell("The post has no comments");
+    }
+    for comment in &comments {
+        let by = format!(
+            "{} on {} ({:+})",
+            comment.creator,
+            comment.date(),
+            comment.score
+        );
+        let mut lines = comment.body.lines();
+        println!(
+            "💬 {}: {}",
+            theme::label(&by),
+            lines.next().unwrap_or_default()
+        );
+        for line in lines {
+            println!("   {}", line);
+        }
+    }
+
+    Ok(())
+}
+
+/// Show one random post from the search, giving back its history entry. The
+/// `next` one picked already is shown rather than picking another, and with
+/// `pick_next` the one after is picked and downloaded while this one is drawn
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+fn show_random_post(
+    args: &Source,
+    settings: &Settings,
+    shown: &BTreeSet<u32>,
+    next: &mut Option<Post>,
+    pick_next: bool,
+) -> Result<Option<Entry>, WaifuError> {
+    // The details go with the post, except that --url-only prints nothing but
+    // its URL, for piping, unless they're formatted to have it in them
+    let details = !settings.url_only || url_in_details();
+    let chosen = match args {
+        #[cfg(feature = "danbooru")]
+        Source::Danbooru(Danbooru { id: Some(id), .. }) => Some(danbooru_api()?.grab_post(*id)?),
+        #[cfg(feature = "safebooru")]
+        Source::Safebooru(args @ Safebooru { id: Some(id), .. }) => {
+            Some(grab_safebooru_post(args, *id)?)
+        }
+        #[cfg(feature = "danbooru")]
+        Source::Danbooru(Danbooru { md5: Some(md5), .. }) => {
+            Some(danbooru_api()?.grab_by_md5(&md5_of(md5)?)?)
+        }
+        #[cfg(feature = "safebooru")]
+        Source::Safebooru(args @ Safebooru { md5: Some(md5), .. }) => {
+            Some(find_safebooru_md5(args, &md5_of(md5)?)?)
+        }
+        _ => None,
+    };
+    if let Some(post) = chosen {
+        if details {
+            PostDetails::from(&post).print()?;
+        }
+        let entry = Entry::from_post(&post);
+        if settings.url_only {
+            if !url_in_details() {
+                println!("{}", post.url);
+            }
+            return Ok(Some(entry));
+        }
+        show_image_with_url(&post.url, settings, Some(&post))?;
+        remember(entry.clone(), settings);
+        return Ok(Some(entry));
+    }
+
+    // One that `waifu daemon` got ready, unless there are details to print,
+    // one was picked already or the picks are seeded
+    let pooled = match args {
+        _ if settings.url_only || next.is_some() || random::seeded() => None,
+        _ if prints_details(args) => None,
+        _ => cache::take_pooled(&search_of(args)?),
+    };
+    if let Some((bytes, entry)) = pooled {
+        let bytes = match &settings.upscale_cmd {
+            Some(command) => upscale::run(command, &bytes)?,
+            None => bytes,
+        };
+        let post = entry.to_post();
+        show_loaded(bytes, settings, post.as_ref())?;
+        if let Some(post) = &post {
+            remember(Entry::from_post(post), settings);
+        }
+        return Ok(post.as_ref().map(Entry::from_post));
+    }
+
+    let videos = settings.poster_cmd.is_some();
+    // With --format json, --details-format or --no-image, --url-only prints the
+    // details, which can have the URL in them
+    let details = settings.url_only && url_in_details();
+    // Posts whose files couldn't be downloaded or decoded are passed over too
+    let mut passed_over = shown.clone();
+    let mut attempts = 0;
+    loop {
+        let image = match next.take() {
+            Some(image) => image,
+            // The file downloads while the details are printed, which can take
+            // requests of their own
+            #[cfg(feature = "async")]
+            None if prints_details(args) && !settings.url_only => {
+                let download = |image: &Post| prefetch(&image.url, settings);
+                pick_random_post(args, details, videos, &passed_over, download)?
+            }
+            #[cfg(not(feature = "async"))]
+            None if prints_details(args) && !settings.url_only => std::thread::scope(|scope| {
+                let download = |image: &Post| {
+                    let url = image.url.clone();
+                    scope.spawn(move || prefetch(&url, settings));
+                };
+                pick_random_post(args, details, videos, &passed_over, download)
+            })?,
+            None => pick_random_post(args, details, videos, &passed_over, |_| ())?,
+        };
+        if settings.url_only {
+            if !details {
+                println!("{}", image.url);
+            }
+            return Ok(Some(Entry::from_post(&image)));
+        }
+
+        #[cfg(feature = "async")]
+        let shown = load_url(&image.url, settings).and_then(|bytes| {
+            let picking = pick_next.then(|| {
+                let mut passed_over = passed_over.clone();
+                passed_over.insert(image.id);
+                pick_ahead(args.clone(), videos, passed_over, Ready::of(settings))
+            });
+            let shown = show_loaded(bytes, settings, Some(&image));
+            *next = picking.and_then(|picking| picking.wait().flatten());
+            shown
+        });
+        #[cfg(not(feature = "async"))]
+        let shown = load_url(&image.url, settings).and_then(|bytes| {
+            std::thread::scope(|scope| {
+                let picking = pick_next.then(|| {
+                    let mut passed_over = passed_over.clone();
+                    passed_over.insert(image.id);
+                    scope.spawn(move || pick_ahead(args, videos, &passed_over, settings))
+                });
+                let shown = show_loaded(bytes, settings, Some(&image));
+                *next = picking.and_then(|picking| picking.join().ok().flatten());
+                shown
+            })
+        });
+        match shown {
+            Ok(()) => {
+                let entry = Entry::from_post(&image);
+                remember(entry.clone(), settings);
+                return Ok(Some(entry));
+            }
+            Err(error) if attempts < MAX_REROLLS && can_reroll(&error) => {
+                eprintln!("Couldn't show post #{}: {}", image.id, error);
+                tell("Picking another one");
+                passed_over.insert(image.id);
+                attempts += 1;
+            }
+            Err(error) => return Err(error),
+        }
+    }
+}
+
+/// Pick a random post from the search, printing its details when asked to
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+fn pick_random_post(
+    args: &Source,
+    details: bool,
+    videos: bool,
+    shown: &BTreeSet<u32>,
+    picked: impl FnOnce(&Post),
+) -> Result<Post, WaifuError> {
+    let image = match args {
+        #[cfg(feature = "danbooru")]
+        Source::Danbooru(args) => pick_danbooru_post(args, videos, shown, ask_to_correct)?,
+        #[cfg(feature = "safebooru")]
+        Source::Safebooru(args) => pick_safebooru_post(args, videos, shown, ask_to_correct)?,
+    };
+    picked(&image);
+
+    if prints_details(args) || details {
+        PostDetails::from(&image).print().map_err(|error| {
+            exit::with_help(
+                error,
+                "There was an error when printing the tags. Please try again later.",
+            )
+        })?;
+    }
+
+    Ok(image)
+}
+
+/// Whether to search for the tags corrected instead, asked when there's
+/// someone at the terminal to answer
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+fn ask_to_correct(question: &str) -> bool {
+    let interactive = std::io::stdin().is_terminal() && std::io::stderr().is_terminal();
+    interactive && confirm(question, true).unwrap_or(false)
+}
+
+/// Whether the details of the posts picked from the search are printed
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+fn prints_details(args: &Source) -> bool {
+    match args {
+        #[cfg(feature = "danbooru")]
+        Source::Danbooru(args) => args.details,
+        #[cfg(feature = "safebooru")]
+        Source::Safebooru(args) => args.details,
+    }
+}
+
+/// Pick the post to show after this one and get its file ready. Anything that
+/// goes wrong is left for when it's picked again in its turn
+#[cfg(all(
+    any(feature = "danbooru", feature = "safebooru"),
+    not(feature = "async")
+))]
+fn pick_ahead(
+    args: &Source,
+    videos: bool,
+    shown: &BTreeSet<u32>,
+    settings: &Settings,
+) -> Option<Post> {
+    let image = pick_random_post(args, false, videos, shown, |_| ()).ok()?;
+    prefetch(&image.url, settings);
+
+    Some(image)
+}
+
+/// Pick the post to show after this one on the runtime's blocking threads,
+/// then start its file downloading. Anything that goes wrong is left for when
+/// it's picked again in its turn
+#[cfg(all(any(feature = "danbooru", feature = "safebooru"), feature = "async"))]
+fn pick_ahead(
+    args: Source,
+    videos: bool,
+    shown: BTreeSet<u32>,
+    ready: Ready,
+) -> pipeline::Ahead<Option<Post>> {
+    pipeline::blocking(move || {
+        let image = pick_random_post(&args, false, videos, &shown, |_| ()).ok()?;
+        ready.start(image.url.clone());
+        Some(image)
+    })
+}
+
+/// How many more random posts are tried when the one picked can't be shown
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+const MAX_REROLLS: usize = 3;
+
+/// Whether another post might do better, as the file was missing or not an
+/// image rather than the terminal failing to draw it
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+fn can_reroll(error: &WaifuError) -> bool {
+    matches!(error.code(), Code::Network | Code::Decode)
+}
+
+/// The MD5 --md5 was given, or that of the file it was given the path of
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+fn md5_of(hash_or_path: &str) -> Result<String, WaifuError> {
+    if crate::md5::is_hex(hash_or_path) {
+        return Ok(hash_or_path.to_lowercase());
+    }
+    let bytes = std::fs::read(hash_or_path).map_err(|e| {
+        format!(
+            "{} isn't an MD5, and couldn't be read as a file: {}",
+            hash_or_path, e
+        )
+    })?;
+
+    Ok(crate::md5::hex(&bytes))
+}
+
+/// The posts from a site --no-repeat leaves out
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+fn recently_shown(site: &str, settings: &Settings) -> BTreeSet<u32> {
+    let Some(within) = settings.no_repeat else {
+        return BTreeSet::new();
+    };
+
+    history::shown_within(site, within).unwrap_or_else(|error| {
+        eprintln!("Couldn't read the history for --no-repeat: {}", error);
+        BTreeSet::new()
+    })
+}
+
+/// Set for the copy of waifu that `waifu daemon` runs to get one more image ready
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+const DAEMON_FILL: &str = "WAIFU_DAEMON_FILL";
+
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+fn run_daemon(args: Daemon, source: Source, settings: &Settings) -> Result<(), WaifuError> {
+    use std::process::{Command, Stdio};
+
+    let search = search_of(&source)?;
+
+    if std::env::var_os(DAEMON_FILL).is_some() {
+        let videos = settings.poster_cmd.is_some();
+        let post = match source {
+            #[cfg(feature = "safebooru")]
+            Source::Safebooru(args) => {
+                let shown = recently_shown("safebooru", settings);
+                pick_safebooru_post(&args, videos, &shown, |_| false)?
+            }
+            #[cfg(feature = "danbooru")]
+            Source::Danbooru(args) => {
+                let shown = recently_shown("danbooru", settings);
+                pick_danbooru_post(&args, videos, &shown, |_| false)?
+            }
+        };
+        let bytes = poster_frame(download(&post.url, settings)?, settings)?;
+        return cache::pool(&search, &bytes, &Entry::from_post(&post));
+    }
+
+    if !quiet() {
+        eprintln!(
+            "Keeping {} images ready, checking every {}",
+            args.pool,
+            humantime::format_duration(args.interval)
+        );
+    }
+    loop {
+        // Each is got by another run of waifu, so one that fails doesn't stop the daemon
+        for _ in cache::pooled(&search)..args.pool as usize {
+            let status = Command::new(std::env::current_exe()?)
+                .args(std::env::args_os().skip(1))
+                .env(DAEMON_FILL, "1")
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .status()?;
+            if !status.success() {
+                eprintln!(
+                    "Couldn't get an image ready, trying again in {}",
+                    humantime::format_duration(args.interval)
+                );
+                break;
+            }
+        }
+
+        std::thread::sleep(args.interval);
+    }
+}
+
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+fn set_wallpaper(args: Wallpaper, source: Source, settings: &Settings) -> Result<(), WaifuError> {
+    if args.list {
+        return list_wallpapers();
+    }
+    if let Some(n) = args.again {
+        let wallpapers = history::load_wallpapers()?;
+        let entry = wallpapers
+            .iter()
+            .rev()
+            .nth(n as usize - 1)
+            .ok_or_else(|| format!("Only {} wallpapers have been set", wallpapers.len()))?;
+        return put_up_wallpaper(entry.clone(), &args, settings);
+    }
+
+    let Some(interval) = args.rotate else {
+        return put_up_wallpaper(pick_wallpaper(&source, settings)?, &args, settings);
+    };
+    if args.once {
+        let last = history::load_wallpapers()?.pop();
+        let due =
+            last.is_none_or(|last| history::now().saturating_sub(last.time) >= interval.as_secs());
+        if !due {
+            return Ok(());
+        }
+        return put_up_wallpaper(pick_wallpaper(&source, settings)?, &args, settings);
+    }
+
+    loop {
+        // One that can't be set doesn't stop the rotation
+        let set = pick_wallpaper(&source, settings)
+            .and_then(|entry| put_up_wallpaper(entry, &args, settings));
+        if let Err(error) = set {
+            eprintln!(
+                "Couldn't change the wallpaper, trying again in {}: {}",
+                humantime::format_duration(interval),
+                error
+            );
+        }
+
+        std::thread::sleep(interval);
+    }
+}
+
+/// A random post from the source to make the wallpaper
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+fn pick_wallpaper(source: &Source, settings: &Settings) -> Result<Entry, WaifuError> {
+    let videos = settings.poster_cmd.is_some();
+    let shown = recently_shown(source.site(), settings);
+    let post = pick_random_post(source, false, videos, &shown, |_| ())?;
+
+    Ok(Entry::from_post(&post))
+}
+
+/// Download the image to the data directory and make it the wallpaper. Only the
+/// newest for each monitor is kept there, as those are what the desktop reads
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+fn put_up_wallpaper(entry: Entry, args: &Wallpaper, settings: &Settings) -> Result<(), WaifuError> {
+    let bytes = load_url(&entry.url, settings)?;
+
+    let dir = dirs::data()?.join("wallpapers");
+    std::fs::create_dir_all(&dir).map_err(|e| format!("Couldn't make {}: {}", dir.display(), e))?;
+    let extension = image::guess_format(&bytes)
+        .ok()
+        .and_then(|format| format.extensions_str().first().copied())
+        .unwrap_or("jpg");
+    let name = entry
+        .id
+        .map_or(history::now().to_string(), |id| id.to_string());
+    let file_name = format!("{}-{}.{}", entry.site, name, extension);
+    // Setting every monitor replaces the ones set for only one of them too
+    let monitor = args.monitor.as_deref();
+    let prefix = format!("{}-", monitor.unwrap_or("all"));
+    let path = dir.join(format!("{}{}", prefix, file_name));
+    std::fs::write(&path, &bytes)
+        .map_err(|e| format!("Couldn't save {}: {}", path.display(), e))?;
+
+    wallpaper::set(&path, monitor)?;
+    remove_replaced(&dir, &path, |name| {
+        !name.starts_with(LOCKSCREEN) && (monitor.is_none() || name.starts_with(&prefix))
+    });
+    tell(format!("Set {} as the wallpaper", entry.post()));
+
+    // A copy of its own, so it stays when the wallpaper changes without it
+    if args.lockscreen {
+        let lock_path = dir.join(format!("{}{}", LOCKSCREEN, file_name));
+        std::fs::copy(&path, &lock_path)
+            .map_err(|e| format!("Couldn't save {}: {}", lock_path.display(), e))?;
+        wallpaper::set_lockscreen(&lock_path)?;
+        remove_replaced(&dir, &lock_path, |name| name.starts_with(LOCKSCREEN));
+        tell(format!("Set {} as the lock screen image", entry.post()));
+    }
+
+    let entry = Entry {
+        time: history::now(),
+        ..entry
+    };
+    // Kept even with --no-history, as it's what --rotate --once goes by
+    if let Err(error) = history::record_wallpaper(&entry) {
+        eprintln!("Couldn't add the wallpaper to the list of them: {}", error);
+    }
+    remember(entry, settings);
+
+    Ok(())
+}
+
+/// Pick another image to serve every interval, while the requests for it are
+/// answered. It stops only when waifu does
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+fn serve_images(args: Serve, source: Source, settings: &Settings) -> Result<(), WaifuError> {
+    let listener = std::net::TcpListener::bind((args.bind, args.port))
+        .map_err(|e| format!("Couldn't listen on {}:{}: {}", args.bind, args.port, e))?;
+    tell(format!(
+        "Serving random images at http://{}:{}/, and their details at /post.json",
+        args.bind, args.port
+    ));
+
+    let current = Mutex::new(None);
+    std::thread::scope(|scope| {
+        scope.spawn(|| loop {
+            match pick_served(&source, settings) {
+                Ok(picked) => {
+                    *current.lock().unwrap_or_else(PoisonError::into_inner) = Some(picked);
+                }
+                Err(error) => eprintln!(
+                    "Couldn't pick another image, trying again in {}: {}",
+                    humantime::format_duration(args.interval),
+                    error
+                ),
+            }
+            std::thread::sleep(args.interval);
+        });
+        serve::run(&listener, &current, args.interval);
+    });
+
+    Ok(())
+}
+
+/// A random post from the source, downloaded ready to serve
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+fn pick_served(source: &Source, settings: &Settings) -> Result<serve::Current, WaifuError> {
+    let videos = settings.poster_cmd.is_some();
+    let shown = recently_shown(source.site(), settings);
+    let post = pick_random_post(source, false, videos, &shown, |_| ())?;
+    let bytes = load_url(&post.url, settings)?;
+
+    let entry = Entry::from_post(&post);
+    let mut details = serde_json::to_value(&entry)?;
+    details["page"] = post.page.clone().into();
+    remember(entry, settings);
+    let attribution = post.attribution();
+
+    Ok(serve::Current {
+        bytes: bytes.into(),
+        key: format!("{}-{}", post.site, post.id),
+        caption: if attribution.is_empty() {
+            post.page.clone()
+        } else {
+            attribution
+        },
+        link: post.page.clone(),
+        details,
+    })
+}
+
+/// What the files kept for the lock screen start with, rather than a monitor
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+const LOCKSCREEN: &str = "lockscreen-";
+
+/// Remove the files in the directory that the one just set replaces
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+fn remove_replaced(dir: &Path, kept: &Path, replaced: impl Fn(&str) -> bool) {
+    let Ok(files) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for file in files.flatten() {
+        if file.path() != kept && replaced(&file.file_name().to_string_lossy()) {
+            let _ = std::fs::remove_file(file.path());
+        }
+    }
+}
+
+/// Print the wallpapers set before, newest first, numbered for --again
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+fn list_wallpapers() -> Result<(), WaifuError> {
+    let wallpapers = history::load_wallpapers()?;
+    if wallpapers.is_empty() {
+        println!("No wallpaper has been set yet");
+    }
+    for (i, entry) in wallpapers.iter().rev().enumerate() {
+        println!(
+            "{:>4}  {}  {:<18}  {}",
+            i + 1,
+            entry.shown_at(),
+            entry.post(),
+            entry.url
+        );
+    }
+
+    Ok(())
+}
+
+/// Set for the copy of waifu that gets the next image for `waifu motd` ready
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+const MOTD_REFRESH: &str = "WAIFU_MOTD_REFRESH";
+
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+fn show_motd(source: Source, settings: &Settings) -> Result<(), WaifuError> {
+    if settings.stdout.is_some() {
+        return Err("motd can't write to --stdout".into());
+    }
+    // An image got ready in the background may be from yesterday
+    if settings.daily {
+        return show_random_image(source, settings);
+    }
+
+    if std::env::var_os(MOTD_REFRESH).is_some() {
+        return refresh_motd(source, settings);
+    }
+
+    // Only the first time is there nothing ready, and then it's worth the wait
+    let (bytes, entry) = match cache::get_motd() {
+        Some(motd) => motd,
+        None => {
+            refresh_motd(source, settings)?;
+            cache::get_motd().ok_or("Couldn't keep the image to show")?
+        }
+    };
+    refresh_motd_in_background()?;
+
+    let bytes = match &settings.upscale_cmd {
+        Some(command) => upscale::run(command, &bytes)?,
+        None => bytes,
+    };
+    let post = entry.to_post();
+    show_loaded(bytes, settings, post.as_ref())?;
+    remember(
+        post.as_ref()
+            .map_or(Entry::from_url(&entry.url), Entry::from_post),
+        settings,
+    );
+
+    Ok(())
+}
+
+/// Pick a random post and keep its image for the next `waifu motd`
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+fn refresh_motd(source: Source, settings: &Settings) -> Result<(), WaifuError> {
+    let videos = settings.poster_cmd.is_some();
+    let post = match source {
+        #[cfg(feature = "danbooru")]
+        Source::Danbooru(args) => {
+            let shown = recently_shown("danbooru", settings);
+            pick_danbooru_post(&args, videos, &shown, |_| false)?
+        }
+        #[cfg(feature = "safebooru")]
+        Source::Safebooru(args) => {
+            let shown = recently_shown("safebooru", settings);
+            pick_safebooru_post(&args, videos, &shown, |_| false)?
+        }
+    };
+    let bytes = poster_frame(download(&post.url, settings)?, settings)?;
+
+    cache::put_motd(&bytes, &Entry::from_post(&post))
+}
+
+/// Run waifu again the same way, detached, to get the next image ready
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+fn refresh_motd_in_background() -> Result<(), WaifuError> {
+    use std::process::{Command, Stdio};
+
+    Command::new(std::env::current_exe()?)
+        .args(std::env::args_os().skip(1))
+        .env(MOTD_REFRESH, "1")
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| format!("Couldn't get the next image ready: {}", e))?;
+
+    Ok(())
+}
+
+/// Add an image that was just shown to the history, unless that's turned off
+#[cfg(feature = "json")]
+fn remember(entry: Entry, settings: &Settings) {
+    if !settings.history {
+        return;
+    }
+    if let Err(error) = history::record(&entry) {
+        eprintln!("Couldn't add the image to the history: {}", error);
+    }
+    remember_last(Shown::Url(entry), settings);
+}
+
+/// Keep an image that was just shown for `waifu last`, unless that's turned off
+#[cfg(feature = "json")]
+fn remember_last(image: Shown, settings: &Settings) {
+    if !settings.history {
+        return;
+    }
+    if let Err(error) = history::record_last(image) {
+        eprintln!("Couldn't keep the image for `waifu last`: {}", error);
+    }
+}
+
+#[cfg(feature = "json")]
+fn show_last(last: Last, settings: &Settings) -> Result<(), WaifuError> {
+    match last.image {
+        Shown::Url(entry) => show_image_with_url(&entry.url, settings, entry.to_post().as_ref()),
+        Shown::Path(image_path) => show_image_with_path(image_path, settings),
+    }
+}
+
+#[cfg(feature = "json")]
+fn show_history(args: History, settings: &Settings) -> Result<(), WaifuError> {
+    let entries = history::load()?;
+    // Newest first, which is how they're numbered too
+    let mut newest = entries.iter().rev();
+
+    match args.action.unwrap_or(HistoryAction::List { limit: 20 }) {
+        HistoryAction::List { limit } => {
+            if entries.is_empty() {
+                println!("Nothing has been shown yet");
+            }
+            for (i, entry) in newest.take(limit).enumerate() {
+                println!(
+                    "{:>4}  {}  {:<18}  {}",
+                    i + 1,
+                    entry.shown_at(),
+                    entry.post(),
+                    entry.url
+                );
+            }
+        }
+        HistoryAction::Show { n } => {
+            let entry = newest
+                .nth(n as usize - 1)
+                .ok_or_else(|| format!("There are only {} images in the history", entries.len()))?;
+
+            if !formatted_details() {
+                println!(
+                    "🕒 {title}: {}",
+                    entry.shown_at(),
+                    title = theme::label("Shown")
+                );
+            }
+            print_entry(entry)?;
+            show_image_with_url(&entry.url, settings, entry.to_post().as_ref())?;
+        }
+        HistoryAction::Stats { top } => {
+            if entries.is_empty() {
+                println!("Nothing has been shown yet");
+                return Ok(());
+            }
+
+            let stats = history::stats(&entries);
+            println!("{} images in the history", entries.len());
+            let tables = [
+                ("Sources", &stats.sites),
+                ("Artists", &stats.artists),
+                ("Characters", &stats.characters),
+                ("Tags", &stats.tags),
+            ];
+            for (title, counts) in tables {
+                if counts.is_empty() {
+                    continue;
+                }
+                println!("\n{}", theme::label(title));
+                for (name, count) in counts.iter().take(top) {
+                    println!("{:>6}  {}", count, name);
+                }
+            }
+        }
+        HistoryAction::Export { format, output } => {
+            let exported = history::export(&entries, format)?;
+            match output {
+                Some(path) => {
+                    std::fs::write(&path, exported).map_err(|e| {
+                        format!("Couldn't write the history to {}: {}", path.display(), e)
+                    })?;
+                    tell(format!(
+                        "Exported {} images to {}",
+                        entries.len(),
+                        path.display()
+                    ));
+                }
+                None => print!("{}", exported),
+            }
+        }
+        HistoryAction::Clear => {
+            history::clear()?;
+            tell("Cleared the history");
+        }
+    }
+
+    Ok(())
+}
+
+/// Print what's known about an image from the history or the favorites
+#[cfg(feature = "json")]
+fn print_entry(entry: &Entry) -> Result<(), WaifuError> {
+    if !formatted_details() {
+        println!("📌 {title}: {}", entry.post(), title = theme::label("Post"));
+    }
+
+    PostDetails {
+        site: entry.site.clone(),
+        id: entry.id,
+        source: entry.source.clone(),
+        artist: entry.artist.clone(),
+        character: entry.character.clone(),
+        url: entry.url.clone(),
+        tags: entry.tags.split_whitespace().map(String::from).collect(),
+        time: Some(entry.time),
+        ..PostDetails::default()
+    }
+    .print()
+}
+
+/// What --details-format can fill in, like `{artist}`. Time is when a history
+/// entry was shown, in seconds since the Unix epoch. The last four are for
+/// images from files and URLs rather than booru posts
+#[cfg(feature = "json")]
+const PLACEHOLDERS: [&str; 15] = [
+    "site",
+    "id",
+    "source",
+    "artist",
+    "character",
+    "url",
+    "rating",
+    "width",
+    "height",
+    "tags",
+    "time",
+    "path",
+    "format",
+    "content_type",
+    "size",
+];
+
+#[cfg(feature = "json")]
+fn placeholder() -> regex::Regex {
+    regex::Regex::new(r"\{(\w+)\}").unwrap()
+}
+
+/// Make sure a --details-format template asks for nothing but the placeholders
+#[cfg(feature = "json")]
+fn check_template(template: &str) -> Result<(), WaifuError> {
+    for captures in placeholder().captures_iter(template) {
+        let name = &captures[1];
+        if !PLACEHOLDERS.contains(&name) {
+            return Err(format!(
+                "--details-format has no {{{}}}, it can use {{{}}}",
+                name,
+                PLACEHOLDERS.join("}, {")
+            )
+            .into());
+        }
+    }
+
+    Ok(())
+}
+
+/// Print details the way --details-format or --format json ask for, giving back
+/// whether they asked. Placeholders for what isn't known are left empty
+#[cfg(feature = "json")]
+pub(crate) fn print_formatted(details: &impl Serialize) -> Result<bool, WaifuError> {
+    if let Some(template) = details_format() {
+        let details = serde_json::to_value(details)?;
+        let filled =
+            placeholder().replace_all(template, |captures: &regex::Captures| {
+                match &details[&captures[1]] {
+                    serde_json::Value::Null => String::new(),
+                    serde_json::Value::String(text) => text.clone(),
+                    serde_json::Value::Array(tags) => {
+                        let tags: Vec<&str> =
+                            tags.iter().filter_map(serde_json::Value::as_str).collect();
+                        tags.join(" ")
+                    }
+                    value => value.to_string(),
+                }
+            });
+        println!("{}", filled);
+        return Ok(true);
+    }
+    if json() {
+        println!("{}", serde_json::to_string(details)?);
+        return Ok(true);
+    }
+
+    Ok(false)
+}
+
+// Without JSON there's neither option, so the details are always printed a line
+// for each
+#[cfg(not(feature = "json"))]
+pub(crate) fn print_formatted<T>(_: &T) -> Result<bool, WaifuError> {
+    Ok(false)
+}
+
+/// The details --details prints, the same for every booru so each prints them
+/// in the same order and colors, and with the same JSON. What a booru or the
+/// history doesn't know is left out
+#[derive(Debug, Default, Clone)]
+#[cfg_attr(feature = "json", derive(Serialize))]
+#[cfg_attr(not(feature = "json"), allow(dead_code))]
+struct PostDetails {
+    /// The booru it came from, e.g. "danbooru"
+    site: String,
+    id: Option<u32>,
+    source: Option<String>,
+    artist: Option<String>,
+    character: Option<String>,
+    url: String,
+    /// Which other file is shown when the original can't be, e.g. "sample"
+    #[cfg_attr(feature = "json", serde(skip_serializing_if = "Option::is_none"))]
+    variant: Option<String>,
+    rating: Option<String>,
+    width: Option<u32>,
+    height: Option<u32>,
+    tags: Vec<String>,
+    /// When a history entry was shown, in seconds since the Unix epoch
+    #[cfg_attr(feature = "json", serde(skip_serializing_if = "Option::is_none"))]
+    time: Option<u64>,
+    /// Each tag's category and post count on Danbooru, with --tag-info
+    #[cfg(feature = "danbooru")]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tag_info: Vec<crate::api::danbooru::TagInfo>,
+}
+
+impl From<&Post> for PostDetails {
+    fn from(post: &Post) -> Self {
+        PostDetails {
+            site: post.site.clone(),
+            id: Some(post.id),
+            source: post.source.as_ref().map(crate::api::Source::url),
+            artist: post.artist.clone(),
+            character: post.character.clone(),
+            url: post.url.clone(),
+            variant: post.variant.clone(),
+            rating: post.rating.map(|rating| rating.name().to_string()),
+            width: Some(post.width),
+            height: Some(post.height),
+            tags: post.tags.clone(),
+            time: None,
+            #[cfg(feature = "danbooru")]
+            tag_info: Vec::new(),
+        }
+    }
+}
+
+impl PostDetails {
+    /// Print them the way --details-format or --format json ask for, or else
+    /// as a line for each, with an emoji and label in front
+    fn print(&self) -> Result<(), WaifuError> {
+        use std::io::{self, Write};
+
+        if no_image() {
+            println!("{}", self.describe());
+            return Ok(());
+        }
+
+        #[cfg(feature = "danbooru")]
+        if tag_info() && self.tag_info.is_empty() && !self.tags.is_empty() {
+            let details = PostDetails {
+                tag_info: danbooru_api()?.tag_info(&self.tags)?,
+                ..self.clone()
+            };
+            return details.print();
+        }
+        if print_formatted(self)? {
+            return Ok(());
+        }
+
+        let fields = [
+            ("✨", "Character", &self.character),
+            ("ℹ️", "Source", &self.source),
+            ("🎨", "Artist", &self.artist),
+        ];
+        for (icon, title, value) in fields {
+            if let Some(value) = value {
+                println!("{} {}: {}", icon, theme::label(title), value);
+            }
+        }
+        println!("✉️ {title}: {}", self.url, title = theme::label("Link"));
+        if let Some(variant) = &self.variant {
+            println!(
+                "🔻 {title}: The {} file rather than the original",
+                variant,
+                title = theme::label("Shown")
+            );
+        }
+        if let Some(rating) = &self.rating {
+            println!("⚖️ {title}: {}", rating, title = theme::label("Rating"));
+        }
+        if let (Some(width), Some(height)) = (self.width, self.height) {
+            println!(
+                "📐 {title}: {w} x {h}",
+                title = theme::label("Dimensions"),
+                w = width,
+                h = height
+            );
+        }
+
+        if self.tags.is_empty() {
+            return Ok(());
+        }
+        let stdout = io::stdout();
+        let mut buffer = io::BufWriter::new(stdout.lock());
+        #[cfg(feature = "danbooru")]
+        if !self.tag_info.is_empty() {
+            return print_tag_info(&mut buffer, &self.tag_info);
+        }
+        write!(buffer, "🏷️ {}:", theme::label("Tags"))?;
+        self.tags
+            .iter()
+            .try_for_each(|tag| write!(buffer, " {}", tag))?;
+        writeln!(buffer)?;
+
+        Ok(())
+    }
+}
+
+impl PostDetails {
+    /// The details in plain sentences, without emoji or colors, then the URL on
+    /// a line of its own, e.g. "hatsune miku, drawn by wlop. Rated general,
+    /// 1200 x 1600 pixels. Tagged long hair, smile and 30 more."
+    fn describe(&self) -> String {
+        const TAGS: usize = 10;
+
+        // Tags are written with underscores for spaces, and several are split by spaces
+        let readable = |names: &str| -> String {
+            names
+                .split_whitespace()
+                .map(|name| name.replace('_', " "))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        let mut sentences = Vec::new();
+        match (&self.character, &self.artist) {
+            (Some(character), Some(artist)) => sentences.push(format!(
+                "{}, drawn by {}",
+                readable(character),
+                readable(artist)
+            )),
+            (Some(character), None) => sentences.push(readable(character)),
+            (None, Some(artist)) => sentences.push(format!("Drawn by {}", readable(artist))),
+            (None, None) => {}
+        }
+
+        let mut about = Vec::new();
+        if let Some(rating) = &self.rating {
+            about.push(format!("Rated {}", rating));
+        }
+        if let (Some(width), Some(height)) = (self.width, self.height) {
+            about.push(format!("{} x {} pixels", width, height));
+        }
+        if !about.is_empty() {
+            sentences.push(about.join(", "));
+        }
+
+        // The artist and character were named already
+        let named: Vec<&str> = [&self.character, &self.artist]
+            .into_iter()
+            .flatten()
+            .flat_map(|names| names.split_whitespace())
+            .collect();
+        let tags: Vec<String> = self
+            .tags
+            .iter()
+            .filter(|tag| !named.contains(&tag.as_str()))
+            .map(|tag| tag.replace('_', " "))
+            .collect();
+        match tags.len() {
+            0 => {}
+            1 => sentences.push(format!("Tagged {}", tags[0])),
+            n if n <= TAGS => sentences.push(format!(
+                "Tagged {} and {}",
+                tags[..n - 1].join(", "),
+                tags[n - 1]
+            )),
+            n => sentences.push(format!(
+                "Tagged {} and {} more",
+                tags[..TAGS].join(", "),
+                n - TAGS
+            )),
+        }
+
+        let mut text = sentences
+            .iter()
+            .map(|sentence| format!("{}.", sentence))
+            .collect::<Vec<_>>()
+            .join(" ");
+        if !text.is_empty() {
+            text.push('\n');
+        }
+        text.push_str(&self.url);
+        text
+    }
+}
+
+/// The tags on a line for each category, the way Danbooru lists them, colored like
+/// it colors them and with their post counts
+#[cfg(feature = "danbooru")]
+fn print_tag_info(
+    out: &mut impl std::io::Write,
+    tags: &[crate::api::danbooru::TagInfo],
+) -> Result<(), WaifuError> {
+    use colored::{Color, Colorize};
+
+    writeln!(out, "🏷️ {}:", theme::label("Tags"))?;
+    let categories = [
+        ("artist", "Artist", Color::Red),
+        ("copyright", "Copyright", Color::Magenta),
+        ("character", "Character", Color::Green),
+        ("general", "General", Color::Blue),
+        ("meta", "Meta", Color::Yellow),
+    ];
+    for (category, title, color) in categories {
+        let mut listed = tags
+            .iter()
+            .filter(|tag| tag.category == category)
+            .peekable();
+        if listed.peek().is_none() {
+            continue;
+        }
+        write!(out, "   {}:", title.color(color))?;
+        for tag in listed {
+            write!(out, " {} ({})", tag.name.color(color), tag.post_count)?;
+        }
+        writeln!(out)?;
+    }
+
+    Ok(())
+}
+
+/// What --details prints for an image from a file, a URL or stdin
+#[derive(Default)]
+#[cfg_attr(feature = "json", derive(Serialize))]
+struct ImageDetails {
+    #[cfg_attr(feature = "json", serde(skip_serializing_if = "Option::is_none"))]
+    path: Option<String>,
+    #[cfg_attr(feature = "json", serde(skip_serializing_if = "Option::is_none"))]
+    url: Option<String>,
+    #[cfg_attr(feature = "json", serde(skip_serializing_if = "Option::is_none"))]
+    content_type: Option<String>,
+    format: Option<String>,
+    width: u32,
+    height: u32,
+    /// In bytes
+    size: usize,
+}
+
+impl ImageDetails {
+    /// The format, dimensions and size of image data, decoding it when its
+    /// header doesn't say how big it is
+    fn of(bytes: &[u8]) -> Result<ImageDetails, WaifuError> {
+        let reader = image::io::Reader::new(std::io::Cursor::new(bytes)).with_guessed_format()?;
+        let format = reader
+            .format()
+            .and_then(|format| format.extensions_str().first())
+            .map(|extension| extension.to_uppercase());
+        let (width, height) = match reader.into_dimensions() {
+            Ok(dimensions) => dimensions,
+            Err(_) => {
+                let image = decode_downloaded(bytes, None)?;
+                (image.width(), image.height())
+            }
+        };
+
+        Ok(ImageDetails {
+            format,
+            width,
+            height,
+            size: bytes.len(),
+            ..ImageDetails::default()
+        })
+    }
+
+    /// A sentence on the format, dimensions and size, then where it's from on
+    /// a line of its own, e.g. "A PNG image, 640 x 480 pixels, 12.30 KiB."
+    fn describe(&self, noun: &str) -> String {
+        let mut about = vec![match &self.format {
+            Some(format) => format!("A {} {}", format, noun),
+            None if noun.starts_with(['a', 'e', 'i', 'o', 'u']) => format!("An {}", noun),
+            None => format!("A {}", noun),
+        }];
+        if self.width > 0 && self.height > 0 {
+            about.push(format!("{} x {} pixels", self.width, self.height));
+        }
+        about.push(indicatif::HumanBytes(self.size as u64).to_string());
+
+        let mut text = format!("{}.", about.join(", "));
+        if let Some(origin) = self.path.as_ref().or(self.url.as_ref()) {
+            text.push('\n');
+            text.push_str(origin);
+        }
+        text
+    }
+
+    fn print(&self) -> Result<(), WaifuError> {
+        if print_formatted(self)? {
+            return Ok(());
+        }
+
+        let fields = [
+            ("📁", "Path", &self.path),
+            ("✉️", "Link", &self.url),
+            ("📄", "Type", &self.content_type),
+            ("🖼️", "Format", &self.format),
+        ];
+        for (icon, title, value) in fields {
+            if let Some(value) = value {
+                println!("{} {}: {}", icon, theme::label(title), value);
+            }
+        }
+        println!(
+            "📐 {title}: {} x {}",
+            self.width,
+            self.height,
+            title = theme::label("Dimensions")
+        );
+        println!(
+            "💾 {title}: {}",
+            indicatif::HumanBytes(self.size as u64),
+            title = theme::label("Size")
+        );
+
+        Ok(())
+    }
+}
+
+/// The image shown `n` back in the history, for something to `do` to it
+#[cfg(feature = "json")]
+fn latest_shown(n: u32, r#do: &str) -> Result<Entry, WaifuError> {
+    let entries = history::load()?;
+    if entries.is_empty() {
+        return Err(format!(
+            "There's no image in the history to {}. Only images from \
+             the boorus and the web are kept there",
+            r#do
+        )
+        .into());
+    }
+    let total = entries.len();
+    entries
+        .into_iter()
+        .rev()
+        .nth(n as usize - 1)
+        .ok_or_else(|| format!("There are only {} images in the history", total).into())
+}
+
+#[cfg(feature = "danbooru")]
+fn vote(args: Vote) -> Result<(), WaifuError> {
+    let entry = latest_shown(args.n, "vote on")?;
+    let score = if args.upvote { 1 } else { -1 };
+    danbooru_api()?.vote(danbooru_id(&entry)?, score)?;
+    let way = if args.upvote { "up" } else { "down" };
+    tell(format!("Voted {} {} on Danbooru", entry.name(), way));
+
+    Ok(())
+}
+
+/// The ID of the Danbooru post the entry is for, to do something to it there
+#[cfg(feature = "danbooru")]
+fn danbooru_id(entry: &Entry) -> Result<u32, WaifuError> {
+    match entry.id {
+        Some(id) if entry.site == "danbooru" => Ok(id),
+        _ => Err(format!("{} isn't a Danbooru post", entry.name()).into()),
+    }
+}
+
+#[cfg(feature = "json")]
+fn manage_favorites(args: Favorites, settings: &Settings) -> Result<(), WaifuError> {
+    let favorites = favorites::load()?;
+    // Newest first, which is how they're numbered too
+    let newest = |n: u32| {
+        favorites
+            .len()
+            .checked_sub(n as usize)
+            .ok_or_else(|| format!("There are only {} favorites", favorites.len()))
+    };
+
+    match args.action.unwrap_or(FavoritesAction::List) {
+        FavoritesAction::Add {
+            n,
+            #[cfg(feature = "danbooru")]
+            remote,
+        } => {
+            let mut entry = latest_shown(n, "add")?;
+            // From now on it's when it became a favorite
+            entry.time = history::now();
+
+            let name = entry.name();
+            #[cfg(feature = "danbooru")]
+            if remote {
+                danbooru_api()?.favorite(danbooru_id(&entry)?, true)?;
+                tell(format!("Favorited {} on Danbooru", name));
+            }
+            if favorites::add(entry)? {
+                tell(format!("Added {} to the favorites", name));
+            } else {
+                tell(format!("{} is a favorite already", name));
+            }
+        }
+        FavoritesAction::List => {
+            if favorites.is_empty() {
+                println!("There are no favorites yet, add the last image with `waifu fav add`");
+            }
+            for (i, entry) in favorites.iter().rev().enumerate() {
+                println!(
+                    "{:>4}  {}  {:<18}  {}",
+                    i + 1,
+                    entry.shown_at(),
+                    entry.post(),
+                    entry.url
+                );
+            }
+        }
+        FavoritesAction::Show { n } => {
+            let entry = &favorites[newest(n)?];
+
+            if !formatted_details() {
+                println!(
+                    "⭐ {title}: {}",
+                    entry.shown_at(),
+                    title = theme::label("Added")
+                );
+            }
+            print_entry(entry)?;
+            show_image_with_url(&entry.url, settings, entry.to_post().as_ref())?;
+        }
+        FavoritesAction::Remove {
+            n,
+            #[cfg(feature = "danbooru")]
+            remote,
+        } => {
+            let index = newest(n)?;
+            #[cfg(feature = "danbooru")]
+            if remote {
+                let entry = &favorites[index];
+                danbooru_api()?.favorite(danbooru_id(entry)?, false)?;
+                tell(format!("Unfavorited {} on Danbooru", entry.name()));
+            }
+            if let Some(entry) = favorites::remove(index)? {
+                tell(format!("Removed {} from the favorites", entry.name()));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(feature = "config")]
+fn manage_config(args: Config) -> Result<(), WaifuError> {
+    match args.action {
+        ConfigAction::Get { key } => match config::get(&key)? {
+            Some(value) => println!("{}", value),
+            None => return Err(format!("{} isn't set", key).into()),
+        },
+        ConfigAction::Set { key, value } => config::set(&key, &value)?,
+        ConfigAction::Path => println!("{}", config::path()?.display()),
+    }
+
+    Ok(())
+}
+
+#[cfg(feature = "network")]
+fn manage_auth(args: Auth) -> Result<(), WaifuError> {
+    match args.action {
+        AuthAction::Login { site } => {
+            let mut login = auth::Credentials::default();
+            for &field in site.fields() {
+                let question = format!("{}: ", field.label());
+                let answer = match field {
+                    auth::Field::ApiKey => rpassword::prompt_password(question)
+                        .map_err(|e| format!("Couldn't read the API key from the terminal: {}", e))?
+                        .trim()
+                        .to_string(),
+                    _ => prompt(&question)?,
+                };
+                if answer.is_empty() {
+                    return Err(format!("{} can't be empty", field.label()).into());
+                }
+                *login.get_mut(field) = Some(answer);
+            }
+            auth::save(site, &login)?;
+            tell(format!(
+                "Saved the {} login in the system keyring",
+                site.name()
+            ));
+        }
+        AuthAction::Logout { site } => {
+            if auth::delete(site)? {
+                tell(format!(
+                    "Removed the {} login from the system keyring",
+                    site.name()
+                ));
+            } else {
+                tell(format!(
+                    "There's no {} login in the system keyring",
+                    site.name()
+                ));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn print_completions(shell: clap_complete::Shell) -> Result<(), WaifuError> {
+    use clap::CommandFactory;
+    use clap_complete::Shell;
+    use std::io::Write;
+
+    // Written all at once, as clap_complete panics on errors like a closed pipe
+    let mut script = Vec::new();
+    clap_complete::generate(shell, &mut Cli::command(), "waifu", &mut script);
+    let mut script = String::from_utf8(script)?;
+
+    // Tags are completed by asking Danbooru, in the shells that make it easy
+    match shell {
+        Shell::Bash => script.push_str(BASH_TAGS),
+        Shell::Zsh => {
+            // Defined ahead of the first completion, which the script runs itself
+            script = script.replace(":TAGS:_default'", ":TAGS:_waifu_tags'");
+            let start = script.find('\n').map_or(0, |end| end + 1);
+            script.insert_str(start, ZSH_TAGS);
+        }
+        Shell::Fish => script.push_str(FISH_TAGS),
+        _ => (),
+    }
+    std::io::stdout().write_all(script.as_bytes())?;
+
+    Ok(())
+}
+
+fn print_man_page() -> Result<(), WaifuError> {
+    use clap::CommandFactory;
+    use std::io::Write;
+
+    let mut page = Vec::new();
+    // Packages want to know which version it documents
+    let command = Cli::command().version(env!("CARGO_PKG_VERSION"));
+    clap_mangen::Man::new(command).render(&mut page)?;
+    std::io::stdout().write_all(&page)?;
+
+    Ok(())
+}
+
+const BASH_TAGS: &str = r#"
+_waifu_with_tags() {
+    case "${COMP_WORDS[COMP_CWORD-1]}" in
+        -t|--tags)
+            COMPREPLY=($(waifu complete-tag "${COMP_WORDS[COMP_CWORD]}" 2>/dev/null))
+            ;;
+        *)
+            _waifu "$@"
+            ;;
+    esac
+}
+complete -F _waifu_with_tags -o nosort -o bashdefault -o default waifu
+"#;
+
+const ZSH_TAGS: &str = r#"
+_waifu_tags() {
+    local -a tags
+    tags=(${(f)"$(waifu complete-tag "$PREFIX" 2>/dev/null)"})
+    compadd -a tags
+}
+
+"#;
+
+const FISH_TAGS: &str = r#"
+complete -c waifu -n "__fish_seen_subcommand_from dan safe" -s t -l tags -f -a "(waifu complete-tag (commandline -ct) 2>/dev/null)"
+"#;
+
+/// The config file, or an empty one along with why it couldn't be read. The
+/// logins in it are put in the environment
+fn load_config() -> (config::Config, Result<(), WaifuError>) {
+    let (config_file, loaded) = match config::load() {
+        Ok(config_file) => (config_file, Ok(())),
+        Err(error) => (config::Config::default(), Err(error)),
+    };
+    config_file.apply_logins();
+
+    (config_file, loaded)
+}
+
+/// Whether to offer the setup, which is only done once, when there's no config
+/// file yet and someone is at the terminal to answer
+#[cfg(feature = "config")]
+fn first_run(args: &Cli) -> Result<bool, WaifuError> {
+    use std::io::{stderr, stdin};
+
+    // These either have nobody waiting on them or are about the config already
+    let skipped = match args.subcommand {
+        Some(Commands::Config(_) | Commands::Setup | Commands::Completions(_) | Commands::Man) => {
+            true
+        }
+        #[cfg(feature = "network")]
+        Some(Commands::Auth(_)) => true,
+        #[cfg(any(feature = "danbooru", feature = "safebooru"))]
+        Some(Commands::Motd(_) | Commands::Daemon(_)) => true,
+        #[cfg(feature = "danbooru")]
+        Some(Commands::CompleteTag { .. }) => true,
+        _ => false,
+    };
+
+    Ok(!skipped
+        && !args.quiet
+        && stdin().is_terminal()
+        && stderr().is_terminal()
+        && !config::path()?.exists())
+}
+
+/// Ask what to show by default, how, and with which logins, then write the config
+/// file. On the first run it can be turned down, giving back false
+#[cfg(feature = "config")]
+fn setup(first_run: bool) -> Result<bool, WaifuError> {
+    if first_run {
+        eprintln!("There's no config file for waifu yet, a few questions will make one");
+        if !confirm("Set it up now?", true)? {
+            eprintln!(
+                "You can do it later with `waifu setup`, or write {} yourself",
+                config::path()?.display()
+            );
+            return Ok(false);
+        }
+    } else if config::path()?.exists()
+        && !confirm(&format!("Replace {}?", config::path()?.display()), false)?
+    {
+        return Ok(false);
+    }
+    eprintln!("Pressing enter keeps the answer in brackets\n");
+
+    let mut values = Vec::new();
+    #[cfg(any(feature = "danbooru", feature = "safebooru"))]
+    ask_about_boorus(&mut values)?;
+
+    let protocol = choose(
+        "How should images be drawn?",
+        &["auto", "kitty", "iterm", "sixel", "blocks"],
+    )?;
+    if protocol != "auto" {
+        values.push(("options.protocol".to_string(), protocol));
+    }
+
+    config::create(&values)?;
+    eprintln!("\nWrote {}\n", config::path()?.display());
+
+    Ok(true)
+}
+
+/// Ask which booru to show images from, and for the logins to them
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+fn ask_about_boorus(values: &mut Vec<(String, String)>) -> Result<(), WaifuError> {
+    let source = choose(
+        "Which booru should `waifu` show images from?",
+        &["safe", "dan"],
+    )?;
+    values.push(("default.source".to_string(), source));
+    if confirm("Only show images that are safe for work?", true)? {
+        values.push(("default.rating".to_string(), "general".to_string()));
+    }
+
+    // Only the boorus waifu can search so far
+    for site in [auth::Site::Danbooru, auth::Site::Gelbooru] {
+        let [first, rest @ ..] = site.fields() else {
+            continue;
+        };
+        let answer = prompt(&format!(
+            "{} for {} (enter to skip logging in): ",
+            first.label(),
+            capitalized(site.name())
+        ))?;
+        if answer.is_empty() {
+            continue;
+        }
+        values.push((format!("{}.{}", site.name(), first.key()), answer));
+        for &field in rest {
+            let answer =
+                rpassword::prompt_password(format!("{}: ", field.label())).map_err(|e| {
+                    format!(
+                        "Couldn't read the {} from the terminal: {}",
+                        field.label(),
+                        e
+                    )
+                })?;
+            values.push((
+                format!("{}.{}", site.name(), field.key()),
+                answer.trim().to_string(),
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+fn capitalized(name: &str) -> String {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .map(|first| first.to_uppercase().chain(chars).collect())
+        .unwrap_or_default()
+}
+
+/// Ask a yes or no question
+#[cfg(feature = "config")]
+fn confirm(question: &str, default: bool) -> Result<bool, WaifuError> {
+    let hint = if default { "[Y/n]" } else { "[y/N]" };
+    loop {
+        match prompt(&format!("{} {} ", question, hint))?
+            .to_lowercase()
+            .as_str()
+        {
+            "" => return Ok(default),
+            "y" | "yes" => return Ok(true),
+            "n" | "no" => return Ok(false),
+            _ => eprintln!("Answer y or n"),
+        }
+    }
+}
+
+/// Ask for one of a few answers, the first being the default
+#[cfg(feature = "config")]
+fn choose(question: &str, answers: &[&str]) -> Result<String, WaifuError> {
+    loop {
+        let answer = prompt(&format!(
+            "{} {} [{}] ",
+            question,
+            answers.join("/"),
+            answers[0]
+        ))?
+        .to_lowercase();
+        if answer.is_empty() {
+            return Ok(answers[0].to_string());
+        }
+        if answers.contains(&answer.as_str()) {
+            return Ok(answer);
+        }
+        eprintln!("Answer one of {}", answers.join(", "));
+    }
+}
+
+/// Ask for a line on the terminal
+#[cfg(feature = "config")]
+fn prompt(question: &str) -> Result<String, WaifuError> {
+    use std::io::Write;
+
+    eprint!("{}", question);
+    std::io::stderr().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+
+    Ok(answer.trim().to_string())
+}
+
+#[cfg(feature = "danbooru")]
+fn manage_tags(args: Tag) -> Result<(), WaifuError> {
+    match args.action {
+        TagAction::Search { pattern, limit } => {
+            let tags = danbooru_api()?.search_tags(&pattern, limit as usize)?;
+            if json() {
+                println!("{}", serde_json::to_string(&tags)?);
+                return Ok(());
+            }
+            if tags.is_empty() {
+                let message = format!("Danbooru has no tags matching {}", pattern);
+                return Err(WaifuError::NoResults(message));
+            }
+
+            println!("{:>9}  {:<9}  Tag", "Posts", "Category");
+            for tag in tags {
+                println!("{:>9}  {:<9}  {}", tag.post_count, tag.category, tag.name);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(feature = "danbooru")]
+fn show_related(tag: &str, limit: usize) -> Result<(), WaifuError> {
+    let related = danbooru_api()?.related_tags(tag, limit)?;
+    if json() {
+        println!("{}", serde_json::to_string(&related)?);
+        return Ok(());
+    }
+    if related.is_empty() {
+        let message = format!("Danbooru has no tags related to {}", tag);
+        return Err(WaifuError::NoResults(message));
+    }
+
+    println!("{:>6}  {:>9}  {:<9}  Tag", "Shared", "Posts", "Category");
+    for related in related {
+        println!(
+            "{:>5.0}%  {:>9}  {:<9}  {}",
+            related.frequency * 100.0,
+            related.tag.post_count,
+            related.tag.category,
+            related.tag.name
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(feature = "danbooru")]
+fn show_wiki(tag: &str) -> Result<(), WaifuError> {
+    let page = danbooru_api()?.wiki_page(tag)?;
+    if json() {
+        println!("{}", serde_json::to_string(&page)?);
+        return Ok(());
+    }
+
+    println!("{}", theme::label(&page.title.replace('_', " ")));
+    if !page.other_names.is_empty() {
+        println!("Also known as {}", page.other_names.join(", "));
+    }
+    let (width, _) = viuer::terminal_size();
+    for paragraph in &page.excerpt {
+        println!();
+        for line in paragraph.lines() {
+            for wrapped in wrap_words(line, width.into()) {
+                println!("{}", wrapped);
+            }
+        }
+    }
+    if page.excerpt.is_empty() {
+        println!("\nThe wiki page is empty");
+    }
+
+    Ok(())
+}
+
+/// Break text into lines no wider than the width, at spaces where it can
+#[cfg(feature = "danbooru")]
+fn wrap_words(text: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        if !line.is_empty() && line.chars().count() + 1 + word.chars().count() > width {
+            lines.push(std::mem::take(&mut line));
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(word);
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+
+    lines
+}
+
+#[cfg(feature = "network")]
+fn manage_cache(args: Cache, ttl: Duration, limit: u64) -> Result<(), WaifuError> {
+    use indicatif::HumanBytes;
+
+    match args.action.unwrap_or(CacheAction::Stats) {
+        CacheAction::Stats => {
+            let stats = cache::stats(ttl)?;
+            println!("{}", cache::dir()?.display());
+            println!(
+                "{} images, {} of {}",
+                stats.images,
+                HumanBytes(stats.size),
+                HumanBytes(limit)
+            );
+            if stats.expired > 0 {
+                println!(
+                    "{} older than {}, which `waifu cache prune` removes",
+                    stats.expired,
+                    humantime::format_duration(ttl)
+                );
+            }
+        }
+        CacheAction::Prune => {
+            let (removed, freed) = cache::prune(ttl, limit)?;
+            tell(format!(
+                "Removed {} images, freeing {}",
+                removed,
+                HumanBytes(freed)
+            ));
+        }
+        CacheAction::Clear => {
+            cache::clear()?;
+            tell("Cleared the cache");
+        }
+    }
+
+    Ok(())
+}
+
+/// Show an image from the web, along with the booru post it came from if there is one
+fn show_image_with_url(
+    image_url: &str,
+    settings: &Settings,
+    post: Option<&Post>,
+) -> Result<(), WaifuError> {
+    let bytes = load_url(image_url, settings)?;
+    let origin = ImageDetails {
+        url: Some(image_url.to_string()),
+        ..ImageDetails::default()
+    };
+    if describe_instead(&bytes, origin)? {
+        share(image_url, post, settings)?;
+    } else {
+        show_loaded(bytes, settings, post)?;
+        if post.is_none() {
+            share(image_url, None, settings)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Show an image that's ready, censored and captioned for the post it came from,
+/// with a QR code of the post beneath it
+fn show_loaded(bytes: Vec<u8>, settings: &Settings, post: Option<&Post>) -> Result<(), WaifuError> {
+    draw_loaded(bytes, settings, post)?;
+    if let Some(post) = post {
+        share(&post.url, Some(post), settings)?;
+    }
+
+    Ok(())
+}
+
+/// Print a QR code of the post, or of the image's URL when there's none, open it
+/// in the browser, post it to the webhook, hand it to the downloader, and copy what
+/// --copy asks for
+fn share(url: &str, post: Option<&Post>, settings: &Settings) -> Result<(), WaifuError> {
+    let page = post.map_or(url.to_string(), |post| post.page.clone());
+    *UP.lock().unwrap_or_else(PoisonError::into_inner) = Some(page.clone());
+    if settings.qr {
+        let code = render::qr::encode(&page).ok_or("The URL is too long for a QR code")?;
+        code.print()?;
+    }
+    if settings.open {
+        browser::open(&page)?;
+    }
+    #[cfg(feature = "network")]
+    if let Some(webhook) = &settings.webhook {
+        webhook::post(webhook, url, post)?;
+    }
+    // {url} is what downloaders like gallery-dl know best, and {image} is there
+    // for ones that only fetch files
+    if let Some(command) = &settings.download_cmd {
+        hook::run(command, &[("url", &page), ("image", url)])?;
+    }
+
+    let copied = match settings.copy {
+        None => return Ok(()),
+        Some(clipboard::Field::Url) => Some(url.to_string()),
+        Some(clipboard::Field::Tags) => post.map(|post| post.tags.join(" ")),
+        Some(clipboard::Field::Source) => post.map(|post| {
+            post.source
+                .as_ref()
+                .map_or_else(|| post.page.clone(), crate::api::Source::url)
+        }),
+    };
+    match copied {
+        Some(text) => {
+            clipboard::copy(&text)?;
+            tell("📋 Copied to the clipboard");
+        }
+        None => tell("📋 There's nothing to copy, as the image isn't from a booru"),
+    }
+
+    Ok(())
+}
+
+fn draw_loaded(bytes: Vec<u8>, settings: &Settings, post: Option<&Post>) -> Result<(), WaifuError> {
+    let censor = settings.censor.filter(|_| post.is_some_and(Post::nsfw));
+    let caption = post
+        .filter(|_| settings.caption)
+        .map(Post::attribution)
+        .filter(|caption| !caption.is_empty());
+
+    if let (Some(viewer), None, None) = (&settings.viewer, censor, &caption) {
+        return render::viewer::open_bytes(viewer, &bytes);
+    }
+
+    let edit = |mut image: image::DynamicImage| {
+        if let Some(censor) = censor {
+            image = transform::censor(&image, censor);
+        }
+        if let Some(caption) = &caption {
+            image = render::caption::draw(&image, caption);
+        }
+        image
+    };
+
+    #[cfg(feature = "animation")]
+    if settings.viewer.is_none() {
+        if let Some(mut frames) = animation::frames(&bytes) {
+            for frame in &mut frames {
+                frame.image = edit(std::mem::take(&mut frame.image));
+            }
+            return animation::play(&frames, settings);
+        }
+    }
+
+    let image = edit(decode_downloaded(
+        &bytes,
+        post.map(|post| post.url.as_str()),
+    )?);
+
+    // The viewer gets the edited image rather than the original
+    match &settings.viewer {
+        Some(viewer) => render::viewer::open_image(viewer, &image),
+        None => render::print(&image, settings),
+    }
+}
+
+/// Look up the post behind a booru post page, e.g. https://danbooru.donmai.us/posts/123456
+/// or https://safebooru.org/index.php?page=post&s=view&id=123456, and its details. None
+/// when the URL isn't one
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+fn grab_post_from_page(url: &str) -> Option<Result<Post, WaifuError>> {
+    let (host, id) = post_page(url)?;
+
+    match host.as_str() {
+        #[cfg(feature = "danbooru")]
+        host if is_danbooru(host) => Some(danbooru_api().and_then(|api| api.grab_post(id))),
+        #[cfg(feature = "safebooru")]
+        _ => Some(safebooru_api(&host).and_then(|api| api.grab_post(id))),
+        #[cfg(not(feature = "safebooru"))]
+        _ => None,
+    }
+}
+
+// Without a booru, every URL is taken to be an image
+#[cfg(not(any(feature = "danbooru", feature = "safebooru")))]
+fn grab_post_from_page(_: &str) -> Option<Result<Post, WaifuError>> {
+    None
+}
+
+/// Whether a URL is a booru post page rather than an image
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+fn is_post_page(url: &str) -> bool {
+    post_page(url).is_some()
+}
+
+#[cfg(not(any(feature = "danbooru", feature = "safebooru")))]
+fn is_post_page(_: &str) -> bool {
+    false
+}
+
+// The booru and post ID of a post page, for the boorus waifu was built with
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+fn post_page(url: &str) -> Option<(String, u32)> {
+    let url = reqwest::Url::parse(url).ok()?;
+    let host = url.host_str()?;
+    let host = host.strip_prefix("www.").unwrap_or(host);
+
+    let id = match host {
+        #[cfg(feature = "danbooru")]
+        host if is_danbooru(host) => {
+            let mut segments = url.path_segments()?;
+            match (segments.next(), segments.next(), segments.next()) {
+                (Some("posts"), Some(id), None) => id.parse().ok()?,
+                _ => return None,
+            }
+        }
+        #[cfg(feature = "safebooru")]
+        "safebooru.org" | "gelbooru.com" => {
+            let query = |key: &str| {
+                url.query_pairs()
+                    .find(|(k, _)| k == key)
+                    .map(|(_, value)| value.into_owned())
+            };
+            if query("page")? != "post" || query("s")? != "view" {
+                return None;
+            }
+            query("id")?.parse().ok()?
+        }
+        _ => return None,
+    };
+
+    Some((host.to_string(), id))
+}
+
+/// Whether a URL's host is the Danbooru requests go to, e.g. for its post pages
+#[cfg(feature = "danbooru")]
+fn is_danbooru(host: &str) -> bool {
+    danbooru::Api::new().at(danbooru_url()).is_host(host)
+}
+
+/// Show an image from a URL given by the user, looking up the image when it's a booru post page
+fn show_url(url: &str, details: bool, settings: &Settings) -> Result<(), WaifuError> {
+    match grab_post_from_page(url) {
+        Some(post) => {
+            let post = post?;
+            if details {
+                PostDetails::from(&post).print()?;
+            }
+            show_image_with_url(&post.url, settings, Some(&post))?;
+            #[cfg(feature = "json")]
+            remember(Entry::from_post(&post), settings);
+        }
+        None => {
+            let bytes = load_url(url, settings)?;
+            if details {
+                let (url, content_type) = origin(url);
+                ImageDetails {
+                    url: Some(url),
+                    content_type,
+                    ..ImageDetails::of(&bytes)?
+                }
+                .print()?;
+            }
+            let origin = ImageDetails {
+                url: Some(url.to_string()),
+                ..ImageDetails::default()
+            };
+            if !describe_instead(&bytes, origin)? {
+                show_loaded(bytes, settings, None)?;
+            }
+            share(url, None, settings)?;
+            #[cfg(feature = "json")]
+            remember(Entry::from_url(url), settings);
+        }
+    }
+
+    Ok(())
+}
+
+/// Upload an image and print the link, which is all that's printed so it can be
+/// piped on
+#[cfg(feature = "network")]
+fn upload_image(args: Share, settings: &Settings) -> Result<(), WaifuError> {
+    let (bytes, source) = match args.image {
+        Some(url) if url.starts_with("http://") || url.starts_with("https://") => {
+            (download(&url, settings)?, url)
+        }
+        Some(path) => {
+            let bytes =
+                std::fs::read(&path).map_err(|e| format!("Couldn't read {}: {}", path, e))?;
+            (bytes, path)
+        }
+        None => match history::load_last()? {
+            Some(Last {
+                image: Shown::Url(entry),
+                ..
+            }) => (download(&entry.url, settings)?, entry.url),
+            Some(Last {
+                image: Shown::Path(path),
+                ..
+            }) => {
+                let bytes = std::fs::read(&path)
+                    .map_err(|e| format!("Couldn't read {}: {}", path.display(), e))?;
+                (bytes, path.to_string_lossy().into_owned())
+            }
+            None => return Err("No image has been shown yet, so give one to upload".into()),
+        },
+    };
+
+    // The host names the file after its own ID, but keeps the extension
+    let extension = image::guess_format(&bytes)
+        .ok()
+        .and_then(|format| format.extensions_str().first().copied())
+        .or_else(|| {
+            let name = source.split(['?', '#']).next().unwrap_or_default();
+            Path::new(name)
+                .extension()
+                .and_then(|extension| extension.to_str())
+        })
+        .unwrap_or("png");
+    let link = upload::upload(args.to, &bytes, &format!("image.{}", extension))?;
+    println!("{}", link);
+
+    Ok(())
+}
+
+fn show_palette(args: Palette, settings: &Settings) -> Result<(), WaifuError> {
+    let image = match args.source {
+        Some(source) if source.starts_with("http://") || source.starts_with("https://") => {
+            let bytes = poster_frame(download(&source, settings)?, settings)?;
+            decode_downloaded(&bytes, Some(&source))?
+        }
+        Some(path) => open_file(Path::new(&path))?,
+        #[cfg(feature = "safebooru")]
+        None => {
+            let default_options = Safebooru::default();
+            let videos = settings.poster_cmd.is_some();
+            let shown = recently_shown("safebooru", settings);
+            let image_url = pick_safebooru_post(&default_options, videos, &shown, |_| false)?.url;
+            let bytes = poster_frame(download(&image_url, settings)?, settings)?;
+            decode_downloaded(&bytes, Some(&image_url))?
+        }
+        #[cfg(not(feature = "safebooru"))]
+        None => return Err("Give a file or URL to take the palette of".into()),
+    };
+
+    render::print(&image, settings)?;
+
+    let colors = palette::extract(&image, args.size as usize);
+    let palette = palette::format(&colors, args.format, settings.colors);
+    match args.output {
+        Some(path) => std::fs::write(&path, palette)
+            .map_err(|e| format!("Couldn't write the palette to {}: {}", path.display(), e))?,
+        None => print!("{}", palette),
+    }
+
+    Ok(())
+}
+
+/// Fetch an image, from the cache when it was downloaded not long ago,
+/// or when the server says it hasn't changed since
+#[cfg(feature = "network")]
+fn download(image_url: &str, settings: &Settings) -> Result<Vec<u8>, WaifuError> {
+    let cached = settings
+        .cache_ttl
+        .and_then(|ttl| cache::get(image_url, ttl));
+    if let Some(cached) = cached.as_ref().filter(|cached| cached.fresh) {
+        tracing::debug!("Using the cached copy of {}", image_url);
+        return Ok(cached.bytes.clone());
+    }
+
+    let validators = cached.as_ref().map(|cached| &cached.validators);
+    match (fetch(image_url, settings, validators)?, cached) {
+        (Fetched::Image(bytes, validators), _) => {
+            if settings.cache_ttl.is_some() {
+                let cached = cache::put(image_url, &bytes, &validators, settings.cache_size);
+                if let Err(error) = cached {
+                    eprintln!("Couldn't cache the image: {}", error);
+                }
+            }
+            Ok(bytes)
+        }
+        (Fetched::NotModified, Some(cached)) => {
+            let _ = cache::refresh(image_url);
+            Ok(cached.bytes)
+        }
+        (Fetched::NotModified, None) => {
+            unreachable!("Only conditional requests can come back unchanged")
+        }
+    }
+}
+
+/// Where an image was downloaded from after any redirects, and what the server
+/// said it was
+#[cfg(feature = "network")]
+fn origin(url: &str) -> (String, Option<String>) {
+    let origin = ORIGINS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .get(url)
+        .cloned();
+    // A copy from the cache doesn't say where it was downloaded from
+    match origin {
+        Some(origin) => (origin.url, origin.content_type),
+        None => (url.to_string(), None),
+    }
+}
+
+// Nothing's downloaded without a client
+#[cfg(not(feature = "network"))]
+fn origin(url: &str) -> (String, Option<String>) {
+    (url.to_string(), None)
+}
+
+// Without a client, URLs from the history or a list can't be shown
+#[cfg(not(feature = "network"))]
+fn download(image_url: &str, _: &Settings) -> Result<Vec<u8>, WaifuError> {
+    Err(WaifuError::Network(format!(
+        "waifu was built without the network feature, so {} can't be downloaded",
+        image_url
+    )))
+}
+
+/// Where an image downloaded this run ended up after any redirects, and what
+/// the server said it was, for --details
+#[cfg(feature = "network")]
+#[derive(Clone)]
+struct Origin {
+    url: String,
+    content_type: Option<String>,
+}
+
+/// Each by the URL it was asked for
+#[cfg(feature = "network")]
+static ORIGINS: Mutex<BTreeMap<String, Origin>> = Mutex::new(BTreeMap::new());
+
+/// What came of downloading an image
+#[cfg(feature = "network")]
+enum Fetched {
+    /// The image, and what identifies this version of it
+    Image(Vec<u8>, cache::Validators),
+    /// The cached copy the request was made conditional on is still current
+    NotModified,
+}
+
+/// Download an image, retrying on connection errors. With the validators of
+/// a cached copy, the server only sends it when it has changed
+#[cfg(feature = "network")]
+fn fetch(
+    image_url: &str,
+    settings: &Settings,
+    cached: Option<&cache::Validators>,
+) -> Result<Fetched, WaifuError> {
+    use reqwest::header;
+    use std::io::Read;
+
+    let client = net::client()?;
+    let mut request = client.get(image_url);
+    if let Some(referer) = referer(image_url, settings.referer.as_deref()) {
+        request = request.header(header::REFERER, referer);
+    }
+    if let Some(cached) = cached {
+        request = cached.apply(request);
+    }
+    let resp = net::send(request)
+        .map_err(|e| WaifuError::Network(format!("Failed to fetch image: {}", e)))?;
+    if resp.status() == reqwest::StatusCode::NOT_MODIFIED && cached.is_some() {
+        return Ok(Fetched::NotModified);
+    }
+
+    ORIGINS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .insert(
+            image_url.to_string(),
+            Origin {
+                url: resp.url().to_string(),
+                content_type: resp
+                    .headers()
+                    .get(header::CONTENT_TYPE)
+                    .and_then(|v| v.to_str().ok())
+                    .map(str::to_string),
+            },
+        );
+
+    let status = resp.status();
+    let ct = resp
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("")
+        .to_string();
+
+    // Videos are let through so a poster frame can be taken from them
+    let media = ct.starts_with("image/") || ct.starts_with("video/");
+    if !status.is_success() || (!ct.is_empty() && !media) {
+        let message = format!(
+            "Failed to fetch image: HTTP {} (content-type: {})",
+            status,
+            if ct.is_empty() { "unknown" } else { &ct }
+        );
+        let bytes = || resp.bytes().map(|bytes| bytes.to_vec()).unwrap_or_default();
+        return Err(dumped(WaifuError::Network, message, bytes, Some(image_url)));
+    }
+
+    // Servers that stream the image without saying how big it is will often say so
+    // when asked, which saves downloading a huge original only to throw it away
+    let length = |resp: &reqwest::blocking::Response| {
+        let len = resp.headers().get(header::CONTENT_LENGTH)?;
+        len.to_str().ok()?.parse::<u64>().ok()
+    };
+    let size = length(&resp).or_else(|| {
+        let mut request = client.head(image_url);
+        if let Some(referer) = referer(image_url, settings.referer.as_deref()) {
+            request = request.header(header::REFERER, referer);
+        }
+        net::send(request)
+            .ok()
+            .filter(|head| head.status().is_success())
+            .and_then(|head| length(&head))
+    });
+    if let Some(len) = size.filter(|&len| len > MAX_IMAGE_BYTES as u64) {
+        return Err(format!(
+            "Image too large ({} bytes > {} bytes)",
+            len, MAX_IMAGE_BYTES
+        )
+        .into());
+    }
+
+    let validators = cache::Validators::from_headers(resp.headers());
+
+    // Read no more than the cap allows, in case the size was missing or wrong
+    let progress = download_progress(size);
+    let mut body = Vec::new();
+    let read = progress
+        .wrap_read(resp.take(MAX_IMAGE_BYTES as u64 + 1))
+        .read_to_end(&mut body);
+    progress.finish_and_clear();
+    read.map_err(|e| WaifuError::Network(format!("Failed to fetch image: {}", e)))?;
+    if body.len() > MAX_IMAGE_BYTES {
+        return Err(format!("Image too large (more than {} bytes)", MAX_IMAGE_BYTES).into());
+    }
+
+    Ok(Fetched::Image(body, validators))
+}
+
+/// A progress bar for a download, or a spinner when its size isn't known.
+/// Hidden when the output isn't going to a terminal
+#[cfg(feature = "network")]
+fn download_progress(size: Option<u64>) -> indicatif::ProgressBar {
+    use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+
+    let progress = match size {
+        Some(size) => ProgressBar::new(size).with_style(
+            ProgressStyle::with_template(
+                "{bytes:>10} / {total_bytes} [{bar:30}] {bytes_per_sec}, {eta} left",
+            )
+            .unwrap()
+            .progress_chars("=> "),
+        ),
+        None => ProgressBar::new_spinner().with_style(
+            ProgressStyle::with_template("{spinner} {bytes} ({bytes_per_sec})").unwrap(),
+        ),
+    };
+    if !std::io::stdout().is_terminal() || quiet() || PREFETCHING.with(Cell::get) {
+        progress.set_draw_target(ProgressDrawTarget::hidden());
+    }
+
+    progress
+}
+
+/// The Referer to download an image with. pixiv answers 403 to requests without its own
+#[cfg(feature = "network")]
+fn referer(image_url: &str, given: Option<&str>) -> Option<String> {
+    if let Some(referer) = given {
+        return Some(referer.to_string());
+    }
+
+    let url = reqwest::Url::parse(image_url).ok()?;
+    let host = url.host_str()?;
+    (host == "pximg.net" || host.ends_with(".pximg.net"))
+        .then(|| "https://www.pixiv.net/".to_string())
+}
+
+/// The error, of the kind, saying where what was downloaded was kept with
+/// --debug-dump, or how to keep it without
+#[cfg(feature = "json")]
+fn dumped(
+    kind: fn(String) -> WaifuError,
+    message: String,
+    bytes: impl FnOnce() -> Vec<u8>,
+    url: Option<&str>,
+) -> WaifuError {
+    if !debug_dump() {
+        return exit::with_help(
+            kind(message),
+            "Pass --debug-dump to keep what was downloaded",
+        );
+    }
+
+    match dump(&bytes(), url, &message) {
+        Ok(path) => kind(format!(
+            "{}. Saved the bytes to {}",
+            message,
+            path.display()
+        )),
+        Err(error) => {
+            eprintln!("Couldn't keep what was downloaded: {}", error);
+            kind(message)
+        }
+    }
+}
+
+// Without JSON there's no --debug-dump to keep them with
+#[cfg(not(feature = "json"))]
+fn dumped(
+    kind: fn(String) -> WaifuError,
+    message: String,
+    _: impl FnOnce() -> Vec<u8>,
+    _: Option<&str>,
+) -> WaifuError {
+    kind(message)
+}
+
+/// Keep the bytes in a file of their own under the state directory, with the URL
+/// they came from and what was wrong with them in a JSON file next to it
+#[cfg(feature = "json")]
+fn dump(bytes: &[u8], url: Option<&str>, why: &str) -> Result<PathBuf, WaifuError> {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    let dir = dirs::state()?.join("dumps");
+    std::fs::create_dir_all(&dir)?;
+    let time = SystemTime::now().duration_since(UNIX_EPOCH)?;
+    let name = format!("{}-{}", time.as_millis(), std::process::id());
+
+    let path = dir.join(format!("{}.bin", name));
+    std::fs::write(&path, bytes)?;
+    let metadata = serde_json::json!({
+        "url": url,
+        "error": why,
+        "time": time.as_secs(),
+        "size": bytes.len(),
+    });
+    std::fs::write(dir.join(format!("{}.json", name)), metadata.to_string())?;
+
+    Ok(path)
+}
+
+/// Decode downloaded image data, keeping a copy around with --debug-dump when
+/// it isn't an image
+fn decode_downloaded(bytes: &[u8], url: Option<&str>) -> Result<image::DynamicImage, WaifuError> {
+    transform::decode(bytes).map_err(|e| {
+        let message = format!("Failed to decode image: {}", e);
+        dumped(WaifuError::Decode, message, || bytes.to_vec(), url)
+    })
+}
+
+/// Images got ready ahead of their turn in a slideshow, by URL or path
+#[cfg(not(all(feature = "network", feature = "async")))]
+static PREFETCHED: Mutex<BTreeMap<String, Vec<u8>>> = Mutex::new(BTreeMap::new());
+
+/// Images being got ready on the runtime ahead of their turn, by URL or path
+#[cfg(all(feature = "network", feature = "async"))]
+static PREFETCHED: Mutex<BTreeMap<String, pipeline::Ahead<Option<Vec<u8>>>>> =
+    Mutex::new(BTreeMap::new());
+
+thread_local! {
+    /// Set on the thread getting the next image ready, which mustn't draw over this one
+    static PREFETCHING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Download an image ready to show, swapped for a still frame when it's a video and upscaled
+fn load_url(image_url: &str, settings: &Settings) -> Result<Vec<u8>, WaifuError> {
+    if let Some(bytes) = take_prefetched(image_url) {
+        return Ok(bytes);
+    }
+
+    let bytes = poster_frame(download(image_url, settings)?, settings)?;
+    match &settings.upscale_cmd {
+        Some(command) => upscale::run(command, &bytes),
+        None => Ok(bytes),
+    }
+}
+
+/// Read an image ready to show, like load_url does for one from the web
+fn load_path(image_path: &Path, settings: &Settings) -> Result<Vec<u8>, WaifuError> {
+    if let Some(bytes) = take_prefetched(&image_path.to_string_lossy()) {
+        return Ok(bytes);
+    }
+
+    let bytes = poster_frame(std::fs::read(image_path)?, settings)?;
+    match &settings.upscale_cmd {
+        Some(command) => upscale::run(command, &bytes),
+        None => Ok(bytes),
+    }
+}
+
+#[cfg(not(all(feature = "network", feature = "async")))]
+fn take_prefetched(key: &str) -> Option<Vec<u8>> {
+    PREFETCHED
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .remove(key)
+}
+
+/// The image got ready ahead, waiting for it to finish when it hasn't yet
+#[cfg(all(feature = "network", feature = "async"))]
+fn take_prefetched(key: &str) -> Option<Vec<u8>> {
+    let ahead = PREFETCHED
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .remove(key);
+    ahead?.wait().flatten()
+}
+
+/// Get the next image of a slideshow ready while this one is up. Anything that
+/// goes wrong is left for when its turn comes, so it's reported then
+#[cfg(not(all(feature = "network", feature = "async")))]
+fn prefetch(item: &str, settings: &Settings) {
+    let web = item.starts_with("http://") || item.starts_with("https://");
+    // A post page has to be looked up first, and viewers open files themselves
+    if web && is_post_page(item)
+        || !web && settings.viewer.is_some() && settings.upscale_cmd.is_none()
+    {
+        return;
+    }
+
+    PREFETCHING.with(|prefetching| prefetching.set(true));
+    let bytes = if web {
+        load_url(item, settings)
+    } else {
+        load_path(Path::new(item), settings)
+    };
+    PREFETCHING.with(|prefetching| prefetching.set(false));
+
+    if let Ok(bytes) = bytes {
+        PREFETCHED
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(item.to_string(), bytes);
+    }
+}
+
+/// Start getting the next image of a slideshow ready on the runtime, so this
+/// one can be shown straight away. Anything that goes wrong is left for when
+/// its turn comes, so it's reported then
+#[cfg(all(feature = "network", feature = "async"))]
+fn prefetch(item: &str, settings: &Settings) {
+    let web = item.starts_with("http://") || item.starts_with("https://");
+    // A post page has to be looked up first, and viewers open files themselves
+    if web && is_post_page(item)
+        || !web && settings.viewer.is_some() && settings.upscale_cmd.is_none()
+    {
+        return;
+    }
+
+    Ready::of(settings).start(item.to_string());
+}
+
+/// What getting an image ready takes from the settings, owned so it can be
+/// done on the runtime
+#[cfg(all(feature = "network", feature = "async"))]
+#[derive(Clone)]
+struct Ready {
+    referer: Option<String>,
+    poster_cmd: Option<String>,
+    upscale_cmd: Option<String>,
+    cache_ttl: Option<Duration>,
+    cache_size: u64,
+}
+
+#[cfg(all(feature = "network", feature = "async"))]
+impl Ready {
+    fn of(settings: &Settings) -> Ready {
+        Ready {
+            referer: settings.referer.clone(),
+            poster_cmd: settings.poster_cmd.clone(),
+            upscale_cmd: settings.upscale_cmd.clone(),
+            cache_ttl: settings.cache_ttl,
+            cache_size: settings.cache_size,
+        }
+    }
+
+    /// Download or read the URL or path, then grab a frame and upscale it on
+    /// the runtime's blocking threads, for take_prefetched
+    fn start(self, item: String) {
+        let web = item.starts_with("http://") || item.starts_with("https://");
+        let cached = self
+            .cache_ttl
+            .filter(|_| web)
+            .and_then(|ttl| cache::get(&item, ttl))
+            .filter(|cached| cached.fresh);
+        let key = item.clone();
+        let ahead = pipeline::spawn(async move {
+            let downloaded = match cached {
+                _ if !web => None,
+                Some(cached) => Some((cached.bytes, None)),
+                None => {
+                    let referer = referer(&item, self.referer.as_deref());
+                    let (bytes, validators) = fetch_ahead(&item, referer).await.ok()?;
+                    Some((bytes, Some(validators)))
+                }
+            };
+            let ready = tokio::task::spawn_blocking(move || {
+                let bytes = match downloaded {
+                    Some((bytes, validators)) => {
+                        if let (Some(validators), Some(_)) = (validators, self.cache_ttl) {
+                            let cached = cache::put(&item, &bytes, &validators, self.cache_size);
+                            if let Err(error) = cached {
+                                tracing::debug!("Couldn't cache {}: {}", item, error);
+                            }
+                        }
+                        bytes
+                    }
+                    None => std::fs::read(&item).ok()?,
+                };
+                self.prepare(bytes)
+            });
+            ready.await.ok()?
+        });
+
+        PREFETCHED
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(key, ahead);
+    }
+
+    /// Swap a video for a still frame and upscale, as load_url does. A video
+    /// without a --poster-cmd is left for its turn to say so
+    fn prepare(&self, bytes: Vec<u8>) -> Option<Vec<u8>> {
+        let bytes = match video::sniff(&bytes) {
+            Some(kind) => video::poster(self.poster_cmd.as_deref()?, &bytes, kind).ok()?,
+            None => bytes,
+        };
+        match &self.upscale_cmd {
+            Some(command) => upscale::run(command, &bytes).ok(),
+            None => Some(bytes),
+        }
+    }
+}
+
+/// Download an image with the async client, like fetch does but without a
+/// progress bar over the one being shown, or a conditional request. It's sent
+/// the same way, kept within the rate limit for the host and tried again
+#[cfg(all(feature = "network", feature = "async"))]
+async fn fetch_ahead(
+    image_url: &str,
+    referer: Option<String>,
+) -> Result<(Vec<u8>, cache::Validators), WaifuError> {
+    use reqwest::header;
+
+    let mut request = pipeline::client()?.get(image_url);
+    if let Some(referer) = referer {
+        request = request.header(header::REFERER, referer);
+    }
+    let mut resp = crate::api::nonblocking::send(&net::retry(), request).await?;
+
+    let ct = resp
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string);
+    ORIGINS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .insert(
+            image_url.to_string(),
+            Origin {
+                url: resp.url().to_string(),
+                content_type: ct.clone(),
+            },
+        );
+
+    let ct = ct.unwrap_or_default();
+    let media = ct.starts_with("image/") || ct.starts_with("video/");
+    if !resp.status().is_success() || (!ct.is_empty() && !media) {
+        let message = format!("Failed to fetch image: HTTP {}", resp.status());
+        return Err(WaifuError::Network(message));
+    }
+    if let Some(len) = resp
+        .content_length()
+        .filter(|&len| len > MAX_IMAGE_BYTES as u64)
+    {
+        return Err(format!(
+            "Image too large ({} bytes > {} bytes)",
+            len, MAX_IMAGE_BYTES
+        )
+        .into());
+    }
+
+    let validators = cache::Validators::from_headers(resp.headers());
+    let mut body = Vec::new();
+    while let Some(chunk) = resp.chunk().await? {
+        body.extend_from_slice(&chunk);
+        if body.len() > MAX_IMAGE_BYTES {
+            return Err(format!("Image too large (more than {} bytes)", MAX_IMAGE_BYTES).into());
+        }
+    }
+
+    Ok((body, validators))
+}
+
+fn show_image_with_path(image_path: PathBuf, settings: &Settings) -> Result<(), WaifuError> {
+    let origin = ImageDetails {
+        path: Some(image_path.display().to_string()),
+        ..ImageDetails::default()
+    };
+    // Viewers can usually open videos themselves
+    if no_image() {
+        describe_instead(&std::fs::read(&image_path)?, origin)?;
+    } else if let (Some(viewer), None) = (&settings.viewer, &settings.upscale_cmd) {
+        render::viewer::open(viewer, &image_path)?;
+    } else {
+        let bytes = load_path(&image_path, settings)?;
+        match &settings.viewer {
+            Some(viewer) => render::viewer::open_bytes(viewer, &bytes)?,
+            None => print_bytes(&bytes, settings)?,
+        }
+    }
+
+    *UP.lock().unwrap_or_else(PoisonError::into_inner) = Some(image_path.display().to_string());
+    #[cfg(feature = "json")]
+    remember_last(Shown::Path(image_path), settings);
+
+    Ok(())
+}
+
+#[cfg(feature = "network")]
+fn show_images_with_urls(args: Url, settings: &Settings) -> Result<(), WaifuError> {
+    let show = |image_url: &String| -> Result<(), WaifuError> {
+        show_url(image_url, args.details, settings)?;
+        // A post page's details have its source already
+        if args.sauce && !is_post_page(image_url) {
+            crate::sauce::find_for_url(image_url)?.print()?;
+        }
+
+        Ok(())
+    };
+
+    let mut image_urls = args.image_urls;
+    if let ([image_url], false) = (image_urls.as_slice(), args.slideshow.enabled) {
+        return show(image_url);
+    }
+
+    show_each(
+        &mut image_urls,
+        &args.slideshow,
+        false,
+        settings,
+        |image_url| show(image_url).map_err(|error| format!("{}: {}", image_url, error).into()),
+        |image_url| prefetch(image_url, settings),
+    )
+}
+
+fn show_images_with_paths(
+    mut image_paths: Vec<PathBuf>,
+    args: &File,
+    settings: &Settings,
+) -> Result<(), WaifuError> {
+    let slideshow = &args.slideshow;
+    let read = |image_path: &PathBuf| {
+        std::fs::read(image_path)
+            .map_err(|e| format!("Couldn't read {}: {}", image_path.display(), e))
+    };
+    let show = |image_path: &PathBuf| -> Result<(), WaifuError> {
+        if args.details {
+            ImageDetails {
+                path: Some(image_path.display().to_string()),
+                ..ImageDetails::of(&read(image_path)?)?
+            }
+            .print()?;
+        }
+        show_image_with_path(image_path.clone(), settings)?;
+        #[cfg(feature = "network")]
+        if args.sauce {
+            crate::sauce::find_for_file(&read(image_path)?)?.print()?;
+        }
+
+        Ok(())
+    };
+    if let ([image_path], false) = (image_paths.as_slice(), slideshow.enabled) {
+        return show(image_path);
+    }
+
+    show_each(
+        &mut image_paths,
+        slideshow,
+        false,
+        settings,
+        |image_path| {
+            show(image_path).map_err(|error| format!("{}: {}", image_path.display(), error).into())
+        },
+        |image_path| prefetch(&image_path.to_string_lossy(), settings),
+    )
+}
+
+fn show_images_from_list(args: Play, settings: &Settings) -> Result<(), WaifuError> {
+    use std::io::{BufRead, BufReader};
+
+    if settings.stdout.is_some() {
+        return Err("Only one image can be written to --stdout".into());
+    }
+
+    let reader: Box<dyn BufRead> = if args.list == Path::new("-") {
+        Box::new(std::io::stdin().lock())
+    } else {
+        let file = std::fs::File::open(&args.list)
+            .map_err(|e| format!("Couldn't read {}: {}", args.list.display(), e))?;
+        Box::new(BufReader::new(file))
+    };
+    let mut lines = reader
+        .lines()
+        .map_while(Result::ok)
+        .map(|line| line.trim().to_string())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'));
+
+    let mut show = |line: &String| -> Result<(), WaifuError> {
+        let shown = if line.starts_with("http://") || line.starts_with("https://") {
+            show_url(line, false, settings)
+        } else {
+            show_image_with_path(PathBuf::from(line), settings)
+        };
+        shown.map_err(|error| format!("{}: {}", line, error).into())
+    };
+
+    // Going round again means keeping the whole list, otherwise
+    // each image is shown as soon as its line comes in
+    if args.slideshow.repeat {
+        let mut items: Vec<String> = lines.collect();
+        return show_each(&mut items, &args.slideshow, false, settings, show, |line| {
+            prefetch(line, settings)
+        });
+    }
+
+    let (mut total, mut failed) = (0, 0);
+    for line in &mut lines {
+        total += 1;
+        if !show_in_turn(&line, &args.slideshow, &mut show)? {
+            failed += 1;
+        }
+    }
+
+    if failed > 0 {
+        return Err(format!("{} of {} images couldn't be shown", failed, total).into());
+    }
+
+    Ok(())
+}
+
+fn show_image_from_dir(args: Dir, settings: &Settings) -> Result<(), WaifuError> {
+    use rand::seq::SliceRandom;
+
+    if args.watch {
+        if settings.stdout.is_some() {
+            return Err("Only one image can be written to --stdout".into());
+        }
+        if !quiet() {
+            eprintln!("Watching {} for new images", args.dir_path.display());
+        }
+        return local::watch(&args.dir_path, args.recursive, |image_path| {
+            let _ = clear_screen();
+            if let Err(error) = show_image_with_path(image_path.to_path_buf(), settings) {
+                eprintln!("{}: {}", image_path.display(), error);
+            }
+        });
+    }
+
+    let mut images = local::images(&args.dir_path, args.recursive)?;
+    if images.is_empty() {
+        return Err(format!("There are no images in {}", args.dir_path.display()).into());
+    }
+
+    if !args.slideshow.enabled {
+        let image_path = images
+            .choose(&mut random::rng())
+            .cloned()
+            .unwrap_or_default();
+        return show_image_with_path(image_path, settings);
+    }
+
+    show_each(
+        &mut images,
+        &args.slideshow,
+        true,
+        settings,
+        |image_path| {
+            show_image_with_path(image_path.clone(), settings)
+                .map_err(|error| format!("{}: {}", image_path.display(), error).into())
+        },
+        |image_path| prefetch(&image_path.to_string_lossy(), settings),
+    )
+}
+
+/// Show several images in turn, either one after another or as a slideshow.
+/// One that can't be shown doesn't stop the rest. In a slideshow the next
+/// image is prefetched while the current one is up
+fn show_each<T: Sync>(
+    items: &mut [T],
+    slideshow: &Slideshow,
+    shuffle: bool,
+    settings: &Settings,
+    mut show: impl FnMut(&T) -> Result<(), WaifuError>,
+    prefetch: impl Fn(&T) + Sync,
+) -> Result<(), WaifuError> {
+    use rand::seq::SliceRandom;
+
+    if settings.stdout.is_some() {
+        return Err("Only one image can be written to --stdout".into());
+    }
+
+    let mut failed = 0;
+    loop {
+        if shuffle {
+            items.shuffle(&mut random::rng());
+        }
+        for (i, item) in items.iter().enumerate() {
+            let next = items.get(i + 1).filter(|_| slideshow.enabled);
+            // Prefetching only starts the next one on the runtime
+            #[cfg(all(feature = "network", feature = "async"))]
+            let shown = {
+                if let Some(next) = next {
+                    prefetch(next);
+                }
+                show_in_turn(item, slideshow, &mut show)?
+            };
+            #[cfg(not(all(feature = "network", feature = "async")))]
+            let shown = std::thread::scope(|scope| {
+                if let Some(next) = next {
+                    scope.spawn(|| prefetch(next));
+                }
+                show_in_turn(item, slideshow, &mut show)
+            })?;
+            if !shown {
+                failed += 1;
+            }
+        }
+
+        if !slideshow.enabled || !slideshow.repeat {
+            break;
+        }
+    }
+
+    if failed > 0 {
+        return Err(format!("{} of {} images couldn't be shown", failed, items.len()).into());
+    }
+
+    Ok(())
+}
+
+/// Show one image of several, returning whether that worked
+fn show_in_turn<T>(
+    item: &T,
+    slideshow: &Slideshow,
+    show: &mut impl FnMut(&T) -> Result<(), WaifuError>,
+) -> Result<bool, WaifuError> {
+    if slideshow.enabled {
+        clear_screen()?;
+    }
+    let shown = show(item).map_err(|error| eprintln!("{}", error)).is_ok();
+    if slideshow.enabled {
+        wait_for_keys(slideshow.interval)?;
+    }
+
+    Ok(shown)
+}
+
+/// The page or file of the image that's up, for the o key to open
+static UP: Mutex<Option<String>> = Mutex::new(None);
+
+/// Wait while an image of a slideshow is up, opening its page, or the file, in
+/// the browser when o is pressed
+fn wait_for_keys(interval: Duration) -> Result<(), WaifuError> {
+    use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+    use crossterm::terminal;
+    use std::time::Instant;
+
+    if !std::io::stdin().is_terminal() || terminal::enable_raw_mode().is_err() {
+        std::thread::sleep(interval);
+        return Ok(());
+    }
+
+    let end = Instant::now() + interval;
+    let mut interrupted = false;
+    let waited = (|| -> Result<(), WaifuError> {
+        loop {
+            let left = end.saturating_duration_since(Instant::now());
+            if left.is_zero() || !event::poll(left)? {
+                return Ok(());
+            }
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            match key.code {
+                KeyCode::Char('o') => {
+                    let up = UP.lock().unwrap_or_else(PoisonError::into_inner).clone();
+                    // Raw mode wants the carriage return too
+                    if let Some(Err(error)) = up.map(|up| browser::open(&up)) {
+                        eprint!("{}\r\n", error);
+                    }
+                }
+                // Raw mode keeps Ctrl-C from stopping the slideshow itself
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    interrupted = true;
+                    return Ok(());
+                }
+                _ => {}
+            }
+        }
+    })();
+    terminal::disable_raw_mode()?;
+    // As the shell has it for a program stopped by Ctrl-C
+    if interrupted {
+        std::process::exit(130);
+    }
+
+    waited
+}
+
+/// Animations would otherwise never let the next image have its turn
+fn play_once(settings: &mut Settings) {
+    if settings.loops.is_none() {
+        settings.loops = Some(1);
+    }
+}
+
+fn clear_screen() -> std::io::Result<()> {
+    use std::io::Write;
+
+    // Descriptions are kept, one after another
+    if std::io::stdout().is_terminal() && !no_image() {
+        print!("\x1b[2J\x1b[H");
+        std::io::stdout().flush()?;
+    }
+
+    Ok(())
+}
+
+/// Swap a video for a still frame of it, when there's a command to grab one
+fn poster_frame(bytes: Vec<u8>, settings: &Settings) -> Result<Vec<u8>, WaifuError> {
+    let Some(kind) = video::sniff(&bytes) else {
+        return Ok(bytes);
+    };
+
+    match &settings.poster_cmd {
+        Some(command) => video::poster(command, &bytes, kind),
+        None => Err(format!(
+            "This is a video ({}) rather than an image. To show a frame of it, pass e.g. \
+             --poster-cmd 'ffmpeg -loglevel error -i {{in}} -frames:v 1 {{out}}'",
+            kind
+        )
+        .into()),
+    }
+}
+
+/// With --no-image, print a sentence about the image and where it's from
+/// instead of drawing it, giving back whether it was
+fn describe_instead(bytes: &[u8], origin: ImageDetails) -> Result<bool, WaifuError> {
+    if !no_image() {
+        return Ok(false);
+    }
+
+    // A video is described by what's known without a frame of it
+    let (details, noun) = match video::sniff(bytes) {
+        Some(kind) => (
+            ImageDetails {
+                format: Some(kind.to_uppercase()),
+                size: bytes.len(),
+                ..ImageDetails::default()
+            },
+            "video",
+        ),
+        None => (ImageDetails::of(bytes)?, "image"),
+    };
+    let details = ImageDetails {
+        path: origin.path,
+        url: origin.url,
+        ..details
+    };
+    println!("{}", details.describe(noun));
+
+    Ok(true)
+}
+
+/// Print an image, playing it when it's animated
+fn print_bytes(bytes: &[u8], settings: &Settings) -> Result<(), WaifuError> {
+    #[cfg(feature = "animation")]
+    if let Some(frames) = animation::frames(bytes) {
+        return animation::play(&frames, settings);
+    }
+    render::print(&transform::decode(bytes)?, settings)
+}
+
+/// Read and decode an image file
+fn open_file(image_path: &Path) -> Result<image::DynamicImage, WaifuError> {
+    let bytes = std::fs::read(image_path)?;
+    Ok(transform::decode(&bytes)?)
+}
+
+// Removed old stdin helper; stdin is handled inline in run()
+
+#[cfg(all(test, feature = "json"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checks_templates() {
+        assert!(check_template("").is_ok());
+        assert!(check_template("{site} #{id}: {tags} ({width}x{height})").is_ok());
+        // Only words in braces are placeholders
+        assert!(check_template("{ id } {} {{").is_ok());
+
+        let error = check_template("{id} {uploader}").unwrap_err().to_string();
+        assert!(
+            error.starts_with("--details-format has no {uploader}"),
+            "{}",
+            error
+        );
+        assert!(error.contains("{site}, {id}"), "{}", error);
+    }
+}
@@ -0,0 +1,209 @@
+use clap::ValueEnum;
+#[cfg(feature = "json")]
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "network")]
+use std::sync::OnceLock;
+
+#[cfg(feature = "network")]
+use crate::error::WaifuError;
+
+/// A site with a login of its own
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Site {
+    #[value(name = "dan")]
+    Danbooru,
+    #[value(name = "gel")]
+    Gelbooru,
+    E621,
+    #[value(name = "derpi")]
+    Derpibooru,
+    Wallhaven,
+    /// For looking up where images came from with --sauce
+    #[value(name = "saucenao")]
+    SauceNao,
+    /// For uploading to it with `waifu share --to imgur`
+    Imgur,
+}
+
+impl Site {
+    pub const ALL: [Site; 7] = [
+        Site::Danbooru,
+        Site::Gelbooru,
+        Site::E621,
+        Site::Derpibooru,
+        Site::Wallhaven,
+        Site::SauceNao,
+        Site::Imgur,
+    ];
+
+    /// Its name in the config file and the keyring
+    #[cfg(feature = "network")]
+    pub fn name(self) -> &'static str {
+        match self {
+            Site::Danbooru => "danbooru",
+            Site::Gelbooru => "gelbooru",
+            Site::E621 => "e621",
+            Site::Derpibooru => "derpibooru",
+            Site::Wallhaven => "wallhaven",
+            Site::SauceNao => "saucenao",
+            Site::Imgur => "imgur",
+        }
+    }
+
+    /// What its environment variables start with, e.g. DANBOORU_API_KEY
+    fn env_prefix(self) -> &'static str {
+        match self {
+            Site::Danbooru => "DANBOORU",
+            Site::Gelbooru => "GELBOORU",
+            Site::E621 => "E621",
+            Site::Derpibooru => "DERPIBOORU",
+            Site::Wallhaven => "WALLHAVEN",
+            Site::SauceNao => "SAUCENAO",
+            Site::Imgur => "IMGUR",
+        }
+    }
+
+    /// The parts its login is made of. Derpibooru, Wallhaven and SauceNAO only want
+    /// the key, and Imgur an app's client ID, given as the key
+    pub fn fields(self) -> &'static [Field] {
+        match self {
+            Site::Danbooru | Site::E621 => &[Field::Username, Field::ApiKey],
+            Site::Gelbooru => &[Field::UserId, Field::ApiKey],
+            Site::Derpibooru | Site::Wallhaven | Site::SauceNao | Site::Imgur => &[Field::ApiKey],
+        }
+    }
+}
+
+/// One part of a login
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Field {
+    Username,
+    UserId,
+    ApiKey,
+}
+
+impl Field {
+    /// How it's asked for when logging in
+    #[cfg(feature = "network")]
+    pub fn label(self) -> &'static str {
+        match self {
+            Field::Username => "Username",
+            Field::UserId => "User ID",
+            Field::ApiKey => "API key",
+        }
+    }
+
+    /// Its name in the config file
+    #[cfg(any(feature = "danbooru", feature = "safebooru"))]
+    pub fn key(self) -> &'static str {
+        match self {
+            Field::Username => "username",
+            Field::UserId => "user_id",
+            Field::ApiKey => "api_key",
+        }
+    }
+
+    fn env_suffix(self) -> &'static str {
+        match self {
+            Field::Username => "USERNAME",
+            Field::UserId => "USER_ID",
+            Field::ApiKey => "API_KEY",
+        }
+    }
+}
+
+/// A login for one booru, from the environment, the config file or the system
+/// keyring. Whichever parts the site doesn't use stay empty
+#[derive(Debug, Default, Clone)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "json", serde(default, deny_unknown_fields))]
+pub struct Credentials {
+    pub username: Option<String>,
+    pub user_id: Option<String>,
+    pub api_key: Option<String>,
+}
+
+impl Credentials {
+    pub fn get(&self, field: Field) -> &Option<String> {
+        match field {
+            Field::Username => &self.username,
+            Field::UserId => &self.user_id,
+            Field::ApiKey => &self.api_key,
+        }
+    }
+
+    #[cfg(feature = "network")]
+    pub fn get_mut(&mut self, field: Field) -> &mut Option<String> {
+        match field {
+            Field::Username => &mut self.username,
+            Field::UserId => &mut self.user_id,
+            Field::ApiKey => &mut self.api_key,
+        }
+    }
+
+    #[cfg(feature = "network")]
+    fn is_empty(&self) -> bool {
+        self.username.is_none() && self.user_id.is_none() && self.api_key.is_none()
+    }
+}
+
+/// The environment variable for one part of a site's login, e.g. E621_API_KEY
+pub fn env_name(site: Site, field: Field) -> String {
+    format!("{}_{}", site.env_prefix(), field.env_suffix())
+}
+
+/// The login for a site. The environment variables win, which is also where the
+/// config file's logins end up. The keyring is only asked when they have nothing
+#[cfg(feature = "network")]
+pub fn credentials(site: Site) -> Credentials {
+    let mut login = Credentials::default();
+    for &field in site.fields() {
+        *login.get_mut(field) = std::env::var(env_name(site, field)).ok();
+    }
+    if !login.is_empty() {
+        return login;
+    }
+
+    keyring(site).clone().unwrap_or_default()
+}
+
+#[cfg(feature = "network")]
+const SERVICE: &str = "waifu";
+
+#[cfg(feature = "network")]
+fn entry(site: Site) -> Result<keyring::Entry, WaifuError> {
+    keyring::Entry::new(SERVICE, site.name())
+        .map_err(|e| format!("Couldn't open the system keyring: {}", e).into())
+}
+
+/// Keep a login in the keyring, replacing the one that was there
+#[cfg(feature = "network")]
+pub fn save(site: Site, login: &Credentials) -> Result<(), WaifuError> {
+    let secret = serde_json::to_string(login)?;
+    entry(site)?
+        .set_password(&secret)
+        .map_err(|e| format!("Couldn't save the login in the system keyring: {}", e).into())
+}
+
+/// Take a login out of the keyring. Gives back whether there was one
+#[cfg(feature = "network")]
+pub fn delete(site: Site) -> Result<bool, WaifuError> {
+    match entry(site)?.delete_credential() {
+        Ok(()) => Ok(true),
+        Err(keyring::Error::NoEntry) => Ok(false),
+        Err(e) => Err(format!("Couldn't remove the login from the system keyring: {}", e).into()),
+    }
+}
+
+/// The site's login in the keyring, if there's one and the keyring can be
+/// reached. Each is only looked up once a run
+#[cfg(feature = "network")]
+fn keyring(site: Site) -> &'static Option<Credentials> {
+    static LOGINS: [OnceLock<Option<Credentials>>; Site::ALL.len()] =
+        [const { OnceLock::new() }; Site::ALL.len()];
+
+    LOGINS[site as usize].get_or_init(|| {
+        let secret = entry(site).ok()?.get_password().ok()?;
+        serde_json::from_str(&secret).ok()
+    })
+}
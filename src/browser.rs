@@ -0,0 +1,39 @@
+use std::io;
+use std::process::{Command, Stdio};
+
+use crate::error::WaifuError;
+
+/// Programs that open a URL in the default browser, tried in order. wslview is
+/// for Linux under WSL, where xdg-open usually has nothing to hand it to
+fn programs() -> &'static [&'static [&'static str]] {
+    if cfg!(target_os = "macos") {
+        &[&["open"]]
+    } else if cfg!(windows) {
+        &[&["rundll32", "url.dll,FileProtocolHandler"]]
+    } else {
+        &[&["xdg-open"], &["wslview"]]
+    }
+}
+
+/// Open the URL in the default browser with the first of the usual programs
+/// that's installed
+pub fn open(url: &str) -> Result<(), WaifuError> {
+    for program in programs() {
+        let status = Command::new(program[0])
+            .args(&program[1..])
+            .arg(url)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status();
+        match status {
+            Ok(status) if status.success() => return Ok(()),
+            // e.g. xdg-open without a browser to hand it to
+            Ok(status) => tracing::debug!("{} couldn't open the URL: {}", program[0], status),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(format!("Couldn't run {}: {}", program[0], e).into()),
+        }
+    }
+
+    Err("Couldn't find a program to open the browser with, like xdg-open or open".into())
+}
@@ -0,0 +1,510 @@
+use reqwest::blocking::RequestBuilder;
+use reqwest::header::{self, HeaderMap};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, FileTimes};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::dirs;
+use crate::error::WaifuError;
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+use crate::history;
+
+/// Where downloaded images are kept, usually `~/.cache/waifu/images`
+pub fn dir() -> Result<PathBuf, WaifuError> {
+    Ok(dirs::cache()?.join("images"))
+}
+
+// API responses are kept apart, so they don't count towards the images
+fn api_dir() -> Result<PathBuf, WaifuError> {
+    Ok(dirs::cache()?.join("api"))
+}
+
+// So are the posts from recent searches
+fn search_dir() -> Result<PathBuf, WaifuError> {
+    Ok(dirs::cache()?.join("searches"))
+}
+
+// And the images `waifu daemon` gets ready
+fn pool_dir() -> Result<PathBuf, WaifuError> {
+    Ok(dirs::cache()?.join("pool"))
+}
+
+/// What a server said identifies the version of a response it sent, for asking
+/// it later whether that has changed
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct Validators {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_modified: Option<String>,
+}
+
+impl Validators {
+    pub fn from_headers(headers: &HeaderMap) -> Validators {
+        let header = |name| {
+            headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string)
+        };
+
+        Validators {
+            etag: header(header::ETAG),
+            last_modified: header(header::LAST_MODIFIED),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.etag.is_none() && self.last_modified.is_none()
+    }
+
+    /// Make the request conditional, so the server can answer 304 Not Modified
+    pub fn apply(&self, mut request: RequestBuilder) -> RequestBuilder {
+        if let Some(etag) = &self.etag {
+            request = request.header(header::IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = &self.last_modified {
+            request = request.header(header::IF_MODIFIED_SINCE, last_modified);
+        }
+        request
+    }
+}
+
+/// A cached copy of what was downloaded from a URL
+pub struct Cached {
+    pub bytes: Vec<u8>,
+    /// Still within its TTL, so it can be used without asking the server
+    pub fresh: bool,
+    pub validators: Validators,
+}
+
+/// The cached image from a URL. One older than `ttl` is only given back when the
+/// server can be asked whether it has changed
+pub fn get(url: &str, ttl: Duration) -> Option<Cached> {
+    let path = path(&dir().ok()?, url);
+    let validators = read_validators(&path, url)?;
+    let age = fs::metadata(&path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| SystemTime::now().duration_since(modified).ok())?;
+
+    let fresh = age <= ttl;
+    if !fresh && validators.is_empty() {
+        return None;
+    }
+    let bytes = fs::read(&path).ok()?;
+
+    // The access time says when it was last used, for evicting the least recently
+    // used images first. Filesystems mounted with noatime don't keep it up themselves
+    if let Ok(file) = fs::File::options().write(true).open(&path) {
+        let _ = file.set_times(FileTimes::new().set_accessed(SystemTime::now()));
+    }
+
+    Some(Cached {
+        bytes,
+        fresh,
+        validators,
+    })
+}
+
+/// Keep a copy of an image downloaded from a URL, then make room in the cache
+/// for it by evicting the least recently used images over `limit` bytes
+pub fn put(url: &str, bytes: &[u8], validators: &Validators, limit: u64) -> Result<(), WaifuError> {
+    write(&path(&dir()?, url), bytes, Some(url), validators)?;
+    evict(entries()?, limit)?;
+
+    Ok(())
+}
+
+/// Start an image's TTL over, after the server said it hasn't changed
+pub fn refresh(url: &str) -> Result<(), WaifuError> {
+    let file = fs::File::options().write(true).open(path(&dir()?, url))?;
+    file.set_times(FileTimes::new().set_modified(SystemTime::now()))?;
+
+    Ok(())
+}
+
+/// The last response from an API URL, which is always checked with the server
+/// before it's used
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+pub fn get_api(url: &str) -> Option<Cached> {
+    let path = path(&api_dir().ok()?, url);
+    let validators = read_validators(&path, url)?;
+    if validators.is_empty() {
+        return None;
+    }
+
+    Some(Cached {
+        bytes: fs::read(&path).ok()?,
+        fresh: false,
+        validators,
+    })
+}
+
+/// Keep a response from an API URL, for when the server says it hasn't changed
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+pub fn put_api(url: &str, bytes: &[u8], validators: &Validators) -> Result<(), WaifuError> {
+    write(&path(&api_dir()?, url), bytes, Some(url), validators)
+}
+
+/// The posts left from a search made within the last `ttl`, and when it was made
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+pub fn get_search(url: &str, ttl: Duration) -> Option<(Vec<u8>, SystemTime)> {
+    let path = path(&search_dir().ok()?, url);
+    // There are no validators, just the URL to check
+    read_validators(&path, url)?;
+    let made = fs::metadata(&path)
+        .and_then(|metadata| metadata.modified())
+        .ok()?;
+    if SystemTime::now().duration_since(made).ok()? > ttl {
+        return None;
+    }
+
+    Some((fs::read(&path).ok()?, made))
+}
+
+/// Keep the posts left from a search. It stays dated from when the search was
+/// made, so taking posts from it doesn't keep it around for longer
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+pub fn put_search(url: &str, bytes: &[u8], made: SystemTime) -> Result<(), WaifuError> {
+    let path = path(&search_dir()?, url);
+    write(&path, bytes, Some(url), &Validators::default())?;
+    fs::File::options()
+        .write(true)
+        .open(&path)?
+        .set_times(FileTimes::new().set_modified(made))?;
+
+    Ok(())
+}
+
+/// How many images `waifu daemon` has ready for a search
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+pub fn pooled(search: &str) -> usize {
+    pooled_images(search).len()
+}
+
+/// Take the image `waifu daemon` got ready first for a search, and its post
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+pub fn take_pooled(search: &str) -> Option<(Vec<u8>, history::Entry)> {
+    for path in pooled_images(search) {
+        let meta = path.with_extension("json");
+        let (Ok(bytes), Ok(text)) = (fs::read(&path), fs::read_to_string(&meta)) else {
+            continue;
+        };
+        // Another run may have taken it in the meantime
+        if fs::remove_file(&path).is_err() {
+            continue;
+        }
+        let _ = fs::remove_file(meta);
+
+        if let Ok(entry) = serde_json::from_str(&text) {
+            return Some((bytes, entry));
+        }
+    }
+
+    None
+}
+
+/// Get an image ready for a search, to be shown later
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+pub fn pool(search: &str, bytes: &[u8], entry: &history::Entry) -> Result<(), WaifuError> {
+    let dir = path(&pool_dir()?, search);
+    fs::create_dir_all(&dir)?;
+    // Named by a hash like the rest, so the search is kept to check against
+    fs::write(dir.join(POOL_SEARCH), search)?;
+
+    let stamp = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or(0);
+    let path = dir.join(format!("{:020}", stamp));
+    // The post goes first, so the image is never there without it
+    fs::write(path.with_extension("json"), serde_json::to_string(entry)?)?;
+    write(&path, bytes, None, &Validators::default())
+}
+
+/// The file in a pool's directory saying which search it's for
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+const POOL_SEARCH: &str = "search.txt";
+
+// Oldest first
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+fn pooled_images(search: &str) -> Vec<PathBuf> {
+    let Some(dir) = pool_dir().ok().map(|dir| path(&dir, search)) else {
+        return Vec::new();
+    };
+    if fs::read_to_string(dir.join(POOL_SEARCH)).ok().as_deref() != Some(search) {
+        return Vec::new();
+    }
+    let mut images: Vec<PathBuf> = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_none())
+        .collect();
+    images.sort();
+
+    images
+}
+
+/// The image `waifu motd` shows next, and the post it's from
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+pub fn get_motd() -> Option<(Vec<u8>, history::Entry)> {
+    let dir = dirs::cache().ok()?;
+    let entry = serde_json::from_str(&fs::read_to_string(dir.join("motd.json")).ok()?).ok()?;
+
+    Some((fs::read(dir.join("motd")).ok()?, entry))
+}
+
+/// Keep the image for the next `waifu motd`
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+pub fn put_motd(bytes: &[u8], entry: &history::Entry) -> Result<(), WaifuError> {
+    let dir = dirs::cache()?;
+    write(&dir.join("motd"), bytes, None, &Validators::default())?;
+    fs::write(dir.join("motd.json"), serde_json::to_string(entry)?)?;
+
+    Ok(())
+}
+
+/// What's kept next to an entry. It's named by a hash of its URL, which another
+/// URL could have too, so the URL is kept to check it's the one asked for
+#[derive(Serialize, Deserialize, Default)]
+struct Meta {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    url: String,
+    #[serde(flatten)]
+    validators: Validators,
+}
+
+fn write(
+    path: &Path,
+    bytes: &[u8],
+    url: Option<&str>,
+    validators: &Validators,
+) -> Result<(), WaifuError> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    // Written next to it first, so a run that's cut short never leaves half an image
+    let partial = path.with_extension("part");
+    fs::write(&partial, bytes)?;
+    fs::rename(&partial, path)?;
+
+    let meta = Meta {
+        url: url.unwrap_or_default().to_string(),
+        validators: validators.clone(),
+    };
+    if meta.url.is_empty() && validators.is_empty() {
+        let _ = fs::remove_file(meta_path(path));
+    } else {
+        fs::write(meta_path(path), serde_json::to_string(&meta)?)?;
+    }
+
+    Ok(())
+}
+
+/// The validators of the entry for the URL, None when there isn't one or it's
+/// another URL's with the same hash
+fn read_validators(path: &Path, url: &str) -> Option<Validators> {
+    let text = fs::read_to_string(meta_path(path)).ok()?;
+    let meta: Meta = serde_json::from_str(&text).ok()?;
+
+    (meta.url == url).then_some(meta.validators)
+}
+
+fn meta_path(path: &Path) -> PathBuf {
+    path.with_extension("meta")
+}
+
+/// A cached image
+struct Entry {
+    path: PathBuf,
+    size: u64,
+    downloaded: SystemTime,
+    used: SystemTime,
+}
+
+impl Entry {
+    fn expired(&self, ttl: Duration) -> bool {
+        SystemTime::now()
+            .duration_since(self.downloaded)
+            .is_ok_and(|age| age > ttl)
+    }
+}
+
+/// What's in the cache
+pub struct Stats {
+    pub images: usize,
+    pub size: u64,
+    /// Images older than the TTL, which are only used again if the server
+    /// says they haven't changed
+    pub expired: usize,
+}
+
+pub fn stats(ttl: Duration) -> Result<Stats, WaifuError> {
+    let entries = entries()?;
+
+    Ok(Stats {
+        images: entries.len(),
+        size: entries.iter().map(|entry| entry.size).sum(),
+        expired: entries.iter().filter(|entry| entry.expired(ttl)).count(),
+    })
+}
+
+/// Remove expired images, then the least recently used ones until the cache is
+/// within `limit` bytes. Returns how many images were removed and the bytes freed
+pub fn prune(ttl: Duration, limit: u64) -> Result<(usize, u64), WaifuError> {
+    let (expired, entries): (Vec<_>, Vec<_>) =
+        entries()?.into_iter().partition(|entry| entry.expired(ttl));
+
+    let mut removed = (0, 0);
+    for entry in expired {
+        remove(&entry)?;
+        removed = (removed.0 + 1, removed.1 + entry.size);
+    }
+    let (evicted, freed) = evict(entries, limit)?;
+
+    Ok((removed.0 + evicted, removed.1 + freed))
+}
+
+/// Remove every cached image, API response and search, and the images got ready
+pub fn clear() -> Result<(), WaifuError> {
+    for dir in [dir()?, api_dir()?, search_dir()?, pool_dir()?] {
+        match fs::remove_dir_all(dir) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+            _ => (),
+        }
+    }
+
+    Ok(())
+}
+
+fn remove(entry: &Entry) -> Result<(), WaifuError> {
+    fs::remove_file(&entry.path)?;
+    let _ = fs::remove_file(meta_path(&entry.path));
+
+    Ok(())
+}
+
+// Remove the least recently used entries until the rest fit in `limit` bytes
+fn evict(mut entries: Vec<Entry>, limit: u64) -> Result<(usize, u64), WaifuError> {
+    let mut size: u64 = entries.iter().map(|entry| entry.size).sum();
+    entries.sort_by_key(|entry| entry.used);
+
+    let mut removed = (0, 0);
+    for entry in entries {
+        if size <= limit {
+            break;
+        }
+        remove(&entry)?;
+        size -= entry.size;
+        removed = (removed.0 + 1, removed.1 + entry.size);
+    }
+
+    Ok(removed)
+}
+
+// Every cached image, leaving out ones still being written and validators
+fn entries() -> Result<Vec<Entry>, WaifuError> {
+    let dir = dir()?;
+    let read_dir = match fs::read_dir(&dir) {
+        Ok(read_dir) => read_dir,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(format!("Couldn't read {}: {}", dir.display(), e).into()),
+    };
+
+    let mut entries = Vec::new();
+    for dir_entry in read_dir.flatten() {
+        let path = dir_entry.path();
+        let Ok(metadata) = dir_entry.metadata() else {
+            continue;
+        };
+        if !metadata.is_file() || path.extension().is_some() {
+            continue;
+        }
+
+        let downloaded = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+        entries.push(Entry {
+            path,
+            size: metadata.len(),
+            downloaded,
+            used: metadata.accessed().unwrap_or(downloaded).max(downloaded),
+        });
+    }
+
+    Ok(entries)
+}
+
+/// Parse a size like 500M or 2G, in bytes unless it ends in K, M or G
+pub fn parse_size(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let split = s
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+
+    let unit = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kb" | "kib" => 1 << 10,
+        "m" | "mb" | "mib" => 1 << 20,
+        "g" | "gb" | "gib" => 1 << 30,
+        _ => return Err(format!("'{}' isn't a size like 500M or 2G", s)),
+    };
+    match number.parse::<f64>() {
+        Ok(number) if number.is_finite() => Ok((number * unit as f64) as u64),
+        _ => Err(format!("'{}' isn't a size like 500M or 2G", s)),
+    }
+}
+
+fn path(dir: &Path, url: &str) -> PathBuf {
+    dir.join(format!("{:016x}", fnv1a(url.as_bytes())))
+}
+
+// Unlike std's hasher this is guaranteed to give the same key in every build
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_sizes() {
+        assert_eq!(parse_size("500"), Ok(500));
+        assert_eq!(parse_size("500B"), Ok(500));
+        assert_eq!(parse_size("2k"), Ok(2048));
+        assert_eq!(parse_size("500M"), Ok(500 << 20));
+        assert_eq!(parse_size(" 1.5 GiB "), Ok(3 << 29));
+        assert_eq!(parse_size("2gb"), Ok(2 << 30));
+    }
+
+    #[test]
+    fn rejects_what_isnt_a_size() {
+        for size in ["", "M", "2T", "big", "1.2.3M", "-5M"] {
+            assert!(parse_size(size).is_err(), "{:?}", size);
+        }
+    }
+
+    #[test]
+    fn only_gives_back_the_urls_own_entry() {
+        let dir = std::env::temp_dir().join(format!("waifu-cache-{}", std::process::id()));
+        let path = path(&dir, "https://example.com/a.png");
+        let validators = Validators {
+            etag: Some("\"a\"".to_string()),
+            last_modified: None,
+        };
+        write(&path, b"a", Some("https://example.com/a.png"), &validators).unwrap();
+
+        let read = read_validators(&path, "https://example.com/a.png");
+        assert_eq!(read.and_then(|read| read.etag), validators.etag);
+        // Another URL that happened to hash the same
+        assert!(read_validators(&path, "https://example.com/b.png").is_none());
+        let _ = fs::remove_dir_all(dir);
+    }
+}
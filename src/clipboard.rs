@@ -0,0 +1,90 @@
+use base64::{engine::general_purpose, Engine};
+use clap::ValueEnum;
+use is_terminal::IsTerminal;
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+
+use crate::error::WaifuError;
+use crate::render::tmux;
+
+/// What --copy puts in the clipboard
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Field {
+    /// The image's URL
+    Url,
+    /// The post's tags, separated by spaces
+    Tags,
+    /// Where the art was originally posted, or else the post's page
+    Source,
+}
+
+/// Programs that take what to copy on stdin, tried in order. On X11 and Wayland
+/// they stay behind to hand out what was copied after waifu exits, which a
+/// clipboard library inside waifu couldn't
+const PROGRAMS: [&[&str]; 6] = [
+    &["pbcopy"],
+    &["wl-copy"],
+    &["xclip", "-selection", "clipboard"],
+    &["xsel", "--clipboard", "--input"],
+    &["clip.exe"],
+    &["clip"],
+];
+
+/// Put the text in the clipboard with the first of the usual programs that's
+/// installed. Over SSH, or when there's none, the terminal is asked to with OSC 52,
+/// which most support
+pub fn copy(text: &str) -> Result<(), WaifuError> {
+    let over_ssh =
+        std::env::var_os("SSH_TTY").is_some() || std::env::var_os("SSH_CONNECTION").is_some();
+    if !over_ssh {
+        for program in PROGRAMS {
+            if run(program, text)? {
+                return Ok(());
+            }
+        }
+    }
+
+    if !io::stdout().is_terminal() {
+        return Err(
+            "Couldn't find a program to copy with, like wl-copy, xclip or pbcopy, \
+             and the terminal can't be asked to when stdout isn't one"
+                .into(),
+        );
+    }
+    let mut sequence = format!("\x1b]52;c;{}\x07", general_purpose::STANDARD.encode(text));
+    if tmux::inside_tmux() {
+        sequence = tmux::wrap(&sequence);
+    }
+    let mut stdout = io::stdout();
+    stdout.write_all(sequence.as_bytes())?;
+    stdout.flush()?;
+
+    Ok(())
+}
+
+/// Whether the program was there to copy the text. Any other failure is an error
+fn run(program: &[&str], text: &str) -> Result<bool, WaifuError> {
+    let child = Command::new(program[0])
+        .args(&program[1..])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn();
+    let mut child = match child {
+        Ok(child) => child,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(format!("Couldn't run {}: {}", program[0], e).into()),
+    };
+
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(text.as_bytes())?;
+    }
+    let status = child.wait()?;
+    if !status.success() {
+        // e.g. xclip without an X server to talk to
+        tracing::debug!("{} couldn't copy: {}", program[0], status);
+        return Ok(false);
+    }
+
+    Ok(true)
+}
@@ -0,0 +1,487 @@
+#[cfg(feature = "config")]
+use serde::Deserialize;
+#[cfg(feature = "config")]
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+#[cfg(feature = "config")]
+use std::fs;
+use std::path::PathBuf;
+
+use crate::auth::{self, Credentials, Site};
+use crate::dirs;
+use crate::error::WaifuError;
+#[cfg(feature = "config")]
+pub use crate::rating::Rating;
+
+/// Defaults read from the config file, which anything given on the command line
+/// overrides. Built without the file, they're all left empty
+#[derive(Debug, Default)]
+#[cfg_attr(feature = "config", derive(Deserialize))]
+#[cfg_attr(feature = "config", serde(default, deny_unknown_fields))]
+pub struct Config {
+    /// What to show when no subcommand is given
+    #[cfg(feature = "config")]
+    pub default: Defaults,
+    /// Options for every run, by their long name, e.g. `width = "50%"` or `no-history = true`
+    #[cfg(feature = "config")]
+    pub options: BTreeMap<String, toml::Value>,
+    /// What --download-via-hook runs on each image shown, e.g. `gallery-dl {url}`
+    pub downloader_cmd: Option<String>,
+    /// Logins, which the environment variables for them win over
+    pub danbooru: Credentials,
+    pub gelbooru: Credentials,
+    pub e621: Credentials,
+    pub derpibooru: Credentials,
+    pub wallhaven: Credentials,
+    pub saucenao: Credentials,
+    pub imgur: Credentials,
+    /// Searches to use by name, e.g. `koishi = "komeiji_koishi rating:g"` for `-t @koishi`
+    #[cfg(feature = "config")]
+    pub aliases: BTreeMap<String, String>,
+    /// Tags for --preset by name, for each booru, e.g. `[presets.cats] safe = "cat"`
+    /// These win over the built in ones of the same name
+    #[cfg(feature = "config")]
+    pub presets: BTreeMap<String, Preset>,
+    pub theme: Theme,
+}
+
+/// The tags a preset searches for on each booru
+#[cfg(feature = "config")]
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Preset {
+    pub safe: Option<String>,
+    pub dan: Option<String>,
+}
+
+#[cfg(feature = "config")]
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Defaults {
+    pub source: Option<Source>,
+    pub tags: Option<String>,
+    pub rating: Option<Rating>,
+    /// Where Safebooru searches go when it doesn't answer, e.g. "tbib.org", or
+    /// "none" for nowhere
+    pub mirror: Option<String>,
+}
+
+/// A booru to pick images from
+#[cfg(feature = "config")]
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Source {
+    Safe,
+    Dan,
+}
+
+/// Colors to print details with, to match the terminal's own
+#[derive(Debug, Default)]
+#[cfg_attr(feature = "config", derive(Deserialize))]
+#[cfg_attr(feature = "config", serde(default, deny_unknown_fields))]
+pub struct Theme {
+    /// The labels in front of each detail, like Artist or Tags
+    pub label: Option<ThemeColor>,
+}
+
+/// A color by name, like "cyan" or "bright blue", or in hex like "#ff79c6"
+#[derive(Debug, Clone, Copy)]
+pub struct ThemeColor(pub colored::Color);
+
+#[cfg(feature = "config")]
+impl<'de> Deserialize<'de> for ThemeColor {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        let hex = name
+            .strip_prefix('#')
+            .filter(|hex| hex.len() == 6)
+            .and_then(|hex| u32::from_str_radix(hex, 16).ok());
+        let color = match hex {
+            Some(rgb) => Ok(colored::Color::TrueColor {
+                r: (rgb >> 16) as u8,
+                g: (rgb >> 8) as u8,
+                b: rgb as u8,
+            }),
+            None => name.parse(),
+        };
+
+        color.map(ThemeColor).map_err(|_| {
+            serde::de::Error::custom(format!(
+                "'{}' isn't a color like \"cyan\", \"bright blue\" or \"#ff79c6\"",
+                name
+            ))
+        })
+    }
+}
+
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+pub const BUILT_IN_PRESETS: [&str; 4] = ["scenery", "cozy", "seasonal", "chibi"];
+
+/// Tags that find good images of each kind without knowing the boorus' tags.
+/// Danbooru's are kept to one tag, as searches without a login can only have two.
+/// Seasonal goes by the month it is in the northern hemisphere
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+fn built_in_preset(name: &str) -> Option<Preset> {
+    let (safe, dan) = match name {
+        "scenery" => ("scenery no_humans", "scenery"),
+        "cozy" => ("indoors blanket", "kotatsu"),
+        "seasonal" => {
+            // Like 2026-10-14T10:22:31Z
+            let now = humantime::format_rfc3339(std::time::SystemTime::now()).to_string();
+            match now[5..7].parse().unwrap_or(1) {
+                3..=5 => ("cherry_blossoms", "cherry_blossoms"),
+                6..=8 => ("summer beach", "summer"),
+                9..=11 => ("autumn_leaves", "autumn_leaves"),
+                _ => ("snow winter", "snow"),
+            }
+        }
+        "chibi" => ("chibi", "chibi"),
+        _ => return None,
+    };
+
+    Some(Preset {
+        safe: Some(safe.to_string()),
+        dan: Some(dan.to_string()),
+    })
+}
+
+/// Where the config file is kept, usually `~/.config/waifu/config.toml`
+pub fn path() -> Result<PathBuf, WaifuError> {
+    Ok(dirs::config()?.join("config.toml"))
+}
+
+/// Read the config file, with WAIFU_SOURCE, WAIFU_TAGS and WAIFU_RATING taking
+/// the place of its defaults. Having none is the same as an empty one
+#[cfg(feature = "config")]
+pub fn load() -> Result<Config, WaifuError> {
+    let path = path()?;
+    let text = match fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(format!("Couldn't read {}: {}", path.display(), e).into()),
+    };
+
+    let mut config: Config = toml::from_str(&text).map_err(|e| {
+        WaifuError::Config(format!("{} isn't a valid config: {}", path.display(), e))
+    })?;
+    config.default.apply_env()?;
+
+    Ok(config)
+}
+
+// Without the feature there's no file to read, so it's always an empty one
+#[cfg(not(feature = "config"))]
+pub fn load() -> Result<Config, WaifuError> {
+    Ok(Config::default())
+}
+
+#[cfg(feature = "config")]
+impl Defaults {
+    fn apply_env(&mut self) -> Result<(), WaifuError> {
+        fn var<T: serde::de::DeserializeOwned>(
+            name: &str,
+            expected: &str,
+        ) -> Result<Option<T>, WaifuError> {
+            let Ok(value) = std::env::var(name) else {
+                return Ok(None);
+            };
+            toml::Value::String(value)
+                .try_into()
+                .map(Some)
+                .map_err(|_| WaifuError::Config(format!("{} has to be {}", name, expected)))
+        }
+
+        if let Some(source) = var("WAIFU_SOURCE", "safe or dan")? {
+            self.source = Some(source);
+        }
+        if let Some(tags) = var("WAIFU_TAGS", "a string")? {
+            self.tags = Some(tags);
+        }
+        if let Some(rating) = var("WAIFU_RATING", "general, safe, questionable or explicit")? {
+            self.rating = Some(rating);
+        }
+
+        Ok(())
+    }
+}
+
+/// Options set in the environment as WAIFU_ and their long name, e.g.
+/// WAIFU_WIDTH=50% or WAIFU_NO_HISTORY=1, as command line arguments to go
+/// between the config file's and the ones actually given. Options given more
+/// than once, like --header, take a line each
+pub fn env_args(command: &clap::Command) -> Result<Vec<OsString>, WaifuError> {
+    use clap::ArgAction;
+
+    let mut args = Vec::new();
+    for arg in command.get_arguments() {
+        let Some(long) = arg.get_long() else {
+            continue;
+        };
+        let name = format!("WAIFU_{}", long.to_uppercase().replace('-', "_"));
+        let Some(value) = std::env::var_os(&name) else {
+            continue;
+        };
+
+        match arg.get_action() {
+            ArgAction::SetTrue | ArgAction::Count => {
+                match value.to_string_lossy().to_lowercase().as_str() {
+                    "1" | "true" | "yes" | "on" => args.push(format!("--{}", long).into()),
+                    "" | "0" | "false" | "no" | "off" => (),
+                    _ => {
+                        return Err(WaifuError::Config(format!(
+                            "{} has to be true or false",
+                            name
+                        )))
+                    }
+                }
+            }
+            ArgAction::Append => {
+                for line in value.to_string_lossy().lines().filter(|l| !l.is_empty()) {
+                    args.push(format!("--{}={}", long, line).into());
+                }
+            }
+            ArgAction::Set => {
+                let mut flag = OsString::from(format!("--{}=", long));
+                flag.push(value);
+                args.push(flag);
+            }
+            _ => (),
+        }
+    }
+
+    Ok(args)
+}
+
+impl Config {
+    /// The options as command line arguments, to go ahead of the ones actually given
+    #[cfg(feature = "config")]
+    pub fn args(&self) -> Result<Vec<OsString>, WaifuError> {
+        let mut args = Vec::new();
+        for (name, value) in &self.options {
+            let flag = format!("--{}", name);
+            let values = match value {
+                toml::Value::Array(values) => values.as_slice(),
+                value => std::slice::from_ref(value),
+            };
+            for value in values {
+                match value {
+                    toml::Value::Boolean(true) => args.push(flag.clone().into()),
+                    toml::Value::Boolean(false) => (),
+                    // Joined with = for options like --no-repeat whose value is optional
+                    toml::Value::String(value) => args.push(format!("{}={}", flag, value).into()),
+                    toml::Value::Integer(_) | toml::Value::Float(_) => {
+                        args.push(format!("{}={}", flag, value).into())
+                    }
+                    _ => {
+                        return Err(WaifuError::Config(format!(
+                            "The {} option in the config has to be a string, number or boolean",
+                            name
+                        )))
+                    }
+                }
+            }
+        }
+
+        Ok(args)
+    }
+
+    #[cfg(not(feature = "config"))]
+    pub fn args(&self) -> Result<Vec<OsString>, WaifuError> {
+        Ok(Vec::new())
+    }
+
+    /// Swap every @name in the tags for the search the config has under that alias
+    #[cfg(any(feature = "danbooru", feature = "safebooru"))]
+    pub fn expand_aliases(&self, tags: Option<String>) -> Result<Option<String>, WaifuError> {
+        let Some(tags) = tags else {
+            return Ok(None);
+        };
+
+        let mut expanded = Vec::new();
+        for tag in tags.split(|c: char| c == ',' || c.is_whitespace()) {
+            let tag = match tag.strip_prefix('@') {
+                Some(name) => self.aliases.get(name).ok_or_else(|| {
+                    WaifuError::Config(format!(
+                        "There's no alias called {} in the config file",
+                        name
+                    ))
+                })?,
+                None => tag,
+            };
+            expanded.push(tag);
+        }
+
+        Ok(Some(expanded.join(" ")))
+    }
+
+    /// Add the tags of the preset for the booru to the search. The config
+    /// file's presets win over the built in ones
+    #[cfg(any(feature = "danbooru", feature = "safebooru"))]
+    pub fn apply_preset(
+        &self,
+        preset: Option<&str>,
+        source: Source,
+        tags: Option<String>,
+    ) -> Result<Option<String>, WaifuError> {
+        let Some(name) = preset else {
+            return Ok(tags);
+        };
+        let preset = self
+            .presets
+            .get(name)
+            .cloned()
+            .or_else(|| built_in_preset(name))
+            .ok_or_else(|| {
+                let names: Vec<&str> = BUILT_IN_PRESETS
+                    .iter()
+                    .copied()
+                    .chain(self.presets.keys().map(String::as_str))
+                    .collect();
+                WaifuError::Config(format!(
+                    "There's no preset called {}, only {}",
+                    name,
+                    names.join(", ")
+                ))
+            })?;
+        let (preset_tags, booru) = match source {
+            Source::Safe => (preset.safe, "Safebooru"),
+            Source::Dan => (preset.dan, "Danbooru"),
+        };
+        let preset_tags = preset_tags.ok_or_else(|| {
+            WaifuError::Config(format!("The {} preset has no tags for {}", name, booru))
+        })?;
+
+        Ok(Some(match tags {
+            Some(tags) => format!("{} {}", preset_tags, tags),
+            None => preset_tags,
+        }))
+    }
+
+    /// The login the config file has for a site
+    pub fn login(&self, site: Site) -> &Credentials {
+        match site {
+            Site::Danbooru => &self.danbooru,
+            Site::Gelbooru => &self.gelbooru,
+            Site::E621 => &self.e621,
+            Site::Derpibooru => &self.derpibooru,
+            Site::Wallhaven => &self.wallhaven,
+            Site::SauceNao => &self.saucenao,
+            Site::Imgur => &self.imgur,
+        }
+    }
+
+    /// Make the logins available the same way as the environment variables for
+    /// them, which win when they're set too
+    pub fn apply_logins(&self) {
+        for site in Site::ALL {
+            for &field in site.fields() {
+                let name = auth::env_name(site, field);
+                if let (Some(value), None) = (self.login(site).get(field), std::env::var_os(&name))
+                {
+                    std::env::set_var(name, value);
+                }
+            }
+        }
+    }
+}
+
+/// The value a key like `default.tags` has in the config file, if it's set
+#[cfg(feature = "config")]
+pub fn get(key: &str) -> Result<Option<String>, WaifuError> {
+    let document = read_document()?;
+    let mut item = document.as_item();
+    for part in key.split('.') {
+        match item.get(part) {
+            Some(next) => item = next,
+            None => return Ok(None),
+        }
+    }
+
+    // Strings are printed as they are rather than quoted
+    Ok(match item.as_str() {
+        Some(value) => Some(value.to_string()),
+        None => Some(item.to_string().trim().to_string()),
+    })
+}
+
+/// Set a key like `default.tags` in the config file, keeping everything else as
+/// it was. The value is read as TOML when it can be, e.g. `true` or `["a", "b"]`,
+/// otherwise it's a string
+#[cfg(feature = "config")]
+pub fn set(key: &str, value: &str) -> Result<(), WaifuError> {
+    let mut document = read_document()?;
+    let value = match value.parse::<toml_edit::Value>() {
+        Ok(value) => toml_edit::Item::Value(value),
+        Err(_) => toml_edit::value(value),
+    };
+    put(&mut document, key, value)?;
+
+    write_text(document.to_string())
+        .map_err(|e| WaifuError::Config(format!("Can't set {}: {}", key, e)))
+}
+
+/// Start a new config file with each of the keys set to a string, replacing
+/// any that was there
+#[cfg(feature = "config")]
+pub fn create(values: &[(String, String)]) -> Result<(), WaifuError> {
+    let mut document = toml_edit::DocumentMut::new();
+    for (key, value) in values {
+        put(&mut document, key, toml_edit::value(value))?;
+    }
+
+    let header = "# Made by `waifu setup`. See the README for everything that can go here\n";
+    write_text(format!("{}{}", header, document))
+}
+
+#[cfg(feature = "config")]
+fn put(
+    document: &mut toml_edit::DocumentMut,
+    key: &str,
+    value: toml_edit::Item,
+) -> Result<(), WaifuError> {
+    use toml_edit::{Item, Table};
+
+    let (parents, name) = match key.rsplit_once('.') {
+        Some((parents, name)) => (parents.split('.').collect(), name),
+        None => (Vec::new(), key),
+    };
+
+    let mut table = document.as_table_mut();
+    for parent in parents {
+        table = table
+            .entry(parent)
+            .or_insert_with(|| Item::Table(Table::new()))
+            .as_table_mut()
+            .ok_or_else(|| WaifuError::Config(format!("{} in {} isn't a table", parent, key)))?;
+    }
+    table[name] = value;
+
+    Ok(())
+}
+
+/// Only written when waifu can still read it
+#[cfg(feature = "config")]
+fn write_text(text: String) -> Result<(), WaifuError> {
+    toml::from_str::<Config>(&text)?;
+
+    let path = path()?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(&path, text).map_err(|e| format!("Couldn't write {}: {}", path.display(), e))?;
+
+    Ok(())
+}
+
+#[cfg(feature = "config")]
+fn read_document() -> Result<toml_edit::DocumentMut, WaifuError> {
+    let path = path()?;
+    let text = match fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(format!("Couldn't read {}: {}", path.display(), e).into()),
+    };
+
+    text.parse()
+        .map_err(|e| WaifuError::Config(format!("{} isn't valid TOML: {}", path.display(), e)))
+}
@@ -0,0 +1,64 @@
+use directories::ProjectDirs;
+use std::path::PathBuf;
+
+use crate::error::WaifuError;
+
+/// Where cached images and API responses go: `$WAIFU_CACHE_DIR` when it's set,
+/// otherwise the platform's cache directory, e.g. `~/.cache/waifu` (or under
+/// `$XDG_CACHE_HOME`) on Linux and `~/Library/Caches/waifu` on macOS
+#[cfg(feature = "network")]
+pub fn cache() -> Result<PathBuf, WaifuError> {
+    if let Some(dir) = env("WAIFU_CACHE_DIR") {
+        return Ok(dir);
+    }
+
+    Ok(project()?.cache_dir().to_path_buf())
+}
+
+/// Where the history goes: `$WAIFU_DATA_DIR` when it's set, otherwise the
+/// platform's data directory, e.g. `~/.local/share/waifu` (or under
+/// `$XDG_DATA_HOME`) on Linux and `~/Library/Application Support/waifu` on macOS
+#[cfg(feature = "json")]
+pub fn data() -> Result<PathBuf, WaifuError> {
+    if let Some(dir) = env("WAIFU_DATA_DIR") {
+        return Ok(dir);
+    }
+
+    Ok(project()?.data_dir().to_path_buf())
+}
+
+/// Where the config file goes: `$WAIFU_CONFIG_DIR` when it's set, otherwise the
+/// platform's config directory, e.g. `~/.config/waifu` (or under `$XDG_CONFIG_HOME`)
+/// on Linux and `~/Library/Application Support/waifu` on macOS
+pub fn config() -> Result<PathBuf, WaifuError> {
+    if let Some(dir) = env("WAIFU_CONFIG_DIR") {
+        return Ok(dir);
+    }
+
+    Ok(project()?.config_dir().to_path_buf())
+}
+
+/// Where --debug-dump keeps downloads: `$WAIFU_STATE_DIR` when it's set, otherwise
+/// the platform's state directory, e.g. `~/.local/state/waifu` (or under
+/// `$XDG_STATE_HOME`) on Linux. Platforms without one use the local data directory
+#[cfg(feature = "json")]
+pub fn state() -> Result<PathBuf, WaifuError> {
+    if let Some(dir) = env("WAIFU_STATE_DIR") {
+        return Ok(dir);
+    }
+
+    let project = project()?;
+    let dir = project.state_dir().unwrap_or(project.data_local_dir());
+    Ok(dir.to_path_buf())
+}
+
+fn project() -> Result<ProjectDirs, WaifuError> {
+    ProjectDirs::from("", "", "waifu")
+        .ok_or_else(|| "Couldn't find your home directory to keep waifu's files in".into())
+}
+
+fn env(name: &str) -> Option<PathBuf> {
+    std::env::var_os(name)
+        .filter(|value| !value.is_empty())
+        .map(PathBuf::from)
+}
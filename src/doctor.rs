@@ -0,0 +1,232 @@
+use std::fmt::Display;
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "danbooru")]
+use crate::app;
+#[cfg(feature = "network")]
+use crate::auth::{self, Site};
+use crate::config::{self, Config};
+use crate::error::WaifuError;
+use crate::render::{self, layout, tmux, ColorDepth, Protocol, Settings};
+use crate::theme;
+
+/// Print what waifu makes of the terminal it's in, whether it can reach the
+/// boorus and which logins it has, for working out why images don't look right
+#[cfg_attr(not(feature = "config"), allow(unused_variables))]
+pub fn run(settings: &Settings, config: &Config) -> Result<(), WaifuError> {
+    line("🩺", "Version", env!("CARGO_PKG_VERSION"));
+    line("🖥️", "Terminal", terminal());
+
+    let drawn_with = render::drawn_with(settings, &settings.config);
+    line("🖼️", "Graphics", drawn_with);
+    if drawn_with == "half blocks" {
+        for hint in block_hints(settings) {
+            line("💡", "Hint", hint);
+        }
+    }
+    let sixel = if cfg!(feature = "sixel") {
+        "built in"
+    } else {
+        "not built in, so sixel terminals get half blocks"
+    };
+    line("🔲", "Sixel", sixel);
+
+    let colors = match settings.colors {
+        ColorDepth::Truecolor => "truecolor",
+        ColorDepth::Ansi256 => "256",
+        ColorDepth::Ansi16 => "16",
+    };
+    let colorterm = std::env::var("COLORTERM").unwrap_or_default();
+    match colorterm.as_str() {
+        "" => line("🎨", "Colors", colors),
+        colorterm => line(
+            "🎨",
+            "Colors",
+            format!("{} (COLORTERM={})", colors, colorterm),
+        ),
+    }
+
+    line("🪟", "tmux", self::tmux());
+    let ssh = if render::over_ssh() {
+        "yes, so only kitty graphics are picked without --protocol"
+    } else {
+        "no"
+    };
+    line("🔐", "SSH", ssh);
+
+    let path = config::path()?;
+    let exists = if path.exists() {
+        ""
+    } else {
+        ", which isn't there"
+    };
+    line("📄", "Config", format!("{}{}", path.display(), exists));
+    #[cfg(feature = "config")]
+    if !config.options.is_empty() {
+        let options: Vec<&str> = config.options.keys().map(String::as_str).collect();
+        line("⚙️", "Options", options.join(", "));
+    }
+
+    #[cfg(any(feature = "danbooru", feature = "safebooru"))]
+    for (site, url) in BOORUS {
+        // --base-url may have Danbooru somewhere else
+        #[cfg(feature = "danbooru")]
+        let url = &url.replacen(crate::api::danbooru::DANBOORU, app::danbooru_url(), 1);
+        line("🌐", site, reach(url));
+    }
+
+    #[cfg(feature = "network")]
+    for site in Site::ALL {
+        line("🔑", site.name(), login(site, config));
+    }
+
+    Ok(())
+}
+
+fn line(icon: &str, title: &str, value: impl Display) {
+    println!("{} {}: {}", icon, theme::label(title), value);
+}
+
+/// TERM along with the program that set it and the size, e.g.
+/// "xterm-kitty (kitty 0.35.2), 120x40 cells of 9x18 pixels"
+fn terminal() -> String {
+    let term = std::env::var("TERM").unwrap_or_else(|_| "unknown".into());
+    let program = std::env::var("TERM_PROGRAM").ok().map(|program| {
+        match std::env::var("TERM_PROGRAM_VERSION") {
+            Ok(version) => format!(" ({} {})", program, version),
+            Err(_) => format!(" ({})", program),
+        }
+    });
+    let size = match crossterm::terminal::size() {
+        Ok((columns, rows)) => format!(", {}x{} cells", columns, rows),
+        Err(_) => ", not a terminal".to_string(),
+    };
+    // Graphics are only sized to the pixel when the terminal says how big its cells are
+    let cells = match layout::cell_size() {
+        Some((width, height)) => format!(" of {}x{} pixels", width, height),
+        None => " of an unknown size in pixels".to_string(),
+    };
+
+    format!("{}{}{}{}", term, program.unwrap_or_default(), size, cells)
+}
+
+/// Why images may be coming out as half blocks, and what to try instead
+fn block_hints(settings: &Settings) -> Vec<&'static str> {
+    let mut hints = Vec::new();
+    match settings.protocol {
+        Protocol::Blocks => hints.push("--protocol blocks was given"),
+        Protocol::Sixel if !cfg!(feature = "sixel") => {
+            hints.push("--protocol sixel needs waifu to be built with the sixel feature")
+        }
+        _ => {}
+    }
+    if tmux::inside_tmux() && tmux::outer_encoding().is_none() {
+        hints.push(
+            "tmux hides which terminal it's in, so try --tmux-passthrough always \
+             if that one has kitty or iTerm graphics",
+        );
+    }
+    if render::over_ssh() {
+        hints.push(
+            "Over SSH only kitty is detected, so try --protocol iterm or sixel \
+             if the terminal has them",
+        );
+    }
+    if hints.is_empty() {
+        hints.push(
+            "The terminal didn't answer for kitty, iTerm or sixel graphics. If it has \
+             them, say which with --protocol",
+        );
+    }
+
+    hints
+}
+
+fn tmux() -> String {
+    if !tmux::inside_tmux() {
+        return "no".into();
+    }
+
+    let outer = match tmux::outer_encoding() {
+        Some(render::graphics::Encoding::Kitty) => "in a terminal with kitty graphics",
+        Some(render::graphics::Encoding::Iterm) => "in a terminal with iTerm graphics",
+        None => "in a terminal without graphics waifu could find",
+    };
+    match tmux::passthrough_allowed() {
+        Some(false) => format!(
+            "yes, {}, but passthrough is off: `tmux set -g allow-passthrough on`",
+            outer
+        ),
+        _ => format!("yes, {}", outer),
+    }
+}
+
+/// The boorus built in, with a small request to send to each
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+const BOORUS: &[(&str, &str)] = &[
+    #[cfg(feature = "danbooru")]
+    ("Danbooru", "https://danbooru.donmai.us/posts.json?limit=1"),
+    #[cfg(feature = "safebooru")]
+    (
+        "Safebooru",
+        "https://safebooru.org/index.php?page=dapi&s=post&q=index&json=1&limit=1",
+    ),
+];
+
+/// Whether the booru answered, and how fast. Sent once, without the retries
+/// searches get, so one that's down doesn't hold the rest up
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+fn reach(url: &str) -> String {
+    let started = Instant::now();
+    let response = crate::net::client().and_then(|client| {
+        client
+            .get(url)
+            .timeout(Duration::from_secs(10))
+            .send()
+            .map_err(WaifuError::from)
+    });
+    let took = started.elapsed().as_millis();
+    match response {
+        Ok(response) if response.status().is_success() => {
+            format!("reachable, {} in {} ms", response.status(), took)
+        }
+        Ok(response) => format!("answered {} in {} ms", response.status(), took),
+        Err(error) => format!("unreachable: {}", error),
+    }
+}
+
+/// Which of the site's login parts are set and where from, never the values
+#[cfg(feature = "network")]
+fn login(site: Site, config: &Config) -> String {
+    let login = auth::credentials(site);
+    let missing: Vec<&str> = site
+        .fields()
+        .iter()
+        .filter(|&&field| login.get(field).is_none())
+        .map(|field| field.label())
+        .collect();
+    if missing.len() == site.fields().len() {
+        return "not logged in".into();
+    }
+
+    let from_config = site
+        .fields()
+        .iter()
+        .any(|&field| config.login(site).get(field).is_some());
+    let from_env = site
+        .fields()
+        .iter()
+        .any(|&field| std::env::var_os(auth::env_name(site, field)).is_some());
+    let from = if from_config {
+        "the config file"
+    } else if from_env {
+        "the environment"
+    } else {
+        "the keyring"
+    };
+    match missing.as_slice() {
+        [] => format!("logged in, from {}", from),
+        missing => format!("{} missing, from {}", missing.join(" and "), from),
+    }
+}
@@ -0,0 +1,150 @@
+use http::StatusCode;
+use thiserror::Error;
+
+use crate::exit::Code;
+
+/// What went wrong, by the kind of failure, so programs can tell a search that
+/// found nothing from the network being down
+#[derive(Debug, Error)]
+pub enum WaifuError {
+    /// A request couldn't be sent, or what it answered couldn't be read
+    #[error("{0}")]
+    Network(String),
+    /// The booru answered, but with an error or with something that isn't posts
+    #[error("{status}: {message}")]
+    Api { status: StatusCode, message: String },
+    /// What was downloaded isn't an image that can be decoded
+    #[error("{0}")]
+    Decode(String),
+    /// The search found no posts
+    #[error("{0}")]
+    NoResults(String),
+    /// The image couldn't be drawn on the terminal
+    #[error("{0}")]
+    Terminal(String),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    /// What reqwest couldn't send or read, as a network error
+    #[cfg(any(feature = "network", feature = "async"))]
+    #[error(transparent)]
+    Http(#[from] reqwest::Error),
+    /// JSON that couldn't be read or written
+    #[cfg(feature = "json")]
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    /// What the image crate couldn't decode or encode
+    #[cfg(feature = "cli")]
+    #[error(transparent)]
+    Image(#[from] image::ImageError),
+    /// The config file, an option or an argument that doesn't make sense
+    #[error("{0}")]
+    Config(String),
+    /// Anything else, said in words
+    #[error("{0}")]
+    Other(String),
+    /// Another error, with what might fix it
+    #[error("{error}")]
+    Help {
+        error: Box<WaifuError>,
+        help: String,
+    },
+}
+
+impl WaifuError {
+    /// An error the booru answered with
+    pub fn api(status: StatusCode, message: impl Into<String>) -> Self {
+        WaifuError::Api {
+            status,
+            message: message.into(),
+        }
+    }
+
+    /// What waifu exits with for it
+    pub fn code(&self) -> Code {
+        match self {
+            WaifuError::Network(_) | WaifuError::Api { .. } => Code::Network,
+            WaifuError::Decode(_) => Code::Decode,
+            WaifuError::NoResults(_) => Code::NoResults,
+            WaifuError::Terminal(_) => Code::Terminal,
+            #[cfg(any(feature = "network", feature = "async"))]
+            WaifuError::Http(_) => Code::Network,
+            #[cfg(feature = "cli")]
+            WaifuError::Image(_) => Code::Decode,
+            #[cfg(feature = "json")]
+            WaifuError::Json(_) => Code::Failure,
+            WaifuError::Io(_) | WaifuError::Config(_) | WaifuError::Other(_) => Code::Failure,
+            WaifuError::Help { error, .. } => error.code(),
+        }
+    }
+
+    /// What the error says might fix it
+    pub fn help(&self) -> Option<&str> {
+        match self {
+            WaifuError::Help { help, .. } => Some(help),
+            _ => None,
+        }
+    }
+
+    /// The error under the help, if there's any
+    pub fn without_help(&self) -> &WaifuError {
+        match self {
+            WaifuError::Help { error, .. } => error.without_help(),
+            error => error,
+        }
+    }
+}
+
+impl From<String> for WaifuError {
+    fn from(message: String) -> Self {
+        WaifuError::Other(message)
+    }
+}
+
+impl From<&str> for WaifuError {
+    fn from(message: &str) -> Self {
+        WaifuError::Other(message.to_string())
+    }
+}
+
+/// Errors of other crates that are only ever said, not matched on
+macro_rules! said {
+    ($($(#[$cfg:meta])* $error:ty),* $(,)?) => {
+        $(
+            $(#[$cfg])*
+            impl From<$error> for WaifuError {
+                fn from(error: $error) -> Self {
+                    WaifuError::Other(error.to_string())
+                }
+            }
+        )*
+    };
+}
+
+said!(
+    std::num::ParseIntError,
+    std::num::ParseFloatError,
+    std::string::FromUtf8Error,
+    std::str::Utf8Error,
+    std::fmt::Error,
+    std::time::SystemTimeError,
+    #[cfg(feature = "cli")]
+    regex::Error,
+    #[cfg(feature = "config")]
+    toml::de::Error,
+    #[cfg(feature = "config")]
+    toml::ser::Error,
+    #[cfg(feature = "config")]
+    toml_edit::TomlError,
+    #[cfg(feature = "cli")]
+    notify::Error,
+    #[cfg(feature = "cli")]
+    viuer::ViuError,
+    #[cfg(feature = "cli")]
+    glob::PatternError,
+    #[cfg(feature = "cli")]
+    glob::GlobError,
+    #[cfg(feature = "cli")]
+    humantime::DurationError,
+    #[cfg(feature = "network")]
+    keyring::Error,
+);
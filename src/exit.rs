@@ -0,0 +1,100 @@
+use std::error::Error;
+
+use crate::error::WaifuError;
+
+/// What waifu exits with when something goes wrong, so wrappers can tell a
+/// search that found nothing from the network being down
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Code {
+    /// Anything without a code of its own, like a bad argument
+    Failure = 1,
+    /// The search found no images
+    NoResults = 2,
+    /// A request couldn't be sent, or the server answered with an error
+    Network = 3,
+    /// The image couldn't be decoded
+    Decode = 4,
+    /// The image couldn't be drawn on the terminal
+    Terminal = 5,
+}
+
+/// Have the error say what might fix it, keeping the code it exits with
+pub fn with_help(error: impl Into<WaifuError>, help: impl Into<String>) -> WaifuError {
+    WaifuError::Help {
+        error: Box::new(error.into()),
+        help: help.into(),
+    }
+}
+
+/// What the error says might fix it
+pub fn help<'a>(error: &'a (dyn Error + 'static)) -> Option<&'a str> {
+    error.downcast_ref::<WaifuError>()?.help()
+}
+
+/// The code to exit with for an error. Those straight from reqwest are network
+/// errors and those from the image crate decode errors, as if each was a WaifuError
+pub fn code(error: &(dyn Error + 'static)) -> i32 {
+    code_of(error) as i32
+}
+
+fn code_of(error: &(dyn Error + 'static)) -> Code {
+    if let Some(error) = error.downcast_ref::<WaifuError>() {
+        error.code()
+    } else if is_network(error) {
+        Code::Network
+    } else if is_decode(error) {
+        Code::Decode
+    } else {
+        Code::Failure
+    }
+}
+
+#[cfg(any(feature = "network", feature = "async"))]
+fn is_network(error: &(dyn Error + 'static)) -> bool {
+    error.is::<reqwest::Error>()
+}
+
+// Nothing's downloaded without a client
+#[cfg(not(any(feature = "network", feature = "async")))]
+fn is_network(_: &(dyn Error + 'static)) -> bool {
+    false
+}
+
+#[cfg(feature = "cli")]
+fn is_decode(error: &(dyn Error + 'static)) -> bool {
+    error.is::<image::ImageError>()
+}
+
+// Nothing's decoded without the command
+#[cfg(not(feature = "cli"))]
+fn is_decode(_: &(dyn Error + 'static)) -> bool {
+    false
+}
+
+/// Print the error, and what might fix it unless --quiet was given, giving back
+/// the code to exit with
+#[cfg(feature = "cli")]
+pub fn report(error: &(dyn Error + 'static)) -> i32 {
+    use colored::Colorize;
+
+    eprintln!("{}", error);
+    if let Some(help) = help(error).filter(|_| !crate::app::quiet()) {
+        eprintln!("\n{}: {}", "help".green(), help);
+    }
+
+    code(error)
+}
+
+/// Print clap's error or help and exit. Bad arguments exit with 1 rather than
+/// clap's own 2, which is for searches that found nothing
+#[cfg(feature = "cli")]
+pub fn usage(error: clap::Error) -> ! {
+    let _ = error.print();
+    let code = if error.use_stderr() {
+        Code::Failure as i32
+    } else {
+        0
+    };
+
+    std::process::exit(code)
+}
@@ -0,0 +1,63 @@
+use std::fs;
+use std::path::PathBuf;
+
+use crate::dirs;
+use crate::error::WaifuError;
+use crate::history::Entry;
+
+/// Where favorites are kept, usually `~/.local/share/waifu/favorites.json`
+pub fn path() -> Result<PathBuf, WaifuError> {
+    Ok(dirs::data()?.join("favorites.json"))
+}
+
+/// Every favorite, in the order they were added
+pub fn load() -> Result<Vec<Entry>, WaifuError> {
+    let path = path()?;
+    match fs::read_to_string(&path) {
+        Ok(text) => serde_json::from_str(&text).map_err(|e| {
+            format!("Couldn't read the favorites in {}: {}", path.display(), e).into()
+        }),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(format!("Couldn't read {}: {}", path.display(), e).into()),
+    }
+}
+
+/// Add an image to the favorites. Returns false when it's one already
+pub fn add(entry: Entry) -> Result<bool, WaifuError> {
+    let mut favorites = load()?;
+    if favorites.iter().any(|favorite| favorite.url == entry.url) {
+        return Ok(false);
+    }
+
+    favorites.push(entry);
+    save(&favorites)?;
+
+    Ok(true)
+}
+
+/// Take the favorite at `index` out, giving it back when there was one
+pub fn remove(index: usize) -> Result<Option<Entry>, WaifuError> {
+    let mut favorites = load()?;
+    if index >= favorites.len() {
+        return Ok(None);
+    }
+
+    let removed = favorites.remove(index);
+    save(&favorites)?;
+
+    Ok(Some(removed))
+}
+
+fn save(favorites: &[Entry]) -> Result<(), WaifuError> {
+    let path = path()?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    // Written next to it first, so they can't be lost halfway through
+    let partial = path.with_extension("part");
+    fs::write(&partial, serde_json::to_string_pretty(favorites)?)?;
+    fs::rename(&partial, &path)?;
+
+    Ok(())
+}
@@ -0,0 +1,430 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+use std::collections::BTreeSet;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::api::{Post, Source};
+use crate::dirs;
+use crate::error::WaifuError;
+use crate::rating::Rating;
+
+/// One image that was shown, as a line of the history file
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Entry {
+    /// When it was shown, in seconds since the Unix epoch
+    pub time: u64,
+    /// "danbooru" or "safebooru" for posts, "url" for images shown with `waifu url`
+    pub site: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<u32>,
+    pub url: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub tags: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub artist: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub character: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    /// The post's page on its booru
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub page: String,
+    #[serde(default)]
+    pub nsfw: bool,
+}
+
+impl Entry {
+    /// An entry for a booru post shown just now
+    pub fn from_post(post: &Post) -> Entry {
+        Entry {
+            time: now(),
+            site: post.site.clone(),
+            id: Some(post.id),
+            url: post.url.clone(),
+            tags: post.tags.join(" "),
+            artist: post.artist.clone(),
+            character: post.character.clone(),
+            source: post.source.as_ref().map(Source::url),
+            page: post.page.clone(),
+            nsfw: post.nsfw(),
+        }
+    }
+
+    /// An entry for an image from a url shown just now
+    pub fn from_url(url: &str) -> Entry {
+        Entry {
+            time: now(),
+            site: "url".to_string(),
+            id: None,
+            url: url.to_string(),
+            tags: String::new(),
+            artist: None,
+            character: None,
+            source: None,
+            page: String::new(),
+            nsfw: false,
+        }
+    }
+
+    /// The post the entry was made from, so it can be shown the same way again.
+    /// Only whether it was nsfw is kept of its rating, and not its size
+    pub fn to_post(&self) -> Option<Post> {
+        let id = self.id?;
+        Some(Post {
+            site: self.site.clone(),
+            id,
+            url: self.url.clone(),
+            variant: None,
+            rating: self.nsfw.then_some(Rating::Questionable),
+            width: 0,
+            height: 0,
+            file_size: None,
+            tags: self.tags.split_whitespace().map(String::from).collect(),
+            artist: self.artist.clone(),
+            character: self.character.clone(),
+            source: self.source.as_deref().and_then(|url| Source::of(url, None)),
+            page: match self.page.as_str() {
+                // Entries from before the page was kept
+                "" => old_page(&self.site, id),
+                page => page.to_string(),
+            },
+        })
+    }
+
+    /// When it was shown, like 2026-10-14T10:22:31Z
+    pub fn shown_at(&self) -> String {
+        let time = UNIX_EPOCH + Duration::from_secs(self.time);
+        humantime::format_rfc3339_seconds(time).to_string()
+    }
+
+    /// "danbooru #123", or just the site when there's no post
+    pub fn post(&self) -> String {
+        match self.id {
+            Some(id) => format!("{} #{}", self.site, id),
+            None => self.site.clone(),
+        }
+    }
+
+    /// The post, or the URL when the image wasn't from one
+    pub fn name(&self) -> String {
+        match self.id {
+            Some(_) => self.post(),
+            None => self.url.clone(),
+        }
+    }
+}
+
+/// The page of a post on its site, for entries made before it was kept
+fn old_page(site: &str, id: u32) -> String {
+    match site {
+        "danbooru" => format!("{}/posts/{}", crate::api::danbooru::DANBOORU, id),
+        "gelbooru" => format!("https://gelbooru.com/index.php?page=post&s=view&id={}", id),
+        "tbib" => format!("https://tbib.org/index.php?page=post&s=view&id={}", id),
+        _ => format!("https://safebooru.org/index.php?page=post&s=view&id={}", id),
+    }
+}
+
+/// The image shown most recently, kept so `waifu last` can show it again
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Last {
+    /// The options it was shown with, as they were typed
+    pub args: Vec<String>,
+    pub image: Shown,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Shown {
+    Url(Entry),
+    Path(PathBuf),
+}
+
+/// The time now, in seconds since the Unix epoch
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Where the history is kept, usually `~/.local/share/waifu/history.jsonl`
+pub fn path() -> Result<PathBuf, WaifuError> {
+    Ok(dirs::data()?.join("history.jsonl"))
+}
+
+fn last_path() -> Result<PathBuf, WaifuError> {
+    Ok(dirs::data()?.join("last.json"))
+}
+
+/// Where the wallpapers `waifu wallpaper` set are listed, apart from the rest
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+fn wallpapers_path() -> Result<PathBuf, WaifuError> {
+    Ok(dirs::data()?.join("wallpapers.jsonl"))
+}
+
+/// The IDs of the posts from a site that were shown within the given time, e.g.
+/// to leave them out of searches
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+pub fn shown_within(site: &str, within: Duration) -> Result<BTreeSet<u32>, WaifuError> {
+    let since = now().saturating_sub(within.as_secs());
+
+    Ok(load()?
+        .into_iter()
+        .filter(|entry| entry.site == site && entry.time >= since)
+        .filter_map(|entry| entry.id)
+        .collect())
+}
+
+/// Add an entry to the end of the history
+pub fn record(entry: &Entry) -> Result<(), WaifuError> {
+    append(&path()?, entry)
+}
+
+/// Add an image that was made the wallpaper to the end of their list
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+pub fn record_wallpaper(entry: &Entry) -> Result<(), WaifuError> {
+    append(&wallpapers_path()?, entry)
+}
+
+fn append(path: &Path, entry: &Entry) -> Result<(), WaifuError> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}", serde_json::to_string(entry)?)?;
+
+    Ok(())
+}
+
+/// Every entry in the history, oldest first. Lines that can't be read are skipped
+pub fn load() -> Result<Vec<Entry>, WaifuError> {
+    read(&path()?)
+}
+
+/// Every image that was made the wallpaper, oldest first
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+pub fn load_wallpapers() -> Result<Vec<Entry>, WaifuError> {
+    read(&wallpapers_path()?)
+}
+
+fn read(path: &Path) -> Result<Vec<Entry>, WaifuError> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(format!("Couldn't read {}: {}", path.display(), e).into()),
+    };
+
+    Ok(text
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect())
+}
+
+/// Remember an image as the last one shown, along with this run's options
+pub fn record_last(image: Shown) -> Result<(), WaifuError> {
+    // `waifu last` may well be run from somewhere else
+    let image = match image {
+        Shown::Path(path) => Shown::Path(fs::canonicalize(&path).unwrap_or(path)),
+        image => image,
+    };
+    let last = Last {
+        args: std::env::args().skip(1).collect(),
+        image,
+    };
+
+    let path = last_path()?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(&path, serde_json::to_string(&last)?)?;
+
+    Ok(())
+}
+
+/// The image shown most recently, if there is one
+pub fn load_last() -> Result<Option<Last>, WaifuError> {
+    let path = last_path()?;
+    match fs::read_to_string(&path) {
+        Ok(text) => Ok(serde_json::from_str(&text).ok()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(format!("Couldn't read {}: {}", path.display(), e).into()),
+    }
+}
+
+/// The posts `--daily` picked for a search on a day, kept so the same ones are
+/// shown all day even after new posts come up
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Daily {
+    /// The day in UTC, e.g. "2024-05-01"
+    pub day: String,
+    /// The API URL searched, without any login in it
+    pub search: String,
+    pub posts: Vec<Entry>,
+}
+
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+fn daily_path() -> Result<PathBuf, WaifuError> {
+    Ok(dirs::data()?.join("daily.json"))
+}
+
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+fn load_dailies() -> Result<Vec<Daily>, WaifuError> {
+    let path = daily_path()?;
+    match fs::read_to_string(&path) {
+        Ok(text) => Ok(serde_json::from_str(&text).unwrap_or_default()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(format!("Couldn't read {}: {}", path.display(), e).into()),
+    }
+}
+
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+impl Daily {
+    pub fn new(day: &str, search: &str) -> Daily {
+        Daily {
+            day: day.to_string(),
+            search: search.to_string(),
+            posts: Vec::new(),
+        }
+    }
+
+    /// The posts picked for the search on the day so far, if any were
+    pub fn load(day: &str, search: &str) -> Result<Daily, WaifuError> {
+        let daily = load_dailies()?
+            .into_iter()
+            .find(|daily| daily.day == day && daily.search == search);
+
+        Ok(daily.unwrap_or_else(|| Daily::new(day, search)))
+    }
+
+    /// Add a post that was picked, forgetting the ones from other days. Failing
+    /// to write it down only means it may be picked again differently
+    pub fn keep(&mut self, entry: &Entry) {
+        self.posts.push(entry.clone());
+        let save = || -> Result<(), WaifuError> {
+            let mut dailies: Vec<Daily> = load_dailies()?
+                .into_iter()
+                .filter(|daily| daily.day == self.day && daily.search != self.search)
+                .collect();
+            dailies.push(Daily {
+                day: self.day.clone(),
+                search: self.search.clone(),
+                posts: self.posts.clone(),
+            });
+
+            let path = daily_path()?;
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            fs::write(&path, serde_json::to_string(&dailies)?)?;
+            Ok(())
+        };
+        if let Err(error) = save() {
+            eprintln!("Couldn't keep today's image: {}", error);
+        }
+    }
+}
+
+/// How often each thing came up in the history, most often first
+pub struct Stats {
+    /// Where the images came from, e.g. "danbooru" or "url"
+    pub sites: Vec<(String, usize)>,
+    pub artists: Vec<(String, usize)>,
+    pub characters: Vec<(String, usize)>,
+    pub tags: Vec<(String, usize)>,
+}
+
+/// Count the sites, artists, characters and tags of the entries. Posts with
+/// more than one artist or character count for each of them
+pub fn stats(entries: &[Entry]) -> Stats {
+    fn ranked<'a>(names: impl Iterator<Item = &'a str>) -> Vec<(String, usize)> {
+        let mut counts = BTreeMap::new();
+        for name in names {
+            *counts.entry(name).or_insert(0) += 1;
+        }
+        let mut ranked: Vec<(String, usize)> = counts
+            .into_iter()
+            .map(|(name, count)| (name.to_string(), count))
+            .collect();
+        // Ties stay in alphabetical order
+        ranked.sort_by_key(|&(_, count)| std::cmp::Reverse(count));
+        ranked
+    }
+    let words = |field: fn(&Entry) -> Option<&String>| {
+        ranked(
+            entries
+                .iter()
+                .filter_map(field)
+                .flat_map(|value| value.split_whitespace()),
+        )
+    };
+
+    Stats {
+        sites: ranked(entries.iter().map(|entry| entry.site.as_str())),
+        artists: words(|entry| entry.artist.as_ref()),
+        characters: words(|entry| entry.character.as_ref()),
+        tags: ranked(
+            entries
+                .iter()
+                .flat_map(|entry| entry.tags.split_whitespace()),
+        ),
+    }
+}
+
+/// How `waifu history export` writes the history out
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// A row for each entry with a header, the time as RFC 3339
+    Csv,
+    /// A list of the entries as they're kept, the time in seconds since the Unix epoch
+    Json,
+}
+
+/// The entries written out in the format, oldest first
+pub fn export(entries: &[Entry], format: ExportFormat) -> Result<String, WaifuError> {
+    if format == ExportFormat::Json {
+        return Ok(serde_json::to_string_pretty(entries)? + "\n");
+    }
+
+    // Quoted only when it has to be, with quotes inside doubled
+    let field = |value: &str| {
+        if value.contains([',', '"', '\n', '\r']) {
+            format!("\"{}\"", value.replace('"', "\"\""))
+        } else {
+            value.to_string()
+        }
+    };
+    let mut csv = String::from("time,site,id,url,tags,artist,character,source,nsfw\n");
+    for entry in entries {
+        let row = [
+            entry.shown_at(),
+            entry.site.clone(),
+            entry.id.map(|id| id.to_string()).unwrap_or_default(),
+            entry.url.clone(),
+            entry.tags.clone(),
+            entry.artist.clone().unwrap_or_default(),
+            entry.character.clone().unwrap_or_default(),
+            entry.source.clone().unwrap_or_default(),
+            entry.nsfw.to_string(),
+        ];
+        let row: Vec<String> = row.iter().map(|value| field(value)).collect();
+        csv.push_str(&row.join(","));
+        csv.push('\n');
+    }
+
+    Ok(csv)
+}
+
+/// Forget everything that was shown
+pub fn clear() -> Result<(), WaifuError> {
+    match fs::remove_file(path()?) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
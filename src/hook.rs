@@ -0,0 +1,75 @@
+use std::process::Command;
+
+use crate::error::WaifuError;
+
+/// Run a user supplied command line through the shell, filling in `{name}` placeholders.
+/// On Unix the values are handed over as positional parameters instead of being pasted
+/// into the command, so paths containing spaces or quotes can't break it.
+pub fn run(template: &str, values: &[(&str, &str)]) -> Result<(), WaifuError> {
+    let mut command = String::from(template);
+
+    let status = if cfg!(windows) {
+        for (name, value) in values {
+            command = command.replace(&format!("{{{}}}", name), &format!("\"{}\"", value));
+        }
+        Command::new("cmd").args(["/C", &command]).status()?
+    } else {
+        for (i, (name, _)) in values.iter().enumerate() {
+            command = command.replace(&format!("{{{}}}", name), &format!("\"${}\"", i + 1));
+        }
+        Command::new("sh")
+            .arg("-c")
+            .arg(&command)
+            .arg("waifu")
+            .args(values.iter().map(|(_, value)| value))
+            .status()?
+    };
+
+    if !status.success() {
+        return Err(format!("Command `{}` failed ({})", template, status).into());
+    }
+
+    Ok(())
+}
+
+/// Run a command that reads `{in}` and writes `{out}`, both temporary files, and
+/// return what it wrote. The input keeps `extension` and the output is a PNG,
+/// since most programs pick the format to write from the extension.
+pub fn run_on_bytes(template: &str, bytes: &[u8], extension: &str) -> Result<Vec<u8>, WaifuError> {
+    use std::fs;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    let stamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or(0);
+
+    let dir = std::env::temp_dir();
+    let input = dir.join(format!("waifu-{}-in.{}", stamp, extension));
+    let output = dir.join(format!("waifu-{}-out.png", stamp));
+    fs::write(&input, bytes)?;
+
+    let result = run(
+        template,
+        &[
+            ("in", &input.to_string_lossy()),
+            ("out", &output.to_string_lossy()),
+        ],
+    )
+    .and_then(|_| {
+        fs::read(&output).map_err(|e| {
+            format!(
+                "Command `{}` didn't write {}: {}",
+                template,
+                output.display(),
+                e
+            )
+            .into()
+        })
+    });
+
+    let _ = fs::remove_file(&input);
+    let _ = fs::remove_file(&output);
+
+    result
+}
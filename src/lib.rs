@@ -0,0 +1,117 @@
+//! View random anime fanart in your terminal. This is the library the `waifu`
+//! command is built on, for picking random posts from the boorus and drawing
+//! images in other programs, e.g. a status bar:
+//!
+//! ```no_run
+//! use std::collections::BTreeSet;
+//! use waifu::api::danbooru::{self, RandomSearch};
+//! use waifu::Rating;
+//!
+//! let search = RandomSearch {
+//!     tags: Some("hatsune_miku".to_string()),
+//!     ratings: vec![Rating::General],
+//!     ..RandomSearch::default()
+//! };
+//! let post = danbooru::Api::new().grab_random_image(&search, false, &BTreeSet::new())?;
+//! println!("{} by {}", post.url, post.artist.as_deref().unwrap_or("someone"));
+//! # Ok::<(), waifu::WaifuError>(())
+//! ```
+//!
+//! Errors are a [`WaifuError`], to tell what kind of failure it was, and carry
+//! the code the command exits with, see [`WaifuError::code`]:
+//!
+//! ```no_run
+//! # use std::collections::BTreeSet;
+//! # use waifu::api::danbooru;
+//! use waifu::WaifuError;
+//!
+//! let api = danbooru::Api::new();
+//! match api.grab_random_image(&Default::default(), false, &BTreeSet::new()) {
+//!     Ok(post) => println!("{}", post.url),
+//!     Err(WaifuError::NoResults(_)) => println!("Nothing found"),
+//!     Err(error) => eprintln!("{}", error),
+//! }
+//! ```
+//!
+//! Without the default `cli` feature only the core is built: the posts, the
+//! queries, and each booru's URLs, with `json` how it reads what they answer.
+//! There's no drawing in the terminal, blocking IO or HTTP client in it, so it
+//! compiles to wasm32 too, for a page that sends the requests itself:
+//!
+//! ```
+//! use waifu::api::{safebooru, Booru, Query, StatusCode};
+//!
+//! let api = safebooru::Api::new(safebooru::SAFEBOORU);
+//! let url = api.url(&Query::builder().tags(["scenery"]).build());
+//! // Fetch the URL, then
+//! let posts = api.parse(StatusCode::OK, br#"[{"id": 1, "tags": "scenery"}]"#)?;
+//! assert_eq!(posts[0].id, 1);
+//! # Ok::<(), waifu::WaifuError>(())
+//! ```
+
+/// The boorus' APIs, and the posts they give back
+pub mod api;
+#[cfg(feature = "cli")]
+mod app;
+#[cfg(feature = "cli")]
+mod auth;
+#[cfg(feature = "cli")]
+mod browser;
+#[cfg(feature = "network")]
+mod cache;
+#[cfg(feature = "cli")]
+mod clipboard;
+#[cfg(feature = "cli")]
+mod config;
+#[cfg(feature = "cli")]
+mod dirs;
+#[cfg(feature = "cli")]
+mod doctor;
+/// The kinds of failure, for matching on
+pub mod error;
+/// What waifu exits with when something goes wrong
+pub mod exit;
+#[cfg(all(feature = "cli", feature = "json"))]
+mod favorites;
+#[cfg(all(feature = "cli", feature = "json"))]
+mod history;
+#[cfg(feature = "cli")]
+mod hook;
+#[cfg(feature = "cli")]
+mod local;
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+mod md5;
+#[cfg(feature = "network")]
+mod net;
+#[cfg(feature = "cli")]
+mod palette;
+#[cfg(all(feature = "network", feature = "async"))]
+mod pipeline;
+#[cfg(feature = "cli")]
+mod random;
+mod rating;
+/// Drawing images in the terminal, and editing them first
+#[cfg(feature = "cli")]
+pub mod render;
+#[cfg(any(feature = "network", feature = "async"))]
+mod retry;
+#[cfg(feature = "network")]
+mod sauce;
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+mod serve;
+#[cfg(feature = "cli")]
+mod theme;
+#[cfg(feature = "network")]
+mod upload;
+#[cfg(feature = "cli")]
+mod upscale;
+mod video;
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+mod wallpaper;
+#[cfg(feature = "network")]
+mod webhook;
+
+#[cfg(feature = "cli")]
+pub use app::run;
+pub use error::WaifuError;
+pub use rating::Rating;
@@ -0,0 +1,133 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::error::WaifuError;
+
+/// Whether a file looks like something waifu can show, going by its extension
+pub fn is_image(path: &Path) -> bool {
+    const EXTRA: [&str; 5] = ["svg", "svgz", "avif", "heic", "heif"];
+
+    let Some(extension) = path.extension().and_then(|extension| extension.to_str()) else {
+        return false;
+    };
+    image::ImageFormat::from_extension(extension).is_some()
+        || EXTRA
+            .iter()
+            .any(|extra| extension.eq_ignore_ascii_case(extra))
+}
+
+/// The images inside a directory, and all the ones below it when `recursive`
+/// is set, sorted by path
+pub fn images(dir: &Path, recursive: bool) -> Result<Vec<PathBuf>, WaifuError> {
+    let mut images = Vec::new();
+    collect(dir, recursive, &mut images)
+        .map_err(|e| format!("Couldn't read {}: {}", dir.display(), e))?;
+    images.sort();
+
+    Ok(images)
+}
+
+fn collect(dir: &Path, recursive: bool, images: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for entry in fs::read_dir(dir)?.filter_map(|entry| entry.ok()) {
+        let path = entry.path();
+        // Symlinked directories aren't followed, since they can loop back up the tree
+        let is_dir = entry.file_type().is_ok_and(|kind| kind.is_dir());
+
+        if is_dir && recursive {
+            // A subdirectory that can't be read shouldn't spoil the rest of them
+            let _ = collect(&path, recursive, images);
+        } else if path.is_file() && is_image(&path) {
+            images.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+/// Watch a directory and call `show` with every image that turns up in it, once
+/// nothing has been written to the file for a moment. Runs until interrupted.
+pub fn watch(dir: &Path, recursive: bool, mut show: impl FnMut(&Path)) -> Result<(), WaifuError> {
+    use notify::event::{EventKind, ModifyKind};
+    use notify::{RecursiveMode, Watcher};
+    use std::collections::HashMap;
+    use std::sync::mpsc::{self, RecvTimeoutError};
+    use std::time::{Duration, Instant};
+
+    // Long enough for most programs to finish writing a file
+    const SETTLE: Duration = Duration::from_millis(400);
+
+    let (sender, receiver) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender)?;
+    let mode = if recursive {
+        RecursiveMode::Recursive
+    } else {
+        RecursiveMode::NonRecursive
+    };
+    watcher
+        .watch(dir, mode)
+        .map_err(|e| format!("Couldn't watch {}: {}", dir.display(), e))?;
+
+    let mut pending: HashMap<PathBuf, Instant> = HashMap::new();
+    loop {
+        match receiver.recv_timeout(SETTLE / 4) {
+            Ok(Ok(event)) => {
+                let written = matches!(
+                    event.kind,
+                    EventKind::Create(_) | EventKind::Modify(ModifyKind::Data(_) | ModifyKind::Any)
+                );
+                // Downloaders often write to a temporary name and rename it when they're done
+                let renamed = matches!(event.kind, EventKind::Modify(ModifyKind::Name(_)));
+                if written || renamed {
+                    if let Some(path) = event.paths.last().filter(|path| is_image(path)) {
+                        pending.insert(path.clone(), Instant::now());
+                    }
+                }
+            }
+            Ok(Err(error)) => eprintln!("Error while watching {}: {}", dir.display(), error),
+            Err(RecvTimeoutError::Timeout) => (),
+            Err(RecvTimeoutError::Disconnected) => return Ok(()),
+        }
+
+        let mut settled: Vec<PathBuf> = pending
+            .iter()
+            .filter(|(_, changed)| changed.elapsed() >= SETTLE)
+            .map(|(path, _)| path.clone())
+            .collect();
+        settled.sort();
+        for path in settled {
+            pending.remove(&path);
+            if path.is_file() {
+                show(&path);
+            }
+        }
+    }
+}
+
+/// Expand glob patterns like `~/Pictures/*.png` among the given paths, for shells
+/// that leave them alone or when they're quoted. Patterns only match images,
+/// while plain paths are kept as they are.
+pub fn expand(paths: &[PathBuf]) -> Result<Vec<PathBuf>, WaifuError> {
+    let mut expanded = Vec::new();
+
+    for path in paths {
+        let pattern = path.to_string_lossy();
+        let is_pattern = pattern.contains(['*', '?', '[']);
+        if !is_pattern || path.exists() {
+            expanded.push(path.clone());
+            continue;
+        }
+
+        let mut matches: Vec<PathBuf> = glob::glob(&pattern)
+            .map_err(|e| format!("'{}' isn't a valid pattern: {}", pattern, e))?
+            .filter_map(|entry| entry.ok())
+            .filter(|path| path.is_file() && is_image(path))
+            .collect();
+        if matches.is_empty() {
+            return Err(format!("No images match '{}'", pattern).into());
+        }
+        matches.sort();
+        expanded.append(&mut matches);
+    }
+
+    Ok(expanded)
+}
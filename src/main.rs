@@ -1,9 +1,5 @@
-mod api;
-mod app;
-
 fn main() {
-    if let Err(error) = app::run() {
-        eprintln!("{}", error);
-        std::process::exit(1);
+    if let Err(error) = waifu::run() {
+        std::process::exit(waifu::exit::report(&error));
     }
 }
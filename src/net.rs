@@ -0,0 +1,353 @@
+use reqwest::blocking::multipart::Form;
+use reqwest::blocking::{Client, RequestBuilder, Response};
+use reqwest::cookie::Jar;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+use reqwest::StatusCode;
+use reqwest::{Certificate, Proxy, Url};
+use std::path::Path;
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+
+pub use crate::api::USER_AGENT;
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+use crate::cache;
+use crate::error::WaifuError;
+pub use crate::retry::Backoff;
+use crate::retry::Retry;
+
+/// Network options given on the command line, shared by every request
+#[derive(Debug)]
+pub struct Options {
+    /// Proxy for all requests, e.g. socks5://127.0.0.1:9050
+    pub proxy: Option<String>,
+    /// Extra headers sent with every request
+    pub headers: Vec<(HeaderName, HeaderValue)>,
+    /// Cookies to start out with, from a cookies.txt file
+    pub cookies: Option<Arc<Jar>>,
+    /// Certificate authorities to trust on top of the usual ones
+    pub ca_certs: Vec<Certificate>,
+    /// Accept any certificate, even invalid or self-signed ones
+    pub insecure: bool,
+    /// How long a whole request can take
+    pub timeout: Duration,
+    /// Times to try again after a connection error, timeout or 429
+    pub retries: u32,
+    pub backoff: Backoff,
+    /// Most requests sent to one host per second, no limit when 0
+    pub rate_limit: f64,
+    pub user_agent: String,
+    /// Keep API responses, to use again when the server says they haven't changed
+    #[cfg(any(feature = "danbooru", feature = "safebooru"))]
+    pub revalidate: bool,
+    /// How long the posts from a search are drawn from before searching again
+    #[cfg(any(feature = "danbooru", feature = "safebooru"))]
+    pub search_ttl: Duration,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            proxy: None,
+            headers: Vec::new(),
+            cookies: None,
+            ca_certs: Vec::new(),
+            insecure: false,
+            timeout: Duration::from_secs(20),
+            retries: 2,
+            backoff: Backoff::Linear,
+            rate_limit: 2.0,
+            user_agent: USER_AGENT.to_string(),
+            #[cfg(any(feature = "danbooru", feature = "safebooru"))]
+            revalidate: true,
+            #[cfg(any(feature = "danbooru", feature = "safebooru"))]
+            search_ttl: Duration::from_secs(5 * 60),
+        }
+    }
+}
+
+/// A client builder, blocking or async as they have the same methods, set up
+/// with the options
+macro_rules! configured {
+    ($builder:expr, $options:expr) => {{
+        let options: &Options = $options;
+        let mut builder = $builder
+            .connect_timeout(CONNECT_TIMEOUT.min(options.timeout))
+            .timeout(options.timeout)
+            .user_agent(&options.user_agent);
+
+        if let Some(proxy) = &options.proxy {
+            let proxy = Proxy::all(proxy)
+                .map_err(|e| format!("Couldn't use the proxy {}: {}", proxy, e))?;
+            builder = builder.proxy(proxy);
+        }
+
+        let headers: HeaderMap = options.headers.iter().cloned().collect();
+        builder = builder.default_headers(headers);
+
+        if let Some(cookies) = &options.cookies {
+            builder = builder.cookie_provider(Arc::clone(cookies));
+        }
+
+        for cert in &options.ca_certs {
+            builder = builder.add_root_certificate(cert.clone());
+        }
+        builder.danger_accept_invalid_certs(options.insecure)
+    }};
+}
+
+/// Connecting shouldn't take long even when the download as a whole does
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+static OPTIONS: OnceLock<Options> = OnceLock::new();
+
+/// Set the network options for the rest of the run
+pub fn configure(options: Options) {
+    let _ = OPTIONS.set(options);
+}
+
+fn options() -> &'static Options {
+    OPTIONS.get_or_init(Options::default)
+}
+
+/// When requests are tried again and how often they're sent, shared by the
+/// blocking client and the async one
+pub fn retry() -> Retry {
+    let options = options();
+    Retry {
+        retries: options.retries,
+        backoff: options.backoff,
+        rate_limit: options.rate_limit,
+    }
+}
+
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+pub fn search_ttl() -> Duration {
+    options().search_ttl
+}
+
+/// Whether API responses are kept, to use again when they haven't changed
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+pub fn revalidate() -> bool {
+    options().revalidate
+}
+
+/// The HTTP client for the whole run, so connections to a booru are kept alive
+/// from the API call to the image download. It goes through the configured proxy,
+/// with the extra headers and cookies. Without a proxy, reqwest picks up
+/// http_proxy, https_proxy and all_proxy by itself
+pub fn client() -> Result<&'static Client, WaifuError> {
+    static CLIENT: OnceLock<Client> = OnceLock::new();
+
+    if let Some(client) = CLIENT.get() {
+        return Ok(client);
+    }
+    let client = build_client(options())?;
+
+    Ok(CLIENT.get_or_init(|| client))
+}
+
+/// A client with the default options, for the boorus' APIs when they aren't
+/// given one of their own
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+pub fn default_client() -> Result<&'static Client, WaifuError> {
+    static CLIENT: OnceLock<Client> = OnceLock::new();
+
+    if let Some(client) = CLIENT.get() {
+        return Ok(client);
+    }
+    let client = build_client(&Options::default())?;
+
+    Ok(CLIENT.get_or_init(|| client))
+}
+
+fn build_client(options: &Options) -> Result<Client, WaifuError> {
+    Ok(configured!(Client::builder(), options).build()?)
+}
+
+/// A client for api::nonblocking, set up with the same options as the one
+/// `client` gives
+#[cfg(feature = "async")]
+pub fn async_client() -> Result<reqwest::Client, WaifuError> {
+    Ok(configured!(reqwest::Client::builder(), options()).build()?)
+}
+
+/// Send a request, trying again after connection errors and timeouts, and after
+/// being told to slow down with a 429
+pub fn send(request: RequestBuilder) -> Result<Response, WaifuError> {
+    send_with(&retry(), request)
+}
+
+/// Send a request the way send does, trying it again like `retry` says
+pub fn send_with(retry: &Retry, request: RequestBuilder) -> Result<Response, WaifuError> {
+    send_each(retry, || request.try_clone())
+}
+
+/// Send a multipart/form-data request the way send does, for uploading files to
+/// sites that want them sent like a form would. Its body can only be read once,
+/// so the form is made again for each attempt
+pub fn send_form(request: RequestBuilder, form: impl Fn() -> Form) -> Result<Response, WaifuError> {
+    send_each(&retry(), || Some(request.try_clone()?.multipart(form())))
+}
+
+fn send_each(
+    retry: &Retry,
+    attempt_with: impl Fn() -> Option<RequestBuilder>,
+) -> Result<Response, WaifuError> {
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+        let (client, request) = attempt_with()
+            .ok_or("This request can't be sent more than once")?
+            .build_split();
+        let request = request?;
+        if let Some(wait) = retry.slot(request.url()) {
+            tracing::debug!("Waiting {:.0?} to keep within the rate limit", wait);
+            std::thread::sleep(wait);
+        }
+
+        tracing::debug!(
+            "{} {} (attempt {})",
+            request.method(),
+            request.url(),
+            attempt
+        );
+        let started = Instant::now();
+        let result = client.execute(request);
+        match &result {
+            Ok(response) => tracing::info!(
+                "{} from {} in {:.0?}",
+                response.status(),
+                response.url(),
+                started.elapsed()
+            ),
+            Err(error) => tracing::warn!("{} after {:.0?}", error, started.elapsed()),
+        }
+
+        match result {
+            Ok(response) => {
+                let (status, headers, url) =
+                    (response.status(), response.headers(), response.url());
+                match retry.after_answer(attempt, status, headers, url)? {
+                    Some(wait) => {
+                        tracing::info!("Rate limited, trying again in {:.0?}", wait);
+                        std::thread::sleep(wait);
+                    }
+                    None => return Ok(response),
+                }
+            }
+            Err(error) => {
+                let wait = retry.after_error(attempt, error)?;
+                tracing::info!("Trying again in {:.0?}", wait);
+                std::thread::sleep(wait);
+            }
+        }
+    }
+}
+
+/// Send a GET to an API like send_with does. With `revalidate` it's answered
+/// from the last response when the server says nothing has changed since.
+/// Gives back the status and the body
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+pub fn send_revalidated(
+    url: &str,
+    request: RequestBuilder,
+    retry: &Retry,
+    revalidate: bool,
+) -> Result<(StatusCode, Vec<u8>), WaifuError> {
+    let cached = revalidate.then(|| cache::get_api(url)).flatten();
+    let request = match &cached {
+        Some(cached) => cached.validators.apply(request),
+        None => request,
+    };
+
+    let response = send_with(retry, request)?;
+    let status = response.status();
+    if let (StatusCode::NOT_MODIFIED, Some(cached)) = (status, cached) {
+        tracing::debug!("{} hasn't changed, using the cached response", url);
+        return Ok((StatusCode::OK, cached.bytes));
+    }
+
+    let validators = cache::Validators::from_headers(response.headers());
+    let bytes = response.bytes()?.to_vec();
+    if revalidate && status.is_success() && !validators.is_empty() {
+        let _ = cache::put_api(url, &bytes, &validators);
+    }
+
+    Ok((status, bytes))
+}
+
+/// Parse a rate limit, which has to be a number of requests per second or 0
+pub fn parse_rate(s: &str) -> Result<f64, String> {
+    match s.trim().parse::<f64>() {
+        Ok(rate) if rate >= 0.0 && rate.is_finite() => Ok(rate),
+        _ => Err(format!("'{}' isn't a number of requests per second", s)),
+    }
+}
+
+/// Parse a header given like 'Name: value'
+pub fn parse_header(s: &str) -> Result<(HeaderName, HeaderValue), String> {
+    let (name, value) = s
+        .split_once(':')
+        .ok_or_else(|| format!("'{}' isn't a header like 'Name: value'", s))?;
+    let name = HeaderName::from_bytes(name.trim().as_bytes())
+        .map_err(|_| format!("'{}' isn't a valid header name", name.trim()))?;
+    let value = HeaderValue::from_str(value.trim())
+        .map_err(|_| format!("'{}' isn't a valid header value", value.trim()))?;
+
+    Ok((name, value))
+}
+
+/// Read the certificates in a PEM bundle, e.g. a private CA's
+pub fn load_certs(path: &Path) -> Result<Vec<Certificate>, WaifuError> {
+    let pem = std::fs::read(path)
+        .map_err(|e| format!("Couldn't read certificates from {}: {}", path.display(), e))?;
+    let certs = Certificate::from_pem_bundle(&pem)
+        .map_err(|e| format!("{} isn't a PEM certificate bundle: {}", path.display(), e))?;
+    if certs.is_empty() {
+        return Err(format!("There are no certificates in {}", path.display()).into());
+    }
+
+    Ok(certs)
+}
+
+/// Read cookies from a file in the Netscape cookies.txt format that browsers'
+/// cookie exporters and curl use
+pub fn load_cookies(path: &Path) -> Result<Jar, WaifuError> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("Couldn't read cookies from {}: {}", path.display(), e))?;
+    let jar = Jar::default();
+
+    for (number, line) in text.lines().enumerate() {
+        // HttpOnly cookies get a prefix that looks like a comment
+        let line = line.strip_prefix("#HttpOnly_").unwrap_or(line);
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let fields: Vec<&str> = line.split('\t').collect();
+        let [domain, subdomains, cookie_path, secure, _expires, name, value] = fields[..] else {
+            return Err(format!(
+                "Line {} of {} isn't in the cookies.txt format",
+                number + 1,
+                path.display()
+            )
+            .into());
+        };
+
+        let host = domain.trim_start_matches('.');
+        let url = Url::parse(&format!("https://{}{}", host, cookie_path))
+            .map_err(|_| format!("'{}' in {} isn't a valid domain", domain, path.display()))?;
+
+        let mut cookie = format!("{}={}; Path={}", name, value, cookie_path);
+        if subdomains == "TRUE" {
+            cookie.push_str(&format!("; Domain={}", host));
+        }
+        if secure == "TRUE" {
+            cookie.push_str("; Secure");
+        }
+        jar.add_cookie_str(&cookie, &url);
+    }
+
+    Ok(jar)
+}
@@ -0,0 +1,79 @@
+use clap::ValueEnum;
+use image::DynamicImage;
+use std::fmt::Write;
+
+use crate::render::palette::{median_cut, Color};
+use crate::render::{background, luminance, ColorDepth};
+
+/// How an extracted palette gets written out
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// Color swatches with their hex codes
+    Swatches,
+    /// *.color0 to *.colorN lines for ~/.Xresources
+    Xresources,
+    /// The same layout as pywal's colors.json
+    #[cfg(feature = "json")]
+    Json,
+}
+
+/// The image's dominant colors, from darkest to lightest
+pub fn extract(image: &DynamicImage, size: usize) -> Vec<Color> {
+    // Every pixel of a full size image adds nothing but time
+    let sample = image.thumbnail(256, 256).to_rgba8();
+
+    let mut colors = median_cut(&sample, size);
+    colors.sort_by_key(|&[r, g, b]| luminance(r, g, b));
+    colors.dedup();
+    colors
+}
+
+pub fn format(colors: &[Color], format: Format, depth: ColorDepth) -> String {
+    let mut out = String::new();
+    let background_color = hex(colors.first().copied().unwrap_or([0; 3]));
+    let foreground_color = hex(colors.last().copied().unwrap_or([255; 3]));
+
+    match format {
+        Format::Swatches => {
+            for &[r, g, b] in colors {
+                let _ = writeln!(
+                    out,
+                    "{}      \x1b[0m {}",
+                    background(r, g, b, depth),
+                    hex([r, g, b])
+                );
+            }
+        }
+        Format::Xresources => {
+            let _ = writeln!(out, "*.background: {}", background_color);
+            let _ = writeln!(out, "*.foreground: {}", foreground_color);
+            let _ = writeln!(out, "*.cursorColor: {}", foreground_color);
+            for (i, &color) in colors.iter().enumerate() {
+                let _ = writeln!(out, "*.color{}: {}", i, hex(color));
+            }
+        }
+        #[cfg(feature = "json")]
+        Format::Json => {
+            let colors: serde_json::Map<String, serde_json::Value> = colors
+                .iter()
+                .enumerate()
+                .map(|(i, &color)| (format!("color{}", i), hex(color).into()))
+                .collect();
+            let json = serde_json::json!({
+                "special": {
+                    "background": background_color,
+                    "foreground": foreground_color,
+                    "cursor": foreground_color,
+                },
+                "colors": colors,
+            });
+            let _ = writeln!(out, "{:#}", json);
+        }
+    }
+
+    out
+}
+
+fn hex([r, g, b]: Color) -> String {
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
@@ -0,0 +1,57 @@
+use std::future::Future;
+use std::sync::OnceLock;
+use tokio::runtime::Runtime;
+use tokio::task::JoinHandle;
+
+use crate::error::WaifuError;
+use crate::net;
+
+/// Work started ahead of when what it gives is needed, e.g. the next image of
+/// a slideshow downloading while this one is drawn
+pub struct Ahead<T>(JoinHandle<T>);
+
+impl<T> Ahead<T> {
+    /// What the work gave, once it's done. None when it panicked
+    pub fn wait(self) -> Option<T> {
+        runtime().block_on(self.0).ok()
+    }
+}
+
+/// Run a future on the runtime alongside whatever the main thread is doing
+pub fn spawn<T: Send + 'static>(work: impl Future<Output = T> + Send + 'static) -> Ahead<T> {
+    Ahead(runtime().spawn(work))
+}
+
+/// Run blocking work, like a booru search or an upscaler, on the runtime's
+/// threads for it
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+pub fn blocking<T: Send + 'static>(work: impl FnOnce() -> T + Send + 'static) -> Ahead<T> {
+    Ahead(runtime().spawn_blocking(work))
+}
+
+/// The async client for downloading ahead, set up once with the network
+/// options like the blocking one
+pub fn client() -> Result<&'static reqwest::Client, WaifuError> {
+    static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+    if let Some(client) = CLIENT.get() {
+        return Ok(client);
+    }
+    let client = net::async_client()?;
+
+    Ok(CLIENT.get_or_init(|| client))
+}
+
+/// The runtime everything ahead runs on, started the first time there is some.
+/// One worker is plenty for a download or two, and the main thread stays free
+/// to draw
+fn runtime() -> &'static Runtime {
+    static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+    RUNTIME.get_or_init(|| {
+        tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .thread_name("waifu-ahead")
+            .enable_all()
+            .build()
+            .expect("Couldn't start the tokio runtime")
+    })
+}
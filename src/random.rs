@@ -0,0 +1,70 @@
+use rand::rngs::{StdRng, ThreadRng};
+use rand::{RngCore, SeedableRng};
+use std::sync::{Mutex, MutexGuard, OnceLock, PoisonError};
+
+static SEEDED: OnceLock<Mutex<StdRng>> = OnceLock::new();
+
+/// Make the random picks for the rest of the run come out the same every time
+/// they're made with this seed. Only the first seed set counts
+pub fn seed(seed: u64) {
+    let _ = SEEDED.set(Mutex::new(StdRng::seed_from_u64(seed)));
+}
+
+/// Whether the picks are seeded, so anything picked on the booru's side
+/// instead, like order:random, has to be left to waifu
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+pub fn seeded() -> bool {
+    SEEDED.get().is_some()
+}
+
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+/// A seed made from some text, the same for the same text on every machine
+pub fn seed_from(text: &str) -> u64 {
+    let hash = crate::md5::hex(text.as_bytes());
+    u64::from_str_radix(&hash[..16], 16).unwrap_or_default()
+}
+
+/// The random numbers to pick with: the seeded ones when a seed was set, the
+/// thread's own otherwise. Seeded ones are held on to until it's dropped, so
+/// picks made meanwhile elsewhere don't change what comes out
+pub fn rng() -> Generator {
+    match SEEDED.get() {
+        Some(seeded) => Generator::Seeded(seeded.lock().unwrap_or_else(PoisonError::into_inner)),
+        None => Generator::Thread(rand::thread_rng()),
+    }
+}
+
+pub enum Generator {
+    Seeded(MutexGuard<'static, StdRng>),
+    Thread(ThreadRng),
+}
+
+impl RngCore for Generator {
+    fn next_u32(&mut self) -> u32 {
+        match self {
+            Generator::Seeded(rng) => rng.next_u32(),
+            Generator::Thread(rng) => rng.next_u32(),
+        }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        match self {
+            Generator::Seeded(rng) => rng.next_u64(),
+            Generator::Thread(rng) => rng.next_u64(),
+        }
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        match self {
+            Generator::Seeded(rng) => rng.fill_bytes(dest),
+            Generator::Thread(rng) => rng.fill_bytes(dest),
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        match self {
+            Generator::Seeded(rng) => rng.try_fill_bytes(dest),
+            Generator::Thread(rng) => rng.try_fill_bytes(dest),
+        }
+    }
+}
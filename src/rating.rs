@@ -0,0 +1,61 @@
+#[cfg(feature = "json")]
+use serde::{Deserialize, Serialize};
+
+/// How explicit a post is, as the boorus rate them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[cfg_attr(feature = "json", serde(rename_all = "lowercase"))]
+pub enum Rating {
+    #[cfg_attr(feature = "cli", value(alias = "g"))]
+    General,
+    /// Not explicit but not for work either, e.g. swimsuits. Danbooru called it
+    /// safe before, which is still understood
+    #[cfg_attr(feature = "json", serde(alias = "safe"))]
+    #[cfg_attr(feature = "cli", value(alias = "s", alias = "safe"))]
+    Sensitive,
+    #[cfg_attr(feature = "cli", value(alias = "q"))]
+    Questionable,
+    #[cfg_attr(feature = "cli", value(alias = "e"))]
+    Explicit,
+}
+
+impl Rating {
+    pub const ALL: [Rating; 4] = [
+        Rating::General,
+        Rating::Sensitive,
+        Rating::Questionable,
+        Rating::Explicit,
+    ];
+
+    /// How Danbooru writes it, in rating:s and the like
+    pub fn letter(self) -> char {
+        match self {
+            Rating::General => 'g',
+            Rating::Sensitive => 's',
+            Rating::Questionable => 'q',
+            Rating::Explicit => 'e',
+        }
+    }
+
+    /// The rating a booru's posts have by its first letter, the same on all of them
+    pub fn from_letter(letter: char) -> Option<Rating> {
+        match letter {
+            'g' => Some(Rating::General),
+            's' => Some(Rating::Sensitive),
+            'q' => Some(Rating::Questionable),
+            'e' => Some(Rating::Explicit),
+            _ => None,
+        }
+    }
+
+    /// What the boorus call it now
+    pub fn name(self) -> &'static str {
+        match self {
+            Rating::General => "general",
+            Rating::Sensitive => "sensitive",
+            Rating::Questionable => "questionable",
+            Rating::Explicit => "explicit",
+        }
+    }
+}
@@ -0,0 +1,244 @@
+use image::codecs::gif::{GifDecoder, GifEncoder, Repeat};
+use image::codecs::png::PngDecoder;
+use image::codecs::webp::WebPDecoder;
+use image::{
+    imageops::FilterType, AnimationDecoder, Delay, DynamicImage, ImageFormat, ImageOutputFormat,
+};
+use is_terminal::IsTerminal;
+use std::io::{self, Cursor, Write};
+use std::thread;
+use std::time::Duration;
+
+use crate::error::WaifuError;
+use crate::render::graphics::{self, Encoding, Size};
+use crate::render::Settings;
+use crate::render::{self, detected_encoding, indent, move_to_offset, tmux, Protocol, Renderer};
+
+/// One frame of an animation, and how long it stays up
+pub struct Frame {
+    pub image: DynamicImage,
+    pub delay: Duration,
+}
+
+/// Decode every frame of an animated GIF, APNG or WebP, or nothing for a still image
+pub fn frames(bytes: &[u8]) -> Option<Vec<Frame>> {
+    let frames = match image::guess_format(bytes).ok()? {
+        ImageFormat::Gif => GifDecoder::new(Cursor::new(bytes))
+            .ok()?
+            .into_frames()
+            .collect_frames()
+            .ok()?,
+        ImageFormat::Png => {
+            let decoder = PngDecoder::new(Cursor::new(bytes)).ok()?;
+            if !decoder.is_apng() {
+                return None;
+            }
+            decoder.apng().into_frames().collect_frames().ok()?
+        }
+        // Still WebP images come out as no frames at all
+        ImageFormat::WebP => WebPDecoder::new(Cursor::new(bytes))
+            .ok()?
+            .into_frames()
+            .collect_frames()
+            .ok()?,
+        _ => return None,
+    };
+    if frames.len() < 2 {
+        return None;
+    }
+
+    let frames = frames
+        .into_iter()
+        .map(|frame| {
+            let (numerator, denominator) = frame.delay().numer_denom_ms();
+            let millis = numerator / denominator.max(1);
+            // Browsers slow down frames that ask for next to no delay, and so do we
+            let millis = if millis < 20 { 100 } else { millis };
+
+            Frame {
+                image: DynamicImage::ImageRgba8(frame.into_buffer()),
+                delay: Duration::from_millis(millis as u64),
+            }
+        })
+        .collect();
+
+    Some(frames)
+}
+
+/// Play an animation, letting kitty and iTerm loop it themselves when they're in use
+/// and redrawing frame after frame with every other renderer
+pub fn play(frames: &[Frame], settings: &Settings) -> Result<(), WaifuError> {
+    // Nobody's watching a pipe, so there's no point in animating into it
+    if !settings.animate || settings.stdout.is_some() || !io::stdout().is_terminal() {
+        return render::print(&frames[0].image, settings);
+    }
+
+    let mut prepared = Vec::with_capacity(frames.len());
+    for frame in frames {
+        let (image, config) = render::prepare(&frame.image, settings)?;
+        prepared.push((image.into_owned(), config, frame.delay));
+    }
+
+    match native_encoding(settings) {
+        Some((encoding, tmux)) => send(&prepared, settings, encoding, tmux),
+        None => redraw(&prepared, settings),
+    }
+}
+
+type Prepared = (DynamicImage, viuer::Config, Duration);
+
+// kitty or iTerm, when that's how the image would be drawn anyway
+fn native_encoding(settings: &Settings) -> Option<(Encoding, bool)> {
+    if !matches!(settings.renderer, Renderer::Viuer) {
+        return None;
+    }
+
+    let wrap = tmux::should_wrap(settings.tmux_passthrough);
+    match settings.protocol {
+        Protocol::Kitty => Some((Encoding::Kitty, wrap)),
+        Protocol::Iterm => Some((Encoding::Iterm, wrap)),
+        Protocol::Auto => tmux::passthrough_encoding(settings.tmux_passthrough)
+            .map(|encoding| (encoding, true))
+            .or_else(|| detected_encoding(&settings.config).map(|encoding| (encoding, false))),
+        Protocol::Sixel | Protocol::Blocks => None,
+    }
+}
+
+// Hand the whole animation to the terminal in one go
+fn send(
+    frames: &[Prepared],
+    settings: &Settings,
+    encoding: Encoding,
+    tmux: bool,
+) -> Result<(), WaifuError> {
+    let (first, config, _) = &frames[0];
+    let (columns, rows, pixels) = graphics::placement(first, settings, config);
+    let size = match pixels {
+        Some((pixel_w, pixel_h)) => Size::Pixels(pixel_w, pixel_h),
+        None => Size::Cells(columns, rows),
+    };
+    // Every frame has to be the same size
+    let (width, height) = pixels.unwrap_or((first.width(), first.height()));
+    let frames = frames
+        .iter()
+        .map(|(image, _, delay)| {
+            let image = image.resize_exact(width, height, FilterType::Triangle);
+            (image, *delay)
+        })
+        .collect::<Vec<_>>();
+
+    let sequences = match encoding {
+        Encoding::Kitty => kitty(&frames, size, settings.loops)?,
+        Encoding::Iterm => vec![graphics::iterm(&gif(&frames, settings.loops)?, size)],
+    };
+
+    let stdout = io::stdout();
+    let lock = stdout.lock();
+    let mut buffer = io::BufWriter::new(lock);
+
+    move_to_offset(&mut buffer, config)?;
+    indent(&mut buffer, config)?;
+    for sequence in sequences {
+        write!(buffer, "{}", graphics::wrap(sequence, tmux))?;
+    }
+    graphics::move_below(&mut buffer, encoding, rows, tmux)?;
+    buffer.flush()?;
+
+    Ok(())
+}
+
+// Kitty's animation commands: the first frame is sent like any image,
+// the rest are added to it and then the loop is started
+fn kitty(
+    frames: &[(DynamicImage, Duration)],
+    size: Size,
+    loops: Option<u32>,
+) -> Result<Vec<String>, WaifuError> {
+    let id: u32 = rand::random::<u32>() | 1;
+    let mut sequences = Vec::new();
+
+    for (i, (image, delay)) in frames.iter().enumerate() {
+        let png = png(image)?;
+        // q=2 stops kitty from answering, which would end up on the shell's input
+        let control = if i == 0 {
+            let size = match size {
+                Size::Cells(columns, rows) => format!(",c={},r={}", columns, rows),
+                Size::Pixels(..) => String::new(),
+            };
+            format!("f=100,a=T,t=d,C=1,q=2,i={}{}", id, size)
+        } else {
+            format!("f=100,a=f,t=d,q=2,i={},z={}", id, delay.as_millis())
+        };
+        sequences.extend(graphics::kitty(&control, &png));
+    }
+
+    let first_delay = frames[0].1.as_millis();
+    sequences.push(format!(
+        "\x1b_Ga=a,q=2,i={},r=1,z={}\x1b\\",
+        id, first_delay
+    ));
+    // v=1 loops forever, any other number plays that many times minus one
+    let loops = loops.map_or(1, |loops| loops + 1);
+    sequences.push(format!("\x1b_Ga=a,q=2,i={},s=3,v={}\x1b\\", id, loops));
+
+    Ok(sequences)
+}
+
+// iTerm animates GIF files on its own
+fn gif(frames: &[(DynamicImage, Duration)], loops: Option<u32>) -> Result<Vec<u8>, WaifuError> {
+    let mut bytes = Vec::new();
+    {
+        let mut encoder = GifEncoder::new(&mut bytes);
+        encoder.set_repeat(match loops {
+            Some(loops) => Repeat::Finite(loops.saturating_sub(1) as u16),
+            None => Repeat::Infinite,
+        })?;
+        for (image, delay) in frames {
+            let delay = Delay::from_saturating_duration(*delay);
+            encoder.encode_frame(image::Frame::from_parts(image.to_rgba8(), 0, 0, delay))?;
+        }
+    }
+
+    Ok(bytes)
+}
+
+fn png(image: &DynamicImage) -> Result<Vec<u8>, WaifuError> {
+    let mut png = Vec::new();
+    image.write_to(&mut Cursor::new(&mut png), ImageOutputFormat::Png)?;
+    Ok(png)
+}
+
+// Draw every frame over the last one until the loops run out, or forever
+fn redraw(frames: &[Prepared], settings: &Settings) -> Result<(), WaifuError> {
+    let mut played = 0;
+    let mut rows = None;
+
+    while settings.loops.is_none_or(|loops| played < loops) {
+        for (image, config, delay) in frames {
+            if let Some(rows) = rows {
+                rewind(rows, config)?;
+            }
+            rows = Some(render::render(image, settings, config)?);
+            thread::sleep(*delay);
+        }
+        played += 1;
+    }
+
+    Ok(())
+}
+
+// Put the cursor back where it was before the last frame was drawn,
+// so the next one lands right on top of it
+fn rewind(rows: u32, config: &viuer::Config) -> io::Result<()> {
+    let mut stdout = io::stdout();
+    if !config.absolute_offset {
+        let up = rows as i64 + config.y as i64;
+        if up > 0 {
+            write!(stdout, "\x1b[{}A", up)?;
+        } else if up < 0 {
+            write!(stdout, "\x1b[{}B", -up)?;
+        }
+    }
+    write!(stdout, "\r")?;
+    stdout.flush()
+}
@@ -0,0 +1,45 @@
+use image::{imageops::FilterType, DynamicImage, GenericImageView};
+use std::io::{self, Write};
+
+use crate::error::WaifuError;
+use crate::render::{fit_cells, indent, luminance, move_to_offset};
+
+// Characters ordered from the least to the most ink on screen
+const RAMP: &[u8] = b" .:-=+*#%@";
+
+/// Draw the image as plain ASCII characters shaded by brightness.
+/// Unless an offset is asked for, no escape codes are written, so the output is safe
+/// for serial consoles and logs.
+pub fn print(image: &DynamicImage, config: &viuer::Config) -> Result<(), WaifuError> {
+    let (width, height) = image.dimensions();
+    let (columns, rows) = fit_cells(width, height, config.width, config.height);
+
+    // A cell is twice as tall as it is wide, which fit_cells already accounts for
+    let pixels = image
+        .resize_exact(columns, rows, FilterType::Triangle)
+        .to_rgba8();
+
+    let stdout = io::stdout();
+    let lock = stdout.lock();
+    let mut buffer = io::BufWriter::new(lock);
+
+    move_to_offset(&mut buffer, config)?;
+
+    for row in pixels.rows() {
+        indent(&mut buffer, config)?;
+        let line: String = row
+            .map(|pixel| {
+                if pixel[3] < 128 {
+                    return ' ';
+                }
+                let shade = luminance(pixel[0], pixel[1], pixel[2]) as usize;
+                RAMP[shade * (RAMP.len() - 1) / 255] as char
+            })
+            .collect();
+        writeln!(buffer, "{}", line.trim_end())?;
+    }
+
+    buffer.flush()?;
+
+    Ok(())
+}
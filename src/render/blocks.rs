@@ -0,0 +1,97 @@
+use image::{imageops::FilterType, DynamicImage, GenericImageView};
+use std::io::{self, Write};
+
+use crate::error::WaifuError;
+use crate::render::dither::{quantize, Dither};
+use crate::render::palette::{self, Color};
+use crate::render::{
+    background, fit_cells, foreground, indent, move_to_offset, ColorDepth, Settings,
+};
+
+/// Draw the image with half blocks, every cell showing two vertically stacked pixels.
+/// Unlike viuer's block printer, colors can be reduced to a palette and dithered first.
+pub fn print(
+    image: &DynamicImage,
+    settings: &Settings,
+    config: &viuer::Config,
+) -> Result<(), WaifuError> {
+    let (width, height) = image.dimensions();
+    let (columns, rows) = fit_cells(width, height, config.width, config.height);
+
+    let mut pixels = image
+        .resize_exact(columns, rows * 2, FilterType::Triangle)
+        .to_rgba8();
+
+    let palette = build_palette(&pixels, settings);
+    // 16 colors look awful without dithering, so it stays on unless turned off
+    let dither = settings.dither.unwrap_or(match settings.colors {
+        ColorDepth::Ansi16 => Dither::FloydSteinberg,
+        _ => Dither::None,
+    });
+    quantize(&mut pixels, &palette, dither);
+    let colors = settings.colors;
+
+    let stdout = io::stdout();
+    let lock = stdout.lock();
+    let mut buffer = io::BufWriter::new(lock);
+
+    let color = |x: u32, y: u32| -> Option<Color> {
+        let pixel = pixels.get_pixel(x, y);
+        (pixel[3] >= 128).then(|| [pixel[0], pixel[1], pixel[2]])
+    };
+
+    move_to_offset(&mut buffer, config)?;
+
+    for row in 0..rows {
+        indent(&mut buffer, config)?;
+        for column in 0..columns {
+            match (color(column, row * 2), color(column, row * 2 + 1)) {
+                (Some([r, g, b]), Some(bottom)) => {
+                    let [br, bg, bb] = bottom;
+                    write!(
+                        buffer,
+                        "{}{}▀",
+                        foreground(r, g, b, colors),
+                        background(br, bg, bb, colors)
+                    )?;
+                }
+                (Some([r, g, b]), None) => {
+                    write!(buffer, "\x1b[49m{}▀", foreground(r, g, b, colors))?;
+                }
+                (None, Some([r, g, b])) => {
+                    write!(buffer, "\x1b[49m{}▄", foreground(r, g, b, colors))?;
+                }
+                (None, None) => write!(buffer, "\x1b[0m ")?,
+            }
+        }
+        writeln!(buffer, "\x1b[0m")?;
+    }
+
+    buffer.flush()?;
+
+    Ok(())
+}
+
+fn build_palette(pixels: &image::RgbaImage, settings: &Settings) -> Vec<Color> {
+    let available = match settings.colors {
+        ColorDepth::Truecolor => None,
+        ColorDepth::Ansi256 => Some(palette::xterm256()),
+        ColorDepth::Ansi16 => Some(palette::ANSI16.to_vec()),
+    };
+
+    match (settings.palette_size, available) {
+        (Some(size), Some(available)) => {
+            // Only colors the terminal can actually show are worth dithering towards
+            let mut colors: Vec<Color> = palette::median_cut(pixels, size as usize)
+                .into_iter()
+                .map(|color| palette::nearest(&available, color))
+                .collect();
+            colors.sort_unstable();
+            colors.dedup();
+            colors
+        }
+        (Some(size), None) => palette::median_cut(pixels, size as usize),
+        (None, Some(available)) => available,
+        (None, None) => Vec::new(),
+    }
+}
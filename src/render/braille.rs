@@ -0,0 +1,88 @@
+use image::{imageops::FilterType, DynamicImage, GenericImageView};
+use std::io::{self, Write};
+
+use crate::error::WaifuError;
+use crate::render::{fit_cells, foreground, indent, luminance, move_to_offset, Settings};
+
+// Bit for each dot of a braille cell, indexed by [row][column]
+const DOTS: [[u32; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+
+/// Draw the image using braille characters. Every terminal cell holds 2x4 dots,
+/// giving eight times as many "pixels" as cells.
+pub fn print(
+    image: &DynamicImage,
+    settings: &Settings,
+    config: &viuer::Config,
+) -> Result<(), WaifuError> {
+    let (width, height) = image.dimensions();
+    let (columns, rows) = fit_cells(width, height, config.width, config.height);
+
+    let pixels = image
+        .resize_exact(columns * 2, rows * 4, FilterType::Triangle)
+        .to_rgba8();
+
+    // Dots are lit wherever a pixel is brighter than the image's average
+    let total: u64 = pixels
+        .pixels()
+        .map(|p| luminance(p[0], p[1], p[2]) as u64)
+        .sum();
+    let count = (pixels.width() * pixels.height()).max(1) as u64;
+    let threshold = (total / count) as u8;
+
+    let stdout = io::stdout();
+    let lock = stdout.lock();
+    let mut buffer = io::BufWriter::new(lock);
+
+    move_to_offset(&mut buffer, config)?;
+
+    for row in 0..rows {
+        indent(&mut buffer, config)?;
+        for column in 0..columns {
+            let mut bits = 0;
+            let (mut r, mut g, mut b, mut lit) = (0u32, 0u32, 0u32, 0u32);
+
+            for (dy, dots) in DOTS.iter().enumerate() {
+                for (dx, bit) in dots.iter().enumerate() {
+                    let pixel = pixels.get_pixel(column * 2 + dx as u32, row * 4 + dy as u32);
+                    if pixel[3] < 128 || luminance(pixel[0], pixel[1], pixel[2]) < threshold {
+                        continue;
+                    }
+
+                    bits |= bit;
+                    r += pixel[0] as u32;
+                    g += pixel[1] as u32;
+                    b += pixel[2] as u32;
+                    lit += 1;
+                }
+            }
+
+            if bits == 0 {
+                write!(buffer, " ")?;
+                continue;
+            }
+
+            if !settings.monochrome {
+                let color = foreground(
+                    (r / lit) as u8,
+                    (g / lit) as u8,
+                    (b / lit) as u8,
+                    settings.colors,
+                );
+                write!(buffer, "{}", color)?;
+            }
+
+            let character = char::from_u32(0x2800 + bits).unwrap_or(' ');
+            write!(buffer, "{}", character)?;
+        }
+
+        if settings.monochrome {
+            writeln!(buffer)?;
+        } else {
+            writeln!(buffer, "\x1b[0m")?;
+        }
+    }
+
+    buffer.flush()?;
+
+    Ok(())
+}
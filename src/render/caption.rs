@@ -0,0 +1,94 @@
+use font8x8::{UnicodeFonts, BASIC_FONTS, LATIN_FONTS};
+use image::{DynamicImage, Rgba, RgbaImage};
+
+const GLYPH: u32 = 8;
+/// Anything longer gets cut short rather than covering the picture
+const MAX_LINES: usize = 3;
+
+/// Write a line of text across the bottom of the image, white on a dark band
+pub fn draw(image: &DynamicImage, text: &str) -> DynamicImage {
+    let mut pixels = image.to_rgba8();
+    let (width, height) = pixels.dimensions();
+
+    // Big enough letters to be readable once the image is shrunk to the terminal
+    let scale = (width / 480).max(1);
+    let glyph = GLYPH * scale;
+    let per_line = (width / glyph).saturating_sub(2).max(1) as usize;
+    let lines = wrap(text, per_line);
+
+    let band = (lines.len() as u32 * (glyph + scale) + glyph).min(height);
+    let top = height - band;
+    for y in top..height {
+        for x in 0..width {
+            let pixel = pixels.get_pixel_mut(x, y);
+            for channel in 0..3 {
+                pixel[channel] /= 4;
+            }
+            pixel[3] = 255;
+        }
+    }
+
+    for (row, line) in lines.iter().enumerate() {
+        let y = top + glyph / 2 + row as u32 * (glyph + scale);
+        for (column, c) in line.chars().enumerate() {
+            let x = glyph + column as u32 * glyph;
+            draw_glyph(&mut pixels, c, x, y, scale);
+        }
+    }
+
+    DynamicImage::ImageRgba8(pixels)
+}
+
+fn draw_glyph(pixels: &mut RgbaImage, c: char, x: u32, y: u32, scale: u32) {
+    let bitmap = BASIC_FONTS
+        .get(c)
+        .or_else(|| LATIN_FONTS.get(c))
+        .or_else(|| BASIC_FONTS.get('?'))
+        .unwrap_or_default();
+
+    for (row, bits) in bitmap.iter().enumerate() {
+        for bit in 0..GLYPH {
+            if bits & (1 << bit) == 0 {
+                continue;
+            }
+            for dy in 0..scale {
+                for dx in 0..scale {
+                    let (px, py) = (x + bit * scale + dx, y + row as u32 * scale + dy);
+                    if px < pixels.width() && py < pixels.height() {
+                        pixels.put_pixel(px, py, Rgba([255, 255, 255, 255]));
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Break the text into lines at spaces, ending with "..." when it doesn't all fit
+fn wrap(text: &str, per_line: usize) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    let mut line = String::new();
+
+    for word in text.split_whitespace() {
+        let word: String = word.chars().take(per_line).collect();
+        let needed = line.chars().count() + word.chars().count() + usize::from(!line.is_empty());
+        if needed > per_line && !line.is_empty() {
+            lines.push(std::mem::take(&mut line));
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(&word);
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+
+    if lines.len() > MAX_LINES {
+        lines.truncate(MAX_LINES);
+        let last = &mut lines[MAX_LINES - 1];
+        let kept: String = last.chars().take(per_line.saturating_sub(3)).collect();
+        *last = format!("{}...", kept);
+    }
+
+    lines
+}
@@ -0,0 +1,101 @@
+use clap::ValueEnum;
+use image::RgbaImage;
+
+use crate::render::palette::{nearest, Color};
+
+/// How colors that aren't in the palette get approximated
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Dither {
+    /// Map every pixel to its closest palette entry
+    None,
+    /// Bayer matrix dithering, which keeps a regular crosshatch pattern
+    Ordered,
+    /// Error diffusion, usually the best looking for photos and paintings
+    FloydSteinberg,
+}
+
+const BAYER: [[f32; 4]; 4] = [
+    [0.0, 8.0, 2.0, 10.0],
+    [12.0, 4.0, 14.0, 6.0],
+    [3.0, 11.0, 1.0, 9.0],
+    [15.0, 7.0, 13.0, 5.0],
+];
+
+/// Replace every opaque pixel of the image with a palette color
+pub fn quantize(image: &mut RgbaImage, palette: &[Color], dither: Dither) {
+    if palette.is_empty() {
+        return;
+    }
+
+    match dither {
+        Dither::None => {
+            for pixel in image.pixels_mut() {
+                let color = nearest(palette, [pixel[0], pixel[1], pixel[2]]);
+                pixel.0[..3].copy_from_slice(&color);
+            }
+        }
+        Dither::Ordered => {
+            // The noise should be about as large as the gap between palette colors
+            let spread = 255.0 / (palette.len() as f32).cbrt();
+
+            for (x, y, pixel) in image.enumerate_pixels_mut() {
+                let offset = (BAYER[y as usize % 4][x as usize % 4] / 16.0 - 0.5) * spread;
+                let shifted = |c: u8| (c as f32 + offset).clamp(0.0, 255.0) as u8;
+                let color = nearest(
+                    palette,
+                    [shifted(pixel[0]), shifted(pixel[1]), shifted(pixel[2])],
+                );
+                pixel.0[..3].copy_from_slice(&color);
+            }
+        }
+        Dither::FloydSteinberg => floyd_steinberg(image, palette),
+    }
+}
+
+fn floyd_steinberg(image: &mut RgbaImage, palette: &[Color]) {
+    let (width, height) = image.dimensions();
+    let (width, height) = (width as usize, height as usize);
+
+    let mut values: Vec<[f32; 3]> = image
+        .pixels()
+        .map(|p| [p[0] as f32, p[1] as f32, p[2] as f32])
+        .collect();
+
+    for y in 0..height {
+        for x in 0..width {
+            let index = y * width + x;
+            let old = values[index];
+            let wanted = old.map(|c| c.clamp(0.0, 255.0) as u8);
+            let color = nearest(palette, wanted);
+
+            let pixel = image.get_pixel_mut(x as u32, y as u32);
+            pixel.0[..3].copy_from_slice(&color);
+
+            if pixel[3] < 128 {
+                continue;
+            }
+
+            let error = [
+                old[0] - color[0] as f32,
+                old[1] - color[1] as f32,
+                old[2] - color[2] as f32,
+            ];
+            let mut spread = |dx: isize, dy: usize, weight: f32| {
+                let nx = x as isize + dx;
+                let ny = y + dy;
+                if nx < 0 || nx as usize >= width || ny >= height {
+                    return;
+                }
+                let target = &mut values[ny * width + nx as usize];
+                for channel in 0..3 {
+                    target[channel] += error[channel] * weight;
+                }
+            };
+
+            spread(1, 0, 7.0 / 16.0);
+            spread(-1, 1, 3.0 / 16.0);
+            spread(0, 1, 5.0 / 16.0);
+            spread(1, 1, 1.0 / 16.0);
+        }
+    }
+}
@@ -0,0 +1,42 @@
+use image::{DynamicImage, ImageBuffer, ImageFormat};
+use zune_core::colorspace::ColorSpace;
+use zune_core::options::DecoderOptions;
+
+/// Decode JPEGs and PNGs with the zune decoders, which are a good deal faster on
+/// large originals. Anything they can't handle is left to the image crate.
+pub fn decode(bytes: &[u8]) -> Option<DynamicImage> {
+    match image::guess_format(bytes).ok()? {
+        ImageFormat::Jpeg => jpeg(bytes),
+        ImageFormat::Png => png(bytes),
+        _ => None,
+    }
+}
+
+fn jpeg(bytes: &[u8]) -> Option<DynamicImage> {
+    let options = DecoderOptions::default().jpeg_set_out_colorspace(ColorSpace::RGB);
+    let mut decoder = zune_jpeg::JpegDecoder::new_with_options(bytes, options);
+    let pixels = decoder.decode().ok()?;
+    let (width, height) = decoder.dimensions()?;
+
+    ImageBuffer::from_raw(width as u32, height as u32, pixels).map(DynamicImage::ImageRgb8)
+}
+
+fn png(bytes: &[u8]) -> Option<DynamicImage> {
+    let options = DecoderOptions::default()
+        .png_set_add_alpha_channel(true)
+        .png_set_strip_to_8bit(true);
+    let mut decoder = zune_png::PngDecoder::new_with_options(bytes, options);
+    let pixels = decoder.decode().ok()?.u8()?;
+    let (width, height) = decoder.get_dimensions()?;
+    let (width, height) = (width as u32, height as u32);
+
+    match decoder.get_colorspace()? {
+        ColorSpace::RGBA => {
+            ImageBuffer::from_raw(width, height, pixels).map(DynamicImage::ImageRgba8)
+        }
+        ColorSpace::LumaA => {
+            ImageBuffer::from_raw(width, height, pixels).map(DynamicImage::ImageLumaA8)
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,178 @@
+use base64::{engine::general_purpose, Engine};
+use image::{imageops::FilterType, DynamicImage, GenericImageView, ImageOutputFormat};
+use std::io::{self, Cursor, Write};
+
+use crate::error::WaifuError;
+use crate::render::layout::{cell_size, fit_pixels, scale_pixels, DEFAULT_CELL_SIZE};
+use crate::render::{fit_cells, indent, move_to_offset, tmux, Settings};
+
+/// Inline image escape codes waifu can write without going through viuer
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    Kitty,
+    Iterm,
+}
+
+/// Draw the image with kitty or iTerm escape codes, skipping any terminal detection.
+/// With `tmux` set, every sequence is wrapped so tmux hands it to the outer terminal.
+///
+/// Both protocols place images with pixel precision, so when the cell size is known
+/// (or a pixel size was asked for) the image is sent at its exact final size rather
+/// than stretched over a grid of cells that only roughly matches its aspect ratio.
+pub fn print(
+    image: &DynamicImage,
+    settings: &Settings,
+    config: &viuer::Config,
+    encoding: Encoding,
+    tmux: bool,
+) -> Result<u32, WaifuError> {
+    let (columns, rows, pixels) = placement(image, settings, config);
+    let resized;
+    let image = match pixels {
+        Some((pixel_w, pixel_h)) => {
+            resized = image.resize_exact(pixel_w, pixel_h, FilterType::Triangle);
+            &resized
+        }
+        None => image,
+    };
+
+    let mut png = Vec::new();
+    image.write_to(&mut Cursor::new(&mut png), ImageOutputFormat::Png)?;
+
+    let stdout = io::stdout();
+    let lock = stdout.lock();
+    let mut buffer = io::BufWriter::new(lock);
+
+    move_to_offset(&mut buffer, config)?;
+    indent(&mut buffer, config)?;
+
+    let size = match pixels {
+        Some((pixel_w, pixel_h)) => Size::Pixels(pixel_w, pixel_h),
+        None => Size::Cells(columns, rows),
+    };
+    let sequences = match encoding {
+        // C=1 keeps the cursor still, it's moved below the image afterwards
+        Encoding::Kitty => kitty(&format!("f=100,a=T,t=d,C=1{}", size.kitty()), &png),
+        Encoding::Iterm => vec![iterm(&png, size)],
+    };
+    for sequence in sequences {
+        write!(buffer, "{}", wrap(sequence, tmux))?;
+    }
+
+    move_below(&mut buffer, encoding, rows, tmux)?;
+    buffer.flush()?;
+
+    Ok(rows)
+}
+
+/// How big an image is drawn with either protocol
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Size {
+    Cells(u32, u32),
+    Pixels(u32, u32),
+}
+
+impl Size {
+    // Without c/r kitty shows the image at its own pixel size
+    fn kitty(self) -> String {
+        match self {
+            Size::Cells(columns, rows) => format!(",c={},r={}", columns, rows),
+            Size::Pixels(..) => String::new(),
+        }
+    }
+}
+
+/// Columns and rows an image covers, along with the exact pixel size to send it at
+/// when the cell size is known or a pixel size was asked for
+pub fn placement(
+    image: &DynamicImage,
+    settings: &Settings,
+    config: &viuer::Config,
+) -> (u32, u32, Option<(u32, u32)>) {
+    let (width, height) = image.dimensions();
+    let (columns, rows) = fit_cells(width, height, config.width, config.height);
+
+    let cell = cell_size();
+    let pixels = match (settings.pixel_width, settings.pixel_height) {
+        (None, None) => {
+            cell.map(|(cell_w, cell_h)| fit_pixels(width, height, columns * cell_w, rows * cell_h))
+        }
+        (pixel_width, pixel_height) => Some(scale_pixels(width, height, pixel_width, pixel_height)),
+    };
+
+    match pixels {
+        Some((pixel_w, pixel_h)) => {
+            let (_, cell_h) = cell.unwrap_or(DEFAULT_CELL_SIZE);
+            (
+                columns,
+                pixel_h.div_ceil(cell_h).max(1),
+                Some((pixel_w, pixel_h)),
+            )
+        }
+        None => (columns, rows, None),
+    }
+}
+
+/// Kitty graphics commands sending `data` along with the `control` keys,
+/// split into the chunks the protocol expects
+pub fn kitty(control: &str, data: &[u8]) -> Vec<String> {
+    let encoded = general_purpose::STANDARD.encode(data);
+    let chunks: Vec<&[u8]> = encoded.as_bytes().chunks(4096).collect();
+
+    chunks
+        .iter()
+        .enumerate()
+        .map(|(i, chunk)| {
+            let more = u8::from(i + 1 < chunks.len());
+            let chunk = String::from_utf8_lossy(chunk);
+            if i == 0 {
+                format!("\x1b_G{},m={};{}\x1b\\", control, more, chunk)
+            } else {
+                format!("\x1b_Gm={};{}\x1b\\", more, chunk)
+            }
+        })
+        .collect()
+}
+
+/// iTerm inline image sequence for an encoded image file
+pub fn iterm(data: &[u8], size: Size) -> String {
+    let size = match size {
+        Size::Pixels(pixel_w, pixel_h) => format!("width={}px;height={}px", pixel_w, pixel_h),
+        Size::Cells(columns, rows) => format!("width={};height={}", columns, rows),
+    };
+
+    format!(
+        "\x1b]1337;File=inline=1;size={};{};preserveAspectRatio=0:{}\x07",
+        data.len(),
+        size,
+        general_purpose::STANDARD.encode(data)
+    )
+}
+
+/// Wrap a sequence for tmux to hand to the outer terminal, when needed
+pub fn wrap(sequence: String, tmux: bool) -> String {
+    if tmux {
+        tmux::wrap(&sequence)
+    } else {
+        sequence
+    }
+}
+
+/// Move below the image ourselves so the prompt doesn't land on top of it
+/// (tmux doesn't know the outer terminal moved the cursor)
+pub fn move_below(
+    out: &mut impl Write,
+    encoding: Encoding,
+    rows: u32,
+    tmux: bool,
+) -> io::Result<()> {
+    if tmux || encoding == Encoding::Kitty {
+        for _ in 0..rows {
+            writeln!(out)?;
+        }
+    } else {
+        writeln!(out)?;
+    }
+
+    Ok(())
+}
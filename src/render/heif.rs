@@ -0,0 +1,66 @@
+use image::error::{DecodingError, ImageFormatHint};
+use image::{DynamicImage, ImageError, ImageResult, RgbImage, RgbaImage};
+use libheif_rs::{
+    color_profile_types, ColorProfile, ColorSpace, DecodingOptions, HeifContext, LibHeif, RgbChroma,
+};
+
+/// Decode an AVIF or HEIC image with libheif, along with its ICC color profile.
+/// libheif already turns the image the way the file says, so there's no EXIF
+/// orientation left to apply afterwards.
+pub fn decode(bytes: &[u8]) -> ImageResult<(DynamicImage, Option<Vec<u8>>)> {
+    let context = HeifContext::read_from_bytes(bytes).map_err(error)?;
+    let handle = context.primary_image_handle().map_err(error)?;
+
+    let alpha = handle.has_alpha_channel();
+    let chroma = if alpha {
+        RgbChroma::Rgba
+    } else {
+        RgbChroma::Rgb
+    };
+    // Terminals only ever see 8 bits per channel anyway
+    let options = DecodingOptions::new().map(|mut options| {
+        options.set_convert_hdr_to_8bit(true);
+        options
+    });
+    let decoded = LibHeif::new()
+        .decode(&handle, ColorSpace::Rgb(chroma), options)
+        .map_err(error)?;
+
+    let plane = decoded
+        .planes()
+        .interleaved
+        .ok_or_else(|| error("libheif returned no pixels"))?;
+    let (width, height) = (plane.width, plane.height);
+    let row = width as usize * if alpha { 4 } else { 3 };
+    let pixels: Vec<u8> = plane
+        .data
+        .chunks(plane.stride)
+        .take(height as usize)
+        .flat_map(|line| &line[..row])
+        .copied()
+        .collect();
+
+    let image = if alpha {
+        RgbaImage::from_raw(width, height, pixels).map(DynamicImage::ImageRgba8)
+    } else {
+        RgbImage::from_raw(width, height, pixels).map(DynamicImage::ImageRgb8)
+    }
+    .ok_or_else(|| error("libheif returned too few pixels"))?;
+
+    let profile = handle
+        .color_profile_raw()
+        .filter(|profile| {
+            [color_profile_types::PROF, color_profile_types::R_ICC]
+                .contains(&profile.profile_type())
+        })
+        .map(|profile| profile.data);
+
+    Ok((image, profile))
+}
+
+fn error(error: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> ImageError {
+    ImageError::Decoding(DecodingError::new(
+        ImageFormatHint::Name("AVIF/HEIC".to_string()),
+        error,
+    ))
+}
@@ -0,0 +1,258 @@
+use clap::ValueEnum;
+use image::{DynamicImage, GenericImageView};
+use std::borrow::Cow;
+use std::str::FromStr;
+
+use crate::render::{fit_cells_with_aspect, Settings, CELL_ASPECT};
+
+/// How an image is sized against the terminal (or the -W/-H box when given)
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Fit {
+    /// Scale up or down until the whole image fits, keeping its aspect ratio
+    Contain,
+    /// Fill the whole area keeping the aspect ratio, cropping whatever spills over
+    Cover,
+    /// Fill the whole area, distorting the image if needed
+    Stretch,
+}
+
+/// A width or height, either in terminal cells or as a share of the terminal
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Size {
+    Cells(u32),
+    Percent(u32),
+}
+
+impl Size {
+    /// Number of cells, given how many the terminal has in that direction
+    pub fn resolve(self, terminal: u32) -> u32 {
+        match self {
+            Size::Cells(cells) => cells,
+            Size::Percent(percent) => (terminal * percent / 100).max(1),
+        }
+    }
+}
+
+/// Parse a factor like --scale or --cell-aspect, which has to be a positive number
+pub fn parse_scale(s: &str) -> Result<f64, String> {
+    match s.trim().parse::<f64>() {
+        Ok(scale) if scale > 0.0 && scale.is_finite() => Ok(scale),
+        _ => Err(format!("'{}' isn't a positive number", s)),
+    }
+}
+
+impl FromStr for Size {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        match s.strip_suffix('%') {
+            Some(percent) => match percent.trim().parse::<u32>() {
+                Ok(p) if (1..=100).contains(&p) => Ok(Size::Percent(p)),
+                _ => Err(format!("'{}' isn't a percentage between 1% and 100%", s)),
+            },
+            None => s
+                .parse::<u32>()
+                .map(Size::Cells)
+                .map_err(|_| format!("'{}' isn't a number of cells or a percentage", s)),
+        }
+    }
+}
+
+/// Work out the final image and viuer configuration for a single print,
+/// applying the fit mode, scale, size limits and centering on top of the user's settings
+pub fn layout<'a>(
+    image: &'a DynamicImage,
+    settings: &Settings,
+) -> (Cow<'a, DynamicImage>, viuer::Config) {
+    let mut config = copy_config(&settings.config);
+    let mut image = Cow::Borrowed(image);
+
+    let pixel_sized = settings.pixel_width.is_some() || settings.pixel_height.is_some();
+    let limited = pixel_sized
+        || settings.max_width.is_some()
+        || settings.max_height.is_some()
+        || settings.no_upscale
+        || settings.scale.is_some()
+        || settings.cell_aspect.is_some();
+    if settings.fit.is_none() && !settings.center && !limited {
+        return (image, config);
+    }
+
+    if pixel_sized {
+        // The cells covered by the requested pixel size, for renderers that can't do better
+        let (width, height) = image.dimensions();
+        let (cell_w, cell_h) = cell_size().unwrap_or(DEFAULT_CELL_SIZE);
+        let (pixel_w, pixel_h) =
+            scale_pixels(width, height, settings.pixel_width, settings.pixel_height);
+        config.width = Some(pixel_w.div_ceil(cell_w).max(1));
+        config.height = Some(pixel_h.div_ceil(cell_h).max(1));
+    }
+
+    let aspect = settings.cell_aspect.unwrap_or(CELL_ASPECT);
+    let (term_w, term_h) = viuer::terminal_size();
+    let (term_w, term_h) = (term_w as u32, (term_h as u32).saturating_sub(1).max(1));
+    let box_w = config.width.unwrap_or(term_w);
+    let box_h = config.height.unwrap_or(term_h);
+
+    let (width, height) = image.dimensions();
+    let (columns, rows) = match settings.fit {
+        Some(Fit::Contain) => contain(width, height, box_w, box_h, aspect),
+        Some(Fit::Cover) => {
+            image = Cow::Owned(crop_to_ratio(
+                &image,
+                box_w as f64 / (box_h as f64 * aspect),
+            ));
+            (box_w, box_h)
+        }
+        Some(Fit::Stretch) => (box_w, box_h),
+        None => fit_cells_with_aspect(width, height, config.width, config.height, aspect),
+    };
+
+    let (columns, rows) = match settings.scale {
+        Some(scale) => {
+            let scaled = |n: u32| ((n as f64 * scale).round() as u32).max(1);
+            (scaled(columns), scaled(rows))
+        }
+        None => (columns, rows),
+    };
+
+    let (width, height) = image.dimensions();
+    let (columns, rows) = if settings.no_upscale {
+        // One pixel per column, and as many per row as a cell is taller, is the image's natural size
+        let natural_rows = (height as f64 / aspect).ceil() as u32;
+        shrink(columns, rows, width, natural_rows.max(1))
+    } else {
+        (columns, rows)
+    };
+    let (columns, rows) = shrink(
+        columns,
+        rows,
+        settings.max_width.unwrap_or(u32::MAX),
+        settings.max_height.unwrap_or(u32::MAX),
+    );
+
+    config.width = Some(columns);
+    config.height = Some(rows);
+
+    if settings.center {
+        config.x = (term_w.saturating_sub(columns) / 2) as u16;
+        // Without an absolute offset there's no telling where the cursor is
+        if config.absolute_offset {
+            config.y = ((term_h + 1).saturating_sub(rows) / 2) as i16;
+        }
+    }
+
+    (image, config)
+}
+
+// Largest (columns, rows) with the image's aspect ratio inside the box,
+// remembering that a cell is `aspect` times as tall as it is wide
+fn contain(width: u32, height: u32, box_w: u32, box_h: u32, aspect: f64) -> (u32, u32) {
+    let (width, height) = (width.max(1) as f64, height.max(1) as f64);
+    let (box_w, box_h) = (box_w as f64, box_h as f64);
+
+    let rows = (height * box_w / width / aspect).floor();
+    if rows <= box_h {
+        (box_w as u32, (rows as u32).max(1))
+    } else {
+        let columns = (width * box_h * aspect / height).floor();
+        ((columns as u32).max(1), box_h as u32)
+    }
+}
+
+// Scale (columns, rows) down proportionally until they're within the limits
+fn shrink(columns: u32, rows: u32, max_columns: u32, max_rows: u32) -> (u32, u32) {
+    if columns <= max_columns && rows <= max_rows {
+        return (columns, rows);
+    }
+
+    let scale = f64::min(
+        max_columns as f64 / columns.max(1) as f64,
+        max_rows as f64 / rows.max(1) as f64,
+    );
+    let scaled = |n: u32| ((n as f64 * scale).round() as u32).max(1);
+
+    (scaled(columns), scaled(rows))
+}
+
+// Crop the middle of the image to the given width to height ratio
+fn crop_to_ratio(image: &DynamicImage, ratio: f64) -> DynamicImage {
+    let (width, height) = image.dimensions();
+    let (w, h) = (width as f64, height as f64);
+
+    let (crop_w, crop_h) = if w / h > ratio {
+        ((h * ratio).floor(), h)
+    } else {
+        (w, (w / ratio).floor())
+    };
+    let (crop_w, crop_h) = (
+        (crop_w as u32).clamp(1, width),
+        (crop_h as u32).clamp(1, height),
+    );
+
+    image.crop_imm((width - crop_w) / 2, (height - crop_h) / 2, crop_w, crop_h)
+}
+
+/// Typical cell size in pixels, for when the terminal won't say
+pub const DEFAULT_CELL_SIZE: (u32, u32) = (8, 16);
+
+/// Size of a terminal cell in pixels, when the terminal reports its pixel dimensions
+pub fn cell_size() -> Option<(u32, u32)> {
+    let size = crossterm::terminal::window_size().ok()?;
+    if size.width == 0 || size.height == 0 || size.columns == 0 || size.rows == 0 {
+        return None;
+    }
+
+    Some((
+        (size.width / size.columns) as u32,
+        (size.height / size.rows) as u32,
+    ))
+}
+
+/// Pixel size asked for with --pixel-width/--pixel-height, keeping the aspect
+/// ratio when only one of them is given
+pub fn scale_pixels(
+    width: u32,
+    height: u32,
+    pixel_width: Option<u32>,
+    pixel_height: Option<u32>,
+) -> (u32, u32) {
+    let (w, h) = (width.max(1) as u64, height.max(1) as u64);
+    match (pixel_width, pixel_height) {
+        (Some(pw), Some(ph)) => (pw, ph),
+        (Some(pw), None) => (pw, ((h * pw as u64 / w) as u32).max(1)),
+        (None, Some(ph)) => (((w * ph as u64 / h) as u32).max(1), ph),
+        (None, None) => (width, height),
+    }
+}
+
+/// Largest pixel size with the image's aspect ratio inside a box of pixels
+pub fn fit_pixels(width: u32, height: u32, box_w: u32, box_h: u32) -> (u32, u32) {
+    let (w, h) = (width.max(1) as u64, height.max(1) as u64);
+    let (box_w, box_h) = (box_w as u64, box_h as u64);
+
+    if h * box_w / w <= box_h {
+        (box_w as u32, ((h * box_w / w) as u32).max(1))
+    } else {
+        (((w * box_h / h) as u32).max(1), box_h as u32)
+    }
+}
+
+/// viuer's Config isn't Clone, so copy it field by field
+pub fn copy_config(config: &viuer::Config) -> viuer::Config {
+    viuer::Config {
+        transparent: config.transparent,
+        absolute_offset: config.absolute_offset,
+        x: config.x,
+        y: config.y,
+        restore_cursor: config.restore_cursor,
+        width: config.width,
+        height: config.height,
+        truecolor: config.truecolor,
+        use_kitty: config.use_kitty,
+        use_iterm: config.use_iterm,
+        #[cfg(feature = "sixel")]
+        use_sixel: config.use_sixel,
+    }
+}
@@ -0,0 +1,617 @@
+#[cfg(feature = "animation")]
+pub mod animation;
+pub mod ascii;
+pub mod blocks;
+pub mod braille;
+pub mod caption;
+pub mod dither;
+#[cfg(feature = "fast-decode")]
+pub mod fast_decode;
+pub mod graphics;
+#[cfg(feature = "heif")]
+pub mod heif;
+pub mod layout;
+pub mod output;
+pub mod palette;
+pub mod prescale;
+pub mod qr;
+pub mod svg;
+pub mod tmux;
+pub mod transform;
+pub mod viewer;
+
+use clap::ValueEnum;
+use image::{DynamicImage, GenericImageView};
+use std::borrow::Cow;
+use std::io::{self, Write};
+#[cfg(feature = "network")]
+use std::time::Duration;
+
+use crate::error::WaifuError;
+use dither::Dither;
+use graphics::Encoding;
+use layout::Fit;
+use tmux::Passthrough;
+use transform::{Background, Censor, Crop, Filter};
+
+/// How the image gets drawn in the terminal
+pub enum Renderer {
+    /// Let viuer pick between kitty, iTerm, sixel and half blocks
+    Viuer,
+    Braille,
+    Ascii,
+}
+
+/// How many colors the terminal can show
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorDepth {
+    #[value(name = "truecolor")]
+    Truecolor,
+    #[value(name = "256")]
+    Ansi256,
+    /// The standard ANSI colors, for minimal terminals and the Linux console
+    #[value(name = "16")]
+    Ansi16,
+}
+
+/// Terminal graphics protocol used by the default renderer
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Protocol {
+    /// Use the best protocol the terminal supports. Over SSH, anything
+    /// but kitty falls back to half blocks since detection can't be trusted
+    Auto,
+    Kitty,
+    Iterm,
+    /// Use sixel if the terminal reports support and waifu was built with it,
+    /// half blocks otherwise
+    Sixel,
+    Blocks,
+}
+
+/// Everything needed to draw an image, on top of viuer's own configuration
+pub struct Settings {
+    pub config: viuer::Config,
+    pub renderer: Renderer,
+    pub monochrome: bool,
+    pub colors: ColorDepth,
+    pub dither: Option<Dither>,
+    /// Number of colors to reduce the image to when drawing half blocks
+    pub palette_size: Option<u16>,
+    pub protocol: Protocol,
+    pub tmux_passthrough: Passthrough,
+    /// External program to open images with instead of drawing them
+    pub viewer: Option<String>,
+    pub fit: Option<Fit>,
+    pub center: bool,
+    /// Most columns the image may cover
+    pub max_width: Option<u32>,
+    /// Most rows the image may cover
+    pub max_height: Option<u32>,
+    /// Never draw the image bigger than its own pixel size
+    pub no_upscale: bool,
+    /// Factor applied to the size the image would otherwise get
+    pub scale: Option<f64>,
+    /// Exact size in pixels, for kitty and iTerm
+    pub pixel_width: Option<u32>,
+    pub pixel_height: Option<u32>,
+    pub crop: Option<Crop>,
+    /// Percentages from -100 to 100, 0 leaving the image as it is
+    pub brightness: i32,
+    pub contrast: i32,
+    pub saturation: i32,
+    /// Color adjustments, applied in order
+    pub filters: Vec<Filter>,
+    /// What transparent pixels are drawn over, left up to the renderer when not given
+    pub background: Option<Background>,
+    /// How to obscure questionable and explicit images from the boorus
+    pub censor: Option<Censor>,
+    /// Write the post's attribution onto booru images
+    pub caption: bool,
+    /// Print a QR code of the post's page beneath the image
+    pub qr: bool,
+    /// What to put in the clipboard once the image is shown
+    pub copy: Option<crate::clipboard::Field>,
+    /// Open the post's page in the browser once the image is shown
+    pub open: bool,
+    /// Discord or Slack webhook the image is posted to once it's shown
+    #[cfg(feature = "network")]
+    pub webhook: Option<String>,
+    /// Command each image shown is handed to for downloading
+    pub download_cmd: Option<String>,
+    /// External program images are run through before being shown
+    pub upscale_cmd: Option<String>,
+    /// External program that grabs a still frame of videos
+    pub poster_cmd: Option<String>,
+    /// Referer header sent when downloading images
+    #[cfg(feature = "network")]
+    pub referer: Option<String>,
+    /// How long downloaded images are reused for, when they're cached at all
+    #[cfg(feature = "network")]
+    pub cache_ttl: Option<Duration>,
+    /// Most bytes the image cache can take up
+    #[cfg(feature = "network")]
+    pub cache_size: u64,
+    /// Write the image to stdout in this format rather than drawing it
+    pub stdout: Option<output::Format>,
+    /// Add the images shown to the history
+    #[cfg(feature = "json")]
+    pub history: bool,
+    /// Print the API URL of booru searches rather than sending them
+    #[cfg(any(feature = "danbooru", feature = "safebooru"))]
+    pub dry_run: bool,
+    /// Print the image URL of random posts rather than showing them
+    #[cfg(any(feature = "danbooru", feature = "safebooru"))]
+    pub url_only: bool,
+    /// Pass over booru posts shown this recently
+    #[cfg(any(feature = "danbooru", feature = "safebooru"))]
+    pub no_repeat: Option<Duration>,
+    /// Pick the same posts for the same search all day
+    #[cfg(any(feature = "danbooru", feature = "safebooru"))]
+    pub daily: bool,
+    /// Play animated images rather than showing their first frame
+    pub animate: bool,
+    /// Times to play animations, forever when not given
+    pub loops: Option<u32>,
+    /// How many times taller than wide the terminal's cells are, when it isn't the usual 2
+    pub cell_aspect: Option<f64>,
+}
+
+impl Default for Settings {
+    /// What `waifu` draws with when given no options: as big as fits in the
+    /// terminal, with the best protocol and colors it supports
+    fn default() -> Self {
+        let colors = detect_colors();
+        let mut config = viuer::Config {
+            truecolor: colors == ColorDepth::Truecolor,
+            ..Default::default()
+        };
+        configure(&mut config, Protocol::Auto);
+
+        Settings {
+            config,
+            renderer: Renderer::Viuer,
+            monochrome: false,
+            colors,
+            dither: None,
+            palette_size: None,
+            protocol: Protocol::Auto,
+            tmux_passthrough: Passthrough::Auto,
+            viewer: None,
+            fit: None,
+            center: false,
+            max_width: None,
+            max_height: None,
+            no_upscale: false,
+            scale: None,
+            pixel_width: None,
+            pixel_height: None,
+            crop: None,
+            brightness: 0,
+            contrast: 0,
+            saturation: 0,
+            filters: Vec::new(),
+            background: None,
+            censor: None,
+            caption: false,
+            qr: false,
+            copy: None,
+            open: false,
+            #[cfg(feature = "network")]
+            webhook: None,
+            download_cmd: None,
+            upscale_cmd: None,
+            poster_cmd: None,
+            #[cfg(feature = "network")]
+            referer: None,
+            #[cfg(feature = "network")]
+            cache_ttl: None,
+            #[cfg(feature = "network")]
+            cache_size: 500 << 20,
+            stdout: None,
+            #[cfg(feature = "json")]
+            history: false,
+            #[cfg(any(feature = "danbooru", feature = "safebooru"))]
+            dry_run: false,
+            #[cfg(any(feature = "danbooru", feature = "safebooru"))]
+            url_only: false,
+            #[cfg(any(feature = "danbooru", feature = "safebooru"))]
+            no_repeat: None,
+            #[cfg(any(feature = "danbooru", feature = "safebooru"))]
+            daily: false,
+            animate: false,
+            loops: None,
+            cell_aspect: None,
+        }
+    }
+}
+
+/// Draw the image in the terminal, or write it to stdout with `stdout` set
+pub fn print(image: &DynamicImage, settings: &Settings) -> Result<(), WaifuError> {
+    if let Some(format) = settings.stdout {
+        return output::write(image, settings, format);
+    }
+
+    let (image, config) = prepare(image, settings)?;
+    render(&image, settings, &config).map_err(|e| WaifuError::Terminal(e.to_string()))?;
+
+    Ok(())
+}
+
+/// Apply the image adjustments and work out its final size,
+/// leaving an image any of the renderers can draw as is
+pub fn prepare<'a>(
+    image: &'a DynamicImage,
+    settings: &Settings,
+) -> Result<(Cow<'a, DynamicImage>, viuer::Config), WaifuError> {
+    let image = transform::prepare(image, settings)?;
+    let (laid_out, mut config) = layout::layout(&image, settings);
+    let image = match laid_out {
+        Cow::Borrowed(_) => image,
+        Cow::Owned(laid_out) => Cow::Owned(laid_out),
+    };
+
+    let (width, height) = image.dimensions();
+    let (columns, rows) = fit_cells(width, height, config.width, config.height);
+    let image = transform::flatten(image, settings, columns);
+
+    // Pin the size down first, since the renderers would size a smaller image differently
+    let image = prescale::prescale(image, columns, rows);
+    config.width = Some(columns);
+    config.height = Some(rows);
+
+    Ok((image, config))
+}
+
+/// Draw an image from [`prepare`], returning how many rows it covers
+pub fn render(
+    image: &DynamicImage,
+    settings: &Settings,
+    config: &viuer::Config,
+) -> Result<u32, WaifuError> {
+    let rows = config.height.unwrap_or(1);
+    if tracing::enabled!(tracing::Level::INFO) {
+        tracing::info!(
+            "Drawing {}x{} pixels with {}",
+            image.width(),
+            image.height(),
+            drawn_with(settings, config)
+        );
+    }
+
+    match settings.renderer {
+        Renderer::Viuer => {
+            let wrap = tmux::should_wrap(settings.tmux_passthrough);
+            match settings.protocol {
+                Protocol::Kitty => {
+                    return graphics::print(image, settings, config, Encoding::Kitty, wrap)
+                }
+                Protocol::Iterm => {
+                    return graphics::print(image, settings, config, Encoding::Iterm, wrap)
+                }
+                Protocol::Auto => {
+                    if let Some(encoding) = tmux::passthrough_encoding(settings.tmux_passthrough) {
+                        return graphics::print(image, settings, config, encoding, true);
+                    }
+
+                    // viuer only sizes in cells, so pixel sizes need our own encoder
+                    let pixel_sized =
+                        settings.pixel_width.is_some() || settings.pixel_height.is_some();
+                    if let Some(encoding) = detected_encoding(config).filter(|_| pixel_sized) {
+                        return graphics::print(image, settings, config, encoding, false);
+                    }
+                }
+                Protocol::Sixel | Protocol::Blocks => (),
+            }
+
+            // viuer's block printer can't quantize, so take over when it would be used
+            let quantize = settings.dither.is_some_and(|d| d != Dither::None)
+                || settings.palette_size.is_some()
+                || settings.colors == ColorDepth::Ansi16;
+            if quantize && !graphics_supported(config) {
+                blocks::print(image, settings, config)?;
+            } else {
+                let (_, rows) = viuer::print(image, config)?;
+                return Ok(rows);
+            }
+        }
+        Renderer::Braille => braille::print(image, settings, config)?,
+        Renderer::Ascii => ascii::print(image, config)?,
+    }
+
+    Ok(rows)
+}
+
+/// What the image ends up drawn with, for the logs and `waifu doctor`
+pub fn drawn_with(settings: &Settings, config: &viuer::Config) -> &'static str {
+    match (&settings.renderer, settings.protocol) {
+        (Renderer::Braille, _) => "braille",
+        (Renderer::Ascii, _) => "ascii",
+        (Renderer::Viuer, Protocol::Kitty) => "kitty",
+        (Renderer::Viuer, Protocol::Iterm) => "iterm",
+        (Renderer::Viuer, protocol) => {
+            let passed = tmux::passthrough_encoding(settings.tmux_passthrough)
+                .filter(|_| protocol == Protocol::Auto);
+            match passed.or_else(|| detected_encoding(config)) {
+                Some(Encoding::Kitty) => "kitty",
+                Some(Encoding::Iterm) => "iterm",
+                None if sixel_supported(config) => "sixel",
+                None => "half blocks",
+            }
+        }
+    }
+}
+
+/// Limit which protocols viuer may pick from
+pub fn configure(config: &mut viuer::Config, protocol: Protocol) {
+    match protocol {
+        // Escape code based kitty works fine remotely and is detected through TERM,
+        // while the iTerm and sixel checks either misfire or stall over the connection
+        Protocol::Auto if over_ssh() => {
+            config.use_iterm = false;
+            #[cfg(feature = "sixel")]
+            {
+                config.use_sixel = false;
+            }
+        }
+        Protocol::Auto | Protocol::Kitty | Protocol::Iterm => (),
+        Protocol::Sixel => {
+            config.use_kitty = false;
+            config.use_iterm = false;
+        }
+        Protocol::Blocks => {
+            config.use_kitty = false;
+            config.use_iterm = false;
+            #[cfg(feature = "sixel")]
+            {
+                config.use_sixel = false;
+            }
+        }
+    }
+}
+
+pub fn over_ssh() -> bool {
+    ["SSH_CONNECTION", "SSH_CLIENT", "SSH_TTY"]
+        .iter()
+        .any(|name| std::env::var_os(name).is_some_and(|v| !v.is_empty()))
+}
+
+/// The kitty or iTerm protocol, if viuer would pick one of them
+pub fn detected_encoding(config: &viuer::Config) -> Option<Encoding> {
+    if config.use_iterm && viuer::is_iterm_supported() {
+        Some(Encoding::Iterm)
+    } else if config.use_kitty && viuer::get_kitty_support() != viuer::KittySupport::None {
+        Some(Encoding::Kitty)
+    } else {
+        None
+    }
+}
+
+/// Whether viuer will draw with a graphics protocol rather than falling back to half blocks
+pub fn graphics_supported(config: &viuer::Config) -> bool {
+    sixel_supported(config)
+        || (config.use_iterm && viuer::is_iterm_supported())
+        || (config.use_kitty && viuer::get_kitty_support() != viuer::KittySupport::None)
+}
+
+#[cfg(feature = "sixel")]
+fn sixel_supported(config: &viuer::Config) -> bool {
+    config.use_sixel && viuer::is_sixel_supported()
+}
+
+// Sixel falls back to half blocks without the feature
+#[cfg(not(feature = "sixel"))]
+fn sixel_supported(_: &viuer::Config) -> bool {
+    false
+}
+
+/// Move the cursor to where the image starts, following the same offset rules as viuer.
+/// The horizontal offset is left to [`indent`], which has to be repeated on every row.
+pub fn move_to_offset(out: &mut impl Write, config: &viuer::Config) -> io::Result<()> {
+    if config.absolute_offset {
+        write!(out, "\x1b[{};1H", config.y.max(0) + 1)?;
+    } else if config.y < 0 {
+        write!(out, "\x1b[{}F", -config.y)?;
+    } else {
+        for _ in 0..config.y {
+            writeln!(out)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Shift the start of a row over by the horizontal offset
+pub fn indent(out: &mut impl Write, config: &viuer::Config) -> io::Result<()> {
+    if config.x > 0 {
+        write!(out, "\x1b[{}C", config.x)?;
+    }
+
+    Ok(())
+}
+
+/// How much taller a terminal cell is than it is wide, unless told otherwise
+pub const CELL_ASPECT: f64 = 2.0;
+
+/// Find how many terminal cells (columns, rows) an image should cover.
+/// Mirrors viuer's own fitting so every renderer produces the same footprint:
+/// images are only scaled down, and a cell is treated as twice as tall as it is wide.
+pub fn fit_cells(
+    image_width: u32,
+    image_height: u32,
+    width: Option<u32>,
+    height: Option<u32>,
+) -> (u32, u32) {
+    fit_cells_with_aspect(image_width, image_height, width, height, CELL_ASPECT)
+}
+
+/// [`fit_cells`] for cells that are `aspect` times taller than they are wide
+pub fn fit_cells_with_aspect(
+    image_width: u32,
+    image_height: u32,
+    width: Option<u32>,
+    height: Option<u32>,
+    aspect: f64,
+) -> (u32, u32) {
+    match (width, height) {
+        (None, None) => {
+            let (term_w, term_h) = viuer::terminal_size();
+            let (w, h) = fit_dimensions(
+                image_width,
+                image_height,
+                term_w as u32,
+                term_h as u32,
+                aspect,
+            );
+
+            // Leave a line for the prompt once we're done printing
+            let h = if h == term_h as u32 { h - 1 } else { h };
+            (w, h.max(1))
+        }
+        (Some(w), None) => fit_dimensions(image_width, image_height, w, u32::MAX, aspect),
+        (None, Some(h)) => fit_dimensions(image_width, image_height, u32::MAX, h, aspect),
+        (Some(w), Some(h)) => (w, h),
+    }
+}
+
+fn fit_dimensions(
+    width: u32,
+    height: u32,
+    bound_width: u32,
+    bound_height: u32,
+    aspect: f64,
+) -> (u32, u32) {
+    let (width, height) = (width.max(1) as f64, height.max(1) as f64);
+    let bound_width = bound_width as f64;
+    let bound_height = aspect * bound_height as f64;
+    let cells = |n: f64| (n.floor() as u32).max(1);
+
+    if width <= bound_width && height <= bound_height {
+        return (width as u32, (height / aspect).ceil() as u32);
+    }
+
+    let use_width = bound_width * height <= width * bound_height;
+    if use_width {
+        (
+            bound_width as u32,
+            cells(height * bound_width / width / aspect),
+        )
+    } else {
+        (
+            cells(width * bound_height / height),
+            cells(bound_height / aspect),
+        )
+    }
+}
+
+/// Work out the terminal's color depth from COLORTERM, TERM and its terminfo entry
+pub fn detect_colors() -> ColorDepth {
+    use std::env;
+
+    if let Ok(colorterm) = env::var("COLORTERM") {
+        if colorterm.contains("truecolor") || colorterm.contains("24bit") {
+            return ColorDepth::Truecolor;
+        }
+    }
+
+    let term = env::var("TERM").unwrap_or_default();
+    if term.ends_with("-direct") {
+        return ColorDepth::Truecolor;
+    }
+    if term.contains("256color") {
+        return ColorDepth::Ansi256;
+    }
+    if term == "linux" || term.starts_with("vt") || term == "ansi" {
+        return ColorDepth::Ansi16;
+    }
+
+    // Anything else is up to terminfo, assuming 256 colors if it has nothing to say
+    let advertised = std::process::Command::new("tput")
+        .arg("colors")
+        .output()
+        .ok()
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .and_then(|colors| colors.trim().parse::<i32>().ok());
+
+    match advertised {
+        Some(colors) if colors >= 1 << 24 => ColorDepth::Truecolor,
+        Some(colors) if colors < 256 && colors > 0 => ColorDepth::Ansi16,
+        _ => ColorDepth::Ansi256,
+    }
+}
+
+/// Escape sequence selecting a foreground color, approximated to the closest
+/// color the terminal can show
+pub fn foreground(r: u8, g: u8, b: u8, colors: ColorDepth) -> String {
+    match colors {
+        ColorDepth::Truecolor => format!("\x1b[38;2;{};{};{}m", r, g, b),
+        ColorDepth::Ansi256 => format!("\x1b[38;5;{}m", ansi256(r, g, b)),
+        ColorDepth::Ansi16 => format!("\x1b[{}m", ansi16_code(r, g, b, 30)),
+    }
+}
+
+/// Escape sequence selecting a background color, see [`foreground`]
+pub fn background(r: u8, g: u8, b: u8, colors: ColorDepth) -> String {
+    match colors {
+        ColorDepth::Truecolor => format!("\x1b[48;2;{};{};{}m", r, g, b),
+        ColorDepth::Ansi256 => format!("\x1b[48;5;{}m", ansi256(r, g, b)),
+        ColorDepth::Ansi16 => format!("\x1b[{}m", ansi16_code(r, g, b, 40)),
+    }
+}
+
+// SGR parameter for an ANSI color; bright colors start 60 above the normal ones
+fn ansi16_code(r: u8, g: u8, b: u8, base: u8) -> u8 {
+    let index = palette::ansi16_index([r, g, b]);
+    if index < 8 {
+        base + index
+    } else {
+        base + 60 + index - 8
+    }
+}
+
+/// Closest entry of the xterm 256 color palette (6x6x6 cube or grayscale ramp)
+pub fn ansi256(r: u8, g: u8, b: u8) -> u8 {
+    let level = |c: u8| -> u8 {
+        if c < 48 {
+            0
+        } else if c < 115 {
+            1
+        } else {
+            (c - 35) / 40
+        }
+    };
+    let value = |l: u8| -> u8 {
+        if l == 0 {
+            0
+        } else {
+            55 + l * 40
+        }
+    };
+    let distance = |(r2, g2, b2): (u8, u8, u8)| -> u32 {
+        let d = |a: u8, b: u8| (a as i32 - b as i32).pow(2) as u32;
+        d(r, r2) + d(g, g2) + d(b, b2)
+    };
+
+    let (lr, lg, lb) = (level(r), level(g), level(b));
+    let cube = 16 + 36 * lr + 6 * lg + lb;
+    let cube_rgb = (value(lr), value(lg), value(lb));
+
+    let average = (r as u32 + g as u32 + b as u32) / 3;
+    let gray_level = if average > 238 {
+        23
+    } else {
+        (average.saturating_sub(3) / 10) as u8
+    };
+    let gray = 232 + gray_level;
+    let gray_value = 8 + 10 * gray_level;
+
+    if distance((gray_value, gray_value, gray_value)) < distance(cube_rgb) {
+        gray
+    } else {
+        cube
+    }
+}
+
+/// Perceived brightness of a pixel, from 0 to 255
+pub fn luminance(r: u8, g: u8, b: u8) -> u8 {
+    ((r as u32 * 299 + g as u32 * 587 + b as u32 * 114) / 1000) as u8
+}
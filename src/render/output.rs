@@ -0,0 +1,44 @@
+use clap::ValueEnum;
+use image::{DynamicImage, GenericImageView, ImageOutputFormat};
+use is_terminal::IsTerminal;
+use std::io::{self, Cursor, Write};
+
+use crate::error::WaifuError;
+use crate::render::{fit_cells, transform, Settings};
+
+/// File formats --stdout can write
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Png,
+    Jpeg,
+}
+
+/// Write the image to stdout instead of drawing it, with the crop, adjustments,
+/// filters and background applied but at its full resolution
+pub fn write(image: &DynamicImage, settings: &Settings, format: Format) -> Result<(), WaifuError> {
+    let stdout = io::stdout();
+    if stdout.is_terminal() {
+        let message = "Not writing image data to the terminal. Pipe --stdout into another program, or redirect it to a file";
+        return Err(WaifuError::Terminal(message.into()));
+    }
+
+    let image = transform::prepare(image, settings)?;
+    // Checker squares come out as big, relative to the image, as they'd be on screen
+    let (width, height) = image.dimensions();
+    let (columns, _) = fit_cells(width, height, settings.config.width, settings.config.height);
+    let image = transform::flatten(image, settings, columns);
+
+    let mut bytes = Vec::new();
+    match format {
+        Format::Png => image.write_to(&mut Cursor::new(&mut bytes), ImageOutputFormat::Png)?,
+        // JPEG has no transparency, or anything but 8 bits per channel
+        Format::Jpeg => DynamicImage::ImageRgb8(image.to_rgb8())
+            .write_to(&mut Cursor::new(&mut bytes), ImageOutputFormat::Jpeg(90))?,
+    }
+
+    let mut lock = stdout.lock();
+    lock.write_all(&bytes)?;
+    lock.flush()?;
+
+    Ok(())
+}
@@ -0,0 +1,135 @@
+use image::RgbaImage;
+
+pub type Color = [u8; 3];
+
+/// The colors a 256 color terminal can show, in palette order.
+/// The first 16 depend on the terminal theme and are skipped, so this only
+/// holds the 6x6x6 cube followed by the grayscale ramp.
+pub fn xterm256() -> Vec<Color> {
+    const LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+    let mut colors = Vec::with_capacity(240);
+    for r in LEVELS {
+        for g in LEVELS {
+            for b in LEVELS {
+                colors.push([r, g, b]);
+            }
+        }
+    }
+    for step in 0..24 {
+        let value = 8 + step * 10;
+        colors.push([value, value, value]);
+    }
+
+    colors
+}
+
+/// The standard 16 ANSI colors as xterm draws them, indexed like SGR 30-37 then 90-97
+pub const ANSI16: [Color; 16] = [
+    [0, 0, 0],
+    [205, 0, 0],
+    [0, 205, 0],
+    [205, 205, 0],
+    [0, 0, 238],
+    [205, 0, 205],
+    [0, 205, 205],
+    [229, 229, 229],
+    [127, 127, 127],
+    [255, 0, 0],
+    [0, 255, 0],
+    [255, 255, 0],
+    [92, 92, 255],
+    [255, 0, 255],
+    [0, 255, 255],
+    [255, 255, 255],
+];
+
+/// Position of the closest ANSI color in [`ANSI16`]
+pub fn ansi16_index(color: Color) -> u8 {
+    (0..ANSI16.len())
+        .min_by_key(|&i| distance(ANSI16[i], color))
+        .unwrap_or(0) as u8
+}
+
+pub fn distance(a: Color, b: Color) -> u32 {
+    let d = |x: u8, y: u8| (x as i32 - y as i32).pow(2) as u32;
+    // Weighted towards green, which the eye is most sensitive to
+    2 * d(a[0], b[0]) + 4 * d(a[1], b[1]) + 3 * d(a[2], b[2])
+}
+
+pub fn nearest(palette: &[Color], color: Color) -> Color {
+    palette
+        .iter()
+        .copied()
+        .min_by_key(|entry| distance(*entry, color))
+        .unwrap_or(color)
+}
+
+/// Reduce the colors of an image to a palette of at most `size` entries
+/// using median cut. Transparent pixels are ignored.
+pub fn median_cut(image: &RgbaImage, size: usize) -> Vec<Color> {
+    let pixels: Vec<Color> = image
+        .pixels()
+        .filter(|p| p[3] >= 128)
+        .map(|p| [p[0], p[1], p[2]])
+        .collect();
+
+    if pixels.is_empty() || size == 0 {
+        return Vec::new();
+    }
+
+    let mut boxes = vec![pixels];
+    while boxes.len() < size {
+        // Split the box whose colors are spread out the most
+        let (index, channel, range) = boxes
+            .iter()
+            .enumerate()
+            .map(|(i, colors)| {
+                let (channel, range) = widest_channel(colors);
+                (i, channel, range)
+            })
+            .max_by_key(|(_, _, range)| *range)
+            .unwrap_or((0, 0, 0));
+
+        if range == 0 {
+            break;
+        }
+
+        let mut colors = boxes.swap_remove(index);
+        colors.sort_unstable_by_key(|color| color[channel]);
+        let upper = colors.split_off(colors.len() / 2);
+        boxes.push(colors);
+        boxes.push(upper);
+    }
+
+    // Biggest boxes first, so the most common colors lead the palette
+    boxes.sort_by_key(|colors| std::cmp::Reverse(colors.len()));
+    boxes.iter().map(|colors| average(colors)).collect()
+}
+
+fn widest_channel(colors: &[Color]) -> (usize, u8) {
+    (0..3)
+        .map(|channel| {
+            let min = colors.iter().map(|c| c[channel]).min().unwrap_or(0);
+            let max = colors.iter().map(|c| c[channel]).max().unwrap_or(0);
+            (channel, max - min)
+        })
+        .max_by_key(|(_, range)| *range)
+        .unwrap_or((0, 0))
+}
+
+fn average(colors: &[Color]) -> Color {
+    let count = colors.len().max(1) as u64;
+    let mut sum = [0u64; 3];
+    for color in colors {
+        for channel in 0..3 {
+            sum[channel] += color[channel] as u64;
+        }
+    }
+
+    [
+        (sum[0] / count) as u8,
+        (sum[1] / count) as u8,
+        (sum[2] / count) as u8,
+    ]
+}
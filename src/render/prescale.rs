@@ -0,0 +1,44 @@
+use fast_image_resize::{images::Image, FilterType, PixelType, ResizeAlg, ResizeOptions, Resizer};
+use image::{DynamicImage, GenericImageView, RgbaImage};
+use std::borrow::Cow;
+
+use crate::render::layout::{cell_size, DEFAULT_CELL_SIZE};
+
+/// Shrink a large image to about the pixel size of the cells it will cover before
+/// it reaches the renderers, whose own resizing is a lot slower on big originals.
+/// The aspect ratio is kept, and some headroom is left for high-DPI terminals
+/// that don't report their cell size.
+pub fn prescale(image: Cow<'_, DynamicImage>, columns: u32, rows: u32) -> Cow<'_, DynamicImage> {
+    let (cell_w, cell_h) =
+        cell_size().unwrap_or((DEFAULT_CELL_SIZE.0 * 2, DEFAULT_CELL_SIZE.1 * 2));
+    let (target_w, target_h) = (columns * cell_w, rows * cell_h);
+
+    let (width, height) = image.dimensions();
+    let scale = f64::min(
+        target_w as f64 / width.max(1) as f64,
+        target_h as f64 / height.max(1) as f64,
+    );
+    // Anything close to the right size already isn't worth another pass
+    if scale > 0.5 {
+        return image;
+    }
+
+    let new_w = ((width as f64 * scale).round() as u32).max(1);
+    let new_h = ((height as f64 * scale).round() as u32).max(1);
+    match resize(&image, new_w, new_h) {
+        Some(resized) => Cow::Owned(resized),
+        None => image,
+    }
+}
+
+fn resize(image: &DynamicImage, width: u32, height: u32) -> Option<DynamicImage> {
+    let (src_w, src_h) = image.dimensions();
+    let src =
+        Image::from_vec_u8(src_w, src_h, image.to_rgba8().into_raw(), PixelType::U8x4).ok()?;
+    let mut dst = Image::new(width, height, PixelType::U8x4);
+
+    let options = ResizeOptions::new().resize_alg(ResizeAlg::Convolution(FilterType::Bilinear));
+    Resizer::new().resize(&src, &mut dst, &options).ok()?;
+
+    RgbaImage::from_raw(width, height, dst.into_vec()).map(DynamicImage::ImageRgba8)
+}
@@ -0,0 +1,523 @@
+use std::io::{self, Write};
+
+/// A QR code, drawn under an image so a phone can open the post
+#[derive(Clone)]
+pub struct QrCode {
+    size: usize,
+    /// Row by row, true for the dark modules
+    modules: Vec<bool>,
+    /// Finder, timing and other patterns the data has to go around
+    reserved: Vec<bool>,
+}
+
+/// Error correction codewords in each block, by version, for the low level
+const ECC_PER_BLOCK: [usize; 41] = [
+    0, 7, 10, 15, 20, 26, 18, 20, 24, 30, 18, 20, 24, 26, 30, 22, 24, 28, 30, 28, 28, 28, 28, 30,
+    30, 26, 28, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30,
+];
+
+/// How many blocks the codewords are split into, by version, for the low level
+const BLOCKS: [usize; 41] = [
+    0, 1, 1, 1, 1, 1, 2, 2, 2, 2, 4, 4, 4, 4, 4, 6, 6, 6, 6, 7, 8, 8, 9, 9, 10, 12, 12, 12, 13, 14,
+    15, 16, 17, 18, 19, 19, 20, 21, 22, 24, 25,
+];
+
+/// Encode text in byte mode with the low error correction level, which keeps the
+/// code as small as it can be. None when it's too long for any version
+pub fn encode(text: &str) -> Option<QrCode> {
+    let data = text.as_bytes();
+    let version = (1..=40).find(|&version| {
+        let count_bits = if version <= 9 { 8 } else { 16 };
+        4 + count_bits + data.len() * 8 <= data_codewords(version) * 8
+    })?;
+
+    let mut bits = Bits::default();
+    bits.push(0b0100, 4);
+    bits.push(data.len() as u32, if version <= 9 { 8 } else { 16 });
+    for &byte in data {
+        bits.push(byte as u32, 8);
+    }
+    // The terminator, then padding to a whole byte and alternating pad bytes
+    let capacity = data_codewords(version) * 8;
+    bits.push(0, (capacity - bits.len).min(4));
+    bits.push(0, (8 - bits.len % 8) % 8);
+    for pad in [0xEC, 0x11].into_iter().cycle() {
+        if bits.len >= capacity {
+            break;
+        }
+        bits.push(pad, 8);
+    }
+
+    let mut code = QrCode::blank(version);
+    code.place(&add_error_correction(&bits.bytes, version));
+    let masked = |mask| {
+        let mut masked = code.clone();
+        masked.apply_mask(mask);
+        masked.draw_format(mask);
+        masked
+    };
+    let mask = (0..8)
+        .min_by_key(|&mask| masked(mask).penalty())
+        .unwrap_or(0);
+
+    Some(masked(mask))
+}
+
+impl QrCode {
+    /// Print it with half blocks, two rows of modules to a line, dark on light
+    /// whatever the terminal's colors are
+    pub fn print(&self) -> io::Result<()> {
+        const QUIET: isize = 4;
+        let dark = |x: isize, y: isize| {
+            (0..self.size as isize).contains(&x)
+                && (0..self.size as isize).contains(&y)
+                && self.get(x as usize, y as usize)
+        };
+
+        let stdout = io::stdout();
+        let mut out = io::BufWriter::new(stdout.lock());
+        let end = self.size as isize + QUIET;
+        for y in (-QUIET..end).step_by(2) {
+            for x in -QUIET..end {
+                let foreground = if dark(x, y) { 30 } else { 97 };
+                let background = if dark(x, y + 1) { 40 } else { 107 };
+                write!(out, "\x1b[{};{}m▀", foreground, background)?;
+            }
+            writeln!(out, "\x1b[0m")?;
+        }
+        out.flush()
+    }
+
+    fn blank(version: usize) -> QrCode {
+        let size = version * 4 + 17;
+        let mut code = QrCode {
+            size,
+            modules: vec![false; size * size],
+            reserved: vec![false; size * size],
+        };
+
+        for i in 0..size {
+            code.set_reserved(6, i, i % 2 == 0);
+            code.set_reserved(i, 6, i % 2 == 0);
+        }
+        for (x, y) in [(3, 3), (size - 4, 3), (3, size - 4)] {
+            code.draw_finder(x, y);
+        }
+        let positions = alignment_positions(version, size);
+        let last = positions.len().saturating_sub(1);
+        for (i, &x) in positions.iter().enumerate() {
+            for (j, &y) in positions.iter().enumerate() {
+                // The corners where the finders are
+                if (i, j) != (0, 0) && (i, j) != (0, last) && (i, j) != (last, 0) {
+                    code.draw_alignment(x, y);
+                }
+            }
+        }
+        // The format is drawn over these once the mask is picked
+        code.draw_format(0);
+        code.draw_version(version);
+
+        code
+    }
+
+    fn get(&self, x: usize, y: usize) -> bool {
+        self.modules[y * self.size + x]
+    }
+
+    fn set_reserved(&mut self, x: usize, y: usize, dark: bool) {
+        self.modules[y * self.size + x] = dark;
+        self.reserved[y * self.size + x] = true;
+    }
+
+    fn draw_finder(&mut self, x: usize, y: usize) {
+        for dy in -4isize..=4 {
+            for dx in -4isize..=4 {
+                let (xx, yy) = (x as isize + dx, y as isize + dy);
+                if (0..self.size as isize).contains(&xx) && (0..self.size as isize).contains(&yy) {
+                    let distance = dx.abs().max(dy.abs());
+                    self.set_reserved(xx as usize, yy as usize, distance != 2 && distance != 4);
+                }
+            }
+        }
+    }
+
+    fn draw_alignment(&mut self, x: usize, y: usize) {
+        for dy in -2isize..=2 {
+            for dx in -2isize..=2 {
+                let (xx, yy) = ((x as isize + dx) as usize, (y as isize + dy) as usize);
+                self.set_reserved(xx, yy, dx.abs().max(dy.abs()) != 1);
+            }
+        }
+    }
+
+    /// The error correction level and mask, twice over
+    fn draw_format(&mut self, mask: u32) {
+        // 01 is the low error correction level
+        let data = (0b01 << 3) | mask;
+        let mut remainder = data;
+        for _ in 0..10 {
+            remainder = (remainder << 1) ^ ((remainder >> 9) * 0x537);
+        }
+        let bits = ((data << 10) | remainder) ^ 0x5412;
+        let bit = |i: usize| (bits >> i) & 1 == 1;
+
+        let size = self.size;
+        for i in 0..6 {
+            self.set_reserved(8, i, bit(i));
+        }
+        self.set_reserved(8, 7, bit(6));
+        self.set_reserved(8, 8, bit(7));
+        self.set_reserved(7, 8, bit(8));
+        for i in 9..15 {
+            self.set_reserved(14 - i, 8, bit(i));
+        }
+
+        for i in 0..8 {
+            self.set_reserved(size - 1 - i, 8, bit(i));
+        }
+        for i in 8..15 {
+            self.set_reserved(8, size - 15 + i, bit(i));
+        }
+        // Always dark
+        self.set_reserved(8, size - 8, true);
+    }
+
+    /// Versions 7 and up say which they are in two more places
+    fn draw_version(&mut self, version: usize) {
+        if version < 7 {
+            return;
+        }
+
+        let mut remainder = version as u32;
+        for _ in 0..12 {
+            remainder = (remainder << 1) ^ ((remainder >> 11) * 0x1F25);
+        }
+        let bits = ((version as u32) << 12) | remainder;
+        for i in 0..18 {
+            let dark = (bits >> i) & 1 == 1;
+            let (a, b) = (self.size - 11 + i % 3, i / 3);
+            self.set_reserved(a, b, dark);
+            self.set_reserved(b, a, dark);
+        }
+    }
+
+    /// Lay the codewords out in two-column strips, zigzagging up and down from
+    /// the right, around the reserved modules
+    fn place(&mut self, codewords: &[u8]) {
+        let size = self.size;
+        let mut i = 0;
+        let mut right = size - 1;
+        loop {
+            // The vertical timing pattern is skipped over
+            if right == 6 {
+                right = 5;
+            }
+            for vertical in 0..size {
+                for j in 0..2 {
+                    let x = right - j;
+                    let upward = (right + 1) & 2 == 0;
+                    let y = if upward {
+                        size - 1 - vertical
+                    } else {
+                        vertical
+                    };
+                    if !self.reserved[y * size + x] && i < codewords.len() * 8 {
+                        self.modules[y * size + x] = (codewords[i >> 3] >> (7 - (i & 7))) & 1 == 1;
+                        i += 1;
+                    }
+                }
+            }
+            if right < 2 {
+                break;
+            }
+            right -= 2;
+        }
+    }
+
+    fn apply_mask(&mut self, mask: u32) {
+        for y in 0..self.size {
+            for x in 0..self.size {
+                let flip = match mask {
+                    0 => (x + y) % 2 == 0,
+                    1 => y % 2 == 0,
+                    2 => x % 3 == 0,
+                    3 => (x + y) % 3 == 0,
+                    4 => (x / 3 + y / 2) % 2 == 0,
+                    5 => x * y % 2 + x * y % 3 == 0,
+                    6 => (x * y % 2 + x * y % 3) % 2 == 0,
+                    _ => ((x + y) % 2 + x * y % 3) % 2 == 0,
+                };
+                let index = y * self.size + x;
+                if flip && !self.reserved[index] {
+                    self.modules[index] = !self.modules[index];
+                }
+            }
+        }
+    }
+
+    /// How hard it is to read, for picking the mask: long runs and blocks of one
+    /// color, patterns that look like the finders, and too much of either color
+    fn penalty(&self) -> usize {
+        let size = self.size;
+        let mut penalty = 0;
+
+        for transposed in [false, true] {
+            let at = |a: usize, b: usize| {
+                if transposed {
+                    self.get(a, b)
+                } else {
+                    self.get(b, a)
+                }
+            };
+            for a in 0..size {
+                let line: Vec<bool> = (0..size).map(|b| at(a, b)).collect();
+                let mut run = 1;
+                for b in 1..=size {
+                    if b < size && line[b] == line[b - 1] {
+                        run += 1;
+                        continue;
+                    }
+                    if run >= 5 {
+                        penalty += run - 2;
+                    }
+                    run = 1;
+                }
+                // Light modules past the edge count as the quiet zone
+                let padded: Vec<bool> = [false; 4]
+                    .into_iter()
+                    .chain(line)
+                    .chain([false; 4])
+                    .collect();
+                const FINDER: [bool; 11] = [
+                    true, false, true, true, true, false, true, false, false, false, false,
+                ];
+                for window in padded.windows(11) {
+                    let reversed = window.iter().rev().eq(FINDER.iter());
+                    if window == FINDER || reversed {
+                        penalty += 40;
+                    }
+                }
+            }
+        }
+
+        for y in 0..size - 1 {
+            for x in 0..size - 1 {
+                let color = self.get(x, y);
+                if color == self.get(x + 1, y)
+                    && color == self.get(x, y + 1)
+                    && color == self.get(x + 1, y + 1)
+                {
+                    penalty += 3;
+                }
+            }
+        }
+
+        let dark = self.modules.iter().filter(|&&dark| dark).count();
+        let total = size * size;
+        // Each 5% further from half dark than the first
+        let off = (dark * 20).abs_diff(total * 10);
+        penalty + off.div_ceil(total).saturating_sub(1) * 10
+    }
+}
+
+#[derive(Default)]
+struct Bits {
+    bytes: Vec<u8>,
+    len: usize,
+}
+
+impl Bits {
+    /// Add the lowest `count` bits of the value, highest first
+    fn push(&mut self, value: u32, count: usize) {
+        for i in (0..count).rev() {
+            if self.len.is_multiple_of(8) {
+                self.bytes.push(0);
+            }
+            let bit = ((value >> i) & 1) as u8;
+            *self.bytes.last_mut().unwrap() |= bit << (7 - self.len % 8);
+            self.len += 1;
+        }
+    }
+}
+
+/// The modules left for data and error correction once the patterns are drawn
+fn raw_modules(version: usize) -> usize {
+    let mut modules = (16 * version + 128) * version + 64;
+    if version >= 2 {
+        let alignments = version / 7 + 2;
+        modules -= (25 * alignments - 10) * alignments - 55;
+        if version >= 7 {
+            modules -= 36;
+        }
+    }
+    modules
+}
+
+fn data_codewords(version: usize) -> usize {
+    raw_modules(version) / 8 - ECC_PER_BLOCK[version] * BLOCKS[version]
+}
+
+/// Where the alignment patterns' centers go along each side
+fn alignment_positions(version: usize, size: usize) -> Vec<usize> {
+    if version == 1 {
+        return Vec::new();
+    }
+    let count = version / 7 + 2;
+    let step = (version * 8 + count * 3 + 5) / (count * 4 - 4) * 2;
+    let mut positions: Vec<usize> = (0..count - 1).map(|i| size - 7 - i * step).collect();
+    positions.push(6);
+    positions.reverse();
+    positions
+}
+
+/// Split the data into blocks, give each its error correction codewords, and
+/// interleave them the way readers expect
+fn add_error_correction(data: &[u8], version: usize) -> Vec<u8> {
+    let blocks = BLOCKS[version];
+    let ecc_len = ECC_PER_BLOCK[version];
+    let raw = raw_modules(version) / 8;
+    let short_blocks = blocks - raw % blocks;
+    let short_len = raw / blocks;
+
+    let divisor = reed_solomon_divisor(ecc_len);
+    let mut split = Vec::new();
+    let mut start = 0;
+    for i in 0..blocks {
+        let len = short_len - ecc_len + usize::from(i >= short_blocks);
+        let block = &data[start..start + len];
+        start += len;
+        let ecc = reed_solomon_remainder(block, &divisor);
+        split.push((block, ecc));
+    }
+
+    let mut interleaved = Vec::with_capacity(raw);
+    for i in 0..=short_len - ecc_len {
+        for (block, _) in &split {
+            if let Some(&byte) = block.get(i) {
+                interleaved.push(byte);
+            }
+        }
+    }
+    for i in 0..ecc_len {
+        for (_, ecc) in &split {
+            interleaved.push(ecc[i]);
+        }
+    }
+    interleaved
+}
+
+fn reed_solomon_divisor(degree: usize) -> Vec<u8> {
+    let mut result = vec![0; degree];
+    result[degree - 1] = 1;
+    let mut root = 1;
+    for _ in 0..degree {
+        for j in 0..degree {
+            result[j] = gf_multiply(result[j], root);
+            if j + 1 < degree {
+                result[j] ^= result[j + 1];
+            }
+        }
+        root = gf_multiply(root, 0x02);
+    }
+    result
+}
+
+fn reed_solomon_remainder(data: &[u8], divisor: &[u8]) -> Vec<u8> {
+    let mut result = vec![0; divisor.len()];
+    for &byte in data {
+        let factor = byte ^ result.remove(0);
+        result.push(0);
+        for (value, &coefficient) in result.iter_mut().zip(divisor) {
+            *value ^= gf_multiply(coefficient, factor);
+        }
+    }
+    result
+}
+
+/// Multiply in GF(2^8) modulo x^8 + x^4 + x^3 + x^2 + 1
+fn gf_multiply(x: u8, y: u8) -> u8 {
+    let mut z: u16 = 0;
+    for i in (0..8).rev() {
+        z = (z << 1) ^ ((z >> 7) * 0x11D);
+        z ^= ((y as u16 >> i) & 1) * x as u16;
+    }
+    z as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn picks_the_smallest_version() {
+        // A version 1 code holds 17 bytes at the low level, version 2 holds 32
+        assert_eq!(encode(&"a".repeat(17)).unwrap().size, 21);
+        assert_eq!(encode(&"a".repeat(18)).unwrap().size, 25);
+        assert_eq!(
+            encode("https://danbooru.donmai.us/posts/1").unwrap().size,
+            29
+        );
+        // Version 40 holds 2953
+        assert_eq!(encode(&"a".repeat(2953)).unwrap().size, 177);
+        assert!(encode(&"a".repeat(2954)).is_none());
+    }
+
+    #[test]
+    fn draws_the_patterns() {
+        let code = encode("https://safebooru.org/index.php?page=post&s=view&id=1").unwrap();
+        let size = code.size;
+        for (x, y) in [(0, 0), (size - 7, 0), (0, size - 7)] {
+            for i in 0..7 {
+                // The finders' dark outer ring, the light one in it and the dark middle
+                assert!(code.get(x + i, y) && code.get(x, y + i) && code.get(x + 6, y + i));
+                assert!(code.get(x + 3, y + 3));
+                assert!(!code.get(x + 1 + i.min(4), y + 1));
+            }
+        }
+        for i in 8..size - 8 {
+            assert_eq!(code.get(i, 6), i % 2 == 0);
+            assert_eq!(code.get(6, i), i % 2 == 0);
+        }
+        assert!(code.get(8, size - 8));
+    }
+
+    #[test]
+    fn writes_the_format_for_the_low_level() {
+        // From the standard's table of format information, for masks 0 to 7
+        const FORMATS: [u32; 8] = [
+            0b111011111000100,
+            0b111001011110011,
+            0b111110110101010,
+            0b111100010011101,
+            0b110011000101111,
+            0b110001100011000,
+            0b110110001000001,
+            0b110100101110110,
+        ];
+
+        let code = encode("waifu").unwrap();
+        let size = code.size;
+        let mut bits = 0;
+        for i in 0..8 {
+            bits |= (code.get(size - 1 - i, 8) as u32) << i;
+        }
+        for i in 8..15 {
+            bits |= (code.get(8, size - 15 + i) as u32) << i;
+        }
+        assert!(FORMATS.contains(&bits), "{:015b}", bits);
+    }
+
+    #[test]
+    fn computes_reed_solomon_codewords() {
+        // HELLO WORLD as a version 1 code at the medium level, as worked through in
+        // Thonky's QR code tutorial
+        let data = [
+            0x20, 0x5B, 0x0B, 0x78, 0xD1, 0x72, 0xDC, 0x4D, 0x43, 0x40, 0xEC, 0x11, 0xEC, 0x11,
+            0xEC, 0x11,
+        ];
+        assert_eq!(
+            reed_solomon_remainder(&data, &reed_solomon_divisor(10)),
+            [0xC4, 0x23, 0x27, 0x77, 0xEB, 0xD7, 0xE7, 0xE2, 0x5D, 0x17]
+        );
+    }
+}
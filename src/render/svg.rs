@@ -0,0 +1,65 @@
+use image::error::{DecodingError, ImageFormatHint};
+use image::{DynamicImage, ImageError, ImageResult, RgbaImage};
+
+/// Vector images are drawn at least this many pixels along their longer side,
+/// so small icons and emotes stay sharp once scaled to the terminal
+const MIN_SIZE: f32 = 1024.0;
+/// And at most this many, so huge canvases don't eat all the memory
+const MAX_SIZE: f32 = 4096.0;
+
+/// Whether the data is an SVG document, plain or gzipped
+pub fn is_svg(bytes: &[u8]) -> bool {
+    if bytes.starts_with(&[0x1f, 0x8b]) {
+        // Could be any gzipped file, but usvg will tell
+        return true;
+    }
+
+    let head = &bytes[..bytes.len().min(1024)];
+    let head = String::from_utf8_lossy(head);
+    let head = head.trim_start_matches('\u{feff}').trim_start();
+    (head.starts_with("<?xml") || head.starts_with("<!--") || head.starts_with("<svg"))
+        && head.contains("<svg")
+}
+
+/// Draw an SVG document into an image
+pub fn rasterize(bytes: &[u8]) -> ImageResult<DynamicImage> {
+    let mut options = resvg::usvg::Options::default();
+    options.fontdb_mut().load_system_fonts();
+    let tree = resvg::usvg::Tree::from_data(bytes, &options).map_err(error)?;
+
+    let size = tree.size();
+    let longer = size.width().max(size.height());
+    let scale = longer.clamp(MIN_SIZE, MAX_SIZE) / longer;
+    let width = (size.width() * scale).ceil().max(1.0) as u32;
+    let height = (size.height() * scale).ceil().max(1.0) as u32;
+
+    let mut pixmap = resvg::tiny_skia::Pixmap::new(width, height)
+        .ok_or_else(|| error(format!("can't draw at {}x{}", width, height)))?;
+    resvg::render(
+        &tree,
+        resvg::tiny_skia::Transform::from_scale(scale, scale),
+        &mut pixmap.as_mut(),
+    );
+
+    // tiny-skia works in premultiplied alpha
+    let mut pixels = pixmap.take();
+    for pixel in pixels.chunks_exact_mut(4) {
+        let alpha = pixel[3] as u32;
+        if alpha > 0 && alpha < 255 {
+            for channel in &mut pixel[..3] {
+                *channel = ((*channel as u32 * 255 + alpha / 2) / alpha).min(255) as u8;
+            }
+        }
+    }
+
+    let image = RgbaImage::from_raw(width, height, pixels)
+        .ok_or_else(|| error("the drawn image has the wrong size"))?;
+    Ok(DynamicImage::ImageRgba8(image))
+}
+
+fn error(error: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> ImageError {
+    ImageError::Decoding(DecodingError::new(
+        ImageFormatHint::Name("SVG".to_string()),
+        error,
+    ))
+}
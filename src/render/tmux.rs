@@ -0,0 +1,89 @@
+use clap::ValueEnum;
+use std::env;
+use std::process::Command;
+
+use crate::render::graphics::Encoding;
+
+/// Whether graphics escape codes get wrapped so tmux forwards them to the outer terminal
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Passthrough {
+    /// Only when running inside tmux under a kitty or iTerm compatible terminal
+    Auto,
+    /// Always wrap, assuming kitty when the outer terminal can't be detected
+    Always,
+    /// Leave tmux alone, which usually means falling back to half blocks
+    Never,
+}
+
+pub fn inside_tmux() -> bool {
+    env::var("TMUX").map(|v| !v.is_empty()).unwrap_or(false)
+}
+
+/// Graphics protocol of the terminal tmux is running in, if it has one.
+/// TERM and TERM_PROGRAM describe tmux itself, so look at what the outer terminal
+/// leaves in the environment and ask tmux for the client's terminal name.
+pub fn outer_encoding() -> Option<Encoding> {
+    let is_set = |name: &str| env::var_os(name).is_some_and(|v| !v.is_empty());
+
+    if is_set("KITTY_WINDOW_ID") {
+        return Some(Encoding::Kitty);
+    }
+    if is_set("ITERM_SESSION_ID")
+        || is_set("WEZTERM_PANE")
+        || env::var("LC_TERMINAL").is_ok_and(|v| v == "iTerm2")
+    {
+        return Some(Encoding::Iterm);
+    }
+
+    let output = Command::new("tmux")
+        .args(["display-message", "-p", "#{client_termname}"])
+        .output()
+        .ok()?;
+    let client = String::from_utf8_lossy(&output.stdout);
+    if client.contains("kitty") || client.contains("ghostty") {
+        Some(Encoding::Kitty)
+    } else if client.contains("wezterm") {
+        Some(Encoding::Iterm)
+    } else {
+        None
+    }
+}
+
+/// Whether tmux lets escape codes through to the outer terminal, if it can be
+/// asked. tmux before 3.3 has no setting for it and always does
+pub fn passthrough_allowed() -> Option<bool> {
+    let output = Command::new("tmux")
+        .args(["show-options", "-gv", "allow-passthrough"])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return Some(true);
+    }
+
+    Some(String::from_utf8_lossy(&output.stdout).trim() != "off")
+}
+
+/// Whether escape codes should be wrapped for tmux at all
+pub fn should_wrap(mode: Passthrough) -> bool {
+    match mode {
+        Passthrough::Auto => inside_tmux(),
+        Passthrough::Always => true,
+        Passthrough::Never => false,
+    }
+}
+
+/// The protocol to tunnel through tmux, or None when passthrough shouldn't be used
+pub fn passthrough_encoding(mode: Passthrough) -> Option<Encoding> {
+    match mode {
+        Passthrough::Never => None,
+        Passthrough::Auto if inside_tmux() => outer_encoding(),
+        Passthrough::Auto => None,
+        Passthrough::Always => Some(outer_encoding().unwrap_or(Encoding::Kitty)),
+    }
+}
+
+/// Wrap an escape sequence in tmux's DCS passthrough, doubling every inner escape.
+/// Requires `set -g allow-passthrough on` in tmux 3.3 or later.
+pub fn wrap(sequence: &str) -> String {
+    format!("\x1bPtmux;{}\x1b\\", sequence.replace('\x1b', "\x1b\x1b"))
+}
@@ -0,0 +1,402 @@
+use clap::ValueEnum;
+use image::{DynamicImage, GenericImageView};
+use std::borrow::Cow;
+use std::io::{BufRead, Cursor, Seek};
+use std::str::FromStr;
+
+use crate::error::WaifuError;
+use crate::render::{luminance, Settings};
+
+/// Color adjustment applied to the whole image
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Filter {
+    Gray,
+    Sepia,
+    /// Invert every color, leaving transparency alone
+    Invert,
+}
+
+/// How questionable and explicit images get obscured
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Censor {
+    Blur,
+    Pixelate,
+}
+
+/// What shows through the transparent parts of an image
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Background {
+    /// A solid color, given as `#rrggbb` or `#rgb`
+    Color([u8; 3]),
+    /// The gray checkerboard image editors use
+    Checker,
+    /// The terminal's own background
+    Terminal,
+}
+
+impl FromStr for Background {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        match s.to_lowercase().as_str() {
+            "checker" => return Ok(Background::Checker),
+            "terminal" => return Ok(Background::Terminal),
+            _ => (),
+        }
+
+        let error = || format!("'{}' isn't a #rrggbb color, 'checker' or 'terminal'", s);
+        let hex = s.strip_prefix('#').unwrap_or(s);
+        if !hex.is_ascii() {
+            return Err(error());
+        }
+        let channel = |digits: &str| u8::from_str_radix(digits, 16).map_err(|_| error());
+
+        match hex.len() {
+            6 => Ok(Background::Color([
+                channel(&hex[0..2])?,
+                channel(&hex[2..4])?,
+                channel(&hex[4..6])?,
+            ])),
+            // Each digit of the short form stands for two, so #fa0 is #ffaa00
+            3 => Ok(Background::Color([
+                channel(&hex[0..1])? * 17,
+                channel(&hex[1..2])? * 17,
+                channel(&hex[2..3])? * 17,
+            ])),
+            _ => Err(error()),
+        }
+    }
+}
+
+/// A region of the image, parsed from `WxH+X+Y`. Without an offset it's centered.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Crop {
+    pub width: u32,
+    pub height: u32,
+    pub offset: Option<(u32, u32)>,
+}
+
+impl Crop {
+    /// Parse a bare `WxH` size, which always gets centered
+    pub fn centered(s: &str) -> Result<Self, String> {
+        let (width, height) = parse_size(s)?;
+        Ok(Crop {
+            width,
+            height,
+            offset: None,
+        })
+    }
+}
+
+impl FromStr for Crop {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (size, offset) = match s.find('+') {
+            Some(i) => (&s[..i], Some(&s[i + 1..])),
+            None => (s, None),
+        };
+
+        let (width, height) = parse_size(size)?;
+        let offset = match offset {
+            Some(offset) => {
+                let (x, y) = offset
+                    .split_once('+')
+                    .ok_or_else(|| format!("'{}' should look like WxH+X+Y", s))?;
+                let parse = |n: &str| {
+                    n.parse::<u32>()
+                        .map_err(|_| format!("'{}' should look like WxH+X+Y", s))
+                };
+                Some((parse(x)?, parse(y)?))
+            }
+            None => None,
+        };
+
+        Ok(Crop {
+            width,
+            height,
+            offset,
+        })
+    }
+}
+
+fn parse_size(s: &str) -> Result<(u32, u32), String> {
+    let error = || format!("'{}' should look like WxH, e.g. 800x600", s);
+    let (width, height) = s.trim().split_once(['x', 'X']).ok_or_else(error)?;
+    let width = width.parse::<u32>().map_err(|_| error())?;
+    let height = height.parse::<u32>().map_err(|_| error())?;
+
+    if width == 0 || height == 0 {
+        return Err(error());
+    }
+
+    Ok((width, height))
+}
+
+/// Apply the image adjustments asked for before it gets sized and drawn
+pub fn prepare<'a>(
+    image: &'a DynamicImage,
+    settings: &Settings,
+) -> Result<Cow<'a, DynamicImage>, WaifuError> {
+    let mut image = Cow::Borrowed(image);
+
+    if let Some(crop) = settings.crop {
+        image = Cow::Owned(apply_crop(&image, crop)?);
+    }
+
+    if settings.brightness != 0 || settings.contrast != 0 || settings.saturation != 0 {
+        image = Cow::Owned(adjust(
+            image.into_owned(),
+            settings.brightness,
+            settings.contrast,
+            settings.saturation,
+        ));
+    }
+
+    for filter in &settings.filters {
+        image = Cow::Owned(apply_filter(image.into_owned(), *filter));
+    }
+
+    Ok(image)
+}
+
+/// Blend the image onto the chosen background, once it's known how many
+/// columns it will cover
+pub fn flatten<'a>(
+    image: Cow<'a, DynamicImage>,
+    settings: &Settings,
+    columns: u32,
+) -> Cow<'a, DynamicImage> {
+    match settings.background {
+        Some(Background::Terminal) | None => image,
+        Some(background) if image.color().has_alpha() => {
+            Cow::Owned(blend(&image, background, columns))
+        }
+        Some(_) => image,
+    }
+}
+
+fn blend(image: &DynamicImage, background: Background, columns: u32) -> DynamicImage {
+    let mut pixels = image.to_rgba8();
+    // Each checker square ends up two columns wide, about as tall as it is wide
+    let square = (pixels.width() / columns.max(1) * 2).max(1);
+
+    for (x, y, pixel) in pixels.enumerate_pixels_mut() {
+        let behind = match background {
+            Background::Color(color) => color,
+            Background::Checker if (x / square + y / square).is_multiple_of(2) => [153; 3],
+            Background::Checker => [102; 3],
+            Background::Terminal => continue,
+        };
+
+        let alpha = pixel[3] as u32;
+        for channel in 0..3 {
+            let blended = pixel[channel] as u32 * alpha + behind[channel] as u32 * (255 - alpha);
+            pixel[channel] = (blended / 255) as u8;
+        }
+        pixel[3] = 255;
+    }
+
+    DynamicImage::ImageRgba8(pixels)
+}
+
+fn apply_crop(image: &DynamicImage, crop: Crop) -> Result<DynamicImage, WaifuError> {
+    let (width, height) = image.dimensions();
+    let (x, y) = crop.offset.unwrap_or((
+        width.saturating_sub(crop.width) / 2,
+        height.saturating_sub(crop.height) / 2,
+    ));
+
+    if x >= width || y >= height {
+        return Err(format!(
+            "The crop starts at {},{}, outside of the {}x{} image",
+            x, y, width, height
+        )
+        .into());
+    }
+
+    // Anything past the edges is cut off
+    let crop_w = crop.width.min(width - x);
+    let crop_h = crop.height.min(height - y);
+
+    Ok(image.crop_imm(x, y, crop_w, crop_h))
+}
+
+// Brightness, contrast and saturation are percentages, 0 leaving the image alone
+fn adjust(image: DynamicImage, brightness: i32, contrast: i32, saturation: i32) -> DynamicImage {
+    let mut image = image;
+    if brightness != 0 {
+        image = image.brighten(brightness * 255 / 100);
+    }
+    if contrast != 0 {
+        image = image.adjust_contrast(contrast as f32);
+    }
+    if saturation == 0 {
+        return image;
+    }
+
+    // Push every channel away from (or towards) the pixel's gray value
+    let mut pixels = image.into_rgba8();
+    let factor = 1.0 + saturation as f32 / 100.0;
+    for pixel in pixels.pixels_mut() {
+        let gray = luminance(pixel[0], pixel[1], pixel[2]) as f32;
+        for channel in 0..3 {
+            let value = gray + (pixel[channel] as f32 - gray) * factor;
+            pixel[channel] = value.round().clamp(0.0, 255.0) as u8;
+        }
+    }
+
+    DynamicImage::ImageRgba8(pixels)
+}
+
+fn apply_filter(image: DynamicImage, filter: Filter) -> DynamicImage {
+    match filter {
+        Filter::Gray => image.grayscale(),
+        Filter::Sepia => {
+            let mut pixels = image.into_rgba8();
+            for pixel in pixels.pixels_mut() {
+                let [r, g, b, _] = pixel.0.map(|c| c as f32);
+                let tone = |cr: f32, cg: f32, cb: f32| (r * cr + g * cg + b * cb).min(255.0) as u8;
+                pixel[0] = tone(0.393, 0.769, 0.189);
+                pixel[1] = tone(0.349, 0.686, 0.168);
+                pixel[2] = tone(0.272, 0.534, 0.131);
+            }
+            DynamicImage::ImageRgba8(pixels)
+        }
+        Filter::Invert => {
+            let mut image = image;
+            image.invert();
+            image
+        }
+    }
+}
+
+/// Obscure an image beyond recognition, keeping its size
+pub fn censor(image: &DynamicImage, censor: Censor) -> DynamicImage {
+    use image::imageops::FilterType;
+
+    let (width, height) = image.dimensions();
+    match censor {
+        Censor::Blur => {
+            // Blurring a small copy is much faster and looks the same once scaled back up
+            let small = image.resize(128, 128, FilterType::Triangle);
+            small
+                .blur(6.0)
+                .resize_exact(width, height, FilterType::Triangle)
+        }
+        Censor::Pixelate => {
+            let small = image.resize(16, 16, FilterType::Triangle);
+            small.resize_exact(width, height, FilterType::Nearest)
+        }
+    }
+}
+
+/// Decode an image the right way up and in sRGB, the way it's meant to be seen
+pub fn decode(bytes: &[u8]) -> image::ImageResult<DynamicImage> {
+    if crate::render::svg::is_svg(bytes) {
+        return crate::render::svg::rasterize(bytes);
+    }
+    if is_heif(bytes) {
+        return decode_heif(bytes);
+    }
+
+    #[cfg(feature = "fast-decode")]
+    let image = match crate::render::fast_decode::decode(bytes) {
+        Some(image) => image,
+        None => image::load_from_memory(bytes)?,
+    };
+    #[cfg(not(feature = "fast-decode"))]
+    let image = image::load_from_memory(bytes)?;
+    let image = orient(image, orientation(&mut Cursor::new(bytes)));
+
+    Ok(match icc_profile(bytes) {
+        Some(profile) => to_srgb(image, &profile),
+        None => image,
+    })
+}
+
+/// Whether the data is an AVIF or HEIC image, which share a container with MP4 videos
+pub fn is_heif(bytes: &[u8]) -> bool {
+    const BRANDS: [&[u8]; 10] = [
+        b"avif", b"avis", b"heic", b"heix", b"heim", b"heis", b"hevc", b"hevx", b"mif1", b"msf1",
+    ];
+
+    bytes.len() >= 12 && &bytes[4..8] == b"ftyp" && BRANDS.contains(&&bytes[8..12])
+}
+
+#[cfg(feature = "heif")]
+fn decode_heif(bytes: &[u8]) -> image::ImageResult<DynamicImage> {
+    let (image, profile) = crate::render::heif::decode(bytes)?;
+
+    Ok(match profile {
+        Some(profile) => to_srgb(image, &profile),
+        None => image,
+    })
+}
+
+#[cfg(not(feature = "heif"))]
+fn decode_heif(_bytes: &[u8]) -> image::ImageResult<DynamicImage> {
+    use image::error::{DecodingError, ImageFormatHint};
+
+    Err(image::ImageError::Decoding(DecodingError::new(
+        ImageFormatHint::Name("AVIF/HEIC".to_string()),
+        "waifu was built without AVIF and HEIC support, reinstall it with `--features heif`",
+    )))
+}
+
+// The embedded ICC color profile, for the formats that can carry one
+fn icc_profile(bytes: &[u8]) -> Option<Vec<u8>> {
+    use image::codecs::{jpeg::JpegDecoder, png::PngDecoder, webp::WebPDecoder};
+    use image::{ImageDecoder, ImageFormat};
+
+    match image::guess_format(bytes).ok()? {
+        ImageFormat::Jpeg => JpegDecoder::new(Cursor::new(bytes)).ok()?.icc_profile(),
+        ImageFormat::Png => PngDecoder::new(Cursor::new(bytes)).ok()?.icc_profile(),
+        ImageFormat::WebP => WebPDecoder::new(Cursor::new(bytes)).ok()?.icc_profile(),
+        _ => None,
+    }
+}
+
+// Convert colors from the image's profile to sRGB, which is what terminals assume.
+// Profiles that can't be read, or gray ones, leave the image as it is.
+fn to_srgb(image: DynamicImage, profile: &[u8]) -> DynamicImage {
+    let Some(input) = qcms::Profile::new_from_slice(profile, false) else {
+        return image;
+    };
+    let output = qcms::Profile::new_sRGB();
+    let Some(transform) = qcms::Transform::new(
+        &input,
+        &output,
+        qcms::DataType::RGBA8,
+        qcms::Intent::Perceptual,
+    ) else {
+        return image;
+    };
+
+    let mut pixels = image.into_rgba8();
+    transform.apply(&mut pixels);
+    DynamicImage::ImageRgba8(pixels)
+}
+
+// The EXIF orientation tag of an encoded image, if it has one
+fn orientation(reader: &mut (impl BufRead + Seek)) -> Option<u32> {
+    let exif = exif::Reader::new().read_from_container(reader).ok()?;
+    let field = exif.get_field(exif::Tag::Orientation, exif::In::PRIMARY)?;
+    field.value.get_uint(0)
+}
+
+// Turn the decoded image the right way up, the way a camera's EXIF orientation says
+fn orient(image: DynamicImage, orientation: Option<u32>) -> DynamicImage {
+    match orientation {
+        Some(2) => image.fliph(),
+        Some(3) => image.rotate180(),
+        Some(4) => image.flipv(),
+        Some(5) => image.rotate90().fliph(),
+        Some(6) => image.rotate90(),
+        Some(7) => image.rotate270().fliph(),
+        Some(8) => image.rotate270(),
+        _ => image,
+    }
+}
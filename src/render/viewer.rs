@@ -0,0 +1,49 @@
+use std::fs;
+use std::path::Path;
+
+use crate::error::WaifuError;
+use crate::hook;
+
+/// Hand an image file to an external viewer. `{}` in the command is replaced
+/// by the path, which is appended to the end when there's no placeholder.
+pub fn open(command: &str, path: &Path) -> Result<(), WaifuError> {
+    let command = if command.contains("{}") {
+        command.to_string()
+    } else {
+        format!("{} {{}}", command)
+    };
+
+    hook::run(&command, &[("", &path.to_string_lossy())])
+}
+
+/// Save downloaded image data to a temporary file and hand it to an external viewer.
+/// The file is left behind since viewers like xdg-open return before reading it.
+pub fn open_bytes(command: &str, bytes: &[u8]) -> Result<(), WaifuError> {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    let extension = image::guess_format(bytes)
+        .ok()
+        .and_then(|format| format.extensions_str().first().copied())
+        .unwrap_or("img");
+    let stamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or(0);
+
+    let mut path = std::env::temp_dir();
+    path.push(format!("waifu-{}.{}", stamp, extension));
+    fs::write(&path, bytes)?;
+
+    open(command, &path)
+}
+
+/// Like [`open_bytes`], for an image that only exists decoded
+pub fn open_image(command: &str, image: &image::DynamicImage) -> Result<(), WaifuError> {
+    let mut bytes = Vec::new();
+    image.write_to(
+        &mut std::io::Cursor::new(&mut bytes),
+        image::ImageOutputFormat::Png,
+    )?;
+
+    open_bytes(command, &bytes)
+}
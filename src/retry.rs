@@ -0,0 +1,126 @@
+use reqwest::header::HeaderMap;
+use reqwest::{StatusCode, Url};
+use std::collections::BTreeMap;
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use crate::error::WaifuError;
+
+/// Longest Retry-After worth waiting for rather than giving up straight away
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+/// How long to wait before trying a request again
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backoff {
+    /// The same short wait every time
+    Constant,
+    /// A wait that gets longer by the same amount every time
+    Linear,
+    /// A wait that doubles every time
+    Exponential,
+}
+
+impl Backoff {
+    /// The wait after the given attempt, counting from 1
+    pub fn delay(self, attempt: u32) -> Duration {
+        const BASE: Duration = Duration::from_millis(200);
+
+        match self {
+            Backoff::Constant => BASE,
+            Backoff::Linear => BASE * attempt,
+            Backoff::Exponential => BASE * 2u32.saturating_pow(attempt - 1),
+        }
+    }
+}
+
+/// When requests are sent again and how often they may be sent, the same for
+/// the blocking client and the async one. Each says how long to wait, for the
+/// one sending to sleep or await
+#[derive(Clone, Copy, Debug)]
+pub struct Retry {
+    /// Times to try again after a connection error, timeout or 429
+    pub retries: u32,
+    pub backoff: Backoff,
+    /// Most requests sent to one host per second, no limit when 0
+    pub rate_limit: f64,
+}
+
+impl Default for Retry {
+    fn default() -> Self {
+        Retry {
+            retries: 2,
+            backoff: Backoff::Linear,
+            rate_limit: 2.0,
+        }
+    }
+}
+
+impl Retry {
+    /// How long to wait before sending the request again after it was answered,
+    /// None when the answer is the one to keep. A 429 asking for a longer wait
+    /// than is worth it is an error
+    pub fn after_answer(
+        &self,
+        attempt: u32,
+        status: StatusCode,
+        headers: &HeaderMap,
+        url: &Url,
+    ) -> Result<Option<Duration>, WaifuError> {
+        if status != StatusCode::TOO_MANY_REQUESTS || attempt > self.retries {
+            return Ok(None);
+        }
+
+        let wait = retry_after(headers).unwrap_or(self.backoff.delay(attempt));
+        if wait > MAX_RETRY_AFTER {
+            return Err(WaifuError::Network(format!(
+                "{} is rate limiting requests, try again in {} seconds",
+                url.host_str().unwrap_or("The site"),
+                wait.as_secs()
+            )));
+        }
+
+        Ok(Some(wait))
+    }
+
+    /// How long to wait before sending the request again after it failed, or
+    /// the error to give up with. Only connection errors and timeouts are tried
+    /// again, anything else, like a TLS or redirect error, would fail the same way
+    pub fn after_error(&self, attempt: u32, error: reqwest::Error) -> Result<Duration, WaifuError> {
+        if attempt <= self.retries && (error.is_connect() || error.is_timeout()) {
+            return Ok(self.backoff.delay(attempt));
+        }
+
+        if attempt > 1 {
+            let message = format!("{} (gave up after {} attempts)", error, attempt);
+            return Err(WaifuError::Network(message));
+        }
+        Err(error.into())
+    }
+
+    /// How long to wait before a request to the URL's host keeps within the
+    /// rate limit, so going through lots of images doesn't get you banned. The
+    /// slot is taken straight away, so requests to other hosts don't queue up
+    /// behind this one while it waits
+    pub fn slot(&self, url: &Url) -> Option<Duration> {
+        static NEXT_SLOT: Mutex<BTreeMap<String, Instant>> = Mutex::new(BTreeMap::new());
+
+        let host = url.host_str().filter(|_| self.rate_limit > 0.0)?;
+        let gap = Duration::from_secs_f64(1.0 / self.rate_limit);
+        let now = Instant::now();
+        let mut next_slot = NEXT_SLOT.lock().unwrap_or_else(PoisonError::into_inner);
+        let slot = next_slot.get(host).map_or(now, |&next| next.max(now));
+        next_slot.insert(host.to_string(), slot + gap);
+
+        (slot > now).then(|| slot - now)
+    }
+}
+
+/// How long a response asks to wait before trying again. Only the number of
+/// seconds form is understood, the boorus don't send dates
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(reqwest::header::RETRY_AFTER)?;
+    let seconds = value.to_str().ok()?.trim().parse().ok()?;
+
+    Some(Duration::from_secs(seconds))
+}
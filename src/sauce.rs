@@ -0,0 +1,168 @@
+use reqwest::blocking::multipart::{Form, Part};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::app::print_formatted;
+use crate::auth::{self, Site};
+use crate::error::WaifuError;
+use crate::exit;
+use crate::net;
+use crate::theme;
+
+/// Matches less similar than this are most likely some other image
+const MIN_SIMILARITY: f64 = 60.0;
+
+/// The closest match SauceNAO found for an image
+#[derive(Serialize, Debug)]
+pub struct Sauce {
+    /// How alike the images are, as a percentage
+    pub similarity: f64,
+    /// The index it was found in, e.g. "Pixiv Images"
+    pub index: String,
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    /// Where the art was posted, or else the page it was found on
+    pub source: Option<String>,
+}
+
+/// Look up an image on the web by its URL
+pub fn find_for_url(url: &str) -> Result<Sauce, WaifuError> {
+    let request = net::client()?.get(search_url()).query(&[("url", url)]);
+    best_match(net::send(request)?)
+}
+
+/// Look up an image by uploading it
+pub fn find_for_file(bytes: &[u8]) -> Result<Sauce, WaifuError> {
+    let request = net::client()?.post(search_url());
+    let form = || Form::new().part("file", Part::bytes(bytes.to_vec()).file_name("image"));
+    best_match(net::send_form(request, form)?)
+}
+
+/// The JSON API, with the API key from the login when there's one. It works
+/// without, just with fewer searches a day
+fn search_url() -> String {
+    let mut url = "https://saucenao.com/search.php?output_type=2&numres=5".to_string();
+    if let Some(key) = auth::credentials(Site::SauceNao).api_key {
+        url.push_str("&api_key=");
+        url.push_str(&key);
+    }
+
+    url
+}
+
+fn best_match(response: reqwest::blocking::Response) -> Result<Sauce, WaifuError> {
+    let status = response.status();
+    let body: Value = response.json().unwrap_or_default();
+    let header = &body["header"];
+    // It says why in the header, e.g. that the daily limit is used up
+    if !status.is_success() || header["status"].as_i64().unwrap_or(0) < 0 {
+        let message = header["message"]
+            .as_str()
+            .map(strip_tags)
+            .unwrap_or_else(|| format!("{}: SauceNAO couldn't search for the image", status));
+        let error = WaifuError::Network(message);
+        return Err(exit::with_help(
+            error,
+            "An API key raises the limit, see `waifu auth login saucenao`",
+        ));
+    }
+
+    let best = body["results"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(to_sauce)
+        .max_by(|a, b| a.similarity.total_cmp(&b.similarity))
+        .ok_or_else(|| WaifuError::NoResults("SauceNAO found nothing like the image".into()))?;
+
+    Ok(best)
+}
+
+fn to_sauce(result: &Value) -> Sauce {
+    let header = &result["header"];
+    let data = &result["data"];
+    let text = |value: &Value| match value {
+        Value::String(text) if !text.is_empty() => Some(text.clone()),
+        // Several creators come as a list
+        Value::Array(names) => {
+            let names: Vec<&str> = names.iter().filter_map(Value::as_str).collect();
+            (!names.is_empty()).then(|| names.join(", "))
+        }
+        _ => None,
+    };
+    let first = |keys: &[&str]| keys.iter().find_map(|key| text(&data[*key]));
+
+    // e.g. "Index #5: Pixiv Images - 12345.jpg"
+    let index = header["index_name"].as_str().unwrap_or_default();
+    let index = index.split_once(": ").map_or(index, |(_, name)| name);
+    let index = index.split(" - ").next().unwrap_or(index);
+    let page = data["ext_urls"]
+        .as_array()
+        .and_then(|urls| urls.first())
+        .and_then(Value::as_str)
+        .map(String::from);
+
+    Sauce {
+        similarity: header["similarity"]
+            .as_str()
+            .and_then(|similarity| similarity.parse().ok())
+            .unwrap_or(0.0),
+        index: index.to_string(),
+        title: first(&["title", "eng_name", "source_title"]),
+        artist: first(&["member_name", "creator", "author_name", "artist"]),
+        source: first(&["source"])
+            .filter(|source| source.starts_with("http"))
+            .or(page),
+    }
+}
+
+/// SauceNAO's messages are HTML, with links to its site
+fn strip_tags(message: &str) -> String {
+    let mut text = String::new();
+    let mut in_tag = false;
+    for c in message.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' => in_tag = false,
+            c if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+
+    text.trim().to_string()
+}
+
+impl Sauce {
+    /// Print it the way --details-format or --format json ask for, or else as a
+    /// line for each part, like post details
+    pub fn print(&self) -> Result<(), WaifuError> {
+        if print_formatted(self)? {
+            return Ok(());
+        }
+
+        println!(
+            "🔎 {}: {:.1}% in {}",
+            theme::label("Match"),
+            self.similarity,
+            self.index
+        );
+        if self.similarity < MIN_SIMILARITY {
+            println!(
+                "⚠️ {}: It's probably not the same image",
+                theme::label("Note")
+            );
+        }
+        let fields = [
+            ("📖", "Title", &self.title),
+            ("🎨", "Artist", &self.artist),
+            ("ℹ️", "Source", &self.source),
+        ];
+        for (icon, title, value) in fields {
+            if let Some(value) = value {
+                println!("{} {}: {}", icon, theme::label(title), value);
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,175 @@
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+use crate::error::WaifuError;
+
+/// The image being served, with what's shown under it and given as JSON
+pub struct Current {
+    /// Shared with the requests still being answered when it's swapped out
+    pub bytes: Arc<[u8]>,
+    /// The post's ID, or anything else that changes with the image, so browsers
+    /// don't show the one they have cached
+    pub key: String,
+    pub caption: String,
+    /// Where the caption links to, e.g. the post's page
+    pub link: String,
+    pub details: serde_json::Value,
+}
+
+/// The page, which reloads itself every so often. {refresh}, {key}, {link} and
+/// {caption} are filled in
+const PAGE: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<meta http-equiv="refresh" content="{refresh}">
+<title>waifu</title>
+<style>
+body { margin: 0; height: 100vh; display: flex; flex-direction: column; align-items: center;
+       justify-content: center; background: #111; color: #ccc; font: 14px sans-serif; }
+img { max-width: 100vw; max-height: 94vh; object-fit: contain; }
+a { color: inherit; }
+</style>
+</head>
+<body>
+<img src="/image?{key}" alt="">
+<p><a href="{link}">{caption}</a></p>
+</body>
+</html>
+"#;
+
+/// Answer requests for the page at /, the image at /image and its details at
+/// /post.json, each on a thread of its own so a slow one doesn't hold up the
+/// rest, for as long as the listener lasts
+pub fn run(listener: &TcpListener, current: &Mutex<Option<Current>>, refresh: Duration) {
+    std::thread::scope(|scope| {
+        for stream in listener.incoming() {
+            scope.spawn(move || {
+                let answered = stream
+                    .map_err(WaifuError::from)
+                    .and_then(|stream| answer(stream, current, refresh));
+                // Whoever asked is gone, or didn't speak HTTP
+                if let Err(error) = answered {
+                    tracing::debug!("Couldn't answer a request: {}", error);
+                }
+            });
+        }
+    });
+}
+
+/// What to send back, taken from the image being served so it can be written
+/// out without holding on to it
+enum Answer {
+    Page(String),
+    Image(Arc<[u8]>),
+    Json(String),
+    NotReady,
+    NotFound,
+}
+
+fn answer(
+    stream: TcpStream,
+    current: &Mutex<Option<Current>>,
+    refresh: Duration,
+) -> Result<(), WaifuError> {
+    stream.set_read_timeout(Some(Duration::from_secs(10)))?;
+    stream.set_write_timeout(Some(Duration::from_secs(30)))?;
+    let mut reader = BufReader::new(&stream);
+    let mut request = String::new();
+    reader.read_line(&mut request)?;
+    // The headers don't change the answer
+    let mut line = String::new();
+    while reader.read_line(&mut line)? > 2 {
+        line.clear();
+    }
+
+    let mut parts = request.split_whitespace();
+    let method = parts.next().unwrap_or_default();
+    let target = parts.next().unwrap_or_default();
+    let path = target.split('?').next().unwrap_or_default();
+    let head = method == "HEAD";
+    if method != "GET" && !head {
+        return respond(
+            &stream,
+            "405 Method Not Allowed",
+            "text/plain",
+            b"GET only",
+            head,
+        );
+    }
+
+    // The lock is let go before writing, so the next image can be swapped in
+    let answer = match (
+        path,
+        current
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .as_ref(),
+    ) {
+        ("/", Some(current)) => Answer::Page(
+            PAGE.replace("{refresh}", &refresh.as_secs().max(1).to_string())
+                .replace("{key}", &escape(&current.key))
+                .replace("{link}", &escape(&current.link))
+                .replace("{caption}", &escape(&current.caption)),
+        ),
+        ("/image", Some(current)) => Answer::Image(Arc::clone(&current.bytes)),
+        ("/post.json", Some(current)) => Answer::Json(current.details.to_string()),
+        ("/" | "/image" | "/post.json", None) => Answer::NotReady,
+        _ => Answer::NotFound,
+    };
+
+    match answer {
+        Answer::Page(page) => respond(
+            &stream,
+            "200 OK",
+            "text/html; charset=utf-8",
+            page.as_bytes(),
+            head,
+        ),
+        Answer::Image(bytes) => {
+            let content_type = image::guess_format(&bytes)
+                .map_or("application/octet-stream", |format| format.to_mime_type());
+            respond(&stream, "200 OK", content_type, &bytes, head)
+        }
+        Answer::Json(json) => respond(&stream, "200 OK", "application/json", json.as_bytes(), head),
+        Answer::NotReady => {
+            let body = b"The first image isn't ready yet, try again in a moment";
+            respond(&stream, "503 Service Unavailable", "text/plain", body, head)
+        }
+        Answer::NotFound => respond(&stream, "404 Not Found", "text/plain", b"Not found", head),
+    }
+}
+
+fn respond(
+    mut stream: &TcpStream,
+    status: &str,
+    content_type: &str,
+    body: &[u8],
+    head: bool,
+) -> Result<(), WaifuError> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n\
+         Cache-Control: no-store\r\nConnection: close\r\n\r\n",
+        status,
+        content_type,
+        body.len()
+    )?;
+    if !head {
+        stream.write_all(body)?;
+    }
+    stream.flush()?;
+
+    Ok(())
+}
+
+/// Text made safe to put in the page, in an attribute or between tags
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,19 @@
+use colored::{Color, ColoredString, Colorize};
+use std::sync::OnceLock;
+
+use crate::config;
+
+static LABEL: OnceLock<Color> = OnceLock::new();
+
+/// Use the colors from the config file for the rest of the run
+pub fn configure(theme: &config::Theme) {
+    if let Some(config::ThemeColor(color)) = theme.label {
+        let _ = LABEL.set(color);
+    }
+}
+
+/// A detail's label, like Artist or Tags, in the theme's color. Purple unless
+/// the config file says otherwise
+pub fn label(title: &str) -> ColoredString {
+    title.color(*LABEL.get().unwrap_or(&Color::Magenta))
+}
@@ -0,0 +1,79 @@
+use clap::ValueEnum;
+use reqwest::blocking::multipart::{Form, Part};
+use serde_json::Value;
+
+use crate::auth::{self, Site};
+use crate::error::WaifuError;
+use crate::exit;
+use crate::net;
+
+/// Where `waifu share` uploads images to
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Host {
+    /// catbox.moe, which keeps files for good and needs no account
+    Catbox,
+    /// Imgur, with the client ID of an app registered there
+    Imgur,
+}
+
+/// Upload the image and give back the link to it. The file name's extension
+/// decides the one the link ends with
+pub fn upload(host: Host, bytes: &[u8], file_name: &str) -> Result<String, WaifuError> {
+    match host {
+        Host::Catbox => catbox(bytes, file_name),
+        Host::Imgur => imgur(bytes, file_name),
+    }
+}
+
+fn catbox(bytes: &[u8], file_name: &str) -> Result<String, WaifuError> {
+    let request = net::client()?.post("https://catbox.moe/user/api.php");
+    let form = || {
+        Form::new()
+            .text("reqtype", "fileupload")
+            .part("fileToUpload", file_part(bytes, file_name))
+    };
+    let response = net::send_form(request, form)?;
+    let status = response.status();
+    // The link, or else what went wrong, as plain text
+    let text = response.text()?;
+    let text = text.trim();
+    if !status.is_success() || !text.starts_with("https://") {
+        let message = format!("{}: catbox.moe couldn't keep the image: {}", status, text);
+        return Err(WaifuError::Network(message));
+    }
+
+    Ok(text.to_string())
+}
+
+fn imgur(bytes: &[u8], file_name: &str) -> Result<String, WaifuError> {
+    let Some(client_id) = auth::credentials(Site::Imgur).api_key else {
+        let error = "Uploading to Imgur takes the client ID of an app registered there";
+        return Err(exit::with_help(
+            error,
+            "Give it as the API key with `waifu auth login imgur`, or set IMGUR_API_KEY",
+        ));
+    };
+
+    let request = net::client()?
+        .post("https://api.imgur.com/3/image")
+        .header("Authorization", format!("Client-ID {}", client_id));
+    let form = || Form::new().part("image", file_part(bytes, file_name));
+    let response = net::send_form(request, form)?;
+    let status = response.status();
+    let body: Value = response.json().unwrap_or_default();
+    match body["data"]["link"].as_str() {
+        Some(link) if status.is_success() => Ok(link.to_string()),
+        _ => {
+            let why = body["data"]["error"]
+                .as_str()
+                .map_or(String::new(), |error| format!(": {}", error));
+            let message = format!("{}: Imgur couldn't keep the image{}", status, why);
+            Err(WaifuError::Network(message))
+        }
+    }
+}
+
+/// The image as a file in a form, which reqwest escapes the name of
+fn file_part(bytes: &[u8], file_name: &str) -> Part {
+    Part::bytes(bytes.to_vec()).file_name(file_name.to_string())
+}
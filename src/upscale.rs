@@ -0,0 +1,18 @@
+use crate::error::WaifuError;
+use crate::hook;
+
+/// Run image data through an external upscaler. `{in}` and `{out}` in the command
+/// are replaced by temporary file paths, and whatever the program writes to `{out}`
+/// is used in place of the original.
+pub fn run(command: &str, bytes: &[u8]) -> Result<Vec<u8>, WaifuError> {
+    if !command.contains("{in}") || !command.contains("{out}") {
+        return Err("The upscale command needs both an {in} and an {out} placeholder".into());
+    }
+
+    let extension = image::guess_format(bytes)
+        .ok()
+        .and_then(|format| format.extensions_str().first().copied())
+        .unwrap_or("img");
+
+    hook::run_on_bytes(command, bytes, extension)
+}
@@ -0,0 +1,46 @@
+#[cfg(feature = "cli")]
+use crate::error::WaifuError;
+#[cfg(feature = "cli")]
+use crate::hook;
+#[cfg(feature = "cli")]
+use crate::render::transform;
+
+/// File extensions of booru posts that are videos or ugoira archives rather than images
+#[cfg(feature = "json")]
+pub const EXTENSIONS: [&str; 4] = ["mp4", "webm", "zip", "swf"];
+
+/// Whether a booru file name or URL points at a video
+#[cfg(any(feature = "danbooru", feature = "safebooru"))]
+pub fn is_video(name: &str) -> bool {
+    let name = name.split(['?', '#']).next().unwrap_or(name);
+    name.rsplit_once('.').is_some_and(|(_, extension)| {
+        EXTENSIONS
+            .iter()
+            .any(|video| extension.eq_ignore_ascii_case(video))
+    })
+}
+
+/// The kind of video in the data, if it's one
+#[cfg(feature = "cli")]
+pub fn sniff(bytes: &[u8]) -> Option<&'static str> {
+    if bytes.starts_with(&[0x1a, 0x45, 0xdf, 0xa3]) {
+        return Some("webm");
+    }
+    if bytes.len() >= 12 && &bytes[4..8] == b"ftyp" && !transform::is_heif(bytes) {
+        return Some("mp4");
+    }
+
+    None
+}
+
+/// Grab a still frame of a video with an external program like ffmpeg. `{in}` and
+/// `{out}` in the command are replaced by temporary file paths, and whatever the
+/// program writes to `{out}` is shown in place of the video.
+#[cfg(feature = "cli")]
+pub fn poster(command: &str, bytes: &[u8], extension: &str) -> Result<Vec<u8>, WaifuError> {
+    if !command.contains("{in}") || !command.contains("{out}") {
+        return Err("The poster command needs both an {in} and an {out} placeholder".into());
+    }
+
+    hook::run_on_bytes(command, bytes, extension)
+}
@@ -0,0 +1,263 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+
+use crate::error::WaifuError;
+
+/// Make the image file the desktop wallpaper, on every monitor or only the one
+/// named. On Linux it's set with whatever the desktop in use goes by: swaymsg
+/// under sway, gsettings under GNOME, swaybg under other Wayland compositors and
+/// feh under X
+pub fn set(path: &Path, monitor: Option<&str>) -> Result<(), WaifuError> {
+    let path = path.canonicalize()?;
+    let path = path.to_string_lossy();
+
+    if cfg!(target_os = "macos") {
+        set_macos(&path, monitor)
+    } else if cfg!(windows) {
+        set_windows(&path, monitor)
+    } else {
+        set_linux(&path, monitor)
+    }
+}
+
+/// Make the image file the lock screen image: in swaylock's config file, or with
+/// gsettings under GNOME, or through the lock screen API on Windows. macOS
+/// shows the desktop picture when it's locked, so there's nothing more to do there
+pub fn set_lockscreen(path: &Path) -> Result<(), WaifuError> {
+    let path = path.canonicalize()?;
+    let path = path.to_string_lossy();
+
+    if cfg!(target_os = "macos") {
+        Ok(())
+    } else if cfg!(windows) {
+        let script = LOCKSCREEN_SCRIPT.replace("{path}", &path.replace('\'', "''"));
+        set_with(
+            "powershell",
+            &["-NoProfile", "-NonInteractive", "-Command", &script],
+        )
+    } else if gnome() {
+        let uri = format!("file://{}", path);
+        set_with(
+            "gsettings",
+            &["set", "org.gnome.desktop.screensaver", "picture-uri", &uri],
+        )
+    } else {
+        set_swaylock(&path)
+    }
+}
+
+/// Windows.System.UserProfile.LockScreen from PowerShell, which has to be
+/// waited on through AsTask
+const LOCKSCREEN_SCRIPT: &str = "\
+    Add-Type -AssemblyName System.Runtime.WindowsRuntime; \
+    [Windows.Storage.StorageFile, Windows.Storage, ContentType = WindowsRuntime] | Out-Null; \
+    [Windows.System.UserProfile.LockScreen, Windows.System.UserProfile, \
+     ContentType = WindowsRuntime] | Out-Null; \
+    $asTask = [System.WindowsRuntimeSystemExtensions].GetMethods() | Where-Object { \
+        $_.Name -eq 'AsTask' -and $_.GetParameters().Count -eq 1 }; \
+    $getFile = ($asTask | Where-Object { \
+        $_.GetParameters()[0].ParameterType.Name -eq 'IAsyncOperation`1' })[0]; \
+    $setImage = ($asTask | Where-Object { \
+        $_.GetParameters()[0].ParameterType.Name -eq 'IAsyncAction' })[0]; \
+    $file = $getFile.MakeGenericMethod([Windows.Storage.StorageFile]).Invoke($null, \
+        @([Windows.Storage.StorageFile]::GetFileFromPathAsync('{path}'))).Result; \
+    $setImage.Invoke($null, \
+        @([Windows.System.UserProfile.LockScreen]::SetImageFileAsync($file))).Wait()";
+
+/// Point the image line of swaylock's config file at the image, keeping the rest
+fn set_swaylock(path: &str) -> Result<(), WaifuError> {
+    let config = swaylock_config()?;
+    let text = match std::fs::read_to_string(&config) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(format!("Couldn't read {}: {}", config.display(), e).into()),
+    };
+
+    // Ones just for an output, like image=DP-1:path, would be drawn over it
+    let mut lines: Vec<&str> = text
+        .lines()
+        .filter(|line| line.split('=').next().map(str::trim) != Some("image"))
+        .collect();
+    let image = format!("image={}", path);
+    lines.push(&image);
+
+    if let Some(dir) = config.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(&config, lines.join("\n") + "\n")
+        .map_err(|e| format!("Couldn't write {}: {}", config.display(), e).into())
+}
+
+/// The config file swaylock reads, ~/.swaylock/config when there's one, as it
+/// looks there first, otherwise the one in the config directory
+fn swaylock_config() -> Result<PathBuf, WaifuError> {
+    let dirs = directories::BaseDirs::new()
+        .ok_or("Couldn't find your home directory to find swaylock's config file in")?;
+    let old = dirs.home_dir().join(".swaylock").join("config");
+    if old.exists() {
+        return Ok(old);
+    }
+
+    Ok(dirs.config_dir().join("swaylock").join("config"))
+}
+
+fn set_macos(path: &str, monitor: Option<&str>) -> Result<(), WaifuError> {
+    let desktop = match monitor {
+        Some(monitor) => {
+            let number: u32 = monitor.parse().map_err(|_| {
+                format!(
+                    "--monitor takes the number of a display on macOS, e.g. 2, not {}",
+                    monitor
+                )
+            })?;
+            format!("desktop {}", number)
+        }
+        None => "every desktop".to_string(),
+    };
+    let script = format!(
+        "tell application \"System Events\" to tell {} to set picture to \"{}\"",
+        desktop,
+        path.replace('\\', "\\\\").replace('"', "\\\"")
+    );
+
+    set_with("osascript", &["-e", &script])
+}
+
+fn set_windows(path: &str, monitor: Option<&str>) -> Result<(), WaifuError> {
+    if monitor.is_some() {
+        return Err(
+            "--monitor isn't supported on Windows, which is given one wallpaper for every monitor"
+                .into(),
+        );
+    }
+
+    // SystemParametersInfo with SPI_SETDESKWALLPAPER, saved to the profile and
+    // sent to every window
+    let script = format!(
+        "Add-Type -TypeDefinition 'using System.Runtime.InteropServices; \
+         public class Wallpaper {{ [DllImport(\"user32.dll\", CharSet = CharSet.Unicode)] \
+         public static extern bool SystemParametersInfo(int action, int param, string value, int flags); }}'; \
+         if (-not [Wallpaper]::SystemParametersInfo(20, 0, '{}', 3)) {{ exit 1 }}",
+        path.replace('\'', "''")
+    );
+
+    set_with(
+        "powershell",
+        &["-NoProfile", "-NonInteractive", "-Command", &script],
+    )
+}
+
+fn set_linux(path: &str, monitor: Option<&str>) -> Result<(), WaifuError> {
+    if std::env::var_os("SWAYSOCK").is_some() {
+        let output = monitor.unwrap_or("*");
+        return set_with("swaymsg", &["output", output, "bg", path, "fill"]);
+    }
+
+    if gnome() {
+        if monitor.is_some() {
+            return Err(
+                "--monitor isn't supported on GNOME, which puts the same wallpaper on every monitor"
+                    .into(),
+            );
+        }
+        let uri = format!("file://{}", path);
+        set_with(
+            "gsettings",
+            &["set", "org.gnome.desktop.background", "picture-uri", &uri],
+        )?;
+        // Only GNOME 42 and later have a wallpaper for the dark style, so this
+        // one may fail
+        run(
+            "gsettings",
+            &[
+                "set",
+                "org.gnome.desktop.background",
+                "picture-uri-dark",
+                &uri,
+            ],
+        )?;
+        return Ok(());
+    }
+
+    if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+        return set_swaybg(path, monitor);
+    }
+
+    if monitor.is_some() {
+        return Err("--monitor isn't supported with feh, which sets every monitor at once".into());
+    }
+    set_with("feh", &["--no-fehbg", "--bg-fill", path])
+}
+
+/// GNOME, or a desktop based on it that keeps its settings in the same place
+fn gnome() -> bool {
+    let desktop = std::env::var("XDG_CURRENT_DESKTOP").unwrap_or_default();
+    desktop
+        .split(':')
+        .any(|name| name == "GNOME" || name == "Unity" || name == "Budgie")
+}
+
+/// swaybg keeps running to draw the wallpaper, so it's left in the background.
+/// The ones started before are stopped first, unless only one monitor changes
+fn set_swaybg(path: &str, monitor: Option<&str>) -> Result<(), WaifuError> {
+    if monitor.is_none() {
+        // pkill fails when there were none
+        run("pkill", &["-x", "swaybg"])?;
+    }
+
+    let mut command = Command::new("swaybg");
+    if let Some(monitor) = monitor {
+        command.args(["-o", monitor]);
+    }
+    let spawned = command
+        .args(["-i", path, "-m", "fill"])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn();
+    match spawned {
+        Ok(_) => Ok(()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Err(not_found("swaybg")),
+        Err(e) => Err(format!("Couldn't run swaybg: {}", e).into()),
+    }
+}
+
+/// Run a program that sets the wallpaper, which has to be there and succeed
+fn set_with(program: &str, args: &[&str]) -> Result<(), WaifuError> {
+    let output = run(program, args)?.ok_or_else(|| not_found(program))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!(
+            "{} couldn't set the wallpaper ({}): {}",
+            program,
+            output.status,
+            stderr.trim()
+        )
+        .into());
+    }
+
+    Ok(())
+}
+
+/// What the program did, or None when it isn't installed
+fn run(program: &str, args: &[&str]) -> Result<Option<Output>, WaifuError> {
+    let output = Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .output();
+    match output {
+        Ok(output) => Ok(Some(output)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(format!("Couldn't run {}: {}", program, e).into()),
+    }
+}
+
+fn not_found(program: &str) -> WaifuError {
+    format!(
+        "Couldn't find {} to set the wallpaper with. Is it installed?",
+        program
+    )
+    .into()
+}
@@ -0,0 +1,62 @@
+use serde_json::{json, Value};
+
+use crate::api::Post;
+use crate::error::WaifuError;
+use crate::net;
+
+/// Post the image to a Discord or Slack channel through one of its webhooks,
+/// linking to the post's page with the artist, character and source under it
+pub fn post(webhook: &str, image_url: &str, post: Option<&Post>) -> Result<(), WaifuError> {
+    let page = post.map_or(image_url.to_string(), |post| post.page.clone());
+    let attribution = post.map(Post::attribution).unwrap_or_default();
+    let body = if webhook.contains("hooks.slack.com") {
+        slack(image_url, &page, &attribution)
+    } else {
+        discord(image_url, &page, &attribution)
+    };
+
+    let response = net::send(net::client()?.post(webhook).json(&body))?;
+    let status = response.status();
+    if !status.is_success() {
+        let text = response.text().unwrap_or_default();
+        let message = format!(
+            "{}: The webhook didn't take the image: {}",
+            status,
+            text.trim()
+        );
+        return Err(WaifuError::Network(message));
+    }
+
+    Ok(())
+}
+
+/// An embed with the image in it, as a plain link would only be shown as long
+/// as Discord keeps a preview of it
+fn discord(image_url: &str, page: &str, attribution: &str) -> Value {
+    let mut embed = json!({
+        "url": page,
+        "title": page,
+        "image": { "url": image_url },
+    });
+    if !attribution.is_empty() {
+        embed["description"] = json!(attribution);
+    }
+
+    json!({ "embeds": [embed] })
+}
+
+/// The text is what notifications show, and the blocks are what's in the channel
+fn slack(image_url: &str, page: &str, attribution: &str) -> Value {
+    let text = match attribution {
+        "" => format!("<{}>", page),
+        attribution => format!("<{}|{}>", page, attribution),
+    };
+
+    json!({
+        "text": text,
+        "blocks": [
+            { "type": "section", "text": { "type": "mrkdwn", "text": text } },
+            { "type": "image", "image_url": image_url, "alt_text": page },
+        ],
+    })
+}